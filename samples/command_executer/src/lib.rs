@@ -283,10 +283,7 @@ pub fn run(args: RunArgs) -> Result<i32, String> {
     print!("{}", output.stdout);
     eprint!("{}", output.stderr);
 
-    let rc = match output.rc {
-        Some(code) => code,
-        _ => 0,
-    };
+    let rc = output.rc.unwrap_or_default();
 
     Ok(rc)
 }
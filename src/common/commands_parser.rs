@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::str::FromStr;
 
+use crate::common::json_output::DESCRIBE_SCHEMA_VERSION;
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult, VMADDR_CID_PARENT};
 use crate::get_id_by_name;
 use crate::new_nitro_cli_failure;
@@ -37,6 +38,10 @@ pub struct RunEnclavesArgs {
     pub cpu_count: Option<u32>,
     /// Enclave name set by the user.
     pub enclave_name: Option<String>,
+    /// Allow CPU 0 and its hyperthread siblings, which the host keeps by default, to be given
+    /// to the enclave.
+    #[serde(default)]
+    pub unsafe_allow_cpu0: bool,
 }
 
 impl RunEnclavesArgs {
@@ -90,6 +95,7 @@ impl RunEnclavesArgs {
                 attach_console: attach_console(args),
                 enclave_name: parse_enclave_name(args)
                     .map_err(|err| err.add_subaction("Parse enclave name".to_string()))?,
+                unsafe_allow_cpu0: unsafe_allow_cpu0(args),
             })
         }
     }
@@ -170,24 +176,29 @@ impl BuildEnclavesArgs {
 pub struct TerminateEnclavesArgs {
     /// The ID of the enclave that is to be terminated.
     pub enclave_id: String,
+    /// The time in seconds after which an unresponsive enclave process is killed.
+    pub timeout_sec: Option<u64>,
 }
 
 impl TerminateEnclavesArgs {
     /// Construct a new `TerminateEnclavesArgs` instance from the given command-line arguments.
     pub fn new_with(args: &ArgMatches) -> NitroCliResult<Self> {
         // If a name is given, find the corresponding EnclaveID
-        match parse_enclave_name(args)
+        let enclave_id = match parse_enclave_name(args)
             .map_err(|e| e.add_subaction("Parse Enclave Name".to_string()))?
         {
-            Some(name) => Ok(TerminateEnclavesArgs {
-                enclave_id: get_id_by_name(name)
-                    .map_err(|e| e.add_subaction("Get ID by Name".to_string()))?,
-            }),
-            None => Ok(TerminateEnclavesArgs {
-                enclave_id: parse_enclave_id(args)
-                    .map_err(|e| e.add_subaction("Parse enclave ID".to_string()))?,
-            }),
-        }
+            Some(name) => {
+                get_id_by_name(name).map_err(|e| e.add_subaction("Get ID by Name".to_string()))?
+            }
+            None => parse_enclave_id(args)
+                .map_err(|e| e.add_subaction("Parse enclave ID".to_string()))?,
+        };
+
+        Ok(TerminateEnclavesArgs {
+            enclave_id,
+            timeout_sec: parse_terminate_timeout(args)
+                .map_err(|e| e.add_subaction("Parse terminate timeout".to_string()))?,
+        })
     }
 }
 
@@ -227,18 +238,24 @@ impl ConsoleArgs {
 pub struct EmptyArgs {}
 
 /// The arguments used by `describe-enclaves` command.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DescribeEnclavesArgs {
     /// True if metadata is requested.
     pub metadata: bool,
+    /// The schema version which the output must follow. Requests which don't carry one, such
+    /// as those sent before it was introduced, get the latest version.
+    #[serde(default = "latest_schema_version")]
+    pub schema_version: u32,
 }
 
 impl DescribeEnclavesArgs {
     /// Construct a new `DescribeEnclavesArgs` instance from the given command-line arguments.
-    pub fn new_with(args: &ArgMatches) -> Self {
-        DescribeEnclavesArgs {
+    pub fn new_with(args: &ArgMatches) -> NitroCliResult<Self> {
+        Ok(DescribeEnclavesArgs {
             metadata: args.is_present("metadata"),
-        }
+            schema_version: parse_schema_version(args)
+                .map_err(|e| e.add_subaction("Parse schema version".to_string()))?,
+        })
     }
 }
 
@@ -403,7 +420,7 @@ fn parse_enclave_cid(args: &ArgMatches) -> NitroCliResult<Option<u64>> {
             ));
         }
 
-        if enclave_cid == u32::max_value() as u64 {
+        if enclave_cid == u32::MAX as u64 {
             return Err(new_nitro_cli_failure!(
                 &format!(
                     "CID {} is a well-known CID, not to be used for enclaves",
@@ -425,7 +442,7 @@ fn parse_enclave_cid(args: &ArgMatches) -> NitroCliResult<Option<u64>> {
         }
 
         // 64-bit CIDs are not yet supported for the vsock device.
-        if enclave_cid > u32::max_value() as u64 {
+        if enclave_cid > u32::MAX as u64 {
             return Err(new_nitro_cli_failure!(
                 &format!(
                     "CID {} is higher than the maximum supported (u32 max) for a vsock device",
@@ -483,6 +500,52 @@ fn parse_disconnect_timeout(args: &ArgMatches) -> NitroCliResult<Option<u64>> {
     Ok(disconnect_timeout)
 }
 
+/// Parse the time after which an unresponsive enclave process is killed from the command-line arguments.
+fn parse_terminate_timeout(args: &ArgMatches) -> NitroCliResult<Option<u64>> {
+    match args.value_of("timeout") {
+        Some(arg) => Ok(Some(arg.parse::<u64>().map_err(|_| {
+            new_nitro_cli_failure!(
+                "`timeout` argument can't be parsed as a number",
+                NitroCliErrorEnum::InvalidArgument
+            )
+            .add_info(vec!["timeout", arg])
+        })?)),
+        None => Ok(None),
+    }
+}
+
+/// The schema version of the `describe-enclaves` output when none is requested.
+fn latest_schema_version() -> u32 {
+    DESCRIBE_SCHEMA_VERSION
+}
+
+/// Parse the requested output schema version from the command-line arguments.
+fn parse_schema_version(args: &ArgMatches) -> NitroCliResult<u32> {
+    let schema_version = match args.value_of("schema-version") {
+        Some(arg) => arg.parse::<u32>().map_err(|_| {
+            new_nitro_cli_failure!(
+                "`schema-version` argument can't be parsed as a number",
+                NitroCliErrorEnum::InvalidArgument
+            )
+            .add_info(vec!["schema-version", arg])
+        })?,
+        None => return Ok(DESCRIBE_SCHEMA_VERSION),
+    };
+
+    if schema_version == 0 || schema_version > DESCRIBE_SCHEMA_VERSION {
+        return Err(new_nitro_cli_failure!(
+            &format!("Schema version {} is not supported", schema_version),
+            NitroCliErrorEnum::UnsupportedSchemaVersion
+        )
+        .add_info(vec![
+            &schema_version.to_string(),
+            &DESCRIBE_SCHEMA_VERSION.to_string(),
+        ]));
+    }
+
+    Ok(schema_version)
+}
+
 /// Parse the list of requested CPU IDs from the command-line arguments.
 fn parse_cpu_ids(args: &ArgMatches) -> NitroCliResult<Option<Vec<u32>>> {
     let cpu_ids_arg = args.values_of("cpu-ids");
@@ -536,6 +599,11 @@ fn attach_console(args: &ArgMatches) -> bool {
     args.is_present("attach-console")
 }
 
+/// Parse the unsafe-allow-cpu0 flag from the command-line arguments.
+fn unsafe_allow_cpu0(args: &ArgMatches) -> bool {
+    args.is_present("unsafe-allow-cpu0")
+}
+
 /// Parse the enclave name from the command-line arguments.
 fn parse_enclave_name(args: &ArgMatches) -> NitroCliResult<Option<String>> {
     Ok(args.value_of("enclave-name").map(|e| e.to_string()))
@@ -1178,6 +1246,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_schema_version() {
+        let app = create_app!();
+        let matches = app.get_matches_from_safe(vec!["nitro-cli", "describe-enclaves"]);
+        let result = parse_schema_version(
+            matches
+                .as_ref()
+                .unwrap()
+                .subcommand_matches("describe-enclaves")
+                .unwrap(),
+        );
+        assert_eq!(result.unwrap(), DESCRIBE_SCHEMA_VERSION);
+
+        for (version, error_code) in [
+            ("0", NitroCliErrorEnum::UnsupportedSchemaVersion),
+            ("2", NitroCliErrorEnum::UnsupportedSchemaVersion),
+            ("latest", NitroCliErrorEnum::InvalidArgument),
+        ] {
+            let app = create_app!();
+            let args = vec![
+                "nitro-cli",
+                "describe-enclaves",
+                "--schema-version",
+                version,
+            ];
+            let matches = app.get_matches_from_safe(args);
+            let result = parse_schema_version(
+                matches
+                    .as_ref()
+                    .unwrap()
+                    .subcommand_matches("describe-enclaves")
+                    .unwrap(),
+            );
+            assert_eq!(result.err().unwrap().error_code, error_code);
+        }
+    }
+
+    #[test]
+    fn test_describe_args_default_schema_version() {
+        #[derive(Serialize)]
+        struct LegacyDescribeEnclavesArgs {
+            metadata: bool,
+        }
+
+        let bytes = serde_cbor::to_vec(&LegacyDescribeEnclavesArgs { metadata: true }).unwrap();
+        let args: DescribeEnclavesArgs = serde_cbor::from_slice(&bytes).unwrap();
+        assert!(args.metadata);
+        assert_eq!(args.schema_version, DESCRIBE_SCHEMA_VERSION);
+    }
+
     #[test]
     fn test_parse_output() {
         let app = create_app!();
@@ -68,6 +68,31 @@ lazy_static! {
             (NitroCliErrorEnum::HasherError, "E57"),
             (NitroCliErrorEnum::EnclaveNamingError, "E58"),
             (NitroCliErrorEnum::EIFSignatureCheckerError, "E59"),
+            (NitroCliErrorEnum::InvalidMemoryRegion, "E60"),
+            (NitroCliErrorEnum::InsufficientNodeMemory, "E61"),
+            (NitroCliErrorEnum::UnsupportedSchemaVersion, "E62"),
+            (NitroCliErrorEnum::IncompleteCpuCore, "E63"),
+            (NitroCliErrorEnum::EnclaveConsoleUnavailable, "E64"),
+            (NitroCliErrorEnum::MalformedEnclaveId, "E65"),
+            (NitroCliErrorEnum::NoHugetlbfsMount, "E66"),
+            (NitroCliErrorEnum::InstanceMetadataUnavailable, "E67"),
+            (NitroCliErrorEnum::EifMeasurementMismatch, "E68"),
+            (NitroCliErrorEnum::NotAnEnclaveProcess, "E69"),
+            (NitroCliErrorEnum::InvalidStateTransition, "E70"),
+            (NitroCliErrorEnum::EnclaveStartTimeout, "E71"),
+            (NitroCliErrorEnum::EifUnsigned, "E72"),
+            (NitroCliErrorEnum::EifSignatureInvalid, "E73"),
+            (NitroCliErrorEnum::EifCertificateMismatch, "E74"),
+            (NitroCliErrorEnum::SocketVanished, "E75"),
+            (NitroCliErrorEnum::CgroupUnsupported, "E76"),
+            (NitroCliErrorEnum::CidInUse, "E77"),
+            (NitroCliErrorEnum::CidSelectionUnsupported, "E78"),
+            (NitroCliErrorEnum::RegionOverlap, "E79"),
+            (NitroCliErrorEnum::EnclaveProcessUnavailable, "E80"),
+            (NitroCliErrorEnum::UnauthorizedPeer, "E81"),
+            (NitroCliErrorEnum::ProtocolVersionMismatch, "E82"),
+            (NitroCliErrorEnum::MemorySlotInUse, "E83"),
+            (NitroCliErrorEnum::HostCpuReserved, "E84"),
         ].iter().cloned().collect();
 }
 
@@ -333,6 +358,205 @@ pub fn get_detailed_info(error_code_str: String, additional_info: &[String]) ->
         "E59" => {
             ret.push_str("EIF signature checker error. Such error appears when validation of the signing certificate fails.");
         }
+        "E60" => {
+            ret.push_str(
+                format!(
+                    "Invalid memory region. Such error appears when a memory region is not backed by hugepages or when its address or size is not aligned to the backing page size. The region at `{}` has a backing page size of `{}` KiB.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E61" => {
+            ret.push_str(
+                format!(
+                    "Insufficient memory available on NUMA node. Such error appears when an enclave memory region is bound to a NUMA node which does not have enough free hugepages. Node `{}` is short of {} hugepages.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E62" => {
+            ret.push_str(
+                format!(
+                    "Unsupported schema version. Such error appears when the user requests an output schema version which is not supported by this version of the Nitro CLI. Requested version `{}`, while the latest supported version is `{}`.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E63" => {
+            ret.push_str(
+                format!(
+                    "Incomplete CPU core. Such error appears when only some of the hyperthread siblings of a CPU core are part of the CPU pool, which would leave a sibling of an enclave vCPU running host workloads. The core with CPUs `{}` would not have CPU(s) `{}` allocated to the enclave.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E64" => {
+            ret.push_str("Enclave console unavailable. Such error appears when the enclave refuses the console connection, which happens when the enclave has not been started in debug mode.");
+        }
+        "E65" => {
+            ret.push_str(
+                format!(
+                    "Malformed enclave ID. The enclave ID `{}` is not of the form `i-<instance ID>-enc<slot ID>`: {}.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E66" => {
+            ret.push_str(
+                format!(
+                    "No hugetlbfs mount found. Such error appears when no hugetlbfs file-system is mounted with {} pages. Mounts considered: {}.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E67" => {
+            ret.push_str(
+                format!(
+                    "Instance metadata unavailable. Such error appears when the instance ID can't be obtained from the instance metadata service at `{}`, which happens when not running on an EC2 instance or when the service is disabled: {}.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E68" => {
+            ret.push_str(
+                format!(
+                    "EIF measurement mismatch. Such error appears when the content of the enclave image file at `{}` doesn't match the measurements recorded in it, meaning that it has been corrupted or tampered with: {}.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E69" => {
+            ret.push_str(
+                format!(
+                    "Not an enclave process. Such error appears when the process with PID {} doesn't own any enclave process socket, meaning that it is not an enclave process or that it can't be inspected by the current user.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E70" => {
+            ret.push_str(
+                format!(
+                    "Invalid enclave state transition. Such error appears when an operation which would move the enclave from the `{}` state to the `{}` state is attempted, which the enclave lifecycle doesn't allow.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E71" => {
+            ret.push_str(
+                format!(
+                    "Enclave start timeout. Such error appears when the enclave has not signaled that it has booted within {} ms of being started.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E72" => {
+            ret.push_str("Unsigned EIF. Such error appears when the signature of an EIF is checked, but the EIF has no signature section.");
+        }
+        "E73" => {
+            ret.push_str("Invalid EIF signature. Such error appears when the signature of an EIF can't be decoded or doesn't match the measurements of the EIF.");
+        }
+        "E74" => {
+            ret.push_str("EIF signing certificate mismatch. Such error appears when an EIF has been signed with a certificate other than the trusted one.");
+        }
+        "E75" => {
+            ret.push_str(
+                format!(
+                    "Socket vanished error. Such error appears when the enclave process socket file `{}` is deleted before its monitoring could start.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E76" => {
+            ret.push_str(
+                format!(
+                    "Cgroup v2 unsupported. Such error appears when `{}` is not part of a cgroup v2 hierarchy, as on hosts which only use cgroup v1.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E77" => {
+            ret.push_str("Enclave CID in use. Such error appears when the requested enclave CID is already used by another enclave.");
+        }
+        "E78" => {
+            ret.push_str("Enclave CID selection unsupported. Such error appears when an enclave is started with a requested CID, but the NE driver can only auto-generate enclave CIDs.");
+        }
+        "E79" => {
+            ret.push_str(
+                format!(
+                    "Memory region overlap. Such error appears when a memory region overlaps the memory region in slot {} which has already been added to the enclave.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E80" => {
+            ret.push_str(
+                format!(
+                    "Enclave process unavailable. Such error appears when the enclave process socket `{}` exists, but keeps refusing connections.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E81" => {
+            ret.push_str(
+                format!(
+                    "Unauthorized peer. Such error appears when the user with UID {} connects to an enclave process which only accepts connections from its own user, root and the allowed users.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E82" => {
+            ret.push_str(
+                format!(
+                    "Protocol version mismatch. Such error appears when the peer speaks version {} of the enclave process protocol, which is incompatible with the version {} of this CLI.",
+                    additional_info.first().unwrap_or(&info_placeholder),
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E83" => {
+            ret.push_str(
+                format!(
+                    "Memory slot in use. Such error appears when a memory region is added to an enclave in slot {}, which already holds another region.",
+                    additional_info.first().unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
+        "E84" => {
+            ret.push_str(
+                format!(
+                    "Host CPU reserved. Such error appears when the CPU with ID {} is requested for an enclave, but it belongs to core 0, which the host keeps for handling critical interrupts.",
+                    additional_info.get(1).unwrap_or(&info_placeholder)
+                )
+                .as_str(),
+            );
+        }
         _ => {
             ret.push_str(format!("No such error code {}", error_code_str).as_str());
         }
@@ -543,6 +767,81 @@ pub fn explain_error(error_code_str: String) {
         "E56" => {
             eprintln!("Logger error. Such error appears when attempting to initialize the underlying logging system fails.");
         }
+        "E60" => {
+            eprintln!("Invalid memory region. Such error appears when a memory region is not backed by hugepages or when its address or size is not aligned to the backing page size.");
+        }
+        "E61" => {
+            eprintln!("Insufficient memory available on NUMA node. Such error appears when an enclave memory region is bound to a NUMA node which does not have enough free hugepages.");
+        }
+        "E62" => {
+            eprintln!("Unsupported schema version. Such error appears when the user requests an output schema version which is not supported by this version of the Nitro CLI.\n\tExample: `nitro-cli describe-enclaves --schema-version 100`. In this case, the user requested the describe-enclaves output to follow a schema version which does not exist. As a solution, request one of the supported schema versions, or omit the flag in order to get the latest one.");
+        }
+        "E63" => {
+            eprintln!("Incomplete CPU core. Such error appears when only some of the hyperthread siblings of a CPU core are part of the CPU pool, which would leave a sibling of an enclave vCPU running host workloads.\n\tExample: (CPU 1 and CPU 5 are siblings, configured CPU pool: [1,2,6]) `nitro-cli run-enclave --cpu-count 2 --memory 80 --eif-path /path/to/my/eif`. In this case, CPU 5 needs to be added to the CPU pool, or CPU 1 removed from it.");
+        }
+        "E64" => {
+            eprintln!("Enclave console unavailable. Such error appears when the enclave refuses the console connection, which happens when the enclave has not been started in debug mode.\n\tExample: `nitro-cli run-enclave --cpu-count 2 --memory 256 --eif-path /path/to/my/eif` followed by `nitro-cli console --enclave-id i-abc0000000000000-enc0123456789012`. In this case, the enclave needs to be started with the `--debug-mode` flag for its console to be available.");
+        }
+        "E65" => {
+            eprintln!("Malformed enclave ID. Such error appears when the provided enclave ID is not of the form `i-<instance ID>-enc<slot ID>`.\n\tExample: `nitro-cli terminate-enclave --enclave-id i-0123456789abcdef0_enc1`. In this case, the separator between the instance ID and the slot ID must be `-enc`. The IDs of the running enclaves are listed by `nitro-cli describe-enclaves`.");
+        }
+        "E66" => {
+            eprintln!("No hugetlbfs mount found. Such error appears when no hugetlbfs file-system is mounted with the page size needed for backing enclave memory.\n\tExample: (only `hugetlbfs /dev/hugepages hugetlbfs rw,pagesize=2M 0 0` is mounted) allocating enclave memory backed by 1 GiB hugepages. In this case, a hugetlbfs instance with the `pagesize=1G` mount option needs to be mounted, for example with `mount -t hugetlbfs -o pagesize=1G none /dev/hugepages1G`.");
+        }
+        "E67" => {
+            eprintln!("Instance metadata unavailable. Such error appears when the instance ID can't be obtained from the instance metadata service, which happens when not running on an EC2 instance or when the service is disabled.\n\tExample: (on a local development machine) creating an enclave process socket for the current instance. In this case, the full enclave ID, including the instance ID, needs to be provided instead. On EC2, check that the instance metadata service is enabled with `aws ec2 describe-instances --query 'Reservations[].Instances[].MetadataOptions'`.");
+        }
+        "E68" => {
+            eprintln!("EIF measurement mismatch. Such error appears when the content of an enclave image file doesn't match the measurements recorded in it, meaning that it has been corrupted or tampered with.\n\tExample: `nitro-cli run-enclave --cpu-count 2 --memory 256 --eif-path image.eif` after `image.eif` has been truncated by an interrupted copy. In this case, the EIF needs to be built or copied again.");
+        }
+        "E69" => {
+            eprintln!("Not an enclave process. Such error appears when the process with a given PID doesn't own any enclave process socket, meaning that it is not an enclave process or that it can't be inspected by the current user.\n\tExample: terminating an enclave by the PID of the CLI instance which launched it. In this case, the PID reported as `ProcessID` by `nitro-cli describe-enclaves` needs to be used instead.");
+        }
+        "E70" => {
+            eprintln!("Invalid enclave state transition. Such error appears when an operation is attempted on an enclave which is not in a state that allows it, such as adding memory or vCPUs to an enclave which has already been started.\n\tExample: starting an enclave, then adding another memory region to it. In this case, the enclave needs to be terminated and launched again with all of its memory.");
+        }
+        "E71" => {
+            eprintln!("Enclave start timeout. Such error appears when the enclave has not signaled that it has booted within the given timeout, in which case the partially-started enclave is terminated.\n\tExample: starting an enclave with a large image and a timeout of a few seconds. In this case, the timeout needs to be increased.");
+        }
+        "E72" => {
+            eprintln!("Unsigned EIF. Such error appears when the signature of an EIF is checked, but the EIF has no signature section.\n\tExample: verifying the signature of an EIF which has been built without a signing certificate and key. In this case, the EIF needs to be built again with `--signing-certificate` and `--private-key`.");
+        }
+        "E73" => {
+            eprintln!("Invalid EIF signature. Such error appears when the signature of an EIF can't be decoded or doesn't match the measurements of the EIF, for instance because the EIF has been modified after being signed.\n\tExample: verifying the signature of an EIF whose ramdisk has been replaced. In this case, the EIF needs to be built and signed again.");
+        }
+        "E74" => {
+            eprintln!("EIF signing certificate mismatch. Such error appears when an EIF has been signed with a certificate other than the trusted one against which its signature is checked.\n\tExample: verifying an EIF signed by a development certificate against the production certificate. In this case, the EIF needs to be signed with the trusted certificate.");
+        }
+        "E75" => {
+            eprintln!("Socket vanished error. Such error appears when the enclave process socket file is deleted between being created and being monitored, so no CLI instance could reach the enclave process through it.\n\tExample: a cleanup job removing the files in `/run/nitro_enclaves` while an enclave is being launched. In this case, the enclave needs to be launched again once the cleanup job is done.");
+        }
+        "E76" => {
+            eprintln!("Cgroup v2 unsupported. Such error appears when the enclave process is to be placed in a cgroup, or its cgroup memory usage is requested, on a host without a cgroup v2 hierarchy.\n\tExample: setting `NITRO_CLI_ENCLAVE_CGROUP` on a host which only mounts cgroup v1 controllers. In this case, the host needs to boot with the unified cgroup hierarchy, or the variable needs to be unset.");
+        }
+        "E77" => {
+            eprintln!("Enclave CID in use. Such error appears when an enclave is started with a CID which another enclave on the same parent instance has already been started with.\n\tExample: `nitro-cli run-enclave --cpu-count 2 --memory 256 --eif-path image.eif --enclave-cid 16`, while an enclave with CID 16 is running. In this case, another CID needs to be chosen, or the running enclave needs to be terminated first.");
+        }
+        "E78" => {
+            eprintln!("Enclave CID selection unsupported. Such error appears when an enclave is started with a requested CID, but the NE driver does not support selecting the enclave CID.\n\tExample: `nitro-cli run-enclave --cpu-count 2 --memory 256 --eif-path image.eif --enclave-cid 16` on a host with an NE driver which only auto-generates CIDs. In this case, the CID needs to be omitted, so that it is auto-generated.");
+        }
+        "E79" => {
+            eprintln!("Memory region overlap. Such error appears when the virtual address range of a memory region to be added to an enclave overlaps the range of a region which has already been added.\n\tExample: adding the same hugepage mapping to an enclave twice. In this case, each region of enclave memory needs to be backed by a distinct mapping.");
+        }
+        "E80" => {
+            eprintln!("Enclave process unavailable. Such error appears when the socket of an enclave process exists, but no connection to it succeeds within the allowed number of attempts, which means that the enclave process has exited without removing its socket or is stuck.\n\tExample: `nitro-cli describe-enclaves` after the enclave process has been killed with `SIGKILL`. In this case, the enclave can be terminated with `nitro-cli terminate-enclave --enclave-id <enclave ID>`, which also removes the stale socket.");
+        }
+        "E81" => {
+            eprintln!("Unauthorized peer. Such error appears when an enclave process restricts its connections with `NITRO_CLI_ALLOWED_PEER_UIDS`, and a user who is neither the owner of the enclave process, root, nor listed in that variable connects to it.\n\tExample: (the enclave has been started by UID 1000 with `NITRO_CLI_ALLOWED_PEER_UIDS=1001`) `nitro-cli describe-enclaves` run by UID 1002. In this case, UID 1002 needs to be added to the variable when starting the enclave.");
+        }
+        "E82" => {
+            eprintln!("Protocol version mismatch. Such error appears when the CLI and an enclave process use different, incompatible versions of the enclave process protocol, which usually happens after the CLI has been upgraded while an enclave started by the previous version is still running.\n\tExample: `nitro-cli describe-enclaves` run by a newer CLI against an enclave started by an older one. In this case, the enclave needs to be restarted with the current CLI.");
+        }
+        "E83" => {
+            eprintln!("Memory slot in use. Such error appears when a memory region is added to an enclave in an explicitly given slot which another region of the enclave already holds.\n\tExample: adding two regions to an enclave, both in slot 0. In this case, the regions need distinct slots, or the slots can be left to be assigned automatically.");
+        }
+        "E84" => {
+            eprintln!("Host CPU reserved. Such error appears when CPU 0, or one of its hyperthread siblings, is requested for an enclave. The host relies on core 0 for handling critical interrupts, so giving it to an enclave may destabilize the host.\n\tExample: `nitro-cli run-enclave --cpu-ids 0 1 --memory 512 --eif-path image.eif`. In this case, other CPUs need to be requested, or `--unsafe-allow-cpu0` needs to be given.");
+        }
         _ => {
             eprintln!("No such error code {}", error_code_str);
         }
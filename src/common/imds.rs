@@ -0,0 +1,191 @@
+// Copyright 2020-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use lazy_static::lazy_static;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::new_nitro_cli_failure;
+
+/// The address of the EC2 instance metadata service.
+const IMDS_ADDR: &str = "169.254.169.254:80";
+
+/// How long to wait for the instance metadata service, which is only reachable on EC2.
+const IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The lifetime requested for IMDSv2 session tokens, in seconds.
+const IMDS_TOKEN_TTL_SECS: u32 = 60;
+
+lazy_static! {
+    /// The instance ID, once it has been obtained.
+    static ref INSTANCE_ID: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Get the ID of the current EC2 instance from the instance metadata service (IMDSv2). The ID
+/// is cached after the first successful query.
+pub fn current_instance_id() -> NitroCliResult<String> {
+    let mut instance_id = INSTANCE_ID.lock().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to acquire lock on the instance ID: {:?}", e),
+            NitroCliErrorEnum::LockAcquireFailure
+        )
+    })?;
+
+    if let Some(instance_id) = instance_id.as_ref() {
+        return Ok(instance_id.clone());
+    }
+
+    let addr: SocketAddr = IMDS_ADDR.parse().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Invalid instance metadata service address: {:?}", e),
+            NitroCliErrorEnum::InstanceMetadataUnavailable
+        )
+    })?;
+    let id = fetch_instance_id(addr)?;
+    *instance_id = Some(id.clone());
+
+    Ok(id)
+}
+
+/// Query the instance metadata service at `addr` for the instance ID.
+fn fetch_instance_id(addr: SocketAddr) -> NitroCliResult<String> {
+    let unavailable = |reason: String| {
+        new_nitro_cli_failure!(
+            &format!(
+                "Failed to get the instance ID from the instance metadata service at {}: {}",
+                addr, reason
+            ),
+            NitroCliErrorEnum::InstanceMetadataUnavailable
+        )
+        .add_info(vec![&addr.to_string(), &reason])
+    };
+
+    let token = imds_request(
+        addr,
+        &format!(
+            "PUT /latest/api/token HTTP/1.1\r\nHost: {}\r\nX-aws-ec2-metadata-token-ttl-seconds: {}\r\nConnection: close\r\n\r\n",
+            addr, IMDS_TOKEN_TTL_SECS
+        ),
+    )
+    .map_err(|reason| unavailable(format!("no session token ({})", reason)))?;
+    let instance_id = imds_request(
+        addr,
+        &format!(
+            "GET /latest/meta-data/instance-id HTTP/1.1\r\nHost: {}\r\nX-aws-ec2-metadata-token: {}\r\nConnection: close\r\n\r\n",
+            addr,
+            token.trim()
+        ),
+    )
+    .map_err(unavailable)?;
+
+    let instance_id = instance_id.trim();
+    if !instance_id.starts_with("i-") {
+        return Err(unavailable(format!(
+            "unexpected instance ID `{}`",
+            instance_id
+        )));
+    }
+
+    Ok(instance_id.to_string())
+}
+
+/// Send an HTTP request to the instance metadata service and return the body of a successful
+/// reply, or the reason for which no such reply has been received.
+fn imds_request(addr: SocketAddr, request: &str) -> Result<String, String> {
+    let mut stream =
+        TcpStream::connect_timeout(&addr, IMDS_TIMEOUT).map_err(|e| format!("{}", e))?;
+    stream
+        .set_read_timeout(Some(IMDS_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(IMDS_TIMEOUT)))
+        .and_then(|_| stream.write_all(request.as_bytes()))
+        .map_err(|e| format!("{}", e))?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| format!("{}", e))?;
+
+    let (head, body) = reply
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "malformed HTTP reply".to_string())?;
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(body.to_string()),
+        _ => Err(format!("HTTP status `{}`", status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve the given replies to consecutive connections, returning the received requests.
+    fn fake_imds(replies: Vec<&'static str>) -> (SocketAddr, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0u8; 4096];
+                let len = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..len]).to_string());
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        (addr, server)
+    }
+
+    /// Tests that the instance ID is requested with an IMDSv2 session token.
+    #[test]
+    fn test_fetch_instance_id() {
+        let (addr, server) = fake_imds(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\ntoken",
+            "HTTP/1.1 200 OK\r\nContent-Length: 19\r\n\r\ni-0123456789abcdef0",
+        ]);
+
+        assert_eq!(fetch_instance_id(addr).unwrap(), "i-0123456789abcdef0");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("PUT /latest/api/token "));
+        assert!(requests[1].starts_with("GET /latest/meta-data/instance-id "));
+        assert!(requests[1].contains("X-aws-ec2-metadata-token: token\r\n"));
+    }
+
+    /// Tests that a missing or failing instance metadata service is reported clearly.
+    #[test]
+    fn test_fetch_instance_id_unavailable() {
+        let (addr, server) = fake_imds(vec!["HTTP/1.1 403 Forbidden\r\n\r\n"]);
+        let err = fetch_instance_id(addr).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InstanceMetadataUnavailable
+        );
+        assert_eq!(
+            err.additional_info[1],
+            "no session token (HTTP status `HTTP/1.1 403 Forbidden`)"
+        );
+        server.join().unwrap();
+
+        // Nothing listens on the port anymore once its listener is dropped.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err = fetch_instance_id(addr).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InstanceMetadataUnavailable
+        );
+    }
+}
@@ -9,9 +9,19 @@ use aws_nitro_enclaves_image_format::utils::eif_reader::SignCertificateInfo;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+/// The latest schema version of the `describe-enclaves` output.
+pub const DESCRIBE_SCHEMA_VERSION: u32 = 1;
+
+/// The `Flags` value of an enclave launched in debug mode.
+pub const DEBUG_MODE_FLAG: &str = "DEBUG_MODE";
+
 /// The information to be provided for a `describe-enclaves` request.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct EnclaveDescribeInfo {
+    #[serde(rename = "SchemaVersion")]
+    #[serde(default)]
+    /// The version of the schema followed by this description.
+    pub schema_version: u32,
     /// Enclave name assigned by the user
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "EnclaveName")]
@@ -40,6 +50,20 @@ pub struct EnclaveDescribeInfo {
     #[serde(rename = "Flags")]
     /// The bit-mask which provides the enclave's launch flags.
     pub flags: String,
+    #[serde(rename = "DebugMode")]
+    #[serde(default)]
+    /// Whether the enclave has been launched in debug mode, in which its PCRs are all zeroes.
+    pub debug_mode: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "StartTime")]
+    #[serde(default)]
+    /// The time at which the enclave has been started, in RFC 3339 format.
+    pub start_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "UptimeSecs")]
+    #[serde(default)]
+    /// For how many seconds the enclave has been running.
+    pub uptime_secs: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     /// Build measurements containing PCRs
@@ -75,6 +99,7 @@ impl EnclaveDescribeInfo {
         metadata: Option<MetadataDescribeInfo>,
     ) -> Self {
         EnclaveDescribeInfo {
+            schema_version: DESCRIBE_SCHEMA_VERSION,
             enclave_name,
             enclave_id,
             process_id: std::process::id(),
@@ -83,7 +108,10 @@ impl EnclaveDescribeInfo {
             cpu_ids,
             memory_mib,
             state,
+            debug_mode: flags == DEBUG_MODE_FLAG,
             flags,
+            start_time: None,
+            uptime_secs: None,
             build_info,
             img_name,
             img_version,
@@ -179,6 +207,45 @@ impl EnclaveBuildInfo {
     pub fn new(measurements: BTreeMap<String, String>) -> Self {
         EnclaveBuildInfo { measurements }
     }
+
+    /// Get the PCRs of the enclave image, if they have been measured.
+    pub fn pcr_measurements(&self) -> Option<PcrMeasurements> {
+        PcrMeasurements::from_measurements(&self.measurements)
+    }
+}
+
+/// The PCRs of an enclave image, which the attestation documents of its enclaves report.
+///
+/// Each PCR is the lowercase hex encoding of a SHA-384 digest, written in the order in which the
+/// digest bytes are produced. A PCR is a single extension of an all-zero register, computed as
+/// `SHA-384(48 zero bytes || SHA-384(data))`, where the data is the concatenation, in file order,
+/// of the contents (without their section headers) of the following EIF sections:
+/// - PCR0: the kernel, the kernel command line and all ramdisks.
+/// - PCR1: the kernel, the kernel command line and the first (bootstrap) ramdisk.
+/// - PCR2: the remaining (application) ramdisks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcrMeasurements {
+    #[serde(rename = "PCR0")]
+    /// The measurement of the whole enclave image.
+    pub pcr0: String,
+    #[serde(rename = "PCR1")]
+    /// The measurement of the kernel and bootstrap.
+    pub pcr1: String,
+    #[serde(rename = "PCR2")]
+    /// The measurement of the application.
+    pub pcr2: String,
+}
+
+impl PcrMeasurements {
+    /// Create a new `PcrMeasurements` instance from the measurements reported at build or
+    /// describe time, if all of its PCRs are among them.
+    pub fn from_measurements(measurements: &BTreeMap<String, String>) -> Option<Self> {
+        Some(PcrMeasurements {
+            pcr0: measurements.get("PCR0")?.clone(),
+            pcr1: measurements.get("PCR1")?.clone(),
+            pcr2: measurements.get("PCR2")?.clone(),
+        })
+    }
 }
 
 /// The information to be provided for a `describe-eif` request.
@@ -7,14 +7,18 @@ use chrono::offset::{Local, Utc};
 use chrono::DateTime;
 use flexi_logger::writers::LogWriter;
 use flexi_logger::{DeferredNow, Record};
+use lazy_static::lazy_static;
 use nix::unistd::Uid;
 use std::env;
-use std::fs::{File, OpenOptions, Permissions};
+use std::ffi::{CString, OsString};
+use std::fs::{self, File, OpenOptions, Permissions};
 use std::io::{Error, ErrorKind, Result, Write};
 use std::ops::{Deref, DerefMut};
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixDatagram;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
 use crate::new_nitro_cli_failure;
@@ -31,6 +35,104 @@ const LOGS_DIR_PATH: &str = "/var/log/nitro_enclaves";
 /// The name of the output log file.
 const LOG_FILE_NAME: &str = "nitro_enclaves.log";
 
+/// The environment variable which selects where enclave processes log to.
+const ENCLAVE_LOG_TARGET_ENV_VAR: &str = "NITRO_CLI_ENCLAVE_LOG_TARGET";
+
+/// The size in bytes above which a dedicated log file is rotated.
+const LOG_FILE_ROTATE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The number of rotated log files which are kept next to a dedicated log file.
+const LOG_FILE_ROTATE_COUNT: u32 = 4;
+
+/// The socket over which journald receives native protocol messages.
+const JOURNALD_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+/// The identifier with which messages are sent to syslog and journald.
+const SYSLOG_IDENTIFIER: &[u8] = b"nitro-enclaves\0";
+
+lazy_static! {
+    /// The ID of the enclave which the current process manages, once it is known.
+    static ref LOG_ENCLAVE_ID: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Log a message prefixed with the `[enc=<id> pid=<pid>]` context of the current process, so
+/// that the messages of concurrent enclave processes can be told apart once aggregated.
+#[macro_export]
+macro_rules! enclave_log {
+    ($level:ident, $($arg:tt)+) => {
+        log::$level!(
+            "{} {}",
+            $crate::common::logger::log_context(),
+            format_args!($($arg)+)
+        )
+    };
+}
+
+/// Set the enclave ID which is included in the context of all `enclave_log!` messages.
+pub fn set_log_enclave_id(enclave_id: &str) {
+    if let Ok(mut id) = LOG_ENCLAVE_ID.lock() {
+        *id = Some(enclave_id.to_string());
+    }
+}
+
+/// Get the `[enc=<id> pid=<pid>]` context of the current process. The enclave ID is `-`
+/// until it has been set with `set_log_enclave_id()`.
+pub fn log_context() -> String {
+    let enclave_id = LOG_ENCLAVE_ID.lock().ok().and_then(|id| id.clone());
+    format_log_context(enclave_id.as_deref(), std::process::id())
+}
+
+/// Format the logging context for the given enclave ID and PID.
+fn format_log_context(enclave_id: Option<&str>, pid: u32) -> String {
+    format!("[enc={} pid={}]", enclave_id.unwrap_or("-"), pid)
+}
+
+/// Where a log writer sends its messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    /// The log file shared by all CLI instances and enclave processes (`file`).
+    SharedFile,
+    /// A dedicated log file, which is rotated once it grows too large (`file:<path>`).
+    File(PathBuf),
+    /// The system logger (`syslog`).
+    Syslog,
+    /// The systemd journal, with the enclave ID as a field of each entry (`journald`).
+    Journald,
+}
+
+impl FromStr for LogTarget {
+    type Err = NitroCliFailure;
+
+    fn from_str(target: &str) -> NitroCliResult<Self> {
+        match target {
+            "file" => Ok(LogTarget::SharedFile),
+            "syslog" => Ok(LogTarget::Syslog),
+            "journald" => Ok(LogTarget::Journald),
+            _ => match target.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LogTarget::File(PathBuf::from(path))),
+                _ => Err(new_nitro_cli_failure!(
+                    &format!(
+                        "Invalid log target `{}`, expected `file`, `file:<path>`, `syslog` or `journald`",
+                        target
+                    ),
+                    NitroCliErrorEnum::LoggerError
+                )),
+            },
+        }
+    }
+}
+
+/// Get the target which enclave processes log to, as given in `NITRO_CLI_ENCLAVE_LOG_TARGET`.
+/// It defaults to the shared log file.
+pub fn enclave_log_target() -> NitroCliResult<LogTarget> {
+    match env::var(ENCLAVE_LOG_TARGET_ENV_VAR) {
+        Ok(target) => target.parse().map_err(|e: NitroCliFailure| {
+            e.add_subaction(format!("Invalid {}", ENCLAVE_LOG_TARGET_ENV_VAR))
+        }),
+        Err(_) => Ok(LogTarget::SharedFile),
+    }
+}
+
 /// A log writer which outputs its messages to a custom file. It also
 /// allows the updating of its ID, in order to indicate which process
 /// is actually logging a message. This implementation will also enable
@@ -39,6 +141,10 @@ const LOG_FILE_NAME: &str = "nitro_enclaves.log";
 pub struct EnclaveProcLogWriter {
     out_file: Arc<Mutex<File>>,
     logger_id: Arc<Mutex<String>>,
+    /// Where the messages are sent, which is the shared log file unless set otherwise.
+    target: Arc<Mutex<LogTarget>>,
+    /// The socket connected to journald, when logging to the journal.
+    journal: Arc<Mutex<Option<UnixDatagram>>>,
 }
 
 impl EnclaveProcLogWriter {
@@ -51,27 +157,131 @@ impl EnclaveProcLogWriter {
                     .map_err(|e| e.add_subaction("Failed to open log file".to_string()))?,
             )),
             logger_id: Arc::new(Mutex::new(String::new())),
+            target: Arc::new(Mutex::new(LogTarget::SharedFile)),
+            journal: Arc::new(Mutex::new(None)),
         })
     }
 
-    /// Check if the log file is present and if it is not, (re)open it.
-    fn safe_open_log_file(&self) -> NitroCliResult<()> {
-        let log_path = &get_log_file_path();
-        if !log_path.exists() {
-            let new_file = open_log_file(log_path)
-                .map_err(|e| e.add_subaction(String::from("Failed to open log file")))?;
-            let mut file_ref = self.out_file.lock().map_err(|e| {
-                new_nitro_cli_failure!(
-                    &format!("Failed to acquire lock: {:?}", e),
-                    NitroCliErrorEnum::LockAcquireFailure
+    /// Send all further messages to the given target, which is checked to be usable first.
+    pub fn set_target(&self, target: LogTarget) -> NitroCliResult<()> {
+        match &target {
+            LogTarget::SharedFile => self.replace_log_file(&get_log_file_path())?,
+            LogTarget::File(path) => self.replace_log_file(path)?,
+            LogTarget::Syslog => unsafe {
+                libc::openlog(
+                    SYSLOG_IDENTIFIER.as_ptr() as *const libc::c_char,
+                    libc::LOG_PID,
+                    libc::LOG_DAEMON,
                 )
-            })?;
-            *file_ref.deref_mut() = new_file;
+            },
+            LogTarget::Journald => {
+                let socket = UnixDatagram::unbound()
+                    .and_then(|socket| socket.connect(JOURNALD_SOCKET_PATH).map(|_| socket))
+                    .map_err(|e| {
+                        new_nitro_cli_failure!(
+                            &format!(
+                                "Failed to connect to journald at {}: {:?}",
+                                JOURNALD_SOCKET_PATH, e
+                            ),
+                            NitroCliErrorEnum::LoggerError
+                        )
+                    })?;
+                *lock(&self.journal)? = Some(socket);
+            }
         }
 
+        *lock(&self.target)? = target;
         Ok(())
     }
 
+    /// Get the path of the file which the messages are written to, if any.
+    fn log_file_path(&self) -> NitroCliResult<Option<PathBuf>> {
+        Ok(match lock(&self.target)?.deref() {
+            LogTarget::SharedFile => Some(get_log_file_path()),
+            LogTarget::File(path) => Some(path.clone()),
+            LogTarget::Syslog | LogTarget::Journald => None,
+        })
+    }
+
+    /// Open the log file at the given path, in place of the current one.
+    fn replace_log_file(&self, log_path: &Path) -> NitroCliResult<()> {
+        let new_file = open_log_file(log_path)
+            .map_err(|e| e.add_subaction(String::from("Failed to open log file")))?;
+        *lock(&self.out_file)? = new_file;
+
+        Ok(())
+    }
+
+    /// Check if the log file is present and if it is not, (re)open it. A dedicated log file
+    /// is rotated first if it has grown too large.
+    fn safe_open_log_file(&self, log_path: &Path) -> NitroCliResult<()> {
+        let dedicated = *lock(&self.target)? != LogTarget::SharedFile;
+        let log_size = lock(&self.out_file)?
+            .metadata()
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if dedicated && log_size >= LOG_FILE_ROTATE_SIZE {
+            rotate_log_file(log_path, LOG_FILE_ROTATE_COUNT)?;
+        }
+
+        if !log_path.exists() {
+            self.replace_log_file(log_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send a message to syslog.
+    fn write_syslog(&self, record: &Record) -> NitroCliResult<()> {
+        let msg = format!("[{}] {}", lock(&self.logger_id)?.deref(), record.args());
+        let msg = CString::new(msg.replace('\0', "")).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to create syslog message: {:?}", e),
+                NitroCliErrorEnum::LoggerError
+            )
+        })?;
+        unsafe {
+            libc::syslog(
+                syslog_priority(record.level()),
+                b"%s\0".as_ptr() as *const libc::c_char,
+                msg.as_ptr(),
+            )
+        };
+
+        Ok(())
+    }
+
+    /// Send a message to journald.
+    fn write_journald(&self, record: &Record) -> NitroCliResult<()> {
+        let enclave_id = LOG_ENCLAVE_ID.lock().ok().and_then(|id| id.clone());
+        let entry = journald_entry(&[
+            ("PRIORITY", &syslog_priority(record.level()).to_string()),
+            (
+                "SYSLOG_IDENTIFIER",
+                std::str::from_utf8(&SYSLOG_IDENTIFIER[..SYSLOG_IDENTIFIER.len() - 1])
+                    .unwrap_or("nitro-enclaves"),
+            ),
+            ("SYSLOG_PID", &std::process::id().to_string()),
+            ("NITRO_ENCLAVE_ID", enclave_id.as_deref().unwrap_or("-")),
+            ("CODE_FILE", record.file().unwrap_or("?")),
+            ("CODE_LINE", &record.line().unwrap_or(0).to_string()),
+            ("MESSAGE", &record.args().to_string()),
+        ]);
+
+        match lock(&self.journal)?.as_ref() {
+            Some(socket) => socket.send(&entry).map(|_| ()).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to send message to journald: {:?}", e),
+                    NitroCliErrorEnum::LoggerError
+                )
+            }),
+            None => Err(new_nitro_cli_failure!(
+                "Not connected to journald",
+                NitroCliErrorEnum::LoggerError
+            )),
+        }
+    }
+
     /// Update the logger ID (correlated with the process which is doing logging).
     pub fn update_logger_id(&self, new_id: &str) -> NitroCliResult<()> {
         let mut old_id = self.logger_id.lock().map_err(|e| {
@@ -111,7 +321,20 @@ impl EnclaveProcLogWriter {
 
 impl LogWriter for EnclaveProcLogWriter {
     fn write(&self, now: &mut DeferredNow, record: &Record) -> Result<()> {
-        if self.safe_open_log_file().is_err() {
+        let log_path = match self.log_file_path() {
+            Ok(Some(log_path)) => log_path,
+            Ok(None) => {
+                let target = self.target.lock().map(|target| target.clone());
+                let result = match target {
+                    Ok(LogTarget::Syslog) => self.write_syslog(record),
+                    _ => self.write_journald(record),
+                };
+                return result.map_err(|e| Error::new(ErrorKind::Other, e.subactions.join(", ")));
+            }
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Failed to lock log target")),
+        };
+
+        if self.safe_open_log_file(&log_path).is_err() {
             return Err(Error::new(
                 ErrorKind::Other,
                 "Failed to safely open log file for writing",
@@ -161,6 +384,76 @@ pub fn get_log_file_base_path() -> String {
     }
 }
 
+/// Acquire a lock held by the log writer.
+fn lock<T>(mutex: &Mutex<T>) -> NitroCliResult<MutexGuard<'_, T>> {
+    mutex.lock().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to acquire lock: {:?}", e),
+            NitroCliErrorEnum::LockAcquireFailure
+        )
+    })
+}
+
+/// Get the syslog priority of a logging level.
+fn syslog_priority(level: log::Level) -> libc::c_int {
+    match level {
+        log::Level::Error => libc::LOG_ERR,
+        log::Level::Warn => libc::LOG_WARNING,
+        log::Level::Info => libc::LOG_INFO,
+        log::Level::Debug | log::Level::Trace => libc::LOG_DEBUG,
+    }
+}
+
+/// Encode a journal entry in the journald native protocol. Values spanning several lines are
+/// given with their size, as the protocol requires.
+fn journald_entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+
+    entry
+}
+
+/// Get the path of the `index`-th most recent rotated copy of a log file.
+fn rotated_log_file_path(log_path: &Path, index: u32) -> PathBuf {
+    let mut rotated_path = OsString::from(log_path.as_os_str());
+    rotated_path.push(format!(".{}", index));
+    PathBuf::from(rotated_path)
+}
+
+/// Rotate a log file, keeping up to `count` copies of it as `<path>.1` (the most recent one)
+/// to `<path>.<count>`. The log file itself is reopened on the next message.
+fn rotate_log_file(log_path: &Path, count: u32) -> NitroCliResult<()> {
+    let rename = |from: &Path, to: &Path| {
+        fs::rename(from, to).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to rotate log file: {:?}", e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![from.to_str().unwrap_or("log file"), "Rename"])
+        })
+    };
+
+    for index in (1..count).rev() {
+        let rotated_path = rotated_log_file_path(log_path, index);
+        if rotated_path.exists() {
+            rename(&rotated_path, &rotated_log_file_path(log_path, index + 1))?;
+        }
+    }
+
+    rename(log_path, &rotated_log_file_path(log_path, 1))
+}
+
 /// Get the path to the log file.
 fn get_log_file_path() -> PathBuf {
     Path::new(&get_log_file_base_path()).join(LOG_FILE_NAME)
@@ -344,4 +637,67 @@ mod tests {
             env::set_var(LOGS_DIR_PATH_ENV_VAR, old_log_path);
         }
     }
+
+    /// Tests that log targets are parsed from their names.
+    #[test]
+    fn test_log_target_from_str() {
+        assert_eq!("file".parse::<LogTarget>().unwrap(), LogTarget::SharedFile);
+        assert_eq!(
+            "file:/tmp/enclave.log".parse::<LogTarget>().unwrap(),
+            LogTarget::File(PathBuf::from("/tmp/enclave.log"))
+        );
+        assert_eq!("syslog".parse::<LogTarget>().unwrap(), LogTarget::Syslog);
+        assert_eq!(
+            "journald".parse::<LogTarget>().unwrap(),
+            LogTarget::Journald
+        );
+
+        for target in &["", "file:", "stderr"] {
+            let err = target.parse::<LogTarget>().unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::LoggerError);
+        }
+    }
+
+    /// Tests that rotating a log file shifts its copies and drops the oldest one.
+    #[test]
+    fn test_rotate_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("enclave.log");
+
+        for content in &["first", "second", "third", "fourth"] {
+            fs::write(&log_path, content).unwrap();
+            rotate_log_file(&log_path, 3).unwrap();
+        }
+
+        assert!(!log_path.exists());
+        let read = |index| fs::read_to_string(rotated_log_file_path(&log_path, index)).unwrap();
+        assert_eq!(read(1), "fourth");
+        assert_eq!(read(2), "third");
+        assert_eq!(read(3), "second");
+        assert!(!rotated_log_file_path(&log_path, 4).exists());
+    }
+
+    /// Tests that journal entries are encoded in the journald native protocol.
+    #[test]
+    fn test_journald_entry() {
+        assert_eq!(
+            journald_entry(&[("PRIORITY", "6"), ("MESSAGE", "hello")]),
+            b"PRIORITY=6\nMESSAGE=hello\n".to_vec()
+        );
+
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(journald_entry(&[("MESSAGE", "a\nb")]), expected);
+    }
+
+    /// Tests that the logging context identifies the enclave and the process.
+    #[test]
+    fn test_format_log_context() {
+        assert_eq!(format_log_context(None, 42), "[enc=- pid=42]");
+        assert_eq!(
+            format_log_context(Some("i-0000000000000000-enc1f"), 42),
+            "[enc=i-0000000000000000-enc1f pid=42]"
+        );
+    }
 }
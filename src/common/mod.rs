@@ -7,10 +7,14 @@
 pub mod commands_parser;
 /// The module which provides mappings between NitroCliErrors and their corresponding code.
 pub mod document_errors;
+/// The module which queries the EC2 instance metadata service.
+pub mod imds;
 /// The module which provides JSON-ready information structures.
 pub mod json_output;
 /// The module which provides the per-process logger.
 pub mod logger;
+/// The module which provides the handshake of the enclave process socket protocol.
+pub mod protocol;
 /// The module which provides signal handling.
 pub mod signal_handler;
 
@@ -27,11 +31,14 @@ use std::os::raw::c_char;
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 
+use crate::enclave_proc::resource_manager::ne_ioctl_error_message;
 use document_errors::ERROR_CODES;
+pub use imds::current_instance_id;
 use logger::get_log_file_base_path;
 
-/// The most common result type provided by Nitro CLI operations.
-pub type NitroCliResult<T> = Result<T, NitroCliFailure>;
+/// The most common result type provided by Nitro CLI operations. Operations which report the
+/// kind of their failure as a `NitroCliError` name it as the error type.
+pub type NitroCliResult<T, E = NitroCliFailure> = Result<T, E>;
 
 /// The CID for the vsock device of the parent VM.
 pub const VMADDR_CID_PARENT: u32 = 3;
@@ -46,12 +53,21 @@ pub const ENCLAVE_PROC_WAIT_TIMEOUT_MSEC: isize = 3000;
 /// in order to signal that it is alive.
 pub const MSG_ENCLAVE_CONFIRM: u64 = 0xEEC0;
 
+/// The reply sent by an enclave process to a CLI instance which has pinged it.
+pub const MSG_ENCLAVE_PONG: u64 = 0xEEC1;
+
 /// The environment variable which holds the path to the Unix sockets directory.
 pub const SOCKETS_DIR_PATH_ENV_VAR: &str = "NITRO_CLI_SOCKETS_PATH";
 
 /// The default path to the Unix sockets directory.
 const SOCKETS_DIR_PATH: &str = "/run/nitro_enclaves";
 
+/// The maximum number of hexadecimal digits in the instance part of an enclave ID.
+const MAX_INSTANCE_ID_DIGITS: usize = 17;
+
+/// The maximum number of hexadecimal digits in the slot part of an enclave ID.
+const MAX_SLOT_ID_DIGITS: usize = 16;
+
 /// Constant used for identifying the backtrace environment variable.
 const BACKTRACE_VAR: &str = "BACKTRACE";
 
@@ -179,6 +195,70 @@ pub enum NitroCliErrorEnum {
     EnclaveNamingError,
     /// Signature checker error
     EIFSignatureCheckerError,
+    /// Invalid memory region error.
+    InvalidMemoryRegion,
+    /// Not enough hugepages on the requested NUMA node error.
+    InsufficientNodeMemory,
+    /// Unsupported output schema version error.
+    UnsupportedSchemaVersion,
+    /// Hyperthread siblings only partially available in the CPU pool error.
+    IncompleteCpuCore,
+    /// Enclave console unavailable error.
+    EnclaveConsoleUnavailable,
+    /// Malformed enclave ID error.
+    MalformedEnclaveId,
+    /// No hugetlbfs mount with the requested page size has been found.
+    NoHugetlbfsMount,
+    /// The EC2 instance metadata service could not provide the instance ID.
+    InstanceMetadataUnavailable,
+    /// The enclave image file doesn't match its recorded measurements.
+    EifMeasurementMismatch,
+    /// The given process is not an enclave process.
+    NotAnEnclaveProcess,
+    /// Invalid enclave state transition.
+    InvalidStateTransition,
+    /// Enclave start timeout.
+    EnclaveStartTimeout,
+    /// Unsigned EIF.
+    EifUnsigned,
+    /// Invalid EIF signature.
+    EifSignatureInvalid,
+    /// EIF signing certificate mismatch.
+    EifCertificateMismatch,
+    /// Socket vanished before monitoring.
+    SocketVanished,
+    /// Cgroup v2 unsupported.
+    CgroupUnsupported,
+    /// Enclave CID already in use error.
+    CidInUse,
+    /// Enclave CID selection unsupported error.
+    CidSelectionUnsupported,
+    /// Memory region overlap error.
+    RegionOverlap,
+    /// Enclave process unavailable error.
+    EnclaveProcessUnavailable,
+    /// Unauthorized peer error.
+    UnauthorizedPeer,
+    /// Protocol version mismatch error.
+    ProtocolVersionMismatch,
+    /// Memory slot in use error.
+    MemorySlotInUse,
+    /// The CPU is kept for the host, since it belongs to core 0.
+    HostCpuReserved,
+}
+
+impl NitroCliErrorEnum {
+    /// Get the documented code of the error (e.g. `E19`), which prefixes the error message.
+    pub fn code(&self) -> &'static str {
+        ERROR_CODES.get(self).unwrap_or(&"E00")
+    }
+
+    /// Get the process exit status reported for the error. This is the numeric part of the
+    /// documented code, so scripts may match either one. An unspecified error still exits
+    /// with a non-zero status.
+    pub fn exit_code(&self) -> i32 {
+        self.code()[1..].parse::<i32>().unwrap_or(0).max(1)
+    }
 }
 
 impl Eq for NitroCliErrorEnum {}
@@ -206,6 +286,13 @@ pub enum EnclaveProcessCommandType {
     ConnectionListenerStop,
     /// Do not execute a command due to insufficient privileges (sent by the CLI, modified by the enclave process).
     NotPermitted,
+    /// Check that the enclave process is alive and responsive (sent by the CLI).
+    Ping,
+    /// Request the most recent console output of an enclave in debug mode (sent by the CLI).
+    GetRecentConsole,
+    /// Keep the connection open for framed `protocol::Command`s, each of which is answered with
+    /// a `protocol::Response`, until the CLI closes it (sent by the CLI).
+    Session,
 }
 
 /// The type of replies that an enclave process can send to a CLI instance.
@@ -295,6 +382,23 @@ impl NitroCliFailure {
     }
 }
 
+/// Display the user-facing error description (the same first line printed by the CLI),
+/// without writing out any backtrace log file.
+impl std::fmt::Display for NitroCliFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            document_errors::get_detailed_info(
+                self.error_code.code().to_string(),
+                &self.additional_info,
+            )
+        )
+    }
+}
+
+impl std::error::Error for NitroCliFailure {}
+
 /// Macro used for constructing a NitroCliFailure in a more convenient manner.
 #[macro_export]
 macro_rules! new_nitro_cli_failure {
@@ -306,6 +410,104 @@ macro_rules! new_nitro_cli_failure {
     };
 }
 
+/// The kinds of failure of the operations on the NE device, for callers which handle some of
+/// them differently. Each one converts into the `NitroCliFailure` which the CLI reports.
+#[derive(Debug)]
+pub enum NitroCliError {
+    /// The NE device could not be opened.
+    DeviceOpenFailed(std::io::Error),
+    /// An NE ioctl failed.
+    IoctlFailed {
+        /// The error code returned by the NE driver.
+        code: i32,
+        /// The name of the ioctl (i.e. NE_CREATE_VM).
+        ioctl: &'static str,
+    },
+    /// A resource needed by the enclave could not be allocated.
+    ResourceAllocation(String),
+    /// A socket operation failed.
+    SocketError(std::io::Error),
+    /// A failure of any other kind.
+    Failure(NitroCliFailure),
+}
+
+impl NitroCliError {
+    /// Get the error code of the `NitroCliFailure` this error converts into.
+    fn error_code(&self) -> NitroCliErrorEnum {
+        match self {
+            NitroCliError::DeviceOpenFailed(_) => NitroCliErrorEnum::FileOperationFailure,
+            NitroCliError::IoctlFailed { ioctl, .. } => match *ioctl {
+                "NE_CREATE_VM" => NitroCliErrorEnum::InvalidEnclaveFd,
+                "NE_SET_USER_MEMORY_REGION" => NitroCliErrorEnum::IoctlSetMemoryRegionFailure,
+                "NE_ADD_VCPU" => NitroCliErrorEnum::IoctlAddVcpuFailure,
+                "NE_START_ENCLAVE" => NitroCliErrorEnum::IoctlEnclaveStartFailure,
+                "NE_GET_IMAGE_LOAD_INFO" => NitroCliErrorEnum::IoctlImageLoadInfoFailure,
+                _ => NitroCliErrorEnum::IoctlFailure,
+            },
+            NitroCliError::ResourceAllocation(_) => NitroCliErrorEnum::InsufficientMemoryAvailable,
+            NitroCliError::SocketError(_) => NitroCliErrorEnum::SocketError,
+            NitroCliError::Failure(failure) => failure.error_code,
+        }
+    }
+    /// Get the root cause of the error, which is the first subaction of the `NitroCliFailure`
+    /// this error converts into.
+    fn subaction(&self) -> String {
+        match self {
+            NitroCliError::DeviceOpenFailed(err) => {
+                format!("Could not open the NE device: {}", err)
+            }
+            NitroCliError::IoctlFailed { code, ioctl } => {
+                format!("{} ioctl failed: {}", ioctl, ne_ioctl_error_message(*code))
+            }
+            NitroCliError::ResourceAllocation(msg) => msg.clone(),
+            NitroCliError::SocketError(err) => format!("Socket operation failed: {}", err),
+            NitroCliError::Failure(failure) => {
+                failure.subactions.first().cloned().unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Display the user-facing error description, as for the `NitroCliFailure` this error
+/// converts into.
+impl std::fmt::Display for NitroCliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NitroCliError::Failure(failure) => write!(f, "{}", failure),
+            err => write!(
+                f,
+                "{}",
+                document_errors::get_detailed_info(err.error_code().code().to_string(), &[])
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NitroCliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NitroCliError::DeviceOpenFailed(err) | NitroCliError::SocketError(err) => Some(err),
+            NitroCliError::Failure(failure) => Some(failure),
+            _ => None,
+        }
+    }
+}
+
+impl From<NitroCliFailure> for NitroCliError {
+    fn from(failure: NitroCliFailure) -> Self {
+        NitroCliError::Failure(failure)
+    }
+}
+
+impl From<NitroCliError> for NitroCliFailure {
+    fn from(err: NitroCliError) -> Self {
+        match err {
+            NitroCliError::Failure(failure) => failure,
+            err => new_nitro_cli_failure!(&err.subaction(), err.error_code()),
+        }
+    }
+}
+
 /// Logs the given backtrace string to a separate, backtrace-specific file.
 /// Returns a string denoting the path to the corresponding log file.
 fn log_backtrace(backtrace: String) -> Result<String, &'static str> {
@@ -342,14 +544,13 @@ fn log_backtrace(backtrace: String) -> Result<String, &'static str> {
 pub fn construct_error_message(failure: &NitroCliFailure) -> String {
     // Suggestive error description comes first.
     let error_info: String = document_errors::get_detailed_info(
-        (*ERROR_CODES.get(&failure.error_code).unwrap_or(&"E00")).to_string(),
+        failure.error_code.code().to_string(),
         &failure.additional_info,
     );
 
     // Include a link to the documentation page.
-    let help_link: String = document_errors::construct_help_link(
-        (*ERROR_CODES.get(&failure.error_code).unwrap_or(&"E00")).to_string(),
-    );
+    let help_link: String =
+        document_errors::construct_help_link(failure.error_code.code().to_string());
     let backtrace: String = document_errors::construct_backtrace(failure);
 
     // Write backtrace to a log file.
@@ -420,7 +621,7 @@ impl<T> ExitGracefully<T> for NitroCliResult<T> {
                 } else {
                     notify_error(&err_str);
                 }
-                std::process::exit(err.error_code as i32);
+                std::process::exit(err.error_code.exit_code());
             }
         }
     }
@@ -552,10 +753,65 @@ pub fn get_sockets_dir_path() -> PathBuf {
 
 /// Get the path to the Unix socket owned by an enclave process which also owns the enclave with the given ID.
 pub fn get_socket_path(enclave_id: &str) -> NitroCliResult<PathBuf> {
+    get_socket_path_in(&get_sockets_dir_path(), enclave_id)
+}
+
+/// Get the path to the Unix socket of the enclave with the given ID, inside a custom sockets directory.
+pub fn get_socket_path_in(base_dir: &Path, enclave_id: &str) -> NitroCliResult<PathBuf> {
     // The full enclave ID is "i-(...)-enc<enc_id>" and we want to extract only <enc_id>.
-    let tokens: Vec<_> = enclave_id.rsplit("-enc").collect();
-    let sockets_path = get_sockets_dir_path();
-    Ok(sockets_path.join(tokens[0]).with_extension("sock"))
+    let (_, enc_id) = validate_enclave_id(enclave_id)?;
+    Ok(base_dir.join(enc_id).with_extension("sock"))
+}
+
+/// Split an enclave ID of the form `i-<instance ID>-enc<slot ID>` into its instance ID and
+/// slot ID. The instance ID has at most 17 hexadecimal digits, while the slot ID is the
+/// hexadecimal form of a 64-bit value, so it has at most 16 digits.
+pub fn validate_enclave_id(enclave_id: &str) -> NitroCliResult<(String, String)> {
+    parse_enclave_id(enclave_id)
+        .map(|(instance_id, slot_id)| (instance_id.to_string(), slot_id.to_string()))
+        .map_err(|reason| {
+            new_nitro_cli_failure!(
+                &format!("Malformed enclave ID {:?}: {}", enclave_id, reason),
+                NitroCliErrorEnum::MalformedEnclaveId
+            )
+            .add_info(vec![enclave_id, &reason])
+        })
+}
+
+/// Parse an enclave ID, returning the reason for which it is malformed on failure.
+fn parse_enclave_id(enclave_id: &str) -> Result<(&str, &str), String> {
+    let instance_and_slot = enclave_id
+        .strip_prefix("i-")
+        .ok_or_else(|| "it does not start with `i-`".to_string())?;
+    let (instance_id, slot_id) = instance_and_slot
+        .rsplit_once("-enc")
+        .ok_or_else(|| "the `-enc` separator is missing".to_string())?;
+
+    check_enclave_id_part("instance ID", instance_id, MAX_INSTANCE_ID_DIGITS)?;
+    check_enclave_id_part("slot ID", slot_id, MAX_SLOT_ID_DIGITS)?;
+
+    Ok((instance_id, slot_id))
+}
+
+/// Check that a part of an enclave ID is a hexadecimal number of at most `max_digits` digits.
+fn check_enclave_id_part(name: &str, part: &str, max_digits: usize) -> Result<(), String> {
+    if part.is_empty() {
+        return Err(format!("the {} is empty", name));
+    }
+    if part.contains('-') {
+        return Err(format!("the {} `{}` has an extra `-` segment", name, part));
+    }
+    if !part.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("the {} `{}` is not hexadecimal", name, part));
+    }
+    if part.len() > max_digits {
+        return Err(format!(
+            "the {} `{}` has more than {} digits",
+            name, part, max_digits
+        ));
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -660,22 +916,195 @@ mod tests {
         );
     }
 
-    /// Tests that `get_socket_path()` returns an invalid socket path,
-    /// given a malformed enclave id.
+    /// Tests that `get_socket_path()` rejects a malformed enclave id.
     #[test]
     fn test_get_socket_path_invalid_id() {
         let enclave_id = "i-0000000000000000_enc0123456789012345";
-        let sockets_path = get_sockets_dir_path();
         let result = get_socket_path(enclave_id);
 
-        assert!(result.is_ok());
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::MalformedEnclaveId);
         assert_eq!(
-            result.unwrap().as_path().to_str().unwrap(),
-            format!(
-                "{}/{}.sock",
-                sockets_path.as_path().to_str().unwrap(),
-                enclave_id
+            err.additional_info,
+            vec![enclave_id, "the `-enc` separator is missing"]
+        );
+    }
+
+    /// Tests that `get_socket_path_in()` places the socket in the given directory and
+    /// rejects malformed enclave ids.
+    #[test]
+    fn test_get_socket_path_in() {
+        let base_dir = Path::new("/run/user/1000/nitro_enclaves");
+        let result = get_socket_path_in(base_dir, "i-0123456789abcdef0-enc1a2b");
+        assert_eq!(result.unwrap(), base_dir.join("1a2b.sock"));
+
+        for (enclave_id, reason) in [
+            ("0123456789abcdef0-enc1a2b", "it does not start with `i-`"),
+            ("i-0123456789abcdef0-enc", "the slot ID is empty"),
+            (
+                "i-0123/../-enc1a2b",
+                "the instance ID `0123/../` is not hexadecimal",
+            ),
+            (
+                "i-0123456789abcdef0-enc1/../x",
+                "the slot ID `1/../x` is not hexadecimal",
+            ),
+        ] {
+            let err = get_socket_path_in(base_dir, enclave_id).unwrap_err();
+            assert_eq!(err.additional_info, vec![enclave_id, reason]);
+        }
+    }
+
+    /// Tests that well-formed enclave ids are split into their instance and slot parts.
+    #[test]
+    fn test_validate_enclave_id_valid() {
+        assert_eq!(
+            validate_enclave_id("i-0000000000000000-enc0123456789012345").unwrap(),
+            (
+                "0000000000000000".to_string(),
+                "0123456789012345".to_string()
             )
         );
+        assert_eq!(
+            validate_enclave_id("i-0123456789abcdef0-enc7").unwrap(),
+            ("0123456789abcdef0".to_string(), "7".to_string())
+        );
+    }
+
+    /// Tests that truncated, extra-segment and non-hexadecimal enclave ids are rejected
+    /// with the malformed part being reported.
+    #[test]
+    fn test_validate_enclave_id_invalid() {
+        for (enclave_id, reason) in [
+            // Truncated ids.
+            ("", "it does not start with `i-`"),
+            ("i-", "the `-enc` separator is missing"),
+            ("i-0000000000000000", "the `-enc` separator is missing"),
+            ("i-0000000000000000-enc", "the slot ID is empty"),
+            ("i--enc0123456789012345", "the instance ID is empty"),
+            // Extra segments.
+            (
+                "i-0000-0000-enc0123",
+                "the instance ID `0000-0000` has an extra `-` segment",
+            ),
+            (
+                "i-0000000000000000-enc01-23",
+                "the slot ID `01-23` has an extra `-` segment",
+            ),
+            (
+                "i-0000-enc0000-enc0123",
+                "the instance ID `0000-enc0000` has an extra `-` segment",
+            ),
+            // Non-hexadecimal or oversized parts.
+            (
+                "i-000000000000000g-enc0123",
+                "the instance ID `000000000000000g` is not hexadecimal",
+            ),
+            (
+                "i-0000000000000000-enc012z",
+                "the slot ID `012z` is not hexadecimal",
+            ),
+            (
+                "i-0000000000000000-enc01234567890123456",
+                "the slot ID `01234567890123456` has more than 16 digits",
+            ),
+            (
+                "i-000000000000000000-enc0",
+                "the instance ID `000000000000000000` has more than 17 digits",
+            ),
+        ] {
+            let err = validate_enclave_id(enclave_id).unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::MalformedEnclaveId);
+            assert_eq!(err.additional_info, vec![enclave_id, reason]);
+        }
+    }
+
+    /// Tests that a `NitroCliFailure` is displayed as its detailed error description
+    /// and that it can be matched on by error code.
+    #[test]
+    fn test_nitro_cli_failure_display() {
+        let failure = new_nitro_cli_failure!(
+            "Could not open device",
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec!["/dev/nitro_enclaves", "Open"]);
+
+        assert_eq!(failure.error_code, NitroCliErrorEnum::FileOperationFailure);
+        assert_eq!(
+            failure.to_string(),
+            document_errors::get_detailed_info(
+                "E19".to_string(),
+                &["/dev/nitro_enclaves".to_string(), "Open".to_string()]
+            )
+        );
+
+        let boxed: Box<dyn std::error::Error> = Box::new(failure);
+        assert!(boxed.to_string().starts_with("[ E19 ]"));
+    }
+
+    /// Tests that each kind of `NitroCliError` converts into a `NitroCliFailure` with the
+    /// error code and description it is displayed with.
+    #[test]
+    fn test_nitro_cli_error_conversion() {
+        let err = NitroCliError::IoctlFailed {
+            code: libc::EINVAL,
+            ioctl: "NE_SET_USER_MEMORY_REGION",
+        };
+        let display = err.to_string();
+        let failure = NitroCliFailure::from(err);
+        assert_eq!(
+            failure.error_code,
+            NitroCliErrorEnum::IoctlSetMemoryRegionFailure
+        );
+        assert_eq!(
+            failure.subactions,
+            vec!["NE_SET_USER_MEMORY_REGION ioctl failed: An error has occurred: 22 (rc: -1)"]
+        );
+        assert_eq!(display, failure.to_string());
+
+        let err =
+            NitroCliError::DeviceOpenFailed(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&err).is_some());
+        let failure = NitroCliFailure::from(err);
+        assert_eq!(failure.error_code, NitroCliErrorEnum::FileOperationFailure);
+        assert!(failure.subactions[0].starts_with("Could not open the NE device: "));
+
+        let err = NitroCliError::ResourceAllocation("No hugepages".to_string());
+        assert!(err.to_string().starts_with("[ E27 ]"));
+        let failure = NitroCliFailure::from(err);
+        assert_eq!(
+            failure.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+        assert_eq!(failure.subactions, vec!["No hugepages"]);
+
+        let err = NitroCliError::SocketError(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert_eq!(
+            NitroCliFailure::from(err).error_code,
+            NitroCliErrorEnum::SocketError
+        );
+
+        // A failure of no other kind is passed through unchanged.
+        let failure = new_nitro_cli_failure!("Slot in use", NitroCliErrorEnum::MemorySlotInUse)
+            .add_info(vec!["1"]);
+        let err = NitroCliError::from(failure);
+        assert!(matches!(&err, NitroCliError::Failure(_)));
+        let failure = NitroCliFailure::from(err);
+        assert_eq!(failure.error_code, NitroCliErrorEnum::MemorySlotInUse);
+        assert_eq!(failure.additional_info, vec!["1"]);
+    }
+
+    /// Tests that every error exits with the numeric part of its documented code.
+    #[test]
+    fn test_error_exit_code() {
+        for (error, code) in ERROR_CODES.iter() {
+            assert_eq!(error.code(), *code);
+            let expected = code[1..].parse::<i32>().unwrap();
+            assert_eq!(error.exit_code(), expected.max(1));
+        }
+
+        assert_eq!(NitroCliErrorEnum::FileOperationFailure.exit_code(), 19);
+        assert_eq!(NitroCliErrorEnum::UnspecifiedError.exit_code(), 1);
     }
 }
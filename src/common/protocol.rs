@@ -0,0 +1,306 @@
+// Copyright 2020-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::common::commands_parser::DescribeEnclavesArgs;
+use crate::common::json_output::{EnclaveDescribeInfo, EnclaveTerminateInfo};
+use crate::common::{read_u64_le, write_u64_le};
+use crate::common::{EnclaveProcessCommandType, ENCLAVE_PROC_WAIT_TIMEOUT_MSEC};
+use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::new_nitro_cli_failure;
+
+/// The version of the protocol spoken over the enclave process socket. It must be increased
+/// whenever the commands, their arguments or the replies exchanged over the socket, including
+/// `Command`, `Response` and their framing, change in an incompatible way.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The largest payload accepted in a single frame.
+const MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+/// A command sent by a CLI instance to an enclave process, over a connection which has been
+/// switched to framed commands with `EnclaveProcessCommandType::Session`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Command {
+    /// Describe the enclave.
+    Describe(DescribeEnclavesArgs),
+    /// Terminate the enclave.
+    Terminate,
+    /// Check that the enclave process is alive and responsive.
+    Ping,
+    /// Get the console output which the enclave process keeps for enclaves in debug mode.
+    GetConsole,
+    /// Get (at most) the given number of bytes of the most recent console output, which the
+    /// enclave process keeps for enclaves in debug mode.
+    GetRecentConsole(u64),
+}
+
+/// A response sent by an enclave process to a CLI instance, one for each `Command`.
+#[derive(Serialize, Deserialize)]
+pub enum Response {
+    /// The description of the enclave, in reply to `Command::Describe`.
+    Describe(Box<EnclaveDescribeInfo>),
+    /// The outcome of the termination, in reply to `Command::Terminate`.
+    Terminate(EnclaveTerminateInfo),
+    /// The reply to `Command::Ping`.
+    Pong,
+    /// The console output of the enclave, in reply to `Command::GetConsole` and
+    /// `Command::GetRecentConsole`.
+    Console(Vec<u8>),
+    /// The command has failed, with the given error message.
+    Error(String),
+}
+
+impl Command {
+    /// Get the type of the one-shot command which is equivalent to this one, and which the
+    /// access policy of the enclave process applies to.
+    pub fn command_type(&self) -> EnclaveProcessCommandType {
+        match self {
+            Command::Describe(_) => EnclaveProcessCommandType::Describe,
+            Command::Terminate => EnclaveProcessCommandType::Terminate,
+            Command::Ping => EnclaveProcessCommandType::Ping,
+            Command::GetConsole | Command::GetRecentConsole(_) => {
+                EnclaveProcessCommandType::GetRecentConsole
+            }
+        }
+    }
+}
+
+/// Exchange protocol versions with the peer, failing if the peer speaks a different version.
+/// Both ends of a connection to the enclave process socket must perform the handshake before
+/// sending anything else.
+pub fn handshake<S: Read + Write>(stream: &mut S) -> NitroCliResult<()> {
+    stream.write_all(&[PROTOCOL_VERSION]).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to send the protocol version: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+
+    let mut version = [0u8; 1];
+    stream.read_exact(&mut version).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to receive the protocol version: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+
+    check_version(version[0])
+}
+
+/// Perform the handshake with an enclave process over a newly-connected stream, giving up if
+/// the enclave process hasn't replied within `ENCLAVE_PROC_WAIT_TIMEOUT_MSEC`. The read
+/// timeout of the stream is restored afterwards.
+pub fn client_handshake(stream: &mut UnixStream) -> NitroCliResult<()> {
+    let set_timeout = |stream: &UnixStream, timeout: Option<Duration>| {
+        stream.set_read_timeout(timeout).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to set the handshake timeout: {:?}", e),
+                NitroCliErrorEnum::SocketError
+            )
+        })
+    };
+
+    let previous_timeout = stream.read_timeout().unwrap_or(None);
+    set_timeout(
+        stream,
+        Some(Duration::from_millis(ENCLAVE_PROC_WAIT_TIMEOUT_MSEC as u64)),
+    )?;
+    handshake(stream)?;
+    set_timeout(stream, previous_timeout)
+}
+
+/// Write a command to the given stream.
+pub fn write_command(stream: &mut dyn Write, cmd: &Command) -> NitroCliResult<()> {
+    write_frame(stream, cmd).map_err(|e| e.add_subaction(format!("Failed to send {:?}", cmd)))
+}
+
+/// Read a command from the given stream.
+pub fn read_command(stream: &mut dyn Read) -> NitroCliResult<Command> {
+    read_frame(stream).map_err(|e| e.add_subaction("Failed to receive command".to_string()))
+}
+
+/// Write a response to the given stream.
+pub fn write_response(stream: &mut dyn Write, response: &Response) -> NitroCliResult<()> {
+    write_frame(stream, response)
+        .map_err(|e| e.add_subaction("Failed to send response".to_string()))
+}
+
+/// Read a response from the given stream.
+pub fn read_response(stream: &mut dyn Read) -> NitroCliResult<Response> {
+    read_frame(stream).map_err(|e| e.add_subaction("Failed to receive response".to_string()))
+}
+
+/// Check that the peer's protocol version matches ours.
+fn check_version(version: u8) -> NitroCliResult<()> {
+    if version != PROTOCOL_VERSION {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "The peer speaks protocol version {}, but version {} is required",
+                version, PROTOCOL_VERSION
+            ),
+            NitroCliErrorEnum::ProtocolVersionMismatch
+        )
+        .add_info(vec![&version.to_string(), &PROTOCOL_VERSION.to_string()]));
+    }
+
+    Ok(())
+}
+
+/// Write a frame, consisting of the protocol version, the LE-encoded 64-bit payload size
+/// and the CBOR-encoded payload.
+fn write_frame<T: Serialize>(stream: &mut dyn Write, data: &T) -> NitroCliResult<()> {
+    let bytes = serde_cbor::to_vec(data).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to encode frame: {:?}", e),
+            NitroCliErrorEnum::SerdeError
+        )
+    })?;
+
+    stream.write_all(&[PROTOCOL_VERSION]).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to write frame version: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+    write_u64_le(stream, bytes.len() as u64)?;
+    stream.write_all(&bytes).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to write frame payload: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })
+}
+
+/// Read a frame written by `write_frame()`, rejecting frames of another protocol version.
+fn read_frame<T: DeserializeOwned>(stream: &mut dyn Read) -> NitroCliResult<T> {
+    let mut version = [0u8; 1];
+    stream.read_exact(&mut version).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read frame version: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+    check_version(version[0])?;
+
+    let size = read_u64_le(stream)?;
+    if size > MAX_FRAME_SIZE {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Frame of {} bytes exceeds the maximum of {} bytes",
+                size, MAX_FRAME_SIZE
+            ),
+            NitroCliErrorEnum::InvalidCommand
+        ));
+    }
+
+    let mut bytes = vec![0u8; size as usize];
+    stream.read_exact(&mut bytes).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read frame payload: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+    serde_cbor::from_slice(&bytes).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to decode frame: {:?}", e),
+            NitroCliErrorEnum::SerdeError
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::json_output::DESCRIBE_SCHEMA_VERSION;
+    use std::io::Cursor;
+    use std::thread;
+
+    /// Tests that commands and responses survive a round trip through the framing.
+    #[test]
+    fn test_command_round_trip() {
+        let describe = Command::Describe(DescribeEnclavesArgs {
+            metadata: true,
+            schema_version: DESCRIBE_SCHEMA_VERSION,
+        });
+        let cmds = vec![
+            describe,
+            Command::Terminate,
+            Command::Ping,
+            Command::GetConsole,
+            Command::GetRecentConsole(4096),
+        ];
+        let mut buf = Vec::new();
+        for cmd in &cmds {
+            write_command(&mut buf, cmd).unwrap();
+        }
+        write_response(&mut buf, &Response::Console(b"hello".to_vec())).unwrap();
+
+        let mut cursor = Cursor::new(buf);
+        for cmd in &cmds {
+            assert_eq!(&read_command(&mut cursor).unwrap(), cmd);
+        }
+        match read_response(&mut cursor).unwrap() {
+            Response::Console(output) => assert_eq!(output, b"hello"),
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    /// Tests that frames of another protocol version or of excessive size are rejected.
+    #[test]
+    fn test_read_frame_rejects_invalid() {
+        let mut buf = Vec::new();
+        write_command(&mut buf, &Command::Ping).unwrap();
+        buf[0] = PROTOCOL_VERSION + 1;
+        let err = read_command(&mut Cursor::new(&buf)).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::ProtocolVersionMismatch);
+
+        let mut buf = vec![PROTOCOL_VERSION];
+        buf.extend_from_slice(&(MAX_FRAME_SIZE + 1).to_le_bytes());
+        let err = read_command(&mut Cursor::new(&buf)).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidCommand);
+    }
+
+    /// Tests that the handshake succeeds between equal versions and fails otherwise.
+    #[test]
+    fn test_handshake() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        let peer = thread::spawn(move || {
+            handshake(&mut server).unwrap();
+            assert_eq!(read_command(&mut server).unwrap(), Command::Ping);
+            write_response(&mut server, &Response::Pong).unwrap();
+        });
+        client_handshake(&mut client).unwrap();
+        assert_eq!(client.read_timeout().unwrap(), None);
+        write_command(&mut client, &Command::Ping).unwrap();
+        assert!(matches!(
+            read_response(&mut client).unwrap(),
+            Response::Pong
+        ));
+        peer.join().unwrap();
+
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        server.write_all(&[PROTOCOL_VERSION + 1]).unwrap();
+        let err = handshake(&mut client).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::ProtocolVersionMismatch);
+        assert_eq!(
+            err.additional_info,
+            vec![
+                (PROTOCOL_VERSION + 1).to_string(),
+                PROTOCOL_VERSION.to_string()
+            ]
+        );
+
+        // A peer which never replies doesn't stall the client.
+        let (mut client, _server) = UnixStream::pair().unwrap();
+        let err = client_handshake(&mut client).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::SocketError);
+    }
+}
@@ -12,6 +12,9 @@ use std::thread;
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
 use crate::new_nitro_cli_failure;
 
+/// The name of the thread which waits for and handles signals.
+pub const SIGNAL_HANDLER_THREAD_NAME: &str = "signal-handler";
+
 /// The custom handler of POSIX signals.
 pub struct SignalHandler {
     sig_set: Option<SigSet>,
@@ -64,23 +67,41 @@ impl SignalHandler {
     }
 
     /// Start listening for events on a dedicated thread and handle them using the provided function.
-    pub fn start_handler(&mut self, fd: RawFd, handler: fn(RawFd, Signal) -> bool) {
+    ///
+    /// The signals are waited for synchronously, so they must be masked (see `mask_all()`) in
+    /// every thread, otherwise their default action may be taken instead. The thread is named
+    /// after `SIGNAL_HANDLER_THREAD_NAME`.
+    pub fn start_handler(
+        &mut self,
+        fd: RawFd,
+        handler: fn(RawFd, Signal) -> bool,
+    ) -> NitroCliResult<()> {
         if self.sig_set.is_none() {
-            return;
+            return Ok(());
         }
 
         let thread_sig_set = self.sig_set.take().unwrap();
-        thread::spawn(move || {
-            let mut stop = false;
-            while !stop {
-                stop = match thread_sig_set.wait() {
-                    Ok(signal) => handler(fd, signal),
-                    Err(e) => {
-                        warn!("Error listening for signals: {}", e);
-                        true
-                    }
-                };
-            }
-        });
+        let builder = thread::Builder::new().name(SIGNAL_HANDLER_THREAD_NAME.to_string());
+        builder
+            .spawn(move || {
+                let mut stop = false;
+                while !stop {
+                    stop = match thread_sig_set.wait() {
+                        Ok(signal) => handler(fd, signal),
+                        Err(e) => {
+                            warn!("Error listening for signals: {}", e);
+                            true
+                        }
+                    };
+                }
+            })
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to spawn signal handler thread: {:?}", e),
+                    NitroCliErrorEnum::ProcessSpawnFailure
+                )
+            })?;
+
+        Ok(())
     }
 }
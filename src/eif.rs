@@ -0,0 +1,1264 @@
+// Copyright 2020-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use aws_nitro_enclaves_image_format::defs::{
+    EifHeader, EifIdentityInfo, EifSectionHeader, EifSectionType, PcrSignature, EIF_MAGIC,
+    MAX_NUM_SECTIONS,
+};
+use aws_nitro_enclaves_image_format::utils::eif_reader::EifReader;
+use aws_nitro_enclaves_image_format::utils::get_pcrs;
+use crc::{crc32, Hasher32};
+use openssl::asn1::Asn1Time;
+use openssl::x509::{X509NameRef, X509};
+use serde::Serialize;
+use sha2::{Digest, Sha384};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::mem::size_of;
+
+use crate::common::json_output::PcrMeasurements;
+use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::enclave_proc::resource_manager::MemoryRegion;
+use crate::new_nitro_cli_failure;
+use crate::utils::ceil_div;
+
+/// The PCRs of an enclave image file, mapping their names (e.g. `PCR0`) to their hex values.
+pub type PcrMap = BTreeMap<String, String>;
+
+/// The size of the chunks in which an enclave image file is copied into enclave memory.
+const EIF_LOAD_CHUNK_SIZE: usize = 2 * 1024 * 1024;
+
+/// The offset of the CRC32 in the EIF header, which is its last field.
+const EIF_CRC_OFFSET: usize = EifHeader::size() - size_of::<u32>();
+
+/// The environment variable which overrides the headroom factor used by `recommended_memory()`.
+pub const MEMORY_HEADROOM_ENV_VAR: &str = "NITRO_CLI_MEMORY_HEADROOM";
+
+/// The default headroom factor used by `recommended_memory()`.
+const DEFAULT_MEMORY_HEADROOM: f64 = 5.0;
+
+/// The offsets in enclave memory at which the sections of an enclave image file are loaded by
+/// `load_with_layout()`, for enclave kernels which expect them at specific places.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// The offset of the kernel image.
+    pub kernel_offset: u64,
+    /// The offset of the first ramdisk, which the other ramdisks follow back to back.
+    pub ramdisk_offset: u64,
+    /// The offset of the kernel command line.
+    pub cmdline_offset: u64,
+}
+
+/// The offset of the Linux boot protocol setup header in an x86 kernel image.
+const BZIMAGE_SETUP_HEADER_OFFSET: usize = 0x200;
+
+/// The offset of the `HdrS` magic in an x86 kernel image.
+const BZIMAGE_MAGIC_OFFSET: usize = 0x202;
+
+/// The offset of the kernel version string pointer in an x86 kernel image, which is relative
+/// to the setup header.
+const BZIMAGE_VERSION_OFFSET: usize = 0x20e;
+
+/// The longest kernel version string which is looked for in a kernel image.
+const MAX_KERNEL_VERSION_LEN: usize = 256;
+
+/// The information about an enclave image file, as reported by `read_metadata()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EifMetadata {
+    /// The version of the enclave kernel, if the image records it.
+    #[serde(rename = "KernelVersion")]
+    pub kernel_version: Option<String>,
+    /// The command line of the enclave kernel.
+    #[serde(rename = "KernelCmdline")]
+    pub cmdline: String,
+    /// The number of ramdisks in the image.
+    #[serde(rename = "RamdiskCount")]
+    pub ramdisk_count: usize,
+    /// The time at which the image has been built, if the image records it.
+    #[serde(rename = "BuildTime")]
+    pub build_time: Option<String>,
+}
+
+/// The signer of an enclave image file, as reported by `verify_signature()`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SignatureInfo {
+    /// The subject name entries of the signing certificate.
+    #[serde(rename = "Subject")]
+    pub subject: BTreeMap<String, String>,
+    /// The start of the validity period of the signing certificate.
+    #[serde(rename = "NotBefore")]
+    pub not_before: String,
+    /// The end of the validity period of the signing certificate.
+    #[serde(rename = "NotAfter")]
+    pub not_after: String,
+    /// Whether the current time is within the validity period of the signing certificate.
+    #[serde(rename = "IsValid")]
+    pub is_valid: bool,
+}
+
+/// Check an enclave image file against the measurements recorded in it, without starting an
+/// enclave. These are the CRC32 of the whole image, stored in its header, and the PCR0 of a
+/// signed image, stored in its signature section. Returns the PCRs of the image.
+pub fn verify_measurements(path: &str) -> NitroCliResult<PcrMap> {
+    let mut eif_reader = EifReader::from_eif(path.to_string()).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to initialize EIF reader: {:?}", e),
+            NitroCliErrorEnum::EifParsingError
+        )
+    })?;
+
+    if !eif_reader.check_crc() {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "EIF checksum mismatch (recorded {:#010x}, computed {:#010x})",
+                eif_reader.header.eif_crc32, eif_reader.eif_crc
+            ),
+            NitroCliErrorEnum::EifMeasurementMismatch
+        )
+        .add_info(vec![path, "CRC32 mismatch"]));
+    }
+
+    let measurements = get_pcrs(
+        &mut eif_reader.image_hasher,
+        &mut eif_reader.bootstrap_hasher,
+        &mut eif_reader.app_hasher,
+        &mut eif_reader.cert_hasher,
+        Sha384::new(),
+        eif_reader.signature_section.is_some(),
+    )
+    .map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to get PCR values: {:?}", e),
+            NitroCliErrorEnum::EifParsingError
+        )
+    })?;
+
+    if eif_reader.signature_section.is_some() {
+        eif_reader
+            .get_certificate_info(measurements.clone())
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to get certificate signing info: {:?}", e),
+                    NitroCliErrorEnum::EifParsingError
+                )
+            })?;
+        if eif_reader.sign_check != Some(true) {
+            return Err(new_nitro_cli_failure!(
+                "The signed PCR0 doesn't match the one of the EIF",
+                NitroCliErrorEnum::EifMeasurementMismatch
+            )
+            .add_info(vec![path, "PCR0 mismatch"]));
+        }
+    }
+
+    Ok(measurements)
+}
+
+/// Check that an enclave image file has been signed with the trusted certificate `cert`, given
+/// in PEM or DER format, and that its signature matches its measurements. Unsigned images, bad
+/// signatures and images signed with another certificate all fail with distinct errors. The
+/// validity period of the certificate is reported rather than enforced, so that the caller's
+/// trust policy can decide about expired certificates.
+pub fn verify_signature(path: &str, cert: &[u8]) -> NitroCliResult<SignatureInfo> {
+    let trusted_cert = X509::from_pem(cert)
+        .or_else(|_| X509::from_der(cert))
+        .map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to parse the trusted signing certificate: {:?}", e),
+                NitroCliErrorEnum::EIFSignatureCheckerError
+            )
+        })?;
+    let mut eif_reader = EifReader::from_eif(path.to_string()).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to initialize EIF reader: {:?}", e),
+            NitroCliErrorEnum::EifParsingError
+        )
+    })?;
+
+    let signature_section = eif_reader.signature_section.as_ref().ok_or_else(|| {
+        new_nitro_cli_failure!(
+            &format!("The EIF {} is not signed", path),
+            NitroCliErrorEnum::EifUnsigned
+        )
+    })?;
+    let bad_signature =
+        |msg: String| new_nitro_cli_failure!(&msg, NitroCliErrorEnum::EifSignatureInvalid);
+    let signatures: Vec<PcrSignature> = serde_cbor::from_slice(signature_section)
+        .map_err(|e| bad_signature(format!("Failed to decode the EIF signature: {:?}", e)))?;
+    let signing_cert = signatures
+        .first()
+        .ok_or_else(|| bad_signature("The EIF signature section is empty".to_string()))
+        .and_then(|signature| {
+            X509::from_pem(&signature.signing_certificate).map_err(|e| {
+                bad_signature(format!(
+                    "Failed to parse the certificate embedded in the EIF: {:?}",
+                    e
+                ))
+            })
+        })?;
+
+    let same_cert = match (signing_cert.to_der(), trusted_cert.to_der()) {
+        (Ok(signing), Ok(trusted)) => signing == trusted,
+        _ => false,
+    };
+    if !same_cert {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "The EIF has been signed by {}, not by the trusted certificate",
+                format_name(signing_cert.subject_name())
+            ),
+            NitroCliErrorEnum::EifCertificateMismatch
+        ));
+    }
+
+    let measurements = get_pcrs(
+        &mut eif_reader.image_hasher,
+        &mut eif_reader.bootstrap_hasher,
+        &mut eif_reader.app_hasher,
+        &mut eif_reader.cert_hasher,
+        Sha384::new(),
+        true,
+    )
+    .map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to get PCR values: {:?}", e),
+            NitroCliErrorEnum::EifParsingError
+        )
+    })?;
+    eif_reader
+        .get_certificate_info(measurements)
+        .map_err(|e| bad_signature(format!("Failed to check the EIF signature: {}", e)))?;
+    if eif_reader.sign_check != Some(true) {
+        return Err(bad_signature(
+            "The EIF signature doesn't match the PCR0 of the EIF".to_string(),
+        ));
+    }
+
+    let is_valid = Asn1Time::days_from_now(0)
+        .ok()
+        .and_then(|now| {
+            let started = now.compare(trusted_cert.not_before()).ok()?;
+            let ended = now.compare(trusted_cert.not_after()).ok()?;
+            Some(started != std::cmp::Ordering::Less && ended != std::cmp::Ordering::Greater)
+        })
+        .unwrap_or(false);
+
+    Ok(SignatureInfo {
+        subject: name_entries(trusted_cert.subject_name()),
+        not_before: trusted_cert.not_before().to_string(),
+        not_after: trusted_cert.not_after().to_string(),
+        is_valid,
+    })
+}
+
+/// Get the entries of a certificate name, mapping their short names (e.g. `CN`) to their values.
+fn name_entries(name: &X509NameRef) -> BTreeMap<String, String> {
+    name.entries()
+        .map(|entry| {
+            let key = entry
+                .object()
+                .nid()
+                .short_name()
+                .map(|key| key.to_string())
+                .unwrap_or_else(|_| entry.object().to_string());
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            (key, value)
+        })
+        .collect()
+}
+
+/// Format a certificate name as `CN=..., O=...`, for error messages.
+fn format_name(name: &X509NameRef) -> String {
+    name_entries(name)
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Check an enclave image file like `verify_measurements()` does and return its PCR0, PCR1 and
+/// PCR2, as needed by attestation consumers.
+pub fn pcr_measurements(path: &str) -> NitroCliResult<PcrMeasurements> {
+    let measurements = verify_measurements(path)?;
+
+    PcrMeasurements::from_measurements(&measurements).ok_or_else(|| {
+        new_nitro_cli_failure!(
+            "Failed to get the PCRs of the EIF",
+            NitroCliErrorEnum::EifParsingError
+        )
+    })
+}
+
+/// Copy an enclave image file, from its current position, into a memory region starting at the
+/// given offset in the region. The file is read in fixed-size chunks directly into the mapped
+/// region, until either the region is full or the end of the file is reached, so no copy of the
+/// image is buffered in memory. Returns the number of copied bytes. Use
+/// `EifChecksum::load_into_region()` to check the image as well.
+pub fn load_into_region(
+    file: &mut File,
+    region: &mut MemoryRegion,
+    offset: u64,
+) -> NitroCliResult<u64> {
+    copy_into_region(file, region, offset, None)
+}
+
+/// Copy an enclave image file into a memory region as `load_into_region()` does, adding the
+/// copied bytes to `checksum` if one is given.
+fn copy_into_region(
+    file: &mut File,
+    region: &mut MemoryRegion,
+    offset: u64,
+    checksum: Option<&mut EifChecksum>,
+) -> NitroCliResult<u64> {
+    if offset > region.mem_size() {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Offset {} is out of the memory region of {} bytes",
+                offset,
+                region.mem_size()
+            ),
+            NitroCliErrorEnum::MemoryOverflow
+        ));
+    }
+
+    // SAFETY: the region maps `mem_size()` bytes at `mem_addr()` for as long as it is borrowed,
+    // and `offset` has been checked to be within those bytes, so the slice below only covers the
+    // mapping, which nothing else accesses while the region is mutably borrowed.
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(region.mem_addr() as *mut u8, region.mem_size() as usize)
+    };
+    let written = copy_in_chunks(file, &mut bytes[offset as usize..], checksum)?;
+
+    Ok(written as u64)
+}
+
+/// Load the kernel, command line and ramdisk sections of an enclave image file at the offsets
+/// given by `layout`, counted from the start of the enclave memory made of the concatenated
+/// `regions`. The other sections are not loaded. Every ramdisk is loaded, each right after the
+/// previous one. Fails without copying anything if a section would not fit in the enclave memory
+/// or if the image doesn't match the CRC32 recorded in its header, which covers all sections.
+pub fn load_with_layout(
+    file: &mut File,
+    regions: &mut [MemoryRegion],
+    layout: &MemoryLayout,
+) -> NitroCliResult<()> {
+    let total_size: u64 = regions.iter().map(|region| region.mem_size()).sum();
+    let mut placements = Vec::new();
+    let mut ramdisk_offset = layout.ramdisk_offset;
+
+    for (name, header, data_offset) in read_sections(file)? {
+        let offset = match header.section_type {
+            EifSectionType::EifSectionKernel => layout.kernel_offset,
+            EifSectionType::EifSectionCmdline => layout.cmdline_offset,
+            EifSectionType::EifSectionRamdisk => ramdisk_offset,
+            _ => continue,
+        };
+        let end = offset.checked_add(header.section_size);
+        if end.map_or(true, |end| end > total_size) {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "The {} section ({} bytes at offset {:#x}) overflows the enclave memory of {} bytes",
+                    name, header.section_size, offset, total_size
+                ),
+                NitroCliErrorEnum::MemoryOverflow
+            ));
+        }
+        if header.section_type == EifSectionType::EifSectionRamdisk {
+            ramdisk_offset = offset + header.section_size;
+        }
+
+        placements.push((name, data_offset, offset, header.section_size));
+    }
+
+    verify_checksum(file)?;
+
+    for (name, data_offset, offset, size) in placements {
+        file.seek(SeekFrom::Start(data_offset)).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to seek to the {} section: {:?}", name, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+        })?;
+        copy_to_regions(file, regions, offset, size)
+            .map_err(|e| e.add_subaction(format!("Failed to load the {} section", name)))?;
+    }
+
+    Ok(())
+}
+
+/// Check a whole enclave image file against the CRC32 recorded in its header.
+fn verify_checksum(file: &mut File) -> NitroCliResult<()> {
+    file.rewind().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to rewind the EIF: {:?}", e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+    })?;
+
+    let mut checksum = EifChecksum::new();
+    let mut chunk = vec![0u8; EIF_LOAD_CHUNK_SIZE];
+    while copy_in_chunks(file, &mut chunk, Some(&mut checksum))? == chunk.len() {}
+
+    checksum.verify()
+}
+
+/// Get the suggested minimum memory, in MiB, for an enclave booting the given image.
+///
+/// The suggestion is computed from the sections which are loaded into enclave memory (the
+/// kernel, its command line and the ramdisks) as `size * (1 + headroom)`. Once the enclave has
+/// booted, the kernel has been decompressed and the ramdisks have been unpacked next to their
+/// loaded copies, which by itself takes about four times the size of the loaded sections. The
+/// default headroom of 5 leaves as much again for the runtime heap of the enclave application.
+/// It can be changed with the `NITRO_CLI_MEMORY_HEADROOM` environment variable.
+pub fn recommended_memory(path: &str) -> NitroCliResult<u64> {
+    let mut file = File::open(path).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to open the EIF: {:?}", e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![path, "Open"])
+    })?;
+    let sections_size = read_sections(&mut file)
+        .map_err(|e| e.add_subaction(format!("Failed to read the sections of {}", path)))?
+        .iter()
+        .filter(|(_, header, _)| {
+            matches!(
+                header.section_type,
+                EifSectionType::EifSectionKernel
+                    | EifSectionType::EifSectionCmdline
+                    | EifSectionType::EifSectionRamdisk
+            )
+        })
+        .map(|(_, header, _)| header.section_size)
+        .sum();
+
+    Ok(memory_with_headroom(sections_size, memory_headroom()))
+}
+
+/// Get the headroom factor used by `recommended_memory()`.
+fn memory_headroom() -> f64 {
+    std::env::var(MEMORY_HEADROOM_ENV_VAR)
+        .ok()
+        .and_then(|headroom| parse_headroom(&headroom))
+        .unwrap_or(DEFAULT_MEMORY_HEADROOM)
+}
+
+/// Parse a headroom factor, which must be a non-negative number.
+fn parse_headroom(headroom: &str) -> Option<f64> {
+    headroom
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|headroom| headroom.is_finite() && *headroom >= 0.0)
+}
+
+/// Get the memory, in MiB, needed by `size` bytes of loaded sections and their headroom.
+fn memory_with_headroom(size: u64, headroom: f64) -> u64 {
+    let bytes = (size as f64 * (1.0 + headroom)).ceil() as u64;
+    ceil_div(bytes, 1024 * 1024)
+}
+
+/// Read the information about an enclave image file from its header and sections, without
+/// loading it into enclave memory. The kernel version and build time are taken from the metadata
+/// section of the image, the kernel version otherwise being read from an x86 kernel image.
+pub fn read_metadata(path: &str) -> NitroCliResult<EifMetadata> {
+    let mut file = File::open(path).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to open the EIF: {:?}", e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![path, "Open"])
+    })?;
+    let sections = read_sections(&mut file)
+        .map_err(|e| e.add_subaction(format!("Failed to read the sections of {}", path)))?;
+
+    let mut metadata = EifMetadata::default();
+    let mut kernel_version = None;
+    for (name, header, data_offset) in sections {
+        let section_type = header.section_type;
+        let size = match section_type {
+            EifSectionType::EifSectionKernel => {
+                std::cmp::min(header.section_size as usize, max_kernel_version_end())
+            }
+            EifSectionType::EifSectionCmdline | EifSectionType::EifSectionMetadata => {
+                header.section_size as usize
+            }
+            EifSectionType::EifSectionRamdisk => {
+                metadata.ramdisk_count += 1;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let mut data = vec![0u8; size];
+        file.seek(SeekFrom::Start(data_offset))
+            .and_then(|_| file.read_exact(&mut data))
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to read the {} section: {:?}", name, e),
+                    NitroCliErrorEnum::EifParsingError
+                )
+            })?;
+
+        match section_type {
+            EifSectionType::EifSectionKernel => kernel_version = bzimage_kernel_version(&data),
+            EifSectionType::EifSectionCmdline => {
+                metadata.cmdline = String::from_utf8_lossy(&data)
+                    .trim_end_matches('\0')
+                    .to_string();
+            }
+            _ => {
+                let identity: EifIdentityInfo = serde_json::from_slice(&data).map_err(|e| {
+                    new_nitro_cli_failure!(
+                        &format!("Invalid EIF metadata section: {:?}", e),
+                        NitroCliErrorEnum::EifParsingError
+                    )
+                })?;
+                metadata.kernel_version = Some(identity.build_info.img_kernel);
+                metadata.build_time = Some(identity.build_info.build_time);
+            }
+        }
+    }
+
+    if metadata.kernel_version.is_none() {
+        metadata.kernel_version = kernel_version;
+    }
+
+    Ok(metadata)
+}
+
+/// Get the number of bytes at the start of an x86 kernel image which hold its version string.
+fn max_kernel_version_end() -> usize {
+    BZIMAGE_SETUP_HEADER_OFFSET + u16::MAX as usize + MAX_KERNEL_VERSION_LEN
+}
+
+/// Get the version string of an x86 kernel image, from the start of the image. Other kernel
+/// images, such as the ones of aarch64 enclaves, record no version string.
+fn bzimage_kernel_version(kernel: &[u8]) -> Option<String> {
+    if kernel.get(BZIMAGE_MAGIC_OFFSET..BZIMAGE_MAGIC_OFFSET + 4) != Some(&b"HdrS"[..]) {
+        return None;
+    }
+
+    let pointer = kernel.get(BZIMAGE_VERSION_OFFSET..BZIMAGE_VERSION_OFFSET + 2)?;
+    let start = BZIMAGE_SETUP_HEADER_OFFSET + u16::from_le_bytes([pointer[0], pointer[1]]) as usize;
+    let bytes = kernel.get(start..)?;
+    let bytes = &bytes[..std::cmp::min(bytes.len(), MAX_KERNEL_VERSION_LEN)];
+    let end = bytes.iter().position(|&byte| byte == 0)?;
+
+    // The version string is followed by the build host and date, e.g. `5.10.0 (user@host) #1`.
+    std::str::from_utf8(&bytes[..end])
+        .ok()?
+        .split_whitespace()
+        .next()
+        .map(|version| version.to_string())
+}
+
+/// Read the section headers of an enclave image file. Each section is returned along with a
+/// name for reporting it and the file offset of its data.
+fn read_sections(file: &mut File) -> NitroCliResult<Vec<(String, EifSectionHeader, u64)>> {
+    let parsing_error =
+        |msg: String| new_nitro_cli_failure!(&msg, NitroCliErrorEnum::EifParsingError);
+    let file_size = file
+        .metadata()
+        .map_err(|e| parsing_error(format!("Failed to get EIF metadata: {:?}", e)))?
+        .len();
+    let mut read_at = |offset: u64, buf: &mut [u8]| {
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(buf))
+            .map_err(|e| parsing_error(format!("Failed to read EIF at offset {}: {:?}", offset, e)))
+    };
+
+    let mut header_bytes = [0u8; EifHeader::size()];
+    read_at(0, &mut header_bytes)?;
+    let header = EifHeader::from_be_bytes(&header_bytes)
+        .map_err(|e| parsing_error(format!("Invalid EIF header: {}", e)))?;
+    if header.magic != EIF_MAGIC {
+        return Err(parsing_error("The file is not an EIF".to_string()));
+    }
+    if header.num_sections as usize > MAX_NUM_SECTIONS {
+        return Err(parsing_error(format!(
+            "The EIF header lists {} sections, but at most {} are supported",
+            header.num_sections, MAX_NUM_SECTIONS
+        )));
+    }
+
+    let mut sections = Vec::new();
+    let mut ramdisks = 0;
+    for index in 0..header.num_sections as usize {
+        let section_offset = header.section_offsets[index];
+        let mut section_bytes = [0u8; EifSectionHeader::size()];
+        read_at(section_offset, &mut section_bytes)?;
+        let section = EifSectionHeader::from_be_bytes(&section_bytes).map_err(|e| {
+            parsing_error(format!("Invalid header of EIF section {}: {}", index, e))
+        })?;
+
+        let name = match section.section_type {
+            EifSectionType::EifSectionKernel => "kernel".to_string(),
+            EifSectionType::EifSectionCmdline => "cmdline".to_string(),
+            EifSectionType::EifSectionRamdisk => {
+                ramdisks += 1;
+                format!("ramdisk {}", ramdisks)
+            }
+            EifSectionType::EifSectionSignature => "signature".to_string(),
+            EifSectionType::EifSectionMetadata => "metadata".to_string(),
+            EifSectionType::EifSectionInvalid => format!("invalid section {}", index),
+        };
+        let data_offset = section_offset + EifSectionHeader::size() as u64;
+        if data_offset
+            .checked_add(section.section_size)
+            .map_or(true, |end| end > file_size)
+        {
+            return Err(parsing_error(format!(
+                "The {} section ({} bytes at offset {}) is truncated, the EIF has {} bytes",
+                name, section.section_size, data_offset, file_size
+            )));
+        }
+        sections.push((name, section, data_offset));
+    }
+
+    Ok(sections)
+}
+
+/// Copy `size` bytes of `file`, from its current position, into the enclave memory made of the
+/// concatenated `regions`, starting at `offset` in it.
+fn copy_to_regions(
+    file: &mut File,
+    regions: &mut [MemoryRegion],
+    offset: u64,
+    size: u64,
+) -> NitroCliResult<()> {
+    let mut region_start = 0;
+    let mut dest_offset = offset;
+    let end = offset + size;
+
+    for region in regions.iter_mut() {
+        let region_end = region_start + region.mem_size();
+        if dest_offset < end && dest_offset < region_end {
+            let start = (dest_offset - region_start) as usize;
+            let len = (std::cmp::min(end, region_end) - dest_offset) as usize;
+            let bytes = unsafe {
+                std::slice::from_raw_parts_mut(
+                    region.mem_addr() as *mut u8,
+                    region.mem_size() as usize,
+                )
+            };
+            let written = copy_in_chunks(file, &mut bytes[start..start + len], None)?;
+            if written < len {
+                return Err(new_nitro_cli_failure!(
+                    &format!(
+                        "Unexpected end of enclave image ({} bytes missing)",
+                        len - written
+                    ),
+                    NitroCliErrorEnum::EifParsingError
+                ));
+            }
+            dest_offset += len as u64;
+        }
+        region_start = region_end;
+    }
+
+    Ok(())
+}
+
+/// Fill `dest` from `file` in chunks of `EIF_LOAD_CHUNK_SIZE` bytes, stopping early at the end
+/// of the file, and add the copied bytes to `checksum` if one is given. Returns the number of
+/// copied bytes.
+pub(crate) fn copy_in_chunks(
+    file: &mut File,
+    dest: &mut [u8],
+    mut checksum: Option<&mut EifChecksum>,
+) -> NitroCliResult<usize> {
+    let mut written = 0;
+
+    while written < dest.len() {
+        let end = std::cmp::min(written + EIF_LOAD_CHUNK_SIZE, dest.len());
+        let len = match file.read(&mut dest[written..end]) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(new_nitro_cli_failure!(
+                    &format!("Error while reading from enclave image: {:?}", e),
+                    NitroCliErrorEnum::EifParsingError
+                ))
+            }
+        };
+
+        if let Some(checksum) = checksum.as_mut() {
+            checksum.update(&dest[written..written + len]);
+        }
+        written += len;
+    }
+
+    Ok(written)
+}
+
+/// The CRC32 of an enclave image file, computed over its bytes as they are being copied and
+/// checked against the one recorded in its header once the whole image has been seen.
+pub struct EifChecksum {
+    /// The CRC32 of the bytes seen so far, without the recorded CRC32 itself.
+    digest: crc32::Digest,
+    /// The number of bytes seen so far.
+    offset: usize,
+    /// The CRC32 recorded in the EIF header, in big-endian order.
+    recorded: [u8; 4],
+}
+
+impl EifChecksum {
+    /// Create a new checksum, before any byte of the image has been seen.
+    pub fn new() -> Self {
+        EifChecksum {
+            digest: crc32::Digest::new_with_initial(crc32::IEEE, 0),
+            offset: 0,
+            recorded: [0; 4],
+        }
+    }
+
+    /// Add the next bytes of the image to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        let start = self.offset;
+        let end = start + bytes.len();
+        let crc_range = EIF_CRC_OFFSET..EifHeader::size();
+
+        if end <= crc_range.start || start >= crc_range.end {
+            self.digest.write(bytes);
+        } else {
+            let crc_start = crc_range.start.saturating_sub(start);
+            let crc_end = std::cmp::min(crc_range.end - start, bytes.len());
+            self.digest.write(&bytes[..crc_start]);
+            let recorded_start = start + crc_start - crc_range.start;
+            self.recorded[recorded_start..recorded_start + crc_end - crc_start]
+                .copy_from_slice(&bytes[crc_start..crc_end]);
+            self.digest.write(&bytes[crc_end..]);
+        }
+
+        self.offset = end;
+    }
+
+    /// Copy an enclave image file into a memory region as `eif::load_into_region()` does,
+    /// adding the copied bytes to the checksum. An image loaded from its start, possibly across
+    /// several regions, can then be checked with `verify()` once all of it has been copied.
+    /// Returns the number of copied bytes.
+    pub fn load_into_region(
+        &mut self,
+        file: &mut File,
+        region: &mut MemoryRegion,
+        offset: u64,
+    ) -> NitroCliResult<u64> {
+        copy_into_region(file, region, offset, Some(self))
+    }
+
+    /// Check the checksum of the whole image against the one recorded in its header.
+    pub fn verify(&self) -> NitroCliResult<()> {
+        let recorded = u32::from_be_bytes(self.recorded);
+        let computed = self.digest.sum32();
+
+        if self.offset < EifHeader::size() || recorded != computed {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "EIF checksum mismatch after loading {} bytes (recorded {:#010x}, computed {:#010x})",
+                    self.offset, recorded, computed
+                ),
+                NitroCliErrorEnum::EifMeasurementMismatch
+            )
+            .add_info(vec!["enclave memory", "CRC32 mismatch"]));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for EifChecksum {
+    fn default() -> Self {
+        EifChecksum::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_nitro_enclaves_image_format::defs::{
+        EifSectionHeader, EifSectionType, CURRENT_VERSION, EIF_MAGIC, MAX_NUM_SECTIONS,
+    };
+    use std::io::{Seek, Write};
+
+    /// Build an unsigned EIF made of a single kernel section, with a valid CRC32.
+    fn build_eif(kernel: &[u8]) -> Vec<u8> {
+        build_eif_with_sections(&[(EifSectionType::EifSectionKernel, kernel)])
+    }
+
+    /// Build an unsigned EIF made of the given sections, with a valid CRC32.
+    fn build_eif_with_sections(section_data: &[(EifSectionType, &[u8])]) -> Vec<u8> {
+        let mut header = EifHeader {
+            magic: EIF_MAGIC,
+            version: CURRENT_VERSION,
+            flags: 0,
+            default_mem: 0,
+            default_cpus: 0,
+            reserved: 0,
+            num_sections: section_data.len() as u16,
+            section_offsets: [0; MAX_NUM_SECTIONS],
+            section_sizes: [0; MAX_NUM_SECTIONS],
+            unused: 0,
+            eif_crc32: 0,
+        };
+
+        let mut sections = Vec::new();
+        for (index, (section_type, data)) in section_data.iter().enumerate() {
+            header.section_offsets[index] = (EifHeader::size() + sections.len()) as u64;
+            header.section_sizes[index] = data.len() as u64;
+            sections.extend_from_slice(
+                &EifSectionHeader {
+                    section_type: *section_type,
+                    flags: 0,
+                    section_size: data.len() as u64,
+                }
+                .to_be_bytes(),
+            );
+            sections.extend_from_slice(data);
+        }
+
+        let mut digest = crc32::Digest::new_with_initial(crc32::IEEE, 0);
+        digest.write(&header.to_be_bytes()[..EIF_CRC_OFFSET]);
+        digest.write(&sections);
+        header.eif_crc32 = digest.sum32();
+
+        let mut eif = header.to_be_bytes();
+        eif.extend_from_slice(&sections);
+        eif
+    }
+
+    /// Tests that the checksum doesn't depend on how the image is split while being copied.
+    #[test]
+    fn test_eif_checksum() {
+        let eif = build_eif(b"kernel");
+
+        for chunk_size in &[1, 3, EIF_CRC_OFFSET + 2, eif.len()] {
+            let mut checksum = EifChecksum::new();
+            for chunk in eif.chunks(*chunk_size) {
+                checksum.update(chunk);
+            }
+            assert!(checksum.verify().is_ok());
+        }
+
+        let mut corrupted = eif.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        let mut checksum = EifChecksum::new();
+        checksum.update(&corrupted);
+        let err = checksum.verify().unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifMeasurementMismatch);
+
+        let mut checksum = EifChecksum::new();
+        checksum.update(&eif[..eif.len() - 1]);
+        assert!(checksum.verify().is_err());
+    }
+
+    /// Tests that an EIF is copied into a memory region up to the end of either of them.
+    #[test]
+    fn test_load_into_region() {
+        let eif = build_eif(&vec![0x5a; 3 * EIF_LOAD_CHUNK_SIZE / 2]);
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&eif).unwrap();
+
+        let size = 2 * EIF_LOAD_CHUNK_SIZE;
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+        // The region is unmapped on drop.
+        let mut region = MemoryRegion::new_with(0, addr as u64, size as u64);
+        let bytes = unsafe { std::slice::from_raw_parts(addr as *const u8, size) };
+
+        file.rewind().unwrap();
+        let mut checksum = EifChecksum::new();
+        let written = checksum
+            .load_into_region(&mut file, &mut region, 16)
+            .unwrap();
+        assert_eq!(written, eif.len() as u64);
+        assert_eq!(&bytes[16..16 + eif.len()], &eif[..]);
+        assert!(bytes[..16].iter().all(|&byte| byte == 0));
+        assert!(checksum.verify().is_ok());
+
+        // The image is copied the same way without a checksum.
+        file.rewind().unwrap();
+        let written = load_into_region(&mut file, &mut region, 32).unwrap();
+        assert_eq!(written, eif.len() as u64);
+        assert_eq!(&bytes[32..32 + eif.len()], &eif[..]);
+
+        // The checksum carries over when the image is split, and fails until all of it is
+        // loaded.
+        file.rewind().unwrap();
+        let mut checksum = EifChecksum::new();
+        let offset = (size - 100) as u64;
+        assert_eq!(
+            checksum
+                .load_into_region(&mut file, &mut region, offset)
+                .unwrap(),
+            100
+        );
+        assert_eq!(&bytes[size - 100..], &eif[..100]);
+        assert!(checksum.verify().is_err());
+        let written = checksum
+            .load_into_region(&mut file, &mut region, 0)
+            .unwrap();
+        assert_eq!(written, eif.len() as u64 - 100);
+        assert!(checksum.verify().is_ok());
+
+        let err = load_into_region(&mut file, &mut region, size as u64 + 1).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::MemoryOverflow);
+    }
+
+    /// Tests that the EIF sections are loaded at the offsets of the memory layout, across regions.
+    #[test]
+    fn test_load_with_layout() {
+        let eif = build_eif_with_sections(&[
+            (EifSectionType::EifSectionKernel, b"kernel"),
+            (EifSectionType::EifSectionCmdline, b"console=ttyS0"),
+            (EifSectionType::EifSectionRamdisk, b"ramdisk1"),
+            (EifSectionType::EifSectionRamdisk, b"ramdisk2"),
+        ]);
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&eif).unwrap();
+
+        let size = 4096;
+        let mut regions: Vec<MemoryRegion> = (0..2)
+            .map(|_| {
+                let addr = unsafe {
+                    libc::mmap(
+                        std::ptr::null_mut(),
+                        size,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                        -1,
+                        0,
+                    )
+                };
+                assert_ne!(addr, libc::MAP_FAILED);
+                // The region is unmapped on drop.
+                MemoryRegion::new_with(0, addr as u64, size as u64)
+            })
+            .collect();
+        let memory: Vec<&[u8]> = regions
+            .iter()
+            .map(|region| unsafe {
+                std::slice::from_raw_parts(region.mem_addr() as *const u8, size)
+            })
+            .collect();
+
+        // The cmdline straddles both regions.
+        let layout = MemoryLayout {
+            kernel_offset: 16,
+            ramdisk_offset: 4200,
+            cmdline_offset: 4090,
+        };
+        load_with_layout(&mut file, &mut regions, &layout).unwrap();
+        assert_eq!(&memory[0][16..22], b"kernel");
+        assert_eq!(&memory[0][4090..], b"consol");
+        assert_eq!(&memory[1][..7], b"e=ttyS0");
+        assert_eq!(&memory[1][104..120], b"ramdisk1ramdisk2");
+
+        let layout = MemoryLayout {
+            ramdisk_offset: 2 * size as u64 - 10,
+            ..layout
+        };
+        let err = load_with_layout(&mut file, &mut regions, &layout).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::MemoryOverflow);
+        assert_eq!(
+            err.subactions[0],
+            "The ramdisk 2 section (8 bytes at offset 0x1ffe) overflows the enclave memory of 8192 bytes"
+        );
+
+        let layout = MemoryLayout {
+            kernel_offset: u64::MAX,
+            ..MemoryLayout::default()
+        };
+        let err = load_with_layout(&mut file, &mut regions, &layout).unwrap_err();
+        assert!(err.subactions[0].starts_with("The kernel section"));
+
+        // A corrupted ramdisk fails the check of the image, before anything is copied.
+        let mut file = tempfile::tempfile().unwrap();
+        let mut corrupted = eif.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        file.write_all(&corrupted).unwrap();
+        let layout = MemoryLayout {
+            kernel_offset: 1024,
+            ramdisk_offset: 2048,
+            cmdline_offset: 3072,
+        };
+        let err = load_with_layout(&mut file, &mut regions, &layout).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifMeasurementMismatch);
+        assert!(memory[0][1024..4090].iter().all(|&byte| byte == 0));
+    }
+
+    /// Tests that an EIF is checked against its recorded CRC32.
+    #[test]
+    fn test_verify_measurements() {
+        let eif = build_eif(b"kernel");
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&eif).unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let measurements = verify_measurements(path).unwrap();
+        assert!(measurements.contains_key("PCR0"));
+        assert!(!measurements.contains_key("PCR8"));
+
+        let pcrs = pcr_measurements(path).unwrap();
+        assert_eq!(pcrs.pcr0, measurements["PCR0"]);
+        assert_eq!(pcrs.pcr1, measurements["PCR1"]);
+        // Without ramdisks, the application is measured over no data at all.
+        let empty: &[u8] = &[];
+        let pcr2 = Sha384::digest(&[&[0u8; 48][..], &Sha384::digest(empty)[..]].concat());
+        assert_eq!(pcrs.pcr2, hex::encode(pcr2));
+
+        let mut corrupted = eif.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&corrupted).unwrap();
+
+        let err = verify_measurements(file.path().to_str().unwrap()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifMeasurementMismatch);
+        assert_eq!(err.additional_info[1], "CRC32 mismatch");
+    }
+
+    /// Build the start of an x86 kernel image, whose version string is `version`.
+    fn build_bzimage(version: &str) -> Vec<u8> {
+        let mut kernel = vec![0u8; 0x400];
+        kernel[BZIMAGE_MAGIC_OFFSET..BZIMAGE_MAGIC_OFFSET + 4].copy_from_slice(b"HdrS");
+        kernel[BZIMAGE_VERSION_OFFSET..BZIMAGE_VERSION_OFFSET + 2]
+            .copy_from_slice(&0x100u16.to_le_bytes());
+        kernel[0x300..0x300 + version.len()].copy_from_slice(version.as_bytes());
+        kernel
+    }
+
+    /// Write `eif` to a temporary file, returning the file along with its path.
+    fn write_eif(eif: &[u8]) -> (tempfile::NamedTempFile, String) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(eif).unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        (file, path)
+    }
+
+    /// Tests that the recommended memory covers the loaded sections and their headroom.
+    #[test]
+    fn test_recommended_memory() {
+        let kernel = vec![0u8; 1024 * 1024];
+        let ramdisk = vec![0u8; 512 * 1024];
+        let (_file, path) = write_eif(&build_eif_with_sections(&[
+            (EifSectionType::EifSectionKernel, &kernel),
+            (EifSectionType::EifSectionRamdisk, &ramdisk),
+            (EifSectionType::EifSectionRamdisk, &ramdisk),
+            (EifSectionType::EifSectionMetadata, &ramdisk),
+        ]));
+
+        // The metadata section isn't loaded into enclave memory.
+        assert_eq!(
+            recommended_memory(&path).unwrap(),
+            memory_with_headroom(2 * 1024 * 1024, memory_headroom())
+        );
+        assert_eq!(memory_with_headroom(2 * 1024 * 1024, 5.0), 12);
+        assert_eq!(memory_with_headroom(2 * 1024 * 1024 + 1, 0.0), 3);
+        assert_eq!(memory_with_headroom(0, 5.0), 0);
+
+        assert_eq!(parse_headroom(" 1.5 "), Some(1.5));
+        assert_eq!(parse_headroom("-1"), None);
+        assert_eq!(parse_headroom("NaN"), None);
+        assert_eq!(parse_headroom("lots"), None);
+
+        let err = recommended_memory("/nonexistent.eif").unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+    }
+
+    /// Tests that the metadata of an EIF is read from its sections.
+    #[test]
+    fn test_read_metadata() {
+        let kernel = build_bzimage("5.10.186 (builder@host) #1 SMP");
+        let identity = br#"{"ImageName":"hello","ImageVersion":"1.0","BuildMetadata":{"BuildTime":"2026-10-14T10:00:00Z","BuildTool":"nitro-cli","BuildToolVersion":"1.3.1","OperatingSystem":"Linux","KernelVersion":"6.1.0"},"DockerInfo":{},"CustomMetadata":{}}"#;
+        let sections: Vec<(EifSectionType, &[u8])> = vec![
+            (EifSectionType::EifSectionKernel, &kernel),
+            (EifSectionType::EifSectionCmdline, b"console=ttyS0 quiet\0"),
+            (EifSectionType::EifSectionRamdisk, b"bootstrap"),
+            (EifSectionType::EifSectionRamdisk, b"application"),
+        ];
+
+        let (_file, path) = write_eif(&build_eif_with_sections(&sections));
+        assert_eq!(
+            read_metadata(&path).unwrap(),
+            EifMetadata {
+                kernel_version: Some("5.10.186".to_string()),
+                cmdline: "console=ttyS0 quiet".to_string(),
+                ramdisk_count: 2,
+                build_time: None,
+            }
+        );
+
+        // The metadata section takes precedence over the kernel image.
+        let mut sections = sections;
+        sections.push((EifSectionType::EifSectionMetadata, identity));
+        let (_file, path) = write_eif(&build_eif_with_sections(&sections));
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.kernel_version.as_deref(), Some("6.1.0"));
+        assert_eq!(metadata.build_time.as_deref(), Some("2026-10-14T10:00:00Z"));
+
+        let (_file, path) = write_eif(&build_eif(b"not a bzImage"));
+        assert_eq!(read_metadata(&path).unwrap().kernel_version, None);
+    }
+
+    /// Tests that truncated and corrupt EIFs are reported as parsing errors.
+    #[test]
+    fn test_read_metadata_corrupt() {
+        let eif = build_eif_with_sections(&[
+            (EifSectionType::EifSectionKernel, b"kernel"),
+            (EifSectionType::EifSectionMetadata, b"{not json"),
+        ]);
+        let (_file, path) = write_eif(&eif);
+        let err = read_metadata(&path).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifParsingError);
+
+        for len in &[EifHeader::size() - 1, eif.len() - 1] {
+            let (_file, path) = write_eif(&eif[..*len]);
+            let err = read_metadata(&path).unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::EifParsingError);
+        }
+
+        let mut bad_magic = eif.clone();
+        bad_magic[0] ^= 0xff;
+        let (_file, path) = write_eif(&bad_magic);
+        assert!(read_metadata(&path).is_err());
+
+        // The section count follows the magic, version, flags, memory, CPUs and reserved fields.
+        let mut too_many_sections = eif;
+        too_many_sections[26..28].copy_from_slice(&(MAX_NUM_SECTIONS as u16 + 1).to_be_bytes());
+        let (_file, path) = write_eif(&too_many_sections);
+        let err = read_metadata(&path).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifParsingError);
+    }
+
+    /// Generate a self-signed P-384 certificate for `common_name`, returning the PEM-encoded
+    /// certificate and private key.
+    fn signing_identity(common_name: &str) -> (Vec<u8>, Vec<u8>) {
+        use openssl::bn::BigNum;
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::hash::MessageDigest;
+        use openssl::nid::Nid;
+        use openssl::pkey::PKey;
+        use openssl::x509::X509NameBuilder;
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha384()).unwrap();
+
+        (
+            cert.build().to_pem().unwrap(),
+            key.private_key_to_pem_pkcs8().unwrap(),
+        )
+    }
+
+    /// Build an EIF signed with the given certificate and key, returning its path.
+    fn build_signed_eif(dir: &std::path::Path, cert: &[u8], key: &[u8]) -> String {
+        use aws_nitro_enclaves_image_format::defs::EifBuildInfo;
+        use aws_nitro_enclaves_image_format::utils::{EifBuilder, SignEnclaveInfo};
+
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let kernel = write("kernel", b"kernel");
+        let ramdisk = write("ramdisk", b"ramdisk contents");
+        let cert_path = write("cert.pem", cert);
+        let key_path = write("key.pem", key);
+        let sign_info =
+            SignEnclaveInfo::new(cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+        let identity = EifIdentityInfo {
+            img_name: "test".to_string(),
+            img_version: "1.0".to_string(),
+            build_info: EifBuildInfo {
+                build_time: String::new(),
+                build_tool: String::new(),
+                build_tool_version: String::new(),
+                img_os: String::new(),
+                img_kernel: String::new(),
+            },
+            docker_info: serde_json::Value::Null,
+            custom_info: serde_json::Value::Null,
+        };
+
+        let mut builder = EifBuilder::new(
+            &kernel,
+            "console=ttyS0".to_string(),
+            Some(sign_info),
+            Sha384::new(),
+            0,
+            identity,
+        );
+        builder.add_ramdisk(&ramdisk);
+        let eif_path = dir.join("signed.eif");
+        builder.write_to(&mut File::create(&eif_path).unwrap());
+
+        eif_path.to_str().unwrap().to_string()
+    }
+
+    /// Tests that EIF signatures are checked against the trusted certificate.
+    #[test]
+    fn test_verify_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = signing_identity("enclave-signer");
+        let path = build_signed_eif(dir.path(), &cert, &key);
+
+        let info = verify_signature(&path, &cert).unwrap();
+        assert_eq!(info.subject["CN"], "enclave-signer");
+        assert!(info.is_valid);
+        // DER certificates are accepted as well.
+        let der = X509::from_pem(&cert).unwrap().to_der().unwrap();
+        assert_eq!(verify_signature(&path, &der).unwrap(), info);
+
+        let (other_cert, _) = signing_identity("someone-else");
+        let err = verify_signature(&path, &other_cert).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifCertificateMismatch);
+
+        let mut tampered = std::fs::read(&path).unwrap();
+        let start = tampered
+            .windows(7)
+            .position(|window| window == b"ramdisk")
+            .unwrap();
+        tampered[start] = b'R';
+        std::fs::write(&path, &tampered).unwrap();
+        let err = verify_signature(&path, &cert).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifSignatureInvalid);
+
+        let (_file, unsigned_path) = write_eif(&build_eif(b"kernel"));
+        let err = verify_signature(&unsigned_path, &cert).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EifUnsigned);
+    }
+}
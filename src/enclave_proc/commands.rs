@@ -16,6 +16,7 @@ use crate::common::commands_parser::RunEnclavesArgs;
 use crate::common::construct_error_message;
 use crate::common::json_output::EnclaveTerminateInfo;
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::eif::recommended_memory;
 use crate::enclave_proc::connection::Connection;
 use crate::enclave_proc::connection::{safe_conn_eprintln, safe_conn_println};
 use crate::enclave_proc::cpu_info::CpuInfo;
@@ -57,6 +58,22 @@ pub fn run_enclaves(
         .add_info(vec![&args.eif_path, "Open"])
     })?;
 
+    // Only warn about memory below the recommendation, since the application may need less.
+    match recommended_memory(&args.eif_path) {
+        Ok(recommended) if args.memory_mib < recommended => safe_conn_eprintln(
+            connection,
+            &format!(
+                "Warning: {} MiB of memory have been requested, but at least {} MiB are recommended for this EIF.",
+                args.memory_mib, recommended
+            ),
+        )?,
+        Ok(_) => (),
+        Err(e) => debug!(
+            "Failed to compute the recommended memory: {}",
+            construct_error_message(&e)
+        ),
+    }
+
     let cpu_ids = CpuInfo::new()
         .map_err(|e| e.add_subaction("Failed to construct CPU information".to_string()))?
         .get_cpu_config(args)
@@ -14,6 +14,7 @@ use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex};
 
+use crate::common::protocol::{read_command, write_response, Command, Response};
 use crate::common::{receive_from_stream, write_u64_le};
 use crate::common::{
     EnclaveProcessCommandType, EnclaveProcessReply, ExitGracefully, NitroCliErrorEnum,
@@ -51,6 +52,9 @@ struct ConnectionData {
 pub struct Connection {
     /// The thread-safe data used internally by the connection.
     data: Arc<Mutex<ConnectionData>>,
+    /// Whether the connection carries framed commands, as requested by a
+    /// `EnclaveProcessCommandType::Session` command received on it earlier.
+    session: bool,
 }
 
 impl Drop for ConnectionData {
@@ -86,13 +90,19 @@ impl CommandRequesterPolicy {
             EnclaveProcessCommandType::GetEnclaveName,
             EnclaveProcessCommandType::GetIDbyName,
             EnclaveProcessCommandType::ConnectionListenerStop,
+            EnclaveProcessCommandType::Ping,
+            EnclaveProcessCommandType::GetRecentConsole,
+            EnclaveProcessCommandType::Session,
         ];
+        // Each command sent over a session is checked against the policy on its own.
         let cmds_read_only = vec![
             EnclaveProcessCommandType::Describe,
             EnclaveProcessCommandType::GetEnclaveCID,
             EnclaveProcessCommandType::GetEnclaveFlags,
             EnclaveProcessCommandType::GetEnclaveName,
             EnclaveProcessCommandType::GetIDbyName,
+            EnclaveProcessCommandType::Ping,
+            EnclaveProcessCommandType::Session,
         ];
         let mut policy = HashMap::new();
 
@@ -155,9 +165,23 @@ impl Connection {
 
         Connection {
             data: Arc::new(Mutex::new(conn_data)),
+            session: false,
         }
     }
 
+    /// Create a new connection instance for a session, which carries framed commands.
+    pub fn new_session(epoll_flags: EpollFlags, input_stream: UnixStream) -> Self {
+        Connection {
+            session: true,
+            ..Connection::new(epoll_flags, Some(input_stream))
+        }
+    }
+
+    /// Check if the connection carries framed commands.
+    pub fn is_session(&self) -> bool {
+        self.session
+    }
+
     /// Read a command and its corresponding credentials.
     pub fn read_command(&self) -> NitroCliResult<EnclaveProcessCommandType> {
         let mut lock = self.data.lock().map_err(|e| {
@@ -174,44 +198,74 @@ impl Connection {
         }
 
         // First, read the incoming command.
-        let mut cmd =
+        let cmd =
             receive_from_stream::<EnclaveProcessCommandType>(lock.input_stream.as_mut().unwrap())?;
 
-        // Next, read the credentials of the command requester.
-        let conn_fd = lock.input_stream.as_ref().unwrap().as_raw_fd();
-        let socket_creds = nix::sys::socket::getsockopt(conn_fd, PeerCredentials);
-
-        // If the credentials cannot be read, the command will be skipped.
-        let user_creds = match socket_creds {
-            Ok(creds) => creds,
-            Err(e) => {
-                warn!("Failed to get user credentials: {}", e);
-                return Ok(EnclaveProcessCommandType::NotPermitted);
-            }
-        };
-
-        // Apply the default command access policy based on the user's credentials.
-        let policy = CommandRequesterPolicy::new_with_defaults();
-        if !policy.can_execute_command(cmd, &user_creds) {
-            // Log the failed execution attempt.
-            warn!(
-                "The requester with credentials ({:?}) is not allowed to perform '{:?}'.",
-                user_creds, cmd
-            );
-
-            // Force the command to be skipped by the main event loop.
-            cmd = EnclaveProcessCommandType::NotPermitted;
-        } else {
-            // Log the successful execution attempt.
-            debug!(
-                "The requester with credentials ({:?}) is allowed to perform '{:?}'.",
-                user_creds, cmd
-            );
+        // Force the command to be skipped by the main event loop if it is not permitted.
+        if !is_permitted(lock.input_stream.as_ref().unwrap(), cmd) {
+            return Ok(EnclaveProcessCommandType::NotPermitted);
         }
 
         Ok(cmd)
     }
 
+    /// Read a framed command from a session, returning it along with whether its requester
+    /// is allowed to perform it.
+    pub fn read_framed_command(&self) -> NitroCliResult<(Command, bool)> {
+        let mut lock = self.data.lock().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to acquire lock: {:?}", e),
+                NitroCliErrorEnum::LockAcquireFailure
+            )
+        })?;
+        if lock.input_stream.is_none() {
+            return Err(new_nitro_cli_failure!(
+                "Cannot read a command from this connection",
+                NitroCliErrorEnum::UnusableConnectionError
+            ));
+        }
+
+        let cmd = read_command(lock.input_stream.as_mut().unwrap())?;
+        let permitted = is_permitted(lock.input_stream.as_ref().unwrap(), cmd.command_type());
+
+        Ok((cmd, permitted))
+    }
+
+    /// Write the response to a framed command on this connection.
+    pub fn write_response(&self, response: &Response) -> NitroCliResult<()> {
+        let mut lock = self.data.lock().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to acquire lock: {:?}", e),
+                NitroCliErrorEnum::LockAcquireFailure
+            )
+        })?;
+        if lock.input_stream.is_none() {
+            return Err(new_nitro_cli_failure!(
+                "Cannot write a response to this connection",
+                NitroCliErrorEnum::SocketError
+            ));
+        }
+
+        write_response(lock.input_stream.as_mut().unwrap(), response)
+    }
+
+    /// Take the stream out of this connection, which then no longer closes it when dropped.
+    pub fn take_stream(&self) -> NitroCliResult<UnixStream> {
+        let mut lock = self.data.lock().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to acquire lock: {:?}", e),
+                NitroCliErrorEnum::LockAcquireFailure
+            )
+        })?;
+
+        lock.input_stream.take().ok_or_else(|| {
+            new_nitro_cli_failure!(
+                "Cannot take the stream of this connection",
+                NitroCliErrorEnum::UnusableConnectionError
+            )
+        })
+    }
+
     /// Read an object of the specified type from this connection.
     pub fn read<T>(&self) -> NitroCliResult<T>
     where
@@ -251,6 +305,32 @@ impl Connection {
         write_u64_le(lock.input_stream.as_mut().unwrap(), value)
     }
 
+    /// Write a buffer, preceded by its LE-encoded 64-bit size, on this connection.
+    pub fn write_bytes(&self, data: &[u8]) -> NitroCliResult<()> {
+        let mut lock = self.data.lock().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to acquire lock: {:?}", e),
+                NitroCliErrorEnum::LockAcquireFailure
+            )
+        })?;
+        if lock.input_stream.is_none() {
+            return Err(new_nitro_cli_failure!(
+                "Cannot write a buffer to this connection",
+                NitroCliErrorEnum::SocketError
+            ));
+        }
+
+        let mut stream = lock.input_stream.as_mut().unwrap();
+        write_u64_le(&mut stream, data.len() as u64)
+            .map_err(|e| e.add_subaction("Write buffer size".to_string()))?;
+        stream.write_all(data).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to write to stream: {:?}", e),
+                NitroCliErrorEnum::SocketError
+            )
+        })
+    }
+
     /// Write a message to the standard output of the connection's other end.
     pub fn println(&self, msg: &str) -> NitroCliResult<()> {
         let mut msg_str = msg.to_string();
@@ -327,6 +407,37 @@ impl Connection {
     }
 }
 
+/// Check if the requester on the other end of the given stream is allowed to perform `cmd`, as
+/// given by the default command access policy.
+fn is_permitted(stream: &UnixStream, cmd: EnclaveProcessCommandType) -> bool {
+    // If the credentials cannot be read, the command will be skipped.
+    let user_creds = match nix::sys::socket::getsockopt(stream.as_raw_fd(), PeerCredentials) {
+        Ok(creds) => creds,
+        Err(e) => {
+            warn!("Failed to get user credentials: {}", e);
+            return false;
+        }
+    };
+
+    // Apply the default command access policy based on the user's credentials.
+    let policy = CommandRequesterPolicy::new_with_defaults();
+    if !policy.can_execute_command(cmd, &user_creds) {
+        // Log the failed execution attempt.
+        warn!(
+            "The requester with credentials ({:?}) is not allowed to perform '{:?}'.",
+            user_creds, cmd
+        );
+        return false;
+    }
+
+    // Log the successful execution attempt.
+    debug!(
+        "The requester with credentials ({:?}) is allowed to perform '{:?}'.",
+        user_creds, cmd
+    );
+    true
+}
+
 /// Print a message to a connection's standard output, if the connection is available.
 pub fn safe_conn_println(conn: Option<&Connection>, msg: &str) -> NitroCliResult<()> {
     if conn.is_none() {
@@ -15,17 +15,28 @@ use std::os::raw::c_char;
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{self, Sender};
 use std::thread::{self, JoinHandle};
 
 use super::connection::Connection;
-use super::socket::EnclaveProcSock;
+use super::socket::{authorize_peer, EnclaveProcSock, ShutdownReason};
 use crate::common::commands_parser::EmptyArgs;
+use crate::common::protocol::{client_handshake, handshake};
 use crate::common::{enclave_proc_command_send_single, receive_from_stream};
 use crate::common::{
     EnclaveProcessCommandType, ExitGracefully, NitroCliErrorEnum, NitroCliFailure, NitroCliResult,
 };
 use crate::new_nitro_cli_failure;
 
+/// Environment variable which restricts the connections to the enclave process socket to its
+/// owner, root and the users with the listed UIDs (separated by commas). When it is unset, any
+/// user may connect, and the commands of users other than the owner and root are limited to
+/// read-only ones.
+const ALLOWED_PEER_UIDS_ENV_VAR: &str = "NITRO_CLI_ALLOWED_PEER_UIDS";
+
+/// Marks the `epoll` events of sessions, whose data is otherwise the descriptor of the stream.
+const SESSION_EVENT_TAG: u64 = 1 << 32;
+
 /// A listener which waits for incoming connections on the enclave process socket.
 #[derive(Default)]
 pub struct ConnectionListener {
@@ -35,6 +46,9 @@ pub struct ConnectionListener {
     listener_thread: Option<JoinHandle<()>>,
     /// The Unix socket that the listener binds to.
     socket: EnclaveProcSock,
+    /// The UIDs which may connect besides the owner of the enclave process and root, if
+    /// connections are restricted at all.
+    allowed_peers: Option<Vec<u32>>,
 }
 
 /// The listener must be cloned when launching the listening thread.
@@ -45,6 +59,7 @@ impl Clone for ConnectionListener {
             epoll_fd: self.epoll_fd,
             listener_thread: None,
             socket: self.socket.clone(),
+            allowed_peers: self.allowed_peers.clone(),
         }
     }
 }
@@ -61,9 +76,15 @@ impl ConnectionListener {
             })?,
             listener_thread: None,
             socket: EnclaveProcSock::default(),
+            allowed_peers: allowed_peer_uids(),
         })
     }
 
+    /// Record that a CLI instance has pinged the enclave process.
+    pub fn record_ping(&self) {
+        self.socket.record_ping();
+    }
+
     /// Expose the `epoll` descriptor.
     pub fn get_epoll_fd(&self) -> RawFd {
         self.epoll_fd
@@ -75,6 +96,13 @@ impl ConnectionListener {
         self.socket = EnclaveProcSock::new(enclave_id)
             .map_err(|e| e.add_subaction("Failed to create enclave process socket".to_string()))?;
 
+        // A killed enclave process which owned the same slot may have left its socket behind.
+        if EnclaveProcSock::cleanup_stale(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to reclaim enclave process socket".to_string()))?
+        {
+            warn!("Removed stale socket {:?}.", self.socket.get_path());
+        }
+
         // Bind the listener to the socket and spawn the listener thread.
         let listener = UnixListener::bind(self.socket.get_path()).map_err(|e| {
             new_nitro_cli_failure!(
@@ -83,8 +111,11 @@ impl ConnectionListener {
             )
         })?;
         self.enable_credentials_passing(&listener);
+        let shutdown_notifier = self
+            .start_shutdown_forwarder()
+            .map_err(|e| e.add_subaction("Failed to start shutdown forwarder".to_string()))?;
         self.socket
-            .start_monitoring(true)
+            .start_monitoring(Some(shutdown_notifier))
             .map_err(|e| e.add_subaction("Failed to start monitoring socket".to_string()))?;
         debug!(
             "Connection listener started on socket {:?}.",
@@ -105,10 +136,50 @@ impl ConnectionListener {
         Ok(())
     }
 
+    /// Forward shutdown requests coming from the socket monitor to the event loop, as a
+    /// `ConnectionListenerStop` command, so that the enclave process can tear down cleanly.
+    fn start_shutdown_forwarder(&self) -> NitroCliResult<Sender<ShutdownReason>> {
+        let (local_stream, mut thread_stream) = UnixStream::pair().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to create stream pair: {:?}", e),
+                NitroCliErrorEnum::SocketPairCreationFailure
+            )
+        })?;
+        self.add_stream_to_epoll(local_stream)?;
+
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        thread::spawn(move || {
+            // The channel gets closed without any request when the socket is removed on purpose.
+            if let Ok(reason) = shutdown_rx.recv() {
+                warn!("The enclave process will now close: {:?}.", reason);
+                if let Err(e) = enclave_proc_command_send_single::<EmptyArgs>(
+                    EnclaveProcessCommandType::ConnectionListenerStop,
+                    None,
+                    &mut thread_stream,
+                ) {
+                    warn!("Failed to forward shutdown request: {:?}", e);
+                }
+            }
+        });
+
+        Ok(shutdown_tx)
+    }
+
     /// Add a stream to `epoll`.
     pub fn add_stream_to_epoll(&self, stream: UnixStream) -> NitroCliResult<()> {
+        self.register_stream(stream, 0)
+    }
+
+    /// Add the stream of a session to `epoll`, so that its next framed command gets served
+    /// once it arrives.
+    pub fn add_session_to_epoll(&self, stream: UnixStream) -> NitroCliResult<()> {
+        self.register_stream(stream, SESSION_EVENT_TAG)
+    }
+
+    /// Add a stream to `epoll`, tagging the data of its events with `tag`.
+    fn register_stream(&self, stream: UnixStream, tag: u64) -> NitroCliResult<()> {
         let stream_fd = stream.as_raw_fd();
-        let mut cli_evt = EpollEvent::new(EpollFlags::EPOLLIN, stream.into_raw_fd() as u64);
+        let mut cli_evt = EpollEvent::new(EpollFlags::EPOLLIN, stream.into_raw_fd() as u64 | tag);
         epoll::epoll_ctl(self.epoll_fd, EpollOp::EpollCtlAdd, stream_fd, &mut cli_evt).map_err(
             |e| {
                 new_nitro_cli_failure!(
@@ -144,15 +215,27 @@ impl ConnectionListener {
         &self,
         mut stream: UnixStream,
     ) -> NitroCliResult<EnclaveProcessCommandType> {
+        // Connections from unauthorized peers are closed before reading anything from them.
+        if let Some(allowed_peers) = self.allowed_peers.as_ref() {
+            authorize_peer(&stream, allowed_peers)
+                .map_err(|e| e.add_subaction("Failed to authorize connection".to_string()))?;
+        }
+
+        handshake(&mut stream)
+            .map_err(|e| e.add_subaction("Failed to perform the protocol handshake".to_string()))?;
         let cmd_type =
             receive_from_stream::<EnclaveProcessCommandType>(&mut stream).map_err(|e| {
                 e.add_subaction("Failed to receive command type from stream".to_string())
             })?;
 
         // All connections must be registered with epoll, with the exception of the shutdown one.
+        // The second copy of the shutdown command is consumed before the stream gets closed, since
+        // the sender would otherwise fail to write it.
         if cmd_type != EnclaveProcessCommandType::ConnectionListenerStop {
             self.add_stream_to_epoll(stream)
                 .map_err(|e| e.add_subaction("Failed to add stream to epoll".to_string()))?;
+        } else {
+            let _ = receive_from_stream::<EnclaveProcessCommandType>(&mut stream);
         }
 
         Ok(cmd_type)
@@ -195,6 +278,14 @@ impl ConnectionListener {
             return Ok(());
         }
 
+        // If the socket has been deleted externally, the listener thread can no longer be
+        // reached, so it is left to finish along with the enclave process.
+        if !self.socket.get_path().exists() {
+            warn!("The listener socket no longer exists, skipping listener thread shutdown.");
+            self.listener_thread.take();
+            return Ok(());
+        }
+
         // Send termination notification to the listener thread.
         let mut self_conn = UnixStream::connect(self.socket.get_path()).map_err(|e| {
             new_nitro_cli_failure!(
@@ -202,6 +293,9 @@ impl ConnectionListener {
                 NitroCliErrorEnum::SocketError
             )
         })?;
+        client_handshake(&mut self_conn).map_err(|e| {
+            e.add_subaction("Failed to notify listener thread of shutdown".to_string())
+        })?;
         enclave_proc_command_send_single::<EmptyArgs>(
             EnclaveProcessCommandType::ConnectionListenerStop,
             None,
@@ -246,7 +340,8 @@ impl ConnectionListener {
             }
         }
 
-        let fd = events[0].data() as RawFd;
+        let data = events[0].data();
+        let fd = (data & !SESSION_EVENT_TAG) as RawFd;
         let input_stream = match enc_fd {
             // This is a connection to an enclave.
             Some(enc_fd) if enc_fd == fd => None,
@@ -264,7 +359,12 @@ impl ConnectionListener {
             )
         })?;
 
-        Ok(Connection::new(events[0].events(), input_stream))
+        Ok(match input_stream {
+            Some(stream) if data & SESSION_EVENT_TAG != 0 => {
+                Connection::new_session(events[0].events(), stream)
+            }
+            _ => Connection::new(events[0].events(), input_stream),
+        })
     }
 
     /// Enable the sending of credentials from incoming connections.
@@ -305,11 +405,38 @@ impl ConnectionListener {
     }
 }
 
+/// Get the UIDs listed in `ALLOWED_PEER_UIDS_ENV_VAR`, if the variable is set.
+fn allowed_peer_uids() -> Option<Vec<u32>> {
+    std::env::var(ALLOWED_PEER_UIDS_ENV_VAR)
+        .ok()
+        .map(|uids| parse_uid_list(&uids))
+}
+
+/// Parse a comma-separated list of UIDs, skipping the invalid ones.
+fn parse_uid_list(uids: &str) -> Vec<u32> {
+    uids.split(',')
+        .map(str::trim)
+        .filter(|uid| !uid.is_empty())
+        .filter_map(|uid| match uid.parse::<u32>() {
+            Ok(uid) => Some(uid),
+            Err(_) => {
+                warn!(
+                    "Ignoring invalid UID `{}` in {}.",
+                    uid, ALLOWED_PEER_UIDS_ENV_VAR
+                );
+                None
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::common::protocol::PROTOCOL_VERSION;
     use crate::common::{get_sockets_dir_path, SOCKETS_DIR_PATH_ENV_VAR};
+    use std::io::Write;
 
     use std::env;
     use std::fs;
@@ -358,6 +485,7 @@ mod tests {
         let connection_listener = ConnectionListener::new().unwrap();
 
         let cmd = EnclaveProcessCommandType::Describe;
+        sock0.write_all(&[PROTOCOL_VERSION]).unwrap();
         let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut sock0);
 
         let result = connection_listener.handle_new_connection(sock1);
@@ -366,6 +494,41 @@ mod tests {
         assert_eq!(result.unwrap(), EnclaveProcessCommandType::Describe);
     }
 
+    /// Tests that connections from peers speaking another protocol version are rejected before
+    /// their command is read.
+    #[test]
+    fn test_handle_new_connection_version_mismatch() {
+        let (mut sock0, sock1) = UnixStream::pair().unwrap();
+        let connection_listener = ConnectionListener::new().unwrap();
+
+        sock0.write_all(&[PROTOCOL_VERSION + 1]).unwrap();
+        let cmd = EnclaveProcessCommandType::Describe;
+        let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut sock0);
+
+        let err = connection_listener
+            .handle_new_connection(sock1)
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::ProtocolVersionMismatch);
+    }
+
+    /// Test that connections from the owner are accepted when peers are restricted, and that
+    /// the allowed UIDs are parsed leniently.
+    #[test]
+    fn test_handle_new_connection_restricted() {
+        let (mut sock0, sock1) = UnixStream::pair().unwrap();
+        let mut connection_listener = ConnectionListener::new().unwrap();
+        connection_listener.allowed_peers = Some(Vec::new());
+
+        let cmd = EnclaveProcessCommandType::Ping;
+        sock0.write_all(&[PROTOCOL_VERSION]).unwrap();
+        let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut sock0);
+        let result = connection_listener.handle_new_connection(sock1);
+        assert_eq!(result.unwrap(), EnclaveProcessCommandType::Ping);
+
+        assert_eq!(parse_uid_list("1000, 1001,,x,-1"), vec![1000, 1001]);
+        assert!(parse_uid_list("").is_empty());
+    }
+
     /// Test that add_stream_to_epoll registers a sockfd and that next subsequent
     /// attempts to register the same sockfd fail (since the sockfd is already registered
     /// once).
@@ -436,13 +599,6 @@ mod tests {
         let pair2 = pair.clone();
 
         let listener_thread = thread::spawn(move || {
-            {
-                let (lock, cvar) = &*pair2;
-                let mut started = lock.lock().unwrap();
-                *started = true;
-                cvar.notify_one();
-            }
-
             // Bind the listener to the socket and spawn the listener thread.
             let listener = UnixListener::bind(connection_listener.socket.get_path())
                 .map_err(|e| format!("Failed to bind connection listener: {:?}", e))
@@ -450,10 +606,18 @@ mod tests {
             connection_listener.enable_credentials_passing(&listener);
             connection_listener
                 .socket
-                .start_monitoring(true)
+                .start_monitoring(None)
                 .map_err(|e| format!("Failed to start socket monitoring: {:?}", e))
                 .unwrap();
 
+            // Only signal readiness once the socket can accept connections.
+            {
+                let (lock, cvar) = &*pair2;
+                let mut started = lock.lock().unwrap();
+                *started = true;
+                cvar.notify_one();
+            }
+
             let res = connection_listener.connection_listener_run(listener);
             assert!(res.is_ok());
         });
@@ -477,6 +641,7 @@ mod tests {
         let my_stream = UnixStream::connect(&dummy_sock_path);
 
         if let Ok(mut my_stream) = my_stream {
+            client_handshake(&mut my_stream).unwrap();
             // Close the listener thread
             let cmd = EnclaveProcessCommandType::ConnectionListenerStop;
             let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut my_stream);
@@ -555,13 +720,6 @@ mod tests {
 
         let conn_clone = connection_listener.clone();
         let listener_thread = thread::spawn(move || {
-            {
-                let (lock, cvar) = &*pair2;
-                let mut started = lock.lock().unwrap();
-                *started = true;
-                cvar.notify_one();
-            }
-
             // Bind the listener to the socket and spawn the listener thread.
             let listener = UnixListener::bind(connection_listener.socket.get_path())
                 .map_err(|e| format!("Failed to bind connection listener: {:?}", e))
@@ -569,10 +727,18 @@ mod tests {
             connection_listener.enable_credentials_passing(&listener);
             connection_listener
                 .socket
-                .start_monitoring(true)
+                .start_monitoring(None)
                 .map_err(|e| format!("Failed to start socket monitoring: {:?}", e))
                 .unwrap();
 
+            // Only signal readiness once the socket can accept connections.
+            {
+                let (lock, cvar) = &*pair2;
+                let mut started = lock.lock().unwrap();
+                *started = true;
+                cvar.notify_one();
+            }
+
             conn_clone.connection_listener_run(listener).unwrap();
         });
 
@@ -595,6 +761,7 @@ mod tests {
         let my_stream = UnixStream::connect(&dummy_sock_path);
 
         if let Ok(mut my_stream) = my_stream {
+            client_handshake(&mut my_stream).unwrap();
             // Run a command other than ConnectionListenerStop
             let cmd = EnclaveProcessCommandType::Describe;
             let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut my_stream);
@@ -612,6 +779,7 @@ mod tests {
         let my_stream = UnixStream::connect(&dummy_sock_path);
 
         if let Ok(mut my_stream) = my_stream {
+            client_handshake(&mut my_stream).unwrap();
             // Close the listener thread
             let cmd = EnclaveProcessCommandType::ConnectionListenerStop;
             let _ = enclave_proc_command_send_single::<EmptyArgs>(cmd, None, &mut my_stream);
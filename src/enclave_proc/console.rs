@@ -0,0 +1,202 @@
+// Copyright 2019-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use log::{debug, info, warn};
+use std::convert::TryFrom;
+use std::env;
+use std::io;
+use std::thread;
+
+use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::new_nitro_cli_failure;
+use crate::utils::{Console, ConsoleHistory};
+use crate::{CID_TO_CONSOLE_PORT_OFFSET, VMADDR_CID_HYPERVISOR};
+
+/// The environment variable which sets how much console output enclave processes keep, in KiB.
+const CONSOLE_HISTORY_ENV_VAR: &str = "NITRO_CLI_CONSOLE_HISTORY_KIB";
+
+/// The largest amount of console output which an enclave process may keep, in KiB.
+const MAX_CONSOLE_HISTORY_KIB: usize = 4096;
+
+/// Connect to the console of the enclave with the given CID. The console is only available if
+/// the enclave has been started in debug mode, otherwise an `EnclaveConsoleUnavailable` error
+/// is returned.
+pub fn attach(cid: u64) -> NitroCliResult<Console> {
+    let port = console_port(cid)?;
+
+    Console::new(VMADDR_CID_HYPERVISOR, port)
+        .map_err(|e| e.add_subaction(format!("Attach to the console of enclave {}", cid)))
+}
+
+/// Capture the console output of the enclave with the given CID on a background thread, keeping
+/// (at most) the last `capacity` bytes in the returned history. The thread connects to the
+/// console itself, since connecting may block for a while, and stops once the console is closed,
+/// which happens when the enclave terminates.
+pub fn capture(cid: u64, capacity: usize) -> NitroCliResult<ConsoleHistory> {
+    let port = console_port(cid)?;
+    let history = ConsoleHistory::new(capacity);
+    let thread_history = history.clone();
+
+    thread::spawn(move || {
+        let mut console = match Console::new(VMADDR_CID_HYPERVISOR, port) {
+            Ok(console) => console,
+            Err(e) => {
+                warn!("Console output of enclave {} is not captured: {:?}", cid, e);
+                return;
+            }
+        };
+        console.set_history(thread_history);
+
+        match console.read_to(&mut io::sink(), None) {
+            Ok(()) => info!("The console of enclave {} has been closed.", cid),
+            Err(e) => debug!("Stopped capturing the console of enclave {}: {:?}", cid, e),
+        }
+    });
+
+    Ok(history)
+}
+
+/// Get how many bytes of console output enclave processes keep, as given in KiB by
+/// `NITRO_CLI_CONSOLE_HISTORY_KIB`. The capture of the console output is off unless it is set to
+/// a non-zero size, since it takes up a console connection of the enclave.
+pub fn history_size() -> NitroCliResult<usize> {
+    match env::var(CONSOLE_HISTORY_ENV_VAR) {
+        Ok(size) => parse_history_size(&size)
+            .map_err(|e| e.add_subaction(format!("Invalid {}", CONSOLE_HISTORY_ENV_VAR))),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Parse a console history size given in KiB, returning it in bytes.
+fn parse_history_size(size: &str) -> NitroCliResult<usize> {
+    let kib = size.trim().parse::<usize>().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Invalid console history size `{}`: {:?}", size, e),
+            NitroCliErrorEnum::IntegerParsingError
+        )
+    })?;
+
+    if kib > MAX_CONSOLE_HISTORY_KIB {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Console history size of {} KiB exceeds the maximum of {} KiB",
+                kib, MAX_CONSOLE_HISTORY_KIB
+            ),
+            NitroCliErrorEnum::InvalidArgument
+        )
+        .add_info(vec![CONSOLE_HISTORY_ENV_VAR, &kib.to_string()]));
+    }
+
+    Ok(kib * 1024)
+}
+
+/// Get the vsock port on which the console of an enclave is exposed.
+fn console_port(cid: u64) -> NitroCliResult<u32> {
+    u32::try_from(cid)
+        .ok()
+        .and_then(|cid| cid.checked_add(CID_TO_CONSOLE_PORT_OFFSET))
+        .ok_or_else(|| {
+            new_nitro_cli_failure!(
+                &format!("Invalid enclave CID for a console connection: {}", cid),
+                NitroCliErrorEnum::IntegerParsingError
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    /// Tests that the console port is derived from the CID and out-of-range CIDs are rejected.
+    #[test]
+    fn test_console_port() {
+        assert_eq!(console_port(16).unwrap(), 10016);
+        assert!(console_port(u32::MAX as u64).is_err());
+        assert!(console_port(u64::MAX).is_err());
+
+        let err = attach(u64::MAX).err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IntegerParsingError);
+    }
+
+    /// Tests that console output is split into lines and the remainder can be streamed.
+    #[test]
+    fn test_read_line_and_stream_to() {
+        let (mut enclave_end, console_end) = UnixStream::pair().unwrap();
+        let mut console = unsafe { Console::from_raw_fd(console_end.into_raw_fd()) };
+
+        std::io::Write::write_all(&mut enclave_end, b"Booting\nInit started\npartial").unwrap();
+
+        let mut line = String::new();
+        assert_eq!(console.read_line(&mut line).unwrap(), 8);
+        assert_eq!(line, "Booting\n");
+
+        line.clear();
+        console.read_line(&mut line).unwrap();
+        assert_eq!(line, "Init started\n");
+
+        std::io::Write::write_all(&mut enclave_end, b" line\nlast").unwrap();
+        drop(enclave_end);
+
+        let mut output = Vec::new();
+        console.stream_to(&mut output).unwrap();
+        assert_eq!(output, b"partial line\nlast");
+
+        line.clear();
+        assert_eq!(console.read_line(&mut line).unwrap(), 0);
+    }
+
+    /// Tests that only the most recent console output is kept, and that it can be read while
+    /// the console is being read on another thread.
+    #[test]
+    fn test_console_tail() {
+        let (mut enclave_end, console_end) = UnixStream::pair().unwrap();
+        let mut console = unsafe { Console::from_raw_fd(console_end.into_raw_fd()) };
+        let history = ConsoleHistory::new(8);
+        console.set_history(history.clone());
+        assert_eq!(console.history().capacity(), 8);
+        assert!(console.tail(4).is_empty());
+
+        let reader = thread::spawn(move || {
+            console.stream_to(io::sink()).unwrap();
+            console
+        });
+        std::io::Write::write_all(&mut enclave_end, b"Booting\n").unwrap();
+        std::io::Write::write_all(&mut enclave_end, b"Kernel panic\n").unwrap();
+        drop(enclave_end);
+        let console = reader.join().unwrap();
+
+        assert_eq!(console.tail(4), b"nic\n");
+        assert_eq!(console.tail(100), b"l panic\n");
+        assert_eq!(history.tail(100), console.tail(8));
+
+        let history = ConsoleHistory::new(0);
+        history.record(b"dropped");
+        assert!(history.tail(100).is_empty());
+    }
+
+    /// Tests that console history sizes are given in KiB and bounded.
+    #[test]
+    fn test_parse_history_size() {
+        assert_eq!(parse_history_size("0").unwrap(), 0);
+        assert_eq!(parse_history_size("64").unwrap(), 64 * 1024);
+        assert_eq!(
+            parse_history_size("big").unwrap_err().error_code,
+            NitroCliErrorEnum::IntegerParsingError
+        );
+
+        let err = parse_history_size(&(MAX_CONSOLE_HISTORY_KIB + 1).to_string()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidArgument);
+        assert_eq!(
+            err.additional_info,
+            vec![
+                CONSOLE_HISTORY_ENV_VAR.to_string(),
+                (MAX_CONSOLE_HISTORY_KIB + 1).to_string()
+            ]
+        );
+    }
+}
@@ -3,16 +3,18 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+use log::warn;
 use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use crate::common::commands_parser::RunEnclavesArgs;
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use crate::enclave_proc::resource_manager::host_reserved_cpus;
 use crate::new_nitro_cli_failure;
 
 /// Path corresponding to the NE CPU pool.
-const POOL_FILENAME: &str = "/sys/module/nitro_enclaves/parameters/ne_cpus";
+pub(crate) const POOL_FILENAME: &str = "/sys/module/nitro_enclaves/parameters/ne_cpus";
 
 /// The CPU configuration requested by the user.
 #[derive(Clone, PartialEq, Eq)]
@@ -64,6 +66,11 @@ impl CpuInfo {
             self.check_cpu_ids(&cpu_ids).map_err(|e| {
                 e.add_subaction("Failed to check whether CPU list is valid".to_string())
             })?;
+            if !args.unsafe_allow_cpu0 {
+                CpuInfo::check_host_cpus(&cpu_ids, &host_reserved_cpus()).map_err(|e| {
+                    e.add_subaction("Failed to check whether CPU list is valid".to_string())
+                })?;
+            }
             Ok(EnclaveCpuConfig::List(cpu_ids))
         } else if let Some(cpu_count) = args.cpu_count {
             if self.cpu_ids.len() < cpu_count as usize {
@@ -127,6 +134,27 @@ impl CpuInfo {
         Ok(())
     }
 
+    /// Verify that a provided list of CPU IDs includes none of the `reserved` CPUs of core 0,
+    /// which the host keeps for handling critical interrupts.
+    pub fn check_host_cpus(cpu_ids: &[u32], reserved: &[u32]) -> NitroCliResult<()> {
+        if let Some(cpu_id) = cpu_ids.iter().find(|cpu_id| reserved.contains(cpu_id)) {
+            warn!(
+                "Refusing to give CPU {} of core 0 to the enclave without --unsafe-allow-cpu0.",
+                cpu_id
+            );
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "The CPU with ID {} belongs to core 0, which is kept for the host",
+                    cpu_id
+                ),
+                NitroCliErrorEnum::HostCpuReserved
+            )
+            .add_info(vec!["cpu-ids", &cpu_id.to_string()]));
+        }
+
+        Ok(())
+    }
+
     /// Get a list of all available CPU IDs.
     pub fn get_cpu_candidates(&self) -> Vec<u32> {
         self.cpu_ids.clone()
@@ -144,7 +172,8 @@ impl CpuInfo {
         })
     }
 
-    fn parse_cpu_pool_line(line_str: &str) -> NitroCliResult<Vec<u32>> {
+    /// Parse a CPU list of the form "id1-id2,id3,...".
+    pub(crate) fn parse_cpu_pool_line(line_str: &str) -> NitroCliResult<Vec<u32>> {
         let mut result: Vec<u32> = Vec::new();
 
         // The CPU pool format is: "id1-id2,id3-id4,..."
@@ -282,6 +311,7 @@ mod tests {
             memory_mib: 0,
             debug_mode: false,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             cpu_ids: None,
             cpu_count: Some(343),
             enclave_name: Some("testName".to_string()),
@@ -315,6 +345,7 @@ mod tests {
             memory_mib: 0,
             debug_mode: false,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             cpu_ids: None,
             cpu_count: Some(2),
             enclave_name: Some("testName".to_string()),
@@ -368,4 +399,32 @@ mod tests {
             assert!(err_str.contains("No such CPU available in the pool"));
         }
     }
+
+    #[test]
+    fn test_check_host_cpus() {
+        assert!(CpuInfo::check_host_cpus(&[1, 3], &[0, 2]).is_ok());
+
+        let err = CpuInfo::check_host_cpus(&[1, 2], &[0, 2]).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::HostCpuReserved);
+        assert_eq!(err.additional_info, vec!["cpu-ids", "2"]);
+
+        // CPU 0 is refused by default, even if it is in the pool.
+        let cpu_info = CpuInfo::from_reader("0,1".as_bytes()).unwrap();
+        let mut run_args = RunEnclavesArgs {
+            eif_path: String::new(),
+            enclave_cid: None,
+            memory_mib: 0,
+            debug_mode: false,
+            attach_console: false,
+            unsafe_allow_cpu0: false,
+            cpu_ids: Some(vec![0]),
+            cpu_count: None,
+            enclave_name: None,
+        };
+        let err = cpu_info.get_cpu_config(&run_args).err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::HostCpuReserved);
+
+        run_args.unsafe_allow_cpu0 = true;
+        assert!(cpu_info.get_cpu_config(&run_args).unwrap() == EnclaveCpuConfig::List(vec![0]));
+    }
 }
@@ -10,39 +10,68 @@ pub mod commands;
 pub mod connection;
 /// The module which provides an enclave socket monitor that listens for incoming connections.
 pub mod connection_listener;
+/// The module which provides access to the console of enclaves started in debug mode.
+pub mod console;
 /// The module which provides CPU information utilities.
 pub mod cpu_info;
+/// The module which provides the backends through which the NE driver ioctls are issued.
+pub mod ne_backend;
 /// The module which provides the enclave manager and its utilities.
 pub mod resource_manager;
 /// The module which provides the managed Unix socket needed to communicate with the enclave process.
 pub mod socket;
 /// The module which provides additional enclave process utilities.
 pub mod utils;
+/// The module which provides a watchdog of enclaves, based on the heartbeats they send over vsock.
+pub mod watchdog;
 
-use log::{info, warn};
+use log::{debug, info, warn};
+use nix::errno::Errno;
 use nix::sys::epoll::EpollFlags;
-use nix::sys::signal::{Signal, SIGHUP};
-use nix::unistd::{daemon, getpid, getppid};
+use nix::sys::signal::{kill, Signal, SIGHUP};
+use nix::unistd::{daemon, getpid, getppid, Pid};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::ErrorKind;
 use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
 use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use super::common::MSG_ENCLAVE_CONFIRM;
 use super::common::{construct_error_message, enclave_proc_command_send_single, notify_error};
 use super::common::{
     EnclaveProcessCommandType, ExitGracefully, NitroCliErrorEnum, NitroCliFailure, NitroCliResult,
 };
+use super::common::{MSG_ENCLAVE_CONFIRM, MSG_ENCLAVE_PONG};
 use crate::common::commands_parser::{DescribeEnclavesArgs, EmptyArgs, RunEnclavesArgs};
-use crate::common::logger::EnclaveProcLogWriter;
+use crate::common::json_output::EnclaveTerminateInfo;
+use crate::common::logger::{EnclaveProcLogWriter, LogTarget};
+use crate::common::protocol::{handshake, Command, Response};
 use crate::common::signal_handler::SignalHandler;
+use crate::common::{get_sockets_dir_path, ENCLAVE_PROC_WAIT_TIMEOUT_MSEC};
 use crate::enclave_proc::connection::safe_conn_println;
+use crate::enclave_proc_comm::{enclave_proc_ping_stream, EnclaveProcClient};
 use crate::new_nitro_cli_failure;
+use crate::utils::ConsoleHistory;
 
 use commands::{describe_enclaves, run_enclaves, terminate_enclaves, DescribeThread};
 use connection::Connection;
 use connection_listener::ConnectionListener;
 use resource_manager::EnclaveManager;
+use socket::EnclaveProcSock;
+use utils::{generate_enclave_id, get_enclave_describe_info};
+
+/// The directory where procfs is mounted.
+pub(crate) const PROC_DIR: &str = "/proc";
+
+/// How long to wait for an enclave process to exit once its enclave has been terminated.
+const ENCLAVE_PROC_EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether an enclave process has exited.
+const ENCLAVE_PROC_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// The type of enclave event that has been handled.
 enum HandledEnclaveEvent {
@@ -54,6 +83,34 @@ enum HandledEnclaveEvent {
     None,
 }
 
+/// The resources owned by an enclave process, which must be torn down in a fixed order.
+struct EnclaveProcess {
+    /// The listener of the enclave process socket.
+    conn_listener: ConnectionListener,
+    /// The manager of the enclave, once one has been launched.
+    enclave_manager: EnclaveManager,
+    /// The most recent console output of the enclave, if it is in debug mode.
+    console_history: Option<ConsoleHistory>,
+}
+
+impl EnclaveProcess {
+    /// Tear the enclave process down. This can be done more than once, since the steps which
+    /// have already been performed are skipped.
+    ///
+    /// The enclave descriptor is released and the enclave memory is freed first. The listener
+    /// thread is then stopped and joined, which removes the socket. CLI instances find enclaves
+    /// through their sockets, so the socket must be the last to go: once it has been removed,
+    /// the resources of the enclave are guaranteed to be available again.
+    fn shutdown(&mut self) -> NitroCliResult<()> {
+        self.enclave_manager
+            .release_enclave()
+            .map_err(|e| e.add_subaction("Failed to release the enclave".to_string()))?;
+        self.conn_listener
+            .stop()
+            .map_err(|e| e.add_subaction("Failed to stop the connection listener".to_string()))
+    }
+}
+
 /// Obtain the logger ID from the full enclave ID.
 fn get_logger_id(enclave_id: &str) -> String {
     // The full enclave ID is "i-(...)-enc<enc_id>" and we want to extract only <enc_id>.
@@ -72,6 +129,7 @@ fn get_command_action(cmd: EnclaveProcessCommandType) -> String {
         EnclaveProcessCommandType::GetEnclaveCID => "Get Enclave CID".to_string(),
         EnclaveProcessCommandType::GetEnclaveFlags => "Get Enclave Flags".to_string(),
         EnclaveProcessCommandType::ConnectionListenerStop => "Stop Connection Listener".to_string(),
+        EnclaveProcessCommandType::GetRecentConsole => "Get Recent Console".to_string(),
         _ => "Unknown Command".to_string(),
     }
 }
@@ -145,6 +203,12 @@ fn notify_terminate(
 }
 
 /// Launch the POSIX signal handler on a dedicated thread and ensure its events are accessible.
+///
+/// SIGINT, SIGQUIT, SIGTERM and SIGHUP (such as the SIGTERM sent by systemd when stopping a
+/// service) are masked and waited for on the handler thread, which asks the event loop to stop.
+/// The loop then exits normally, so the enclave and the socket get torn down by
+/// `EnclaveProcess::shutdown()`. SIGKILL can't be intercepted, in which case the socket is left
+/// behind and only gets reclaimed once its slot is reused (see `EnclaveProcSock::cleanup_stale()`).
 fn enclave_proc_configure_signal_handler(conn_listener: &ConnectionListener) -> NitroCliResult<()> {
     let mut signal_handler = SignalHandler::new_with_defaults()
         .mask_all()
@@ -166,7 +230,9 @@ fn enclave_proc_configure_signal_handler(conn_listener: &ConnectionListener) ->
                 "Failed to add stream to epoll when configuring signal handler".to_string(),
             )
         })?;
-    signal_handler.start_handler(thread_stream.into_raw_fd(), enclave_proc_handle_signals);
+    signal_handler
+        .start_handler(thread_stream.into_raw_fd(), enclave_proc_handle_signals)
+        .map_err(|e| e.add_subaction("Failed to start signal handler".to_string()))?;
 
     Ok(())
 }
@@ -258,6 +324,7 @@ fn handle_command(
     enclave_manager: &mut EnclaveManager,
     terminate_thread: &mut Option<std::thread::JoinHandle<()>>,
     describe_thread: &mut DescribeThread,
+    console_history: &mut Option<ConsoleHistory>,
 ) -> NitroCliResult<(i32, bool)> {
     Ok(match cmd {
         EnclaveProcessCommandType::Run => {
@@ -270,6 +337,10 @@ fn handle_command(
                         .set_action("Run Enclave".to_string())
                 })?;
                 info!("Run args = {:?}", run_args);
+                let history_size = console::history_size().map_err(|e| {
+                    e.add_subaction("Failed to get the console history size".to_string())
+                        .set_action("Run Enclave".to_string())
+                })?;
 
                 let run_result = run_enclaves(&run_args, Some(connection)).map_err(|e| {
                     e.add_subaction("Failed to trigger enclave run".to_string())
@@ -297,6 +368,22 @@ fn handle_command(
                     .map_err(|e| {
                         e.set_action("Failed to register enclave descriptor".to_string())
                     })?;
+
+                // Keep the console output of enclaves in debug mode for post-mortem debugging, if
+                // asked to.
+                if run_args.debug_mode && history_size > 0 {
+                    let enclave_cid = enclave_manager
+                        .get_console_resources_enclave_cid()
+                        .map_err(|e| {
+                            e.set_action(
+                                "Failed to get console resources (enclave CID)".to_string(),
+                            )
+                        })?;
+                    match console::capture(enclave_cid, history_size) {
+                        Ok(history) => *console_history = Some(history),
+                        Err(e) => warn!("Console output is not captured: {:?}", e),
+                    }
+                }
                 (0, false)
             }
         }
@@ -412,31 +499,170 @@ fn handle_command(
 
         EnclaveProcessCommandType::ConnectionListenerStop => (0, true),
 
+        EnclaveProcessCommandType::Ping => {
+            conn_listener.record_ping();
+            connection.write_u64(MSG_ENCLAVE_PONG).map_err(|e| {
+                e.add_subaction("Failed to write pong".to_string())
+                    .set_action("Ping".to_string())
+            })?;
+            (0, false)
+        }
+
+        EnclaveProcessCommandType::GetRecentConsole => {
+            let bytes = connection.read::<u64>().map_err(|e| {
+                e.add_subaction("Failed to get the console output size".to_string())
+                    .set_action("Get Recent Console".to_string())
+            })?;
+
+            // Enclaves which aren't in debug mode have no console output.
+            let output = console_history.as_ref().map_or_else(Vec::new, |history| {
+                history.tail(usize::try_from(bytes).unwrap_or(usize::MAX))
+            });
+            connection.write_bytes(&output).map_err(|e| {
+                e.add_subaction("Failed to write console output to connection".to_string())
+                    .set_action("Get Recent Console".to_string())
+            })?;
+            (0, false)
+        }
+
+        EnclaveProcessCommandType::Session => {
+            conn_listener
+                .add_session_to_epoll(connection.take_stream()?)
+                .map_err(|e| {
+                    e.add_subaction("Failed to add session to epoll".to_string())
+                        .set_action("Session".to_string())
+                })?;
+            (0, false)
+        }
+
         EnclaveProcessCommandType::NotPermitted => (libc::EACCES, false),
     })
 }
 
+/// Serve the next framed command of a session, returning whether the event loop must be exited.
+/// The session is closed when the CLI closes it or sends anything other than a valid command,
+/// and is otherwise added back to `epoll` once the command has been replied to.
+fn serve_session_command(
+    connection: &Connection,
+    enclave_proc: &mut EnclaveProcess,
+    terminate_thread: &Option<JoinHandle<()>>,
+    describe_thread: &mut DescribeThread,
+) -> NitroCliResult<bool> {
+    let (cmd, permitted) = match connection.read_framed_command() {
+        Ok(value) => value,
+        Err(e) => {
+            debug!("Closing session: {:?}", e.subactions);
+            return Ok(false);
+        }
+    };
+    info!("Received framed command: {:?}", cmd);
+
+    let mut done = false;
+    let result = match &cmd {
+        _ if !permitted => Err(new_nitro_cli_failure!(
+            &format!("The requester is not allowed to perform {:?}", cmd),
+            NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+        )),
+        Command::Describe(args) => {
+            fetch_describe_result(describe_thread, &mut enclave_proc.enclave_manager)
+                .and_then(|_| {
+                    get_enclave_describe_info(&enclave_proc.enclave_manager, args.metadata)
+                })
+                .map(|info| Response::Describe(Box::new(info)))
+        }
+        Command::Terminate if terminate_thread.is_some() => Err(new_nitro_cli_failure!(
+            "The enclave is already being terminated",
+            NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+        )),
+        Command::Terminate => {
+            // As with the one-shot command, the enclave process exits even if termination fails.
+            done = true;
+            let enclave_manager = &mut enclave_proc.enclave_manager;
+            let enclave_name = Some(enclave_manager.enclave_name.clone());
+            let enclave_id = enclave_manager.enclave_id.clone();
+            terminate_enclaves(enclave_manager, None).map(|_| {
+                Response::Terminate(EnclaveTerminateInfo::new(enclave_name, enclave_id, true))
+            })
+        }
+        Command::Ping => {
+            enclave_proc.conn_listener.record_ping();
+            Ok(Response::Pong)
+        }
+        Command::GetConsole | Command::GetRecentConsole(_) => {
+            let bytes = match cmd {
+                Command::GetRecentConsole(bytes) => usize::try_from(bytes).unwrap_or(usize::MAX),
+                _ => usize::MAX,
+            };
+
+            // Enclaves which aren't in debug mode have no console output.
+            let output = enclave_proc
+                .console_history
+                .as_ref()
+                .map_or_else(Vec::new, |history| history.tail(bytes));
+            Ok(Response::Console(output))
+        }
+    };
+
+    let response = result.unwrap_or_else(|e| {
+        let e = e.add_subaction(format!("Failed to execute framed command `{:?}`", cmd));
+        let msg = construct_error_message(&e);
+        warn!("{}", msg);
+        Response::Error(msg)
+    });
+
+    // As with the one-shot commands, clean up before replying.
+    if done {
+        enclave_proc.shutdown()?;
+    }
+
+    match connection.write_response(&response) {
+        Ok(()) if !done => enclave_proc
+            .conn_listener
+            .add_session_to_epoll(connection.take_stream()?)
+            .map_err(|e| e.add_subaction("Failed to add session back to epoll".to_string()))?,
+        Ok(()) => (),
+        Err(e) => warn!("Closing session after failing to reply: {:?}", e.subactions),
+    }
+
+    Ok(done)
+}
+
 /// The main event loop of the enclave process.
 fn process_event_loop(
     comm_stream: UnixStream,
     logger: &EnclaveProcLogWriter,
 ) -> NitroCliResult<()> {
-    let mut conn_listener = ConnectionListener::new()?;
-    let mut enclave_manager = EnclaveManager::default();
-    let mut terminate_thread: Option<std::thread::JoinHandle<()>> = None;
-    let mut describe_thread: DescribeThread = None;
-    let mut done = false;
-    let mut ret_value = Ok(());
+    let mut enclave_proc = EnclaveProcess {
+        conn_listener: ConnectionListener::new()?,
+        enclave_manager: EnclaveManager::default(),
+        console_history: None,
+    };
 
     // Start the signal handler before spawning any other threads. This is done since the
     // handler will mask all relevant signals from the current thread and this setting will
     // be automatically inherited by all threads spawned from this point on; we want this
     // because only the dedicated thread spawned by the handler should listen for signals.
-    enclave_proc_configure_signal_handler(&conn_listener)
+    enclave_proc_configure_signal_handler(&enclave_proc.conn_listener)
         .map_err(|e| e.add_subaction("Failed to configure signal handler".to_string()))?;
 
+    serve_connections(&mut enclave_proc, comm_stream, logger)
+}
+
+/// Serve the CLI communication channel and every connection which follows it, until a
+/// command, a signal or an enclave hang-up ends the event loop.
+fn serve_connections(
+    enclave_proc: &mut EnclaveProcess,
+    comm_stream: UnixStream,
+    logger: &EnclaveProcLogWriter,
+) -> NitroCliResult<()> {
+    let mut terminate_thread: Option<std::thread::JoinHandle<()>> = None;
+    let mut describe_thread: DescribeThread = None;
+    let mut done = false;
+    let mut ret_value = Ok(());
+
     // Add the CLI communication channel to epoll.
-    conn_listener
+    enclave_proc
+        .conn_listener
         .handle_new_connection(comm_stream)
         .map_err(|e| {
             e.add_subaction("Failed to add CLI communication channel to epoll".to_string())
@@ -444,8 +670,9 @@ fn process_event_loop(
 
     while !done {
         // We can get connections to CLI instances, to the enclave or to ourselves.
-        let connection =
-            conn_listener.get_next_connection(enclave_manager.get_enclave_descriptor().ok())?;
+        let connection = enclave_proc
+            .conn_listener
+            .get_next_connection(enclave_proc.enclave_manager.get_enclave_descriptor().ok())?;
 
         // If this is an enclave event, handle it.
         match try_handle_enclave_event(&connection) {
@@ -459,6 +686,17 @@ fn process_event_loop(
             }
         }
 
+        // Sessions carry framed commands instead of one-shot ones.
+        if connection.is_session() {
+            done = serve_session_command(
+                &connection,
+                enclave_proc,
+                &terminate_thread,
+                &mut describe_thread,
+            )?;
+            continue;
+        }
+
         // At this point we have a connection that is not coming from an enclave.
         // Read the command that should be executed.
         let cmd = match connection.read_command() {
@@ -481,10 +719,11 @@ fn process_event_loop(
             cmd,
             logger,
             &connection,
-            &mut conn_listener,
-            &mut enclave_manager,
+            &mut enclave_proc.conn_listener,
+            &mut enclave_proc.enclave_manager,
             &mut terminate_thread,
             &mut describe_thread,
+            &mut enclave_proc.console_history,
         );
 
         // Obtain the status code and whether the event loop must be exited.
@@ -506,9 +745,6 @@ fn process_event_loop(
         // This is done to avoid race conditions where the enclave process has not yet removed the
         // socket and another CLI issues a command on that very-soon-to-be-removed socket.
         if done {
-            // Stop the connection listener.
-            conn_listener.stop()?;
-
             // Wait for the termination thread, if any.
             if terminate_thread.is_some() {
                 terminate_thread.take().unwrap().join().map_err(|e| {
@@ -518,6 +754,9 @@ fn process_event_loop(
                     )
                 })?;
             };
+
+            // Release the enclave, if still held, and stop the connection listener.
+            enclave_proc.shutdown()?;
         }
 
         // Only the commands coming from the CLI must be replied to with the status code.
@@ -540,11 +779,16 @@ fn process_event_loop(
 
     info!("Enclave process {} exited event loop.", process::id());
 
-    ret_value
+    // The loop may also have been left without a command asking for it, e.g. on a hang-up.
+    let shutdown = enclave_proc.shutdown();
+    ret_value.and(shutdown)
 }
 
-/// Create the enclave process.
-fn create_enclave_process(logger: &EnclaveProcLogWriter) -> NitroCliResult<()> {
+/// Create the enclave process, which logs to `log_target` once detached.
+fn create_enclave_process(
+    logger: &EnclaveProcLogWriter,
+    log_target: LogTarget,
+) -> NitroCliResult<()> {
     // To get a detached process, we first:
     // (1) Temporarily ignore specific signals (SIGHUP).
     // (2) Daemonize the current process.
@@ -568,6 +812,9 @@ fn create_enclave_process(logger: &EnclaveProcLogWriter) -> NitroCliResult<()> {
     logger
         .update_logger_id(format!("enc-xxxxxxx:{}", std::process::id()).as_str())
         .map_err(|e| e.add_subaction("Failed to update logger id".to_string()))?;
+    logger
+        .set_target(log_target)
+        .map_err(|e| e.add_subaction("Failed to set the log target".to_string()))?;
     info!("Enclave process PID: {}", process::id());
 
     // We must wait until we're 100% orphaned. That is, our parent must
@@ -584,19 +831,454 @@ fn create_enclave_process(logger: &EnclaveProcLogWriter) -> NitroCliResult<()> {
     Ok(())
 }
 
+/// List the IDs of all enclaves whose enclave processes respond to a ping. Sockets which no
+/// enclave process listens on anymore are removed from the sockets directory.
+pub fn discover_enclaves() -> NitroCliResult<Vec<String>> {
+    discover_enclave_slots(&get_sockets_dir_path())?
+        .into_iter()
+        .map(generate_enclave_id)
+        .collect()
+}
+
+/// Ping the enclave process behind each socket in the given directory, returning the sorted
+/// slot IDs of those which replied.
+fn discover_enclave_slots(sockets_dir: &Path) -> NitroCliResult<Vec<u64>> {
+    let entries = fs::read_dir(sockets_dir).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to access sockets directory: {:?}", e),
+            NitroCliErrorEnum::ReadFromDiskFailure
+        )
+    })?;
+    let mut slot_ids = Vec::new();
+
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let slot_id = match socket_slot_id(&path) {
+            Some(slot_id) => slot_id,
+            None => continue,
+        };
+
+        match UnixStream::connect(&path) {
+            Ok(mut comm) => {
+                // A process which accepts the connection but never replies must not stall discovery.
+                let timeout = Duration::from_millis(ENCLAVE_PROC_WAIT_TIMEOUT_MSEC as u64);
+                let _ = comm.set_read_timeout(Some(timeout));
+                match handshake(&mut comm).and_then(|_| enclave_proc_ping_stream(&mut comm)) {
+                    Ok(()) => slot_ids.push(slot_id),
+                    Err(e) => warn!("Enclave process at {:?} did not reply: {:?}", path, e),
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                match EnclaveProcSock::remove_if_stale(&path) {
+                    Ok(true) => info!("Deleted stale socket: {:?}", path),
+                    Ok(false) => (),
+                    Err(e) => warn!("Failed to delete stale socket {:?}: {:?}", path, e),
+                }
+            }
+            // Don't touch sockets we can't connect to for other reasons, such as permissions.
+            Err(e) => info!("Connection to {:?} failed: {}", path, e),
+        }
+    }
+
+    slot_ids.sort_unstable();
+    Ok(slot_ids)
+}
+
+/// Get the slot ID from the path of an enclave process socket, which is named `<slot ID>.sock`.
+pub(crate) fn socket_slot_id(path: &Path) -> Option<u64> {
+    if path.extension()? != "sock" {
+        return None;
+    }
+
+    let slot_id = path.file_stem()?.to_str()?;
+    if slot_id.is_empty() || !slot_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u64::from_str_radix(slot_id, 16).ok()
+}
+
+/// Terminate the enclave owned by the enclave process with the given PID and wait for that
+/// process to exit. The enclave is found through the socket which the process listens on, in
+/// the sockets directory.
+pub fn terminate_by_pid(pid: u32) -> NitroCliResult<()> {
+    let slot_id = find_enclave_proc_slot(pid, &get_sockets_dir_path(), Path::new(PROC_DIR))?;
+    let enclave_id = generate_enclave_id(slot_id)?;
+
+    EnclaveProcClient::connect_by_id(&enclave_id)
+        .and_then(|mut client| client.terminate())
+        .map_err(|e| e.add_subaction(format!("Failed to terminate enclave {}", enclave_id)))?;
+
+    wait_for_process_exit(pid, ENCLAVE_PROC_EXIT_TIMEOUT)
+}
+
+/// Find the slot ID of the enclave process socket in the given directory which the process with
+/// the given PID listens on. The socket is matched by inode between the process' descriptors and
+/// the Unix sockets of the system, all of them read from the given procfs directory.
+fn find_enclave_proc_slot(pid: u32, sockets_dir: &Path, proc_dir: &Path) -> NitroCliResult<u64> {
+    for path in unix_socket_paths_of(pid, proc_dir)? {
+        if path.parent() == Some(sockets_dir) {
+            if let Some(slot_id) = socket_slot_id(&path) {
+                return Ok(slot_id);
+            }
+        }
+    }
+
+    Err(new_nitro_cli_failure!(
+        &format!(
+            "Process {} is not an enclave process: it doesn't listen on any socket in {:?}",
+            pid, sockets_dir
+        ),
+        NitroCliErrorEnum::NotAnEnclaveProcess
+    )
+    .add_info(vec![&pid.to_string()]))
+}
+
+/// Get the file-system paths of the Unix sockets which the process with the given PID holds a
+/// descriptor of, matching them by inode between the process' descriptors and the Unix sockets
+/// of the system, all of them read from the given procfs directory.
+pub(crate) fn unix_socket_paths_of(pid: u32, proc_dir: &Path) -> NitroCliResult<Vec<PathBuf>> {
+    let fd_dir = proc_dir.join(pid.to_string()).join("fd");
+    let inodes: HashSet<u64> = fs::read_dir(&fd_dir)
+        .map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Process {} is not an enclave process: failed to list {:?}: {}",
+                    pid, fd_dir, e
+                ),
+                NitroCliErrorEnum::NotAnEnclaveProcess
+            )
+            .add_info(vec![&pid.to_string()])
+        })?
+        .filter_map(|entry| fs::read_link(entry.ok()?.path()).ok())
+        .filter_map(|target| {
+            target
+                .to_str()?
+                .strip_prefix("socket:[")?
+                .strip_suffix(']')?
+                .parse()
+                .ok()
+        })
+        .collect();
+
+    let unix_sockets_path = proc_dir.join("net").join("unix");
+    let unix_sockets = fs::read_to_string(&unix_sockets_path).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read {:?}: {:?}", unix_sockets_path, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![&unix_sockets_path.to_string_lossy(), "Read"])
+    })?;
+
+    // Each socket is described by `Num RefCount Protocol Flags Type St Inode [Path]`.
+    Ok(unix_sockets
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || !inodes.contains(&fields[6].parse().ok()?) {
+                return None;
+            }
+            Some(PathBuf::from(fields[7..].join(" ")))
+        })
+        .collect())
+}
+
+/// Wait for the process with the given PID to exit.
+pub(crate) fn wait_for_process_exit(pid: u32, timeout: Duration) -> NitroCliResult<()> {
+    let start = Instant::now();
+
+    while kill(Pid::from_raw(pid as i32), None) != Err(Errno::ESRCH) {
+        if start.elapsed() >= timeout {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Enclave process {} did not exit within {:?} of its enclave being terminated",
+                    pid, timeout
+                ),
+                NitroCliErrorEnum::EnclaveEventWaitError
+            ));
+        }
+        thread::sleep(ENCLAVE_PROC_EXIT_POLL_INTERVAL);
+    }
+
+    Ok(())
+}
+
 /// Launch the enclave process.
 ///
 /// * `comm_fd` - A descriptor used for initial communication with the parent Nitro CLI instance.
 /// * `logger` - The current log writer, whose ID gets updated when an enclave is launched.
-pub fn enclave_process_run(comm_stream: UnixStream, logger: &EnclaveProcLogWriter) {
-    create_enclave_process(logger)
+/// * `log_target` - Where the enclave process logs to, such as a dedicated file or journald.
+pub fn enclave_process_run(
+    comm_stream: UnixStream,
+    logger: &EnclaveProcLogWriter,
+    log_target: LogTarget,
+) {
+    create_enclave_process(logger, log_target)
         .map_err(|e| e.set_action("Run Enclave".to_string()))
         .ok_or_exit_with_errno(None);
     let res = process_event_loop(comm_stream, logger);
     if let Err(mut error_info) = res {
         error_info = error_info.set_action("Run Enclave".to_string());
         notify_error(construct_error_message(&error_info).as_str());
-        process::exit(error_info.error_code as i32);
+        process::exit(error_info.error_code.exit_code());
     }
     process::exit(0);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::protocol::{read_response, write_command};
+    use crate::common::signal_handler::SIGNAL_HANDLER_THREAD_NAME;
+    use crate::common::{get_socket_path, receive_from_stream, write_u64_le, EnclaveProcessReply};
+    use nix::sys::signal::SIGTERM;
+    use std::os::unix::net::UnixListener;
+
+    /// Tests that only valid socket names yield a slot ID.
+    #[test]
+    fn test_socket_slot_id() {
+        assert_eq!(socket_slot_id(Path::new("/run/1f.sock")), Some(0x1f));
+        assert_eq!(socket_slot_id(Path::new("/run/1f.txt")), None);
+        assert_eq!(socket_slot_id(Path::new("/run/.sock")), None);
+        assert_eq!(socket_slot_id(Path::new("/run/+1.sock")), None);
+        assert_eq!(socket_slot_id(Path::new("/run/xyz.sock")), None);
+        assert_eq!(
+            socket_slot_id(Path::new("/run/10000000000000000.sock")),
+            None
+        );
+    }
+
+    /// Tests that a process is mapped to the enclave process socket which it listens on.
+    #[test]
+    fn test_find_enclave_proc_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid = process::id();
+        let proc_dir = Path::new(PROC_DIR);
+
+        let err = find_enclave_proc_slot(pid, dir.path(), proc_dir).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NotAnEnclaveProcess);
+        assert_eq!(err.additional_info, vec![pid.to_string()]);
+
+        let _other = UnixListener::bind(dir.path().join("not-a-slot.sock")).unwrap();
+        let _listener = UnixListener::bind(dir.path().join("2a.sock")).unwrap();
+        assert_eq!(
+            find_enclave_proc_slot(pid, dir.path(), proc_dir).unwrap(),
+            0x2a
+        );
+
+        // Another process doesn't own the socket.
+        let err = find_enclave_proc_slot(1, dir.path(), proc_dir).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NotAnEnclaveProcess);
+    }
+
+    /// Tests that waiting for a process which keeps running times out.
+    #[test]
+    fn test_wait_for_process_exit() {
+        let err = wait_for_process_exit(process::id(), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EnclaveEventWaitError);
+        assert!(wait_for_process_exit(u32::MAX >> 1, Duration::from_millis(100)).is_ok());
+    }
+
+    /// Tests that responsive enclave processes are discovered and stale sockets are removed.
+    #[test]
+    fn test_discover_enclave_slots() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let live = UnixListener::bind(dir.path().join("3.sock")).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = live.accept().unwrap();
+            handshake(&mut stream).unwrap();
+            for _ in 0..2 {
+                let cmd = receive_from_stream::<EnclaveProcessCommandType>(&mut stream).unwrap();
+                assert_eq!(cmd, EnclaveProcessCommandType::Ping);
+            }
+            write_u64_le(&mut stream, MSG_ENCLAVE_PONG).unwrap();
+        });
+
+        // The listener is dropped, so its socket file is left behind without an owner.
+        drop(UnixListener::bind(dir.path().join("5.sock")).unwrap());
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        assert_eq!(discover_enclave_slots(dir.path()).unwrap(), vec![3]);
+        server.join().unwrap();
+
+        assert!(dir.path().join("3.sock").exists());
+        assert!(!dir.path().join("5.sock").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    /// Find the thread ID of the signal handler thread of the current process, which names
+    /// itself only once it has started running.
+    fn signal_handler_tid() -> libc::pid_t {
+        for _ in 0..100 {
+            let tid = fs::read_dir("/proc/self/task")
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .find(|entry| {
+                    fs::read_to_string(entry.path().join("comm"))
+                        .map_or(false, |comm| comm.trim() == SIGNAL_HANDLER_THREAD_NAME)
+                })
+                .and_then(|entry| entry.file_name().to_str()?.parse().ok());
+            if let Some(tid) = tid {
+                return tid;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("The signal handler thread has not been found");
+    }
+
+    /// Tests that shutting down releases the enclave before joining the listener thread, which
+    /// removes the socket last.
+    #[test]
+    fn test_enclave_process_shutdown() {
+        let enclave_id = "i-0000000000000000-enc0000000000000038";
+        let (read_fd, write_fd) = nix::unistd::pipe().unwrap();
+        let mut enclave_proc = EnclaveProcess {
+            conn_listener: ConnectionListener::new().unwrap(),
+            enclave_manager: EnclaveManager::with_descriptor(write_fd, 16),
+            console_history: None,
+        };
+        enclave_proc.conn_listener.start(enclave_id).unwrap();
+        let socket_path = get_socket_path(enclave_id).unwrap();
+
+        // A connection which sends nothing keeps the listener thread busy, so it can't be joined.
+        let client = UnixStream::connect(&socket_path).unwrap();
+        let shutdown = thread::spawn(move || enclave_proc.shutdown());
+
+        // The enclave descriptor gets closed while the listener thread is still running.
+        let mut fds = [libc::pollfd {
+            fd: read_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        assert_eq!(unsafe { libc::poll(fds.as_mut_ptr(), 1, 5000) }, 1);
+        assert_ne!(fds[0].revents & libc::POLLHUP, 0);
+        assert!(!shutdown.is_finished());
+        assert!(socket_path.exists());
+
+        drop(client);
+        shutdown.join().unwrap().unwrap();
+        assert!(!socket_path.exists());
+        nix::unistd::close(read_fd).unwrap();
+    }
+
+    /// Tests that the event loop serves the command which the CLI sends over the spawn channel,
+    /// after the same handshake as `enclave_proc_spawn()` performs.
+    #[test]
+    fn test_serve_spawn_channel() {
+        let (mut cli_socket, enclave_proc_socket) = UnixStream::pair().unwrap();
+        let event_loop = thread::spawn(move || {
+            let logger = EnclaveProcLogWriter::new().unwrap();
+            let mut enclave_proc = EnclaveProcess {
+                conn_listener: ConnectionListener::new().unwrap(),
+                enclave_manager: EnclaveManager::default(),
+                console_history: None,
+            };
+            serve_connections(&mut enclave_proc, enclave_proc_socket, &logger)
+        });
+
+        // The arguments can't be parsed, so the command fails once it has been received.
+        handshake(&mut cli_socket).unwrap();
+        enclave_proc_command_send_single(
+            EnclaveProcessCommandType::Run,
+            Some(&EmptyArgs {}),
+            &mut cli_socket,
+        )
+        .unwrap();
+
+        let status = loop {
+            match receive_from_stream::<EnclaveProcessReply>(&mut cli_socket).unwrap() {
+                EnclaveProcessReply::Status(status) => break status,
+                EnclaveProcessReply::StdErrMessage(msg) => {
+                    assert!(!msg.contains("ProtocolVersionMismatch"), "{}", msg)
+                }
+                EnclaveProcessReply::StdOutMessage(_) => (),
+            }
+        };
+        assert_eq!(status, libc::EINVAL);
+        event_loop.join().unwrap().unwrap();
+    }
+
+    /// Tests that the event loop keeps serving framed commands on a session until one of them
+    /// ends it.
+    #[test]
+    fn test_serve_session() {
+        let (mut cli_socket, enclave_proc_socket) = UnixStream::pair().unwrap();
+        let event_loop = thread::spawn(move || {
+            let logger = EnclaveProcLogWriter::new().unwrap();
+            let mut enclave_proc = EnclaveProcess {
+                conn_listener: ConnectionListener::new().unwrap(),
+                enclave_manager: EnclaveManager::default(),
+                console_history: None,
+            };
+            serve_connections(&mut enclave_proc, enclave_proc_socket, &logger)
+        });
+
+        handshake(&mut cli_socket).unwrap();
+        enclave_proc_command_send_single::<EmptyArgs>(
+            EnclaveProcessCommandType::Session,
+            None,
+            &mut cli_socket,
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            write_command(&mut cli_socket, &Command::Ping).unwrap();
+            assert!(matches!(
+                read_response(&mut cli_socket).unwrap(),
+                Response::Pong
+            ));
+        }
+        write_command(&mut cli_socket, &Command::GetRecentConsole(16)).unwrap();
+        match read_response(&mut cli_socket).unwrap() {
+            Response::Console(output) => assert!(output.is_empty()),
+            _ => panic!("Unexpected response"),
+        }
+
+        // Termination ends the event loop, even without an enclave to terminate.
+        write_command(&mut cli_socket, &Command::Terminate).unwrap();
+        assert!(matches!(
+            read_response(&mut cli_socket).unwrap(),
+            Response::Terminate(_) | Response::Error(_)
+        ));
+        event_loop.join().unwrap().unwrap();
+    }
+
+    /// Tests that a SIGTERM stops the event loop, after which the socket file gets removed.
+    #[test]
+    fn test_sigterm_teardown() {
+        let enclave_id = "i-0000000000000000-enc0000000000000037";
+        let mut conn_listener = ConnectionListener::new().unwrap();
+        enclave_proc_configure_signal_handler(&conn_listener).unwrap();
+        conn_listener.start(enclave_id).unwrap();
+        let socket_path = get_socket_path(enclave_id).unwrap();
+        assert!(socket_path.exists());
+
+        // The signal is sent to the handler thread only, since the test harness' threads
+        // don't mask it.
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                process::id() as libc::pid_t,
+                signal_handler_tid(),
+                SIGTERM as libc::c_int,
+            )
+        };
+        assert_eq!(rc, 0);
+
+        let connection = conn_listener.get_next_connection(None).unwrap();
+        assert_eq!(
+            connection.read_command().unwrap(),
+            EnclaveProcessCommandType::ConnectionListenerStop
+        );
+
+        conn_listener.stop().unwrap();
+        drop(conn_listener);
+        assert!(!socket_path.exists());
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright 2020-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use std::os::unix::io::RawFd;
+
+use crate::enclave_proc::resource_manager::NeIoctl;
+
+#[cfg(feature = "mock")]
+pub use mock::{set_ne_ioctl_backend, MockBackend};
+
+/// A backend which carries out the ioctls issued to the NE driver.
+pub trait NeIoctlBackend {
+    /// Issue an ioctl on the given descriptor with a pointer to its argument, returning either
+    /// the ioctl's return code or the error number with which it has failed.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must point to a valid instance of the argument type of `cmd`.
+    unsafe fn ioctl(&self, fd: RawFd, cmd: NeIoctl, arg: *mut libc::c_void) -> Result<i32, i32>;
+}
+
+/// The backend which issues the ioctls to the NE driver.
+pub struct DriverBackend;
+
+impl NeIoctlBackend for DriverBackend {
+    unsafe fn ioctl(&self, fd: RawFd, cmd: NeIoctl, arg: *mut libc::c_void) -> Result<i32, i32> {
+        let rc = libc::ioctl(fd, cmd.request_code() as _, arg);
+        if rc >= 0 {
+            return Ok(rc);
+        }
+
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    }
+}
+
+/// Issue an ioctl through the backend of the current thread, which is the NE driver unless
+/// another backend has been set with `set_ne_ioctl_backend()`.
+///
+/// # Safety
+///
+/// `arg` must point to a valid instance of the argument type of `cmd`.
+#[cfg(feature = "mock")]
+pub(crate) unsafe fn issue_ne_ioctl(
+    fd: RawFd,
+    cmd: NeIoctl,
+    arg: *mut libc::c_void,
+) -> Result<i32, i32> {
+    mock::current_backend().ioctl(fd, cmd, arg)
+}
+
+/// Issue an ioctl to the NE driver.
+///
+/// # Safety
+///
+/// `arg` must point to a valid instance of the argument type of `cmd`.
+#[cfg(not(feature = "mock"))]
+pub(crate) unsafe fn issue_ne_ioctl(
+    fd: RawFd,
+    cmd: NeIoctl,
+    arg: *mut libc::c_void,
+) -> Result<i32, i32> {
+    DriverBackend.ioctl(fd, cmd, arg)
+}
+
+#[cfg(feature = "mock")]
+mod mock {
+    use driver_bindings::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::os::unix::io::RawFd;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    use super::{DriverBackend, NeIoctlBackend};
+    use crate::enclave_proc::resource_manager::{
+        EnclaveStartInfo, NeAddVcpu, NeCreateVm, NeGetImageLoadInfo, NeIoctl, NeIoctlCommand,
+        NeSetUserMemoryRegion, NeStartEnclave, UserMemoryRegion, NE_DEFAULT_MEMORY_REGION,
+        NE_EIF_IMAGE, NE_ENCLAVE_DEBUG_MODE,
+    };
+    use crate::enclave_proc::utils::MiB;
+
+    /// The argument type of an NE ioctl command.
+    type Arg<C> = <C as NeIoctlCommand>::Arg;
+
+    /// The alignment required for the address and the size of enclave memory regions.
+    const MOCK_MEM_REGION_ALIGNMENT: u64 = 2 * MiB;
+
+    /// The maximum number of memory regions of a mock enclave.
+    const MOCK_MAX_MEM_REGIONS: usize = 256;
+
+    /// The minimum memory size with which a mock enclave may start.
+    const MOCK_MIN_ENCLAVE_MEM_SIZE: u64 = 64 * MiB;
+
+    /// The offset in enclave memory at which mock enclaves expect their image.
+    const MOCK_EIF_LOAD_OFFSET: u64 = 8 * MiB;
+
+    /// The CID of the first started mock enclave.
+    const MOCK_FIRST_ENCLAVE_CID: u64 = 16;
+
+    thread_local! {
+        /// The backend of the current thread.
+        static NE_IOCTL_BACKEND: RefCell<Rc<dyn NeIoctlBackend>> =
+            RefCell::new(Rc::new(DriverBackend));
+    }
+
+    /// Set the backend through which the NE ioctls of the current thread are issued, returning
+    /// the previous one. The backend is per thread so that tests running in parallel can each
+    /// use their own.
+    pub fn set_ne_ioctl_backend(backend: Rc<dyn NeIoctlBackend>) -> Rc<dyn NeIoctlBackend> {
+        NE_IOCTL_BACKEND.with(|current| current.replace(backend))
+    }
+
+    /// Get the backend of the current thread.
+    pub(super) fn current_backend() -> Rc<dyn NeIoctlBackend> {
+        NE_IOCTL_BACKEND.with(|current| current.borrow().clone())
+    }
+
+    /// The resources of a mock enclave.
+    #[derive(Default)]
+    struct MockEnclave {
+        /// The inode of the enclave descriptor, which tells it apart from a descriptor which
+        /// has been opened with the same number once the enclave's has been closed.
+        inode: u64,
+        /// The address and size of each memory region.
+        mem_regions: Vec<(u64, u64)>,
+        /// The IDs of the vCPUs.
+        vcpus: Vec<u32>,
+        /// The CID, once the enclave has started.
+        cid: Option<u64>,
+    }
+
+    /// The state of the mock driver.
+    #[derive(Default)]
+    struct MockState {
+        /// The slot UID of the last created enclave.
+        last_slot_uid: u64,
+        /// The CID of the last started enclave, if any.
+        last_cid: Option<u64>,
+        /// The enclaves, by descriptor.
+        enclaves: HashMap<RawFd, MockEnclave>,
+        /// The number of upcoming ioctls which fail as if interrupted by a signal.
+        pending_interrupts: u32,
+    }
+
+    impl MockState {
+        /// Release the resources of the enclaves whose descriptors have been closed.
+        fn release_closed(&mut self) {
+            self.enclaves
+                .retain(|&fd, enclave| descriptor_inode(fd) == Some(enclave.inode));
+        }
+    }
+
+    /// Get the inode of the file which a descriptor refers to, if the descriptor is open.
+    fn descriptor_inode(fd: RawFd) -> Option<u64> {
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+        if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+            return None;
+        }
+
+        Some(unsafe { stat.assume_init() }.st_ino)
+    }
+
+    /// A backend which simulates the NE driver, including the errors with which it rejects
+    /// invalid requests, without needing an NE-capable instance.
+    ///
+    /// Enclave descriptors are memfds, so they can be closed like real ones. An enclave releases
+    /// its resources once its descriptor has been closed, on the next ioctl.
+    ///
+    /// On x86_64 an enclave must have an even number of vCPUs, as if the CPU pool were made up
+    /// of cores with two threads each.
+    pub struct MockBackend {
+        /// The IDs of the CPUs in the NE CPU pool.
+        cpu_pool: Vec<u32>,
+        /// The state of the mock driver.
+        state: Mutex<MockState>,
+    }
+
+    impl MockBackend {
+        /// Create a mock driver with the given CPU pool.
+        pub fn new(cpu_pool: Vec<u32>) -> Self {
+            MockBackend {
+                cpu_pool,
+                state: Mutex::new(MockState::default()),
+            }
+        }
+
+        /// Make the next `count` ioctls fail with `EINTR`, as if a signal had interrupted them,
+        /// without carrying them out.
+        pub fn interrupt_next(&self, count: u32) {
+            if let Ok(mut state) = self.state.lock() {
+                state.pending_interrupts = count;
+            }
+        }
+
+        /// Create an enclave slot.
+        fn create_vm(state: &mut MockState, slot_uid: &mut u64) -> Result<i32, i32> {
+            let enc_fd = unsafe {
+                libc::memfd_create(b"ne-mock-enclave\0".as_ptr() as *const _, libc::MFD_CLOEXEC)
+            };
+            if enc_fd < 0 {
+                return Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+            }
+            let inode = descriptor_inode(enc_fd).ok_or(libc::EBADF)?;
+
+            state.last_slot_uid += 1;
+            *slot_uid = state.last_slot_uid;
+            state.enclaves.insert(
+                enc_fd,
+                MockEnclave {
+                    inode,
+                    ..Default::default()
+                },
+            );
+
+            Ok(enc_fd)
+        }
+
+        /// Add a memory region to an enclave.
+        fn set_user_memory_region(
+            enclave: &mut MockEnclave,
+            region: &UserMemoryRegion,
+        ) -> Result<i32, i32> {
+            let start = region.userspace_addr;
+            let end = start.saturating_add(region.memory_size);
+
+            if enclave.cid.is_some() {
+                return Err(NE_ERR_NOT_IN_INIT_STATE as i32);
+            }
+            if region.flags != NE_DEFAULT_MEMORY_REGION {
+                return Err(NE_ERR_INVALID_FLAG_VALUE as i32);
+            }
+            if region.memory_size == 0 || region.memory_size % MOCK_MEM_REGION_ALIGNMENT != 0 {
+                return Err(NE_ERR_INVALID_MEM_REGION_SIZE as i32);
+            }
+            if start == 0 || end == u64::MAX {
+                return Err(NE_ERR_INVALID_MEM_REGION_ADDR as i32);
+            }
+            if start % MOCK_MEM_REGION_ALIGNMENT != 0 {
+                return Err(NE_ERR_UNALIGNED_MEM_REGION_ADDR as i32);
+            }
+            if enclave
+                .mem_regions
+                .iter()
+                .any(|&(addr, size)| start < addr + size && addr < end)
+            {
+                return Err(NE_ERR_MEM_REGION_ALREADY_USED as i32);
+            }
+            if enclave.mem_regions.len() >= MOCK_MAX_MEM_REGIONS {
+                return Err(NE_ERR_MEM_MAX_REGIONS as i32);
+            }
+
+            enclave.mem_regions.push((start, region.memory_size));
+            Ok(0)
+        }
+
+        /// Add a vCPU to an enclave, picking a free one from the CPU pool if its ID is 0.
+        fn add_vcpu(&self, state: &mut MockState, fd: RawFd, cpu_id: &mut u32) -> Result<i32, i32> {
+            let used = |cpu_id: u32| {
+                state
+                    .enclaves
+                    .values()
+                    .any(|enclave| enclave.vcpus.contains(&cpu_id))
+            };
+
+            if *cpu_id == 0 {
+                *cpu_id = *self
+                    .cpu_pool
+                    .iter()
+                    .find(|&&cpu_id| !used(cpu_id))
+                    .ok_or(NE_ERR_NO_CPUS_AVAIL_IN_POOL as i32)?;
+            } else if !self.cpu_pool.contains(cpu_id) {
+                return Err(NE_ERR_VCPU_NOT_IN_CPU_POOL as i32);
+            } else if used(*cpu_id) {
+                return Err(NE_ERR_VCPU_ALREADY_USED as i32);
+            }
+
+            let enclave = state.enclaves.get_mut(&fd).ok_or(libc::ENOTTY)?;
+            if enclave.cid.is_some() {
+                return Err(NE_ERR_NOT_IN_INIT_STATE as i32);
+            }
+            enclave.vcpus.push(*cpu_id);
+
+            Ok(0)
+        }
+
+        /// Start an enclave, assigning it a CID if none has been requested. A requested CID
+        /// which another enclave has been started with is rejected as the driver does.
+        fn start_enclave(
+            state: &mut MockState,
+            fd: RawFd,
+            start_info: &mut EnclaveStartInfo,
+        ) -> Result<i32, i32> {
+            let cid_used = |cid: u64| state.enclaves.values().any(|e| e.cid == Some(cid));
+            let mut next_cid = state.last_cid.map_or(MOCK_FIRST_ENCLAVE_CID, |cid| cid + 1);
+            while cid_used(next_cid) {
+                next_cid += 1;
+            }
+            let requested_cid_used =
+                start_info.enclave_cid != 0 && cid_used(start_info.enclave_cid);
+            let enclave = state.enclaves.get_mut(&fd).ok_or(libc::ENOTTY)?;
+
+            if enclave.cid.is_some() {
+                return Err(NE_ERR_NOT_IN_INIT_STATE as i32);
+            }
+            if start_info.flags & !NE_ENCLAVE_DEBUG_MODE != 0 {
+                return Err(NE_ERR_INVALID_FLAG_VALUE as i32);
+            }
+            if enclave.mem_regions.is_empty() {
+                return Err(NE_ERR_NO_MEM_REGIONS_ADDED as i32);
+            }
+            if enclave.vcpus.is_empty() {
+                return Err(NE_ERR_NO_VCPUS_ADDED as i32);
+            }
+            if cfg!(target_arch = "x86_64") && enclave.vcpus.len() % 2 != 0 {
+                return Err(NE_ERR_FULL_CORES_NOT_USED as i32);
+            }
+            let mem_size: u64 = enclave.mem_regions.iter().map(|&(_, size)| size).sum();
+            if mem_size < MOCK_MIN_ENCLAVE_MEM_SIZE {
+                return Err(NE_ERR_ENCLAVE_MEM_MIN_SIZE as i32);
+            }
+            // Well-known CIDs and the CIDs which don't fit 32 bits are rejected.
+            if start_info.enclave_cid != 0
+                && (start_info.enclave_cid <= libc::VMADDR_CID_HOST as u64
+                    || start_info.enclave_cid >= u32::MAX as u64)
+            {
+                return Err(NE_ERR_INVALID_ENCLAVE_CID as i32);
+            }
+            if requested_cid_used {
+                return Err(libc::EADDRINUSE);
+            }
+
+            if start_info.enclave_cid == 0 {
+                start_info.enclave_cid = next_cid;
+                state.last_cid = Some(next_cid);
+            }
+            enclave.cid = Some(start_info.enclave_cid);
+
+            Ok(0)
+        }
+    }
+
+    impl NeIoctlBackend for MockBackend {
+        unsafe fn ioctl(
+            &self,
+            fd: RawFd,
+            cmd: NeIoctl,
+            arg: *mut libc::c_void,
+        ) -> Result<i32, i32> {
+            let mut state = self.state.lock().map_err(|_| libc::EIO)?;
+            if state.pending_interrupts > 0 {
+                state.pending_interrupts -= 1;
+                return Err(libc::EINTR);
+            }
+            state.release_closed();
+
+            // The argument types are the ones which `NeIoctlCommand` ties to each command, and
+            // which `ne_ioctl()` is therefore called with.
+            match cmd {
+                NeIoctl::CreateVm => {
+                    MockBackend::create_vm(&mut state, &mut *(arg as *mut Arg<NeCreateVm>))
+                }
+                NeIoctl::AddVcpu => {
+                    self.add_vcpu(&mut state, fd, &mut *(arg as *mut Arg<NeAddVcpu>))
+                }
+                NeIoctl::SetUserMemoryRegion => {
+                    let enclave = state.enclaves.get_mut(&fd).ok_or(libc::ENOTTY)?;
+                    MockBackend::set_user_memory_region(
+                        enclave,
+                        &*(arg as *const Arg<NeSetUserMemoryRegion>),
+                    )
+                }
+                NeIoctl::GetImageLoadInfo => {
+                    let load_info = &mut *(arg as *mut Arg<NeGetImageLoadInfo>);
+                    if !state.enclaves.contains_key(&fd) {
+                        return Err(libc::ENOTTY);
+                    }
+                    if load_info.flags != NE_EIF_IMAGE {
+                        return Err(NE_ERR_INVALID_FLAG_VALUE as i32);
+                    }
+                    load_info.memory_offset = MOCK_EIF_LOAD_OFFSET;
+                    Ok(0)
+                }
+                NeIoctl::StartEnclave => MockBackend::start_enclave(
+                    &mut state,
+                    fd,
+                    &mut *(arg as *mut Arg<NeStartEnclave>),
+                ),
+            }
+        }
+    }
+}
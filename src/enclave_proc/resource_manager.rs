@@ -9,31 +9,36 @@ use aws_nitro_enclaves_image_format::defs::EifIdentityInfo;
 use driver_bindings::*;
 use eif_loader::{enclave_ready, TIMEOUT_MINUTE_MS};
 use libc::c_int;
-use log::{debug, info};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
-use std::io::Error;
+use std::io::{BufReader, Error};
 use std::mem::size_of;
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::str;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant, SystemTime};
 use vsock::{VsockAddr, VsockListener};
 
 use crate::common::json_output::EnclaveBuildInfo;
+use crate::common::logger::set_log_enclave_id;
 use crate::common::{construct_error_message, notify_error};
 use crate::common::{
-    ExitGracefully, NitroCliErrorEnum, NitroCliFailure, NitroCliResult, ENCLAVE_READY_VSOCK_PORT,
-    VMADDR_CID_PARENT,
+    ExitGracefully, NitroCliError, NitroCliErrorEnum, NitroCliFailure, NitroCliResult,
+    ENCLAVE_READY_VSOCK_PORT, VMADDR_CID_PARENT,
 };
+use crate::eif::{copy_in_chunks, EifChecksum};
 use crate::enclave_proc::connection::Connection;
 use crate::enclave_proc::connection::{safe_conn_eprintln, safe_conn_println};
-use crate::enclave_proc::cpu_info::EnclaveCpuConfig;
-use crate::enclave_proc::utils::get_run_enclaves_info;
-use crate::enclave_proc::utils::{GiB, MiB};
-use crate::new_nitro_cli_failure;
+use crate::enclave_proc::cpu_info::{CpuInfo, EnclaveCpuConfig, POOL_FILENAME};
+use crate::enclave_proc::ne_backend::issue_ne_ioctl;
+use crate::enclave_proc::utils::{generate_enclave_id, get_run_enclaves_info};
+use crate::enclave_proc::utils::{GiB, KiB, MiB};
 use crate::utils::ceil_div;
+use crate::{enclave_log, new_nitro_cli_failure};
 
 /// CamelCase alias for the bindgen generated driver struct (ne_enclave_start_info).
 pub type EnclaveStartInfo = ne_enclave_start_info;
@@ -50,14 +55,17 @@ type UnpackedHandle = (u64, u64, u64, Vec<u32>, u64, u64, EnclaveState);
 /// The bit indicating if an enclave has been launched in debug mode.
 pub const NE_ENCLAVE_DEBUG_MODE: u64 = 0x1;
 
+/// How many times in a row an NE ioctl interrupted by a signal is retried.
+pub const NE_IOCTL_MAX_EINTR_RETRIES: u32 = 16;
+
 /// Constant number used for computing the lower memory limit.
 const ENCLAVE_MEMORY_EIF_SIZE_RATIO: u64 = 4;
 
 /// Enclave Image Format (EIF) flag.
-const NE_EIF_IMAGE: u64 = 0x01;
+pub const NE_EIF_IMAGE: u64 = 0x01;
 
 /// Flag indicating a memory region for enclave general usage.
-const NE_DEFAULT_MEMORY_REGION: u64 = 0;
+pub(crate) const NE_DEFAULT_MEMORY_REGION: u64 = 0;
 
 /// Magic number for Nitro Enclave IOCTL codes.
 const NE_MAGIC: u64 = 0xAE;
@@ -65,6 +73,60 @@ const NE_MAGIC: u64 = 0xAE;
 /// Path corresponding to the Nitro Enclaves device file.
 const NE_DEV_FILEPATH: &str = "/dev/nitro_enclaves";
 
+/// Path to the memory mappings of the current process, including their page sizes.
+const SMAPS_FILEPATH: &str = "/proc/self/smaps";
+
+/// The smallest page size which may back enclave memory.
+const MIN_ENCLAVE_PAGE_SIZE: u64 = 2 * MiB;
+
+/// The size of the regions in a memory region pool.
+const POOL_REGION_SIZE: u64 = 2 * MiB;
+
+/// The amount of memory faulted in between two progress reports, which keeps the cost of
+/// reporting negligible compared with that of faulting in the pages.
+const FAULT_IN_PROGRESS_STEP: u64 = 256 * MiB;
+
+/// The share (in percent) of the largest obtainable hugepage memory above which a request for
+/// enclave memory is warned about, since it may fail once other processes take hugepages.
+const CONTIGUOUS_MEMORY_WARN_PERCENT: u64 = 90;
+
+/// Path to the sysfs directory which holds the per-size hugepage counters.
+const HUGEPAGES_SYSFS_DIR: &str = "/sys/kernel/mm/hugepages";
+
+/// Path to the list of the file-systems mounted in the current mount namespace.
+const MOUNTS_FILEPATH: &str = "/proc/mounts";
+
+/// A counter which keeps the names of the files created on hugetlbfs mounts unique.
+static HUGETLBFS_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Path to the system memory statistics, which include the default hugepage size.
+const MEMINFO_FILEPATH: &str = "/proc/meminfo";
+
+/// Environment variable which sets the fraction of the host memory that enclaves must leave free.
+const MEMORY_RESERVE_FRACTION_ENV_VAR: &str = "NITRO_CLI_MEMORY_RESERVE_FRACTION";
+
+/// The fraction of the host memory that enclaves must leave free, unless configured otherwise.
+const DEFAULT_MEMORY_RESERVE_FRACTION: f64 = 0.2;
+
+/// Environment variable which holds the cgroup v2 directory that the enclave process joins
+/// before allocating enclave memory, so that the memory is accounted to that cgroup.
+const ENCLAVE_CGROUP_ENV_VAR: &str = "NITRO_CLI_ENCLAVE_CGROUP";
+
+/// Path to the mount point of the cgroup v2 hierarchy.
+const CGROUP_ROOT_DIR: &str = "/sys/fs/cgroup";
+
+/// Path to the cgroup membership of the current process.
+const PROC_SELF_CGROUP_FILEPATH: &str = "/proc/self/cgroup";
+
+/// Path to the NUMA memory policy and placement of the current process' mappings.
+const NUMA_MAPS_FILEPATH: &str = "/proc/self/numa_maps";
+
+/// Path to the sysfs directory describing the instance's NUMA nodes.
+const NODE_SYSFS_DIR: &str = "/sys/devices/system/node";
+
+/// Path to the sysfs directory describing the instance's CPUs.
+const CPU_SYSFS_DIR: &str = "/sys/devices/system/cpu";
+
 /// IOCTL code for `NE_CREATE_VM`.
 pub const NE_CREATE_VM: u64 = nix::request_code_read!(NE_MAGIC, 0x20, size_of::<u64>()) as _;
 
@@ -96,6 +158,108 @@ const HUGE_PAGE_MAP: [(libc::c_int, u64); 9] = [
     (libc::MAP_HUGE_2MB, 2 * MiB),
 ];
 
+/// The hugepage sizes which may be explicitly requested for backing a memory region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HugePageSize {
+    /// 2 MiB hugepages.
+    Mib2,
+    /// 1 GiB hugepages.
+    Gib1,
+}
+
+/// Whether the pages of a memory region are faulted in when it is allocated.
+///
+/// Faulting pages in eagerly makes allocation slower, but the enclave then starts with all of its
+/// memory in place, and a lack of hugepages is reported by the allocation itself. Lazily faulted
+/// pages make allocation fast, at the cost of fault latency when the pages are first touched and
+/// of the process being killed if no hugepage is left by then. Pages are faulted in lazily by
+/// default, as released versions have always done, so eager faulting must be asked for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EagerFault(pub bool);
+
+/// Whether CPU 0 and its hyperthread siblings may be selected for an enclave.
+///
+/// The host relies on CPU 0 for handling critical interrupts, so giving core 0 to an enclave may
+/// destabilize the host. It is therefore left out of the selection by default, even if it is in
+/// the NE CPU pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnsafeAllowCpu0(pub bool);
+
+/// The ioctl commands supported by the NE driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeIoctl {
+    /// Create an enclave slot (`NE_CREATE_VM`).
+    CreateVm,
+    /// Add a memory region to an enclave (`NE_SET_USER_MEMORY_REGION`).
+    SetUserMemoryRegion,
+    /// Add a vCPU to an enclave (`NE_ADD_VCPU`).
+    AddVcpu,
+    /// Start an enclave (`NE_START_ENCLAVE`).
+    StartEnclave,
+    /// Get the offset at which the enclave image must be loaded (`NE_GET_IMAGE_LOAD_INFO`).
+    GetImageLoadInfo,
+}
+
+/// An NE ioctl command, which ties the command to the type of its argument.
+///
+/// # Safety
+///
+/// `Arg` must be the argument type which the NE driver expects for `IOCTL`, since the driver
+/// reads and writes through the pointer it is given.
+pub unsafe trait NeIoctlCommand {
+    /// The type of the argument which the driver reads and writes.
+    type Arg;
+    /// The ioctl issued for this command.
+    const IOCTL: NeIoctl;
+}
+
+/// The `NE_CREATE_VM` command, which writes the slot UID of the new enclave.
+pub struct NeCreateVm;
+
+/// The `NE_SET_USER_MEMORY_REGION` command.
+pub struct NeSetUserMemoryRegion;
+
+/// The `NE_ADD_VCPU` command, which writes the ID of the added vCPU.
+pub struct NeAddVcpu;
+
+/// The `NE_START_ENCLAVE` command, which writes the CID of the enclave.
+pub struct NeStartEnclave;
+
+/// The `NE_GET_IMAGE_LOAD_INFO` command, which writes the image load offset.
+pub struct NeGetImageLoadInfo;
+
+unsafe impl NeIoctlCommand for NeCreateVm {
+    type Arg = u64;
+    const IOCTL: NeIoctl = NeIoctl::CreateVm;
+}
+
+unsafe impl NeIoctlCommand for NeSetUserMemoryRegion {
+    type Arg = UserMemoryRegion;
+    const IOCTL: NeIoctl = NeIoctl::SetUserMemoryRegion;
+}
+
+unsafe impl NeIoctlCommand for NeAddVcpu {
+    type Arg = u32;
+    const IOCTL: NeIoctl = NeIoctl::AddVcpu;
+}
+
+unsafe impl NeIoctlCommand for NeStartEnclave {
+    type Arg = EnclaveStartInfo;
+    const IOCTL: NeIoctl = NeIoctl::StartEnclave;
+}
+
+unsafe impl NeIoctlCommand for NeGetImageLoadInfo {
+    type Arg = ImageLoadInfo;
+    const IOCTL: NeIoctl = NeIoctl::GetImageLoadInfo;
+}
+
+/// The CPUs which the NE driver may assign to enclaves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuPool {
+    /// The IDs of the CPUs in the pool.
+    cpu_ids: BTreeSet<u32>,
+}
+
 /// A memory region used by the enclave memory allocator.
 #[derive(Clone, Debug)]
 pub struct MemoryRegion {
@@ -105,6 +269,10 @@ pub struct MemoryRegion {
     mem_size: u64,
     /// The region's virtual address.
     mem_addr: u64,
+    /// The stop flag and thread of the residency monitor, if one has been started.
+    residency_monitor: Option<(Arc<AtomicBool>, Thread)>,
+    /// The size in bytes of the hugepages backing the region, if known.
+    page_size: Option<u64>,
 }
 
 /// The state an enclave may be in.
@@ -128,6 +296,57 @@ struct ResourceAllocator {
     mem_regions: Vec<MemoryRegion>,
 }
 
+/// A pool of 2 MiB memory regions which are handed out to enclaves and reused once they are
+/// released, instead of mapping and unmapping hugepages for each enclave.
+pub struct MemoryRegionPool {
+    /// The regions which are ready to be handed out.
+    free: Vec<MemoryRegion>,
+    /// The number of regions which have been handed out and not released yet.
+    in_use: usize,
+    /// The maximum number of regions the pool may hold, including the ones in use.
+    capacity: usize,
+    /// The function which allocates a new region when the pool grows.
+    allocate: fn() -> NitroCliResult<MemoryRegion>,
+}
+
+/// The utilization of a memory region pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolUtilization {
+    /// The number of regions which have been handed out.
+    pub in_use: usize,
+    /// The number of regions which are ready to be handed out.
+    pub free: usize,
+    /// The maximum number of regions the pool may hold.
+    pub capacity: usize,
+}
+
+/// The host memory used by the memory regions of an enclave.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemStats {
+    /// The resident memory in bytes of the mappings holding the regions, hugepages included.
+    pub rss_bytes: u64,
+    /// The number of hugepages backing the regions.
+    pub hugepages: u64,
+    /// Whether any region is not held by exactly one mapping, as happens when part of it gets
+    /// unmapped or has its protection or memory policy changed.
+    pub split: bool,
+}
+
+/// A mapping listed in an `smaps` file, with its sizes in KiB.
+#[derive(Default)]
+struct SmapsMapping {
+    /// The start address of the mapping.
+    start: u64,
+    /// The end address of the mapping, which is not part of it.
+    end: u64,
+    /// The resident memory, excluding hugepages.
+    rss: u64,
+    /// The memory of the hugepages backing the mapping.
+    hugetlb: u64,
+    /// The size of the pages backing the mapping.
+    kernel_page_size: u64,
+}
+
 /// Helper structure for managing an enclave's resources.
 #[derive(Default)]
 struct EnclaveHandle {
@@ -156,6 +375,8 @@ struct EnclaveHandle {
     build_info: EnclaveBuildInfo,
     /// EIF metadata
     metadata: Option<EifIdentityInfo>,
+    /// When the enclave has been started, on the monotonic and on the wall clock.
+    started_at: Option<(Instant, SystemTime)>,
 }
 
 /// The structure which manages an enclave in a thread-safe manner.
@@ -198,9 +419,110 @@ impl From<&MemoryRegion> for UserMemoryRegion {
     }
 }
 
+impl HugePageSize {
+    /// Get the hugepage size in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            HugePageSize::Mib2 => 2 * MiB,
+            HugePageSize::Gib1 => GiB,
+        }
+    }
+
+    /// Get the `mmap()` flag which selects this hugepage size.
+    pub fn mmap_flag(&self) -> c_int {
+        match self {
+            HugePageSize::Mib2 => libc::MAP_HUGE_2MB,
+            HugePageSize::Gib1 => libc::MAP_HUGE_1GB,
+        }
+    }
+
+    /// Get the path of the file holding the number of reserved hugepages of this size.
+    fn nr_hugepages_path(&self) -> String {
+        format!(
+            "{}/hugepages-{}kB/nr_hugepages",
+            HUGEPAGES_SYSFS_DIR,
+            self.size() / KiB
+        )
+    }
+
+    /// Get the path of the file holding the number of free hugepages of this size.
+    fn free_hugepages_path(&self) -> String {
+        format!(
+            "{}/hugepages-{}kB/free_hugepages",
+            HUGEPAGES_SYSFS_DIR,
+            self.size() / KiB
+        )
+    }
+}
+
+impl NeIoctl {
+    /// Get the name under which the NE driver defines the ioctl.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NeIoctl::CreateVm => "NE_CREATE_VM",
+            NeIoctl::SetUserMemoryRegion => "NE_SET_USER_MEMORY_REGION",
+            NeIoctl::AddVcpu => "NE_ADD_VCPU",
+            NeIoctl::StartEnclave => "NE_START_ENCLAVE",
+            NeIoctl::GetImageLoadInfo => "NE_GET_IMAGE_LOAD_INFO",
+        }
+    }
+
+    /// Get the request code to be passed to `ioctl()`.
+    pub fn request_code(&self) -> libc::c_ulong {
+        (match self {
+            NeIoctl::CreateVm => NE_CREATE_VM,
+            NeIoctl::SetUserMemoryRegion => NE_SET_USER_MEMORY_REGION,
+            NeIoctl::AddVcpu => NE_ADD_VCPU,
+            NeIoctl::StartEnclave => NE_START_ENCLAVE,
+            NeIoctl::GetImageLoadInfo => NE_GET_IMAGE_LOAD_INFO,
+        }) as libc::c_ulong
+    }
+}
+
+impl std::fmt::Display for NeIoctl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl std::fmt::Display for HugePageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HugePageSize::Mib2 => write!(f, "2 MiB"),
+            HugePageSize::Gib1 => write!(f, "1 GiB"),
+        }
+    }
+}
+
+impl CpuPool {
+    /// Get the IDs of the CPUs in the pool, in ascending order.
+    pub fn available(&self) -> Vec<u32> {
+        self.cpu_ids.iter().cloned().collect()
+    }
+
+    /// Check if the CPU with the given ID is in the pool.
+    pub fn contains(&self, cpu_id: u32) -> bool {
+        self.cpu_ids.contains(&cpu_id)
+    }
+
+    /// Get the number of CPUs in the pool.
+    pub fn count(&self) -> usize {
+        self.cpu_ids.len()
+    }
+
+    /// Check if no CPU has been given to the NE driver.
+    pub fn is_empty(&self) -> bool {
+        self.cpu_ids.is_empty()
+    }
+}
+
 impl MemoryRegion {
-    /// Create a new `MemoryRegion` instance with the specified size (in bytes).
-    pub fn new(hugepage_flag: libc::c_int) -> NitroCliResult<Self> {
+    /// Create a new `MemoryRegion` instance with the specified size (in bytes). The region is
+    /// not bound to any NUMA node, so its hugepages may come from any node; use `new_on_node()`
+    /// to require a given node. It isn't bound to the node of the calling thread either, since the
+    /// enclave process doesn't run on the NE CPU pool, whose node the hugepages are usually
+    /// reserved on. `eager_fault` tells whether its pages are faulted in right away.
+    pub fn new(hugepage_flag: libc::c_int, eager_fault: EagerFault) -> NitroCliResult<Self> {
         let region_index = HUGE_PAGE_MAP
             .iter()
             .position(|&page_info| page_info.0 == hugepage_flag)
@@ -215,10 +537,87 @@ impl MemoryRegion {
             })?;
         let region_size = HUGE_PAGE_MAP[region_index].1;
 
+        MemoryRegion::map(region_size, None, eager_fault)
+    }
+
+    /// Create a new `MemoryRegion` instance of `size` bytes, whose hugepages are all allocated
+    /// from the given NUMA node. The largest hugepage size which evenly divides `size` is used.
+    ///
+    /// The region is refused if the node has fewer free hugepages than needed. Its pages are then
+    /// faulted in right away, on kernels which support `MADV_POPULATE_WRITE`, so that hugepages
+    /// taken from the node by others in the meantime make this call fail instead of the process
+    /// being killed on first access. On older kernels, the free pages check is all there is.
+    pub fn new_on_node(size: u64, node: u32) -> NitroCliResult<Self> {
+        MemoryRegion::map(size, Some(node), EagerFault(true))
+    }
+
+    /// Map a region of `size` bytes, bound to the given NUMA node if any, faulting in its pages
+    /// if `eager_fault` is set.
+    fn map(size: u64, node: Option<u32>, eager_fault: EagerFault) -> NitroCliResult<Self> {
+        let (hugepage_flag, page_size) = HUGE_PAGE_MAP
+            .iter()
+            .find(|&&(_, page_size)| size != 0 && size % page_size == 0)
+            .copied()
+            .ok_or_else(|| {
+                new_nitro_cli_failure!(
+                    &format!(
+                        "Memory region size {} is not a multiple of any hugepage size",
+                        size
+                    ),
+                    NitroCliErrorEnum::InvalidMemoryRegion
+                )
+                .add_info(vec![
+                    "(unmapped)",
+                    &(HUGE_PAGE_MAP[HUGE_PAGE_MAP.len() - 1].1 / KiB).to_string(),
+                ])
+            })?;
+
+        // Lacking free hugepages would only make the mapping fail without saying why or, for a
+        // region bound to a node, the process be killed once the pages are accessed. The free
+        // pages of the host, or of the node, are therefore checked beforehand.
+        let needed_pages = size / page_size;
+        match node {
+            None => {
+                let free_pages = get_host_free_hugepages(page_size);
+                if free_pages < needed_pages {
+                    return Err(new_nitro_cli_failure!(
+                        &format!(
+                            "Cannot allocate {} MiB of {} MiB hugepages: need {} pages, only {} free",
+                            size / MiB,
+                            page_size / MiB,
+                            needed_pages,
+                            free_pages
+                        ),
+                        NitroCliErrorEnum::InsufficientMemoryAvailable
+                    )
+                    .add_info(vec!["memory", &(size / MiB).to_string()]));
+                }
+            }
+            Some(node) => {
+                let free_pages = get_free_hugepages(node, page_size);
+                if free_pages < needed_pages {
+                    return Err(new_nitro_cli_failure!(
+                        &format!(
+                            "NUMA node {} has {} free {} MiB hugepages, but {} are needed",
+                            node,
+                            free_pages,
+                            page_size / MiB,
+                            needed_pages
+                        ),
+                        NitroCliErrorEnum::InsufficientNodeMemory
+                    )
+                    .add_info(vec![
+                        &node.to_string(),
+                        &(needed_pages - free_pages).to_string(),
+                    ]));
+                }
+            }
+        }
+
         let addr = unsafe {
             libc::mmap(
                 std::ptr::null_mut(),
-                region_size as usize,
+                size as usize,
                 libc::PROT_READ | libc::PROT_WRITE,
                 libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | hugepage_flag,
                 -1,
@@ -233,128 +632,967 @@ impl MemoryRegion {
             ));
         }
 
-        // Record the allocated region.
-        Ok(MemoryRegion {
+        // Record the allocated region, so that it gets unmapped if binding it fails.
+        let region = MemoryRegion {
             flags: NE_DEFAULT_MEMORY_REGION,
-            mem_size: region_size,
+            mem_size: size,
             mem_addr: addr as u64,
-        })
+            residency_monitor: None,
+            page_size: Some(page_size),
+        };
+        if let Some(node) = node {
+            region.bind_to_node(node)?;
+        }
+        if eager_fault.0 {
+            region.populate()?;
+        }
+
+        Ok(region)
     }
 
-    /// Create a new `MemoryRegion` instance with the specified values.
-    pub fn new_with(flags: u64, mem_addr: u64, mem_size: u64) -> Self {
-        MemoryRegion {
-            flags,
-            mem_size,
-            mem_addr,
+    /// Create a new `MemoryRegion` instance of `size` bytes, backed by hugepages of the given size.
+    pub fn new_with_page_size(size: u64, page_size: HugePageSize) -> NitroCliResult<Self> {
+        if size == 0 || size % page_size.size() != 0 {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Memory region size {} is not a multiple of the {} page size",
+                    size, page_size
+                ),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec!["(unmapped)", &(page_size.size() / KiB).to_string()]));
         }
-    }
 
-    /// Free the memory region, if it has been allocated earlier.
-    fn free(&mut self) -> NitroCliResult<()> {
-        // Do nothing if the region has already been freed.
-        if self.mem_addr == 0 {
-            return Ok(());
+        // The per-size counter is used since `/proc/sys/vm/nr_hugepages` only covers the
+        // default hugepage size.
+        let nr_hugepages_path = page_size.nr_hugepages_path();
+        let reserved_pages = std::fs::read_to_string(&nr_hugepages_path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        if reserved_pages < size / page_size.size() {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Only {} {} hugepages are reserved in {}, but {} are needed",
+                    reserved_pages,
+                    page_size,
+                    nr_hugepages_path,
+                    size / page_size.size()
+                ),
+                NitroCliErrorEnum::InsufficientMemoryAvailable
+            )
+            .add_info(vec!["memory", &(size / MiB).to_string()]));
         }
 
-        let rc =
-            unsafe { libc::munmap(self.mem_addr as *mut libc::c_void, self.mem_size as usize) };
+        // Reserved pages may already be in use, in which case mapping the region would fail
+        // without saying why.
+        let needed_pages = size / page_size.size();
+        let free_pages = MemoryRegion::available_hugepages(page_size)?;
+        if free_pages < needed_pages {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Cannot allocate {} MiB of {} hugepages: need {} pages, only {} free",
+                    size / MiB,
+                    page_size,
+                    needed_pages,
+                    free_pages
+                ),
+                NitroCliErrorEnum::InsufficientMemoryAvailable
+            )
+            .add_info(vec!["memory", &(size / MiB).to_string()]));
+        }
 
-        if rc < 0 {
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | page_size.mmap_flag(),
+                -1,
+                0,
+            )
+        };
+
+        if addr == libc::MAP_FAILED {
             return Err(new_nitro_cli_failure!(
-                "Failed to unmap memory",
-                NitroCliErrorEnum::EnclaveMunmapError
+                &format!(
+                    "Failed to map {} bytes of {} hugepages: {}",
+                    size,
+                    page_size,
+                    Error::last_os_error()
+                ),
+                NitroCliErrorEnum::EnclaveMmapError
             ));
         }
 
-        // Set the address and length to 0 to avoid double-freeing.
-        self.mem_addr = 0;
-        self.mem_size = 0;
+        Ok(MemoryRegion {
+            flags: NE_DEFAULT_MEMORY_REGION,
+            mem_size: size,
+            mem_addr: addr as u64,
+            residency_monitor: None,
+            page_size: Some(page_size.size()),
+        })
+    }
 
-        Ok(())
+    /// Create a new `MemoryRegion` instance like `new_with_page_size()` and fault in all of its
+    /// pages right away. `progress` is called with the number of bytes faulted in so far and the
+    /// region size, at most once per `FAULT_IN_PROGRESS_STEP` bytes and once the region is done.
+    pub fn new_with_progress<F>(
+        size: u64,
+        page_size: HugePageSize,
+        progress: F,
+    ) -> NitroCliResult<Self>
+    where
+        F: FnMut(u64, u64),
+    {
+        let region = MemoryRegion::new_with_page_size(size, page_size)?;
+        let step = ceil_div(FAULT_IN_PROGRESS_STEP, page_size.size()) * page_size.size();
+        region.fault_in(page_size.size(), step, progress)?;
+
+        Ok(region)
     }
 
-    /// Write the content from a file into memory at a given offset.
-    fn fill_from_file(
-        &self,
-        file: &mut File,
-        region_offset: usize,
-        size: usize,
-    ) -> NitroCliResult<()> {
-        let offset_plus_size = region_offset.checked_add(size).ok_or_else(|| {
-            new_nitro_cli_failure!(
-                "Memory overflow when writing EIF file to region",
-                NitroCliErrorEnum::MemoryOverflow
-            )
-        })?;
+    /// Fault in the pages of the region, `step` bytes at a time, reporting the progress after
+    /// each step. `step` must be a multiple of `page_size`.
+    fn fault_in<F>(&self, page_size: u64, step: u64, mut progress: F) -> NitroCliResult<()>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut done = 0;
+
+        while done < self.mem_size {
+            let len = step.min(self.mem_size - done);
+            let addr = (self.mem_addr + done) as *mut libc::c_void;
+            let rc = unsafe { libc::madvise(addr, len as usize, libc::MADV_POPULATE_WRITE) };
+
+            if rc < 0 {
+                let err = Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EINVAL) {
+                    return Err(new_nitro_cli_failure!(
+                        &format!(
+                            "Failed to fault in {} bytes at offset {} of memory region: {}",
+                            len, done, err
+                        ),
+                        NitroCliErrorEnum::EnclaveMmapError
+                    ));
+                }
 
-        if offset_plus_size > self.mem_size as usize {
-            return Err(new_nitro_cli_failure!(
-                "Out of region",
-                NitroCliErrorEnum::MemoryOverflow
-            ));
+                // Older kernels do not support populating a range in advance, so each page is
+                // written to instead.
+                for offset in (0..len).step_by(page_size as usize) {
+                    unsafe { std::ptr::write_volatile((addr as *mut u8).add(offset as usize), 0) };
+                }
+            }
+
+            done += len;
+            progress(done, self.mem_size);
         }
 
-        let bytes = unsafe {
-            std::slice::from_raw_parts_mut(self.mem_addr as *mut u8, self.mem_size as usize)
-        };
+        Ok(())
+    }
 
-        file.read_exact(&mut bytes[region_offset..region_offset + size])
-            .map_err(|e| {
+    /// Get the number of hugepages of the given size which are free on the host.
+    pub fn available_hugepages(page_size: HugePageSize) -> NitroCliResult<u64> {
+        read_hugepage_count(Path::new(&page_size.free_hugepages_path()))
+    }
+
+    /// Estimate the size (in bytes) of the largest single region of `page_size` hugepages which
+    /// can currently be obtained, by mapping trial regions and unmapping them right away.
+    ///
+    /// This is a best-effort heuristic: other processes may take or release hugepages at any
+    /// time, so a region of the returned size is not guaranteed to be obtainable afterwards.
+    pub fn largest_contiguous_available(page_size: u64) -> NitroCliResult<u64> {
+        let hugepage_flag = HUGE_PAGE_MAP
+            .iter()
+            .find(|&&(_, size)| size == page_size)
+            .map(|&(flag, _)| flag)
+            .ok_or_else(|| {
                 new_nitro_cli_failure!(
-                    &format!("Error while reading from enclave image: {:?}", e),
-                    NitroCliErrorEnum::EifParsingError
+                    &format!("Failed to find huge page entry for size {}", page_size),
+                    NitroCliErrorEnum::NoSuchHugepageFlag
                 )
             })?;
 
-        Ok(())
-    }
+        // Private hugetlb mappings reserve their pages when mapped, so a trial mapping fails
+        // without touching any page if not enough of them are free.
+        let pages = largest_mappable(get_available_hugepages(page_size).unwrap_or(0), |pages| {
+            let len = (pages * page_size) as usize;
+            let addr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | hugepage_flag,
+                    -1,
+                    0,
+                )
+            };
+            if addr == libc::MAP_FAILED {
+                return false;
+            }
 
-    /// Get the virtual address of the memory region.
-    pub fn mem_addr(&self) -> u64 {
-        self.mem_addr
-    }
+            unsafe { libc::munmap(addr, len) };
+            true
+        });
 
-    /// Get the size in bytes of the memory region.
-    pub fn mem_size(&self) -> u64 {
-        self.mem_size
+        Ok(pages * page_size)
     }
-}
 
-impl Drop for MemoryRegion {
-    fn drop(&mut self) {
-        self.free()
-            .ok_or_exit_with_errno(Some("Failed to drop memory region"));
+    /// Create a new `MemoryRegion` instance of `size` bytes, backed by a file on the first
+    /// hugetlbfs mount (as listed in `/proc/mounts`) which provides pages of the given size.
+    pub fn new_in_hugetlbfs(size: u64, page_size: HugePageSize) -> NitroCliResult<Self> {
+        let mount = find_hugetlbfs_mount(page_size)?;
+        MemoryRegion::new_at_mount(size, &mount)
     }
-}
 
-impl ResourceAllocator {
-    /// Create a new `ResourceAllocator` instance which must cover at least the requested amount of memory (in bytes).
-    fn new(requested_mem: u64) -> NitroCliResult<Self> {
-        if requested_mem == 0 {
+    /// Create a new `MemoryRegion` instance of `size` bytes, backed by an unlinked file on the
+    /// hugetlbfs file-system mounted at `mount`. The size must be a multiple of the mount's
+    /// page size.
+    pub fn new_at_mount(size: u64, mount: &Path) -> NitroCliResult<Self> {
+        let fs_stats = nix::sys::statfs::statfs(mount).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to get the file-system of {:?}: {}", mount, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![mount.to_str().unwrap_or("(invalid path)"), "Stat"])
+        })?;
+        if fs_stats.filesystem_type() != nix::sys::statfs::HUGETLBFS_MAGIC {
             return Err(new_nitro_cli_failure!(
-                "Cannot start an enclave with no memory",
-                NitroCliErrorEnum::InsufficientMemoryRequested
+                &format!("{:?} is not a hugetlbfs mount", mount),
+                NitroCliErrorEnum::NoHugetlbfsMount
             )
-            .add_info(vec!["memory", &(requested_mem >> 20).to_string()]));
+            .add_info(vec!["any size", mount.to_str().unwrap_or("(invalid path)")]));
         }
 
-        Ok(ResourceAllocator {
-            requested_mem,
-            mem_regions: Vec::new(),
-        })
-    }
-
-    /// Allocate and provide a list of memory regions. This function creates a list of
-    /// memory regions which contain at least `self.requested_mem` bytes. Each region
-    /// is equivalent to a huge-page and is allocated using memory mapping.
-    fn allocate(&mut self) -> NitroCliResult<&Vec<MemoryRegion>> {
-        let mut allocated_pages = BTreeMap::<u64, u32>::new();
-        let mut needed_mem = self.requested_mem as i64;
-        let mut split_index = 0;
+        // The block size of a hugetlbfs mount is its page size.
+        let page_size = fs_stats.block_size() as u64;
+        if size == 0 || size % page_size != 0 {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Memory region size {} is not a multiple of the {} KiB page size of {:?}",
+                    size,
+                    page_size / KiB,
+                    mount
+                ),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec!["(unmapped)", &(page_size / KiB).to_string()]));
+        }
 
-        info!(
-            "Allocating memory regions to hold {} bytes.",
+        // The file is unlinked right away, so its pages are released once it gets unmapped.
+        let file_path = mount.join(format!(
+            "nitro_enclaves-{}-{}",
+            std::process::id(),
+            HUGETLBFS_FILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&file_path)
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to create hugetlbfs file {:?}: {}", file_path, e),
+                    NitroCliErrorEnum::FileOperationFailure
+                )
+                .add_info(vec![file_path.to_str().unwrap_or("(invalid path)"), "Open"])
+            })?;
+        let _ = std::fs::remove_file(&file_path);
+        file.set_len(size).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to resize hugetlbfs file to {} bytes: {}", size, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![
+                file_path.to_str().unwrap_or("(invalid path)"),
+                "Resize",
+            ])
+        })?;
+
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if addr == libc::MAP_FAILED {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Failed to map {} bytes from hugetlbfs mount {:?}: {}",
+                    size,
+                    mount,
+                    Error::last_os_error()
+                ),
+                NitroCliErrorEnum::EnclaveMmapError
+            ));
+        }
+
+        Ok(MemoryRegion {
+            flags: NE_DEFAULT_MEMORY_REGION,
+            mem_size: size,
+            mem_addr: addr as u64,
+            residency_monitor: None,
+            page_size: Some(page_size),
+        })
+    }
+
+    /// Create a new `MemoryRegion` instance of `size` bytes, backed by the file at `path`, which
+    /// is extended to `size` bytes if it is shorter. The mapping is shared, so the region's
+    /// contents can be inspected from the host through the file, and unmapping the region
+    /// leaves the file in place.
+    ///
+    /// File-backed regions must still meet the NE driver's requirements: `size` must be a
+    /// multiple of `page_size`, and the file needs to be on a hugetlbfs mount with pages of that
+    /// size for the region to be accepted. Files on other file-systems are still mapped at an
+    /// address aligned to `page_size`, which is enough for testing and debugging.
+    pub fn from_file(path: &Path, size: u64, page_size: HugePageSize) -> NitroCliResult<Self> {
+        let page_bytes = page_size.size();
+        if size == 0 || size % page_bytes != 0 {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Memory region size {} is not a multiple of the {} KiB page size",
+                    size,
+                    page_bytes / KiB
+                ),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec!["(unmapped)", &(page_bytes / KiB).to_string()]));
+        }
+
+        let path_str = path.to_str().unwrap_or("(invalid path)");
+        let fs_stats = nix::sys::statfs::statfs(path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to get the file-system of {:?}: {}", path, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![path_str, "Stat"])
+        })?;
+        if fs_stats.filesystem_type() != nix::sys::statfs::HUGETLBFS_MAGIC {
+            enclave_log!(
+                warn,
+                "{:?} is not on a hugetlbfs mount, so the NE driver will reject its memory region.",
+                path
+            );
+        } else if fs_stats.block_size() as u64 != page_bytes {
+            // The block size of a hugetlbfs mount is its page size.
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "{:?} is on a hugetlbfs mount with {} KiB pages instead of {} KiB ones",
+                    path,
+                    fs_stats.block_size() as u64 / KiB,
+                    page_bytes / KiB
+                ),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec!["(unmapped)", &(page_bytes / KiB).to_string()]));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to open memory file {:?}: {}", path, e),
+                    NitroCliErrorEnum::FileOperationFailure
+                )
+                .add_info(vec![path_str, "Open"])
+            })?;
+        let file_len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        // Accessing a mapping past the end of its file raises `SIGBUS`.
+        if file_len < size {
+            file.set_len(size).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to resize memory file to {} bytes: {}", size, e),
+                    NitroCliErrorEnum::FileOperationFailure
+                )
+                .add_info(vec![path_str, "Resize"])
+            })?;
+        }
+
+        let mmap_error = |what: &str| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to {} {} bytes for memory file {:?}: {}",
+                    what,
+                    size,
+                    path,
+                    Error::last_os_error()
+                ),
+                NitroCliErrorEnum::EnclaveMmapError
+            )
+        };
+
+        // Reserve enough address space to fit an aligned mapping, then map the file over its
+        // aligned part and give back the rest.
+        let reserved_size = (size + page_bytes) as usize;
+        let reserved = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                reserved_size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if reserved == libc::MAP_FAILED {
+            return Err(mmap_error("reserve"));
+        }
+
+        let reserved_addr = reserved as u64;
+        let addr = ceil_div(reserved_addr, page_bytes) * page_bytes;
+        let mapped = unsafe {
+            libc::mmap(
+                addr as *mut libc::c_void,
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if mapped == libc::MAP_FAILED {
+            let err = mmap_error("map");
+            unsafe { libc::munmap(reserved, reserved_size) };
+            return Err(err);
+        }
+
+        unsafe {
+            if addr > reserved_addr {
+                libc::munmap(reserved, (addr - reserved_addr) as usize);
+            }
+            let tail_addr = addr + size;
+            let tail_size = reserved_addr + reserved_size as u64 - tail_addr;
+            if tail_size > 0 {
+                libc::munmap(tail_addr as *mut libc::c_void, tail_size as usize);
+            }
+        }
+
+        // The pages stay in the file once unmapped, so releasing the region doesn't return
+        // hugepages to the host and its page size isn't recorded for checking that.
+        Ok(MemoryRegion {
+            flags: NE_DEFAULT_MEMORY_REGION,
+            mem_size: size,
+            mem_addr: addr,
+            residency_monitor: None,
+            page_size: None,
+        })
+    }
+
+    /// Create a new `MemoryRegion` instance with the specified values.
+    pub fn new_with(flags: u64, mem_addr: u64, mem_size: u64) -> Self {
+        MemoryRegion {
+            flags,
+            mem_size,
+            mem_addr,
+            residency_monitor: None,
+            page_size: None,
+        }
+    }
+
+    /// Bind the memory region to a NUMA node, from which its pages are then allocated.
+    fn bind_to_node(&self, node: u32) -> NitroCliResult<()> {
+        let bits_per_mask = libc::c_ulong::BITS as usize;
+        let mut nodemask = vec![0 as libc::c_ulong; node as usize / bits_per_mask + 1];
+        nodemask[node as usize / bits_per_mask] |= 1 << (node as usize % bits_per_mask);
+
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                self.mem_addr as *mut libc::c_void,
+                self.mem_size as libc::c_ulong,
+                libc::MPOL_BIND,
+                nodemask.as_ptr(),
+                (nodemask.len() * bits_per_mask + 1) as libc::c_ulong,
+                0 as libc::c_uint,
+            )
+        };
+
+        if rc < 0 {
+            let err = Error::last_os_error();
+            // Kernels without NUMA support only provide node 0, from which all memory comes.
+            if node == 0 && err.raw_os_error() == Some(libc::ENOSYS) {
+                return Ok(());
+            }
+
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Failed to bind memory region to NUMA node {}: {}",
+                    node, err
+                ),
+                NitroCliErrorEnum::EnclaveMmapError
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fault in the pages of a memory region. This is done after binding it to a NUMA node, if
+    /// any, since pages faulted in by `MAP_POPULATE` would come from any node.
+    fn populate(&self) -> NitroCliResult<()> {
+        let rc = unsafe {
+            libc::madvise(
+                self.mem_addr as *mut libc::c_void,
+                self.mem_size as usize,
+                libc::MADV_POPULATE_WRITE,
+            )
+        };
+
+        if rc < 0 {
+            let err = Error::last_os_error();
+            // Older kernels do not support populating the region in advance, in which case its
+            // pages are allocated when they are first accessed.
+            if err.raw_os_error() == Some(libc::EINVAL) {
+                return Ok(());
+            }
+
+            return Err(new_nitro_cli_failure!(
+                &format!("Failed to allocate memory region pages: {}", err),
+                NitroCliErrorEnum::EnclaveMmapError
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Free the memory region, if it has been allocated earlier.
+    fn free(&mut self) -> NitroCliResult<()> {
+        // Do nothing if the region has already been freed.
+        if self.mem_addr == 0 {
+            return Ok(());
+        }
+
+        self.stop_residency_monitor();
+
+        let rc =
+            unsafe { libc::munmap(self.mem_addr as *mut libc::c_void, self.mem_size as usize) };
+
+        if rc < 0 {
+            return Err(new_nitro_cli_failure!(
+                "Failed to unmap memory",
+                NitroCliErrorEnum::EnclaveMunmapError
+            ));
+        }
+
+        // Set the address and length to 0 to avoid double-freeing.
+        self.mem_addr = 0;
+        self.mem_size = 0;
+
+        Ok(())
+    }
+
+    /// Unmap the memory region right away instead of when it gets dropped, so that its hugepages
+    /// can be reused immediately. Failing to unmap it is an error, whereas the number of
+    /// available hugepages of its size not having increased afterwards is only logged, since
+    /// other processes taking hugepages in the meantime hide the increase.
+    pub fn release(mut self) -> NitroCliResult<()> {
+        let page_size = self.page_size.filter(|_| self.mem_addr != 0);
+        let before = page_size.and_then(get_available_hugepages);
+        let pages = page_size.map_or(0, |page_size| self.mem_size / page_size);
+        self.free()
+            .map_err(|e| e.add_subaction("Release memory region".to_string()))?;
+        let after = page_size.and_then(get_available_hugepages);
+
+        if let (Some(page_size), Some(before), Some(after)) = (page_size, before, after) {
+            if after <= before {
+                enclave_log!(
+                    warn,
+                    "Unmapping did not return any of the {} {} KiB hugepages to the host ({} available before, {} after).",
+                    pages,
+                    page_size / KiB,
+                    before,
+                    after
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the content from a file into memory at a given offset.
+    fn fill_from_file(
+        &self,
+        file: &mut File,
+        region_offset: usize,
+        size: usize,
+        checksum: &mut EifChecksum,
+    ) -> NitroCliResult<()> {
+        let offset_plus_size = region_offset.checked_add(size).ok_or_else(|| {
+            new_nitro_cli_failure!(
+                "Memory overflow when writing EIF file to region",
+                NitroCliErrorEnum::MemoryOverflow
+            )
+        })?;
+
+        if offset_plus_size > self.mem_size as usize {
+            return Err(new_nitro_cli_failure!(
+                "Out of region",
+                NitroCliErrorEnum::MemoryOverflow
+            ));
+        }
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(self.mem_addr as *mut u8, self.mem_size as usize)
+        };
+
+        let written = copy_in_chunks(
+            file,
+            &mut bytes[region_offset..region_offset + size],
+            Some(checksum),
+        )?;
+        if written < size {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Unexpected end of enclave image ({} bytes missing)",
+                    size - written
+                ),
+                NitroCliErrorEnum::EifParsingError
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the memory region can be handed to the NE driver, meaning that it is backed
+    /// by hugepages and that both its address and its size are multiples of the page size.
+    pub fn validate_for_enclave(&self) -> NitroCliResult<()> {
+        let smaps = File::open(SMAPS_FILEPATH).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to open {}: {:?}", SMAPS_FILEPATH, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![SMAPS_FILEPATH, "Open"])
+        })?;
+        let page_size =
+            get_backing_page_size(BufReader::new(smaps), self.mem_addr).ok_or_else(|| {
+                new_nitro_cli_failure!(
+                    &format!("Memory region at {:#x} is not mapped", self.mem_addr),
+                    NitroCliErrorEnum::InvalidMemoryRegion
+                )
+                .add_info(vec![&format!("{:#x}", self.mem_addr), "unknown"])
+            })?;
+
+        self.validate_with_page_size(page_size)
+    }
+
+    /// Check the memory region's alignment and size against a given backing page size.
+    fn validate_with_page_size(&self, page_size: u64) -> NitroCliResult<()> {
+        let err_msg = if page_size < MIN_ENCLAVE_PAGE_SIZE {
+            Some(format!(
+                "Memory region at {:#x} is backed by {} KiB pages instead of hugepages",
+                self.mem_addr,
+                page_size / KiB
+            ))
+        } else if self.mem_addr % page_size != 0 {
+            Some(format!(
+                "Memory region address {:#x} is not aligned to the {} KiB page size",
+                self.mem_addr,
+                page_size / KiB
+            ))
+        } else if self.mem_size == 0 || self.mem_size % page_size != 0 {
+            Some(format!(
+                "Memory region size {} is not a multiple of the {} KiB page size",
+                self.mem_size,
+                page_size / KiB
+            ))
+        } else {
+            None
+        };
+
+        match err_msg {
+            Some(err_msg) => Err(new_nitro_cli_failure!(
+                &err_msg,
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec![
+                &format!("{:#x}", self.mem_addr),
+                &(page_size / KiB).to_string(),
+            ])),
+            None => Ok(()),
+        }
+    }
+
+    /// Zero the whole memory region, faulting in all of its pages, and check that it is still
+    /// backed by hugepages afterwards.
+    pub fn zero(&mut self) -> NitroCliResult<()> {
+        let rc = unsafe {
+            libc::madvise(
+                self.mem_addr as *mut libc::c_void,
+                self.mem_size as usize,
+                libc::MADV_HUGEPAGE,
+            )
+        };
+
+        // The hint only applies to transparent hugepages, so it is rejected for hugetlbfs
+        // mappings, which are always backed by hugepages.
+        if rc < 0 && Error::last_os_error().raw_os_error() != Some(libc::EINVAL) {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Failed to advise hugepages for memory region at {:#x}: {}",
+                    self.mem_addr,
+                    Error::last_os_error()
+                ),
+                NitroCliErrorEnum::EnclaveMmapError
+            ));
+        }
+
+        unsafe {
+            std::ptr::write_bytes(self.mem_addr as *mut u8, 0, self.mem_size as usize);
+        }
+
+        self.validate_for_enclave()
+            .map_err(|e| e.add_subaction("Memory region changed after zeroing".to_string()))
+    }
+
+    /// Check whether all pages of the memory region are resident in memory.
+    pub fn is_resident(&self) -> bool {
+        is_range_resident(self.mem_addr, self.mem_size)
+    }
+
+    /// Start a thread which checks every `interval` that all pages of the memory region are
+    /// still resident, logging an error otherwise. The thread exits once the region is freed.
+    pub fn start_residency_monitor(&mut self, interval: Duration) -> JoinHandle<()> {
+        self.stop_residency_monitor();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let (mem_addr, mem_size) = (self.mem_addr, self.mem_size);
+        let monitor =
+            thread::spawn(move || residency_monitor(mem_addr, mem_size, interval, stop_clone));
+
+        self.residency_monitor = Some((stop, monitor.thread().clone()));
+        monitor
+    }
+
+    /// Ask the residency monitor to exit, without waiting for the full check interval.
+    fn stop_residency_monitor(&mut self) {
+        if let Some((stop, monitor)) = self.residency_monitor.take() {
+            stop.store(true, Ordering::SeqCst);
+            monitor.unpark();
+        }
+    }
+
+    /// Get the NUMA node from which most of the region's pages have been allocated. Node 0 is
+    /// reported when NUMA is disabled or when none of the region's pages are resident yet.
+    pub fn numa_node(&self) -> NitroCliResult<u32> {
+        let numa_maps = match File::open(NUMA_MAPS_FILEPATH) {
+            Ok(file) => file,
+            // The file is only provided by kernels built with NUMA support.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(new_nitro_cli_failure!(
+                    &format!("Failed to open {}: {:?}", NUMA_MAPS_FILEPATH, e),
+                    NitroCliErrorEnum::FileOperationFailure
+                )
+                .add_info(vec![NUMA_MAPS_FILEPATH, "Open"]))
+            }
+        };
+
+        get_numa_node(BufReader::new(numa_maps), self.mem_addr).ok_or_else(|| {
+            new_nitro_cli_failure!(
+                &format!("Memory region at {:#x} is not mapped", self.mem_addr),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec![&format!("{:#x}", self.mem_addr), "unknown"])
+        })
+    }
+
+    /// Get the virtual address of the memory region.
+    pub fn mem_addr(&self) -> u64 {
+        self.mem_addr
+    }
+
+    /// Get the size in bytes of the memory region.
+    pub fn mem_size(&self) -> u64 {
+        self.mem_size
+    }
+}
+
+impl Drop for MemoryRegion {
+    fn drop(&mut self) {
+        if let Err(e) = self.free() {
+            enclave_log!(warn, "Failed to release memory region: {:?}", e.subactions);
+        }
+    }
+}
+
+impl MemoryRegionPool {
+    /// Create a pool of at most `capacity` 2 MiB hugepage regions, `initial` of which are
+    /// allocated right away.
+    pub fn new(initial: usize, capacity: usize) -> NitroCliResult<Self> {
+        MemoryRegionPool::with_allocator(initial, capacity, || {
+            MemoryRegion::new_with_page_size(POOL_REGION_SIZE, HugePageSize::Mib2)
+        })
+    }
+
+    /// Create a pool whose regions are allocated by `allocate`.
+    fn with_allocator(
+        initial: usize,
+        capacity: usize,
+        allocate: fn() -> NitroCliResult<MemoryRegion>,
+    ) -> NitroCliResult<Self> {
+        if initial > capacity {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Cannot pre-allocate {} regions in a pool of at most {} regions",
+                    initial, capacity
+                ),
+                NitroCliErrorEnum::InvalidArgument
+            )
+            .add_info(vec!["initial", &initial.to_string()]));
+        }
+
+        let mut pool = MemoryRegionPool {
+            free: Vec::with_capacity(capacity),
+            in_use: 0,
+            capacity,
+            allocate,
+        };
+        for _ in 0..initial {
+            let region = (pool.allocate)()
+                .map_err(|e| e.add_subaction("Failed to pre-allocate pool region".to_string()))?;
+            pool.free.push(region);
+        }
+
+        Ok(pool)
+    }
+
+    /// Hand out a region, allocating a new one if none is free and the pool is not full yet.
+    pub fn acquire(&mut self) -> NitroCliResult<MemoryRegion> {
+        let region = match self.free.pop() {
+            Some(region) => region,
+            None if self.in_use < self.capacity => (self.allocate)()
+                .map_err(|e| e.add_subaction("Failed to grow memory region pool".to_string()))?,
+            None => {
+                return Err(new_nitro_cli_failure!(
+                    &format!(
+                        "All {} regions of the memory region pool are in use",
+                        self.capacity
+                    ),
+                    NitroCliErrorEnum::InsufficientMemoryAvailable
+                )
+                .add_info(vec![
+                    "memory",
+                    &((self.capacity as u64 * POOL_REGION_SIZE) / MiB).to_string(),
+                ]));
+            }
+        };
+        self.in_use += 1;
+
+        Ok(region)
+    }
+
+    /// Give a region back to the pool. It is zeroed first, so that no data is passed from one
+    /// enclave to the next.
+    pub fn release(&mut self, region: MemoryRegion) -> NitroCliResult<()> {
+        if self.in_use == 0 || region.mem_addr == 0 || region.mem_size != POOL_REGION_SIZE {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "The memory region at {:#x} of {} bytes has not been acquired from the pool",
+                    region.mem_addr, region.mem_size
+                ),
+                NitroCliErrorEnum::InvalidMemoryRegion
+            )
+            .add_info(vec![
+                &format!("{:#x}", region.mem_addr),
+                &(region.page_size.unwrap_or(0) / KiB).to_string(),
+            ]));
+        }
+
+        unsafe {
+            std::ptr::write_bytes(region.mem_addr as *mut u8, 0, region.mem_size as usize);
+        }
+        self.in_use -= 1;
+        self.free.push(region);
+
+        Ok(())
+    }
+
+    /// Get the number of regions which are in use and free.
+    pub fn utilization(&self) -> PoolUtilization {
+        PoolUtilization {
+            in_use: self.in_use,
+            free: self.free.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl Drop for MemoryRegionPool {
+    fn drop(&mut self) {
+        // Regions which are still in use are unmapped when their holders drop them.
+        for mut region in self.free.drain(..) {
+            if let Err(e) = region.free() {
+                enclave_log!(warn, "Failed to release pool region: {:?}", e.subactions);
+            }
+        }
+    }
+}
+
+impl ResourceAllocator {
+    /// Create a new `ResourceAllocator` instance which must cover at least the requested amount of memory (in bytes).
+    fn new(requested_mem: u64) -> NitroCliResult<Self> {
+        if requested_mem == 0 {
+            return Err(new_nitro_cli_failure!(
+                "Cannot start an enclave with no memory",
+                NitroCliErrorEnum::InsufficientMemoryRequested
+            )
+            .add_info(vec!["memory", &(requested_mem >> 20).to_string()]));
+        }
+
+        if let Some(cgroup) = enclave_cgroup() {
+            attach_to_cgroup(&cgroup)
+                .map_err(|e| e.add_subaction("Failed to join the enclave cgroup".to_string()))?;
+        }
+
+        match host_total_memory() {
+            Some(host_total) => {
+                enforce_memory_policy(requested_mem, host_total, memory_reserve_fraction())?
+            }
+            None => enclave_log!(
+                warn,
+                "Failed to get the host memory size, skipping the memory policy check"
+            ),
+        }
+
+        let obtainable: u64 = HUGE_PAGE_MAP
+            .iter()
+            .filter_map(|&(_, page_size)| {
+                MemoryRegion::largest_contiguous_available(page_size).ok()
+            })
+            .sum();
+        if approaches_limit(requested_mem, obtainable) {
+            enclave_log!(
+                warn,
+                "Requested {} MiB of enclave memory, but only about {} MiB of hugepages can currently be obtained",
+                requested_mem / MiB,
+                obtainable / MiB
+            );
+        }
+
+        Ok(ResourceAllocator {
+            requested_mem,
+            mem_regions: Vec::new(),
+        })
+    }
+
+    /// Allocate and provide a list of memory regions. This function creates a list of
+    /// memory regions which contain at least `self.requested_mem` bytes. Each region
+    /// is equivalent to a huge-page and is allocated using memory mapping.
+    fn allocate(&mut self) -> NitroCliResult<&Vec<MemoryRegion>> {
+        let mut allocated_pages = BTreeMap::<u64, u32>::new();
+        let mut needed_mem = self.requested_mem as i64;
+        let mut split_index = 0;
+
+        enclave_log!(
+            info,
+            "Allocating memory regions to hold {} bytes.",
             self.requested_mem
         );
 
@@ -363,7 +1601,7 @@ impl ResourceAllocator {
         // page size and retry.
         for page_info in HUGE_PAGE_MAP.iter() {
             while needed_mem >= page_info.1 as i64 {
-                match MemoryRegion::new(page_info.0) {
+                match MemoryRegion::new(page_info.0, EagerFault::default()) {
                     Ok(value) => {
                         needed_mem -= value.mem_size as i64;
                         self.mem_regions.push(value);
@@ -384,7 +1622,7 @@ impl ResourceAllocator {
         if needed_mem > 0 {
             for page_info in HUGE_PAGE_MAP.iter().rev() {
                 while needed_mem > 0 {
-                    match MemoryRegion::new(page_info.0) {
+                    match MemoryRegion::new(page_info.0, EagerFault::default()) {
                         Ok(value) => {
                             needed_mem -= value.mem_size as i64;
                             self.mem_regions.push(value);
@@ -410,7 +1648,7 @@ impl ResourceAllocator {
         // At this point, we may have allocated more than we need, so we release all
         // regions we no longer need, starting with the smallest ones.
         self.mem_regions
-            .sort_by(|reg1, reg2| reg2.mem_size.cmp(&reg1.mem_size));
+            .sort_by_key(|reg| std::cmp::Reverse(reg.mem_size));
 
         needed_mem = self.requested_mem as i64;
         for region in self.mem_regions.iter() {
@@ -435,7 +1673,8 @@ impl ResourceAllocator {
             }
         }
 
-        info!(
+        enclave_log!(
+            info,
             "Allocated {} region(s): {}",
             self.mem_regions.len(),
             allocated_pages
@@ -518,7 +1757,7 @@ impl EnclaveHandle {
             })?;
 
         let mut slot_uid: u64 = 0;
-        let enc_fd = EnclaveHandle::do_ioctl(dev_file.as_raw_fd(), NE_CREATE_VM, &mut slot_uid)
+        let enc_fd = ne_ioctl(dev_file.as_raw_fd(), NeCreateVm, &mut slot_uid)
             .map_err(|e| e.add_subaction("Create VM ioctl failed".to_string()))?;
         let flags: u64 = if debug_mode { NE_ENCLAVE_DEBUG_MODE } else { 0 };
 
@@ -529,6 +1768,10 @@ impl EnclaveHandle {
             ));
         }
 
+        if let Ok(enclave_id) = generate_enclave_id(slot_uid) {
+            set_log_enclave_id(&enclave_id);
+        }
+
         Ok(EnclaveHandle {
             cpu_config,
             cpu_ids: vec![],
@@ -543,6 +1786,7 @@ impl EnclaveHandle {
             state: EnclaveState::default(),
             build_info: EnclaveBuildInfo::new(BTreeMap::new()),
             metadata: None,
+            started_at: None,
         })
     }
 
@@ -556,6 +1800,7 @@ impl EnclaveHandle {
             .map_err(|e| e.add_subaction("Memory initialization issue".to_string()))?;
         self.init_cpus()
             .map_err(|e| e.add_subaction("vCPUs initialization issue".to_string()))?;
+        self.check_numa_locality();
 
         let sockaddr = VsockAddr::new(VMADDR_CID_PARENT, ENCLAVE_READY_VSOCK_PORT);
         let listener = VsockListener::bind(&sockaddr).map_err(|_| {
@@ -661,32 +1906,32 @@ impl EnclaveHandle {
             flags: NE_EIF_IMAGE,
             memory_offset: 0,
         };
-        EnclaveHandle::do_ioctl(self.enc_fd, NE_GET_IMAGE_LOAD_INFO, &mut image_load_info)
+        ne_ioctl(self.enc_fd, NeGetImageLoadInfo, &mut image_load_info)
             .map_err(|e| e.add_subaction("Get image load info ioctl failed".to_string()))?;
 
-        debug!("Memory load information: {:?}", image_load_info);
+        enclave_log!(debug, "Memory load information: {:?}", image_load_info);
         write_eif_to_regions(eif_file, regions, image_load_info.memory_offset as usize)
             .map_err(|e| e.add_subaction("Write EIF to enclave memory regions".to_string()))?;
 
         // Provide the regions to the driver for ownership change.
         for region in regions {
             let mut user_mem_region: UserMemoryRegion = region.into();
-            EnclaveHandle::do_ioctl(self.enc_fd, NE_SET_USER_MEMORY_REGION, &mut user_mem_region)
+            ne_ioctl(self.enc_fd, NeSetUserMemoryRegion, &mut user_mem_region)
                 .map_err(|e| e.add_subaction("Set user memory region ioctl failed".to_string()))?;
         }
 
-        info!("Finished initializing memory.");
+        enclave_log!(info, "Finished initializing memory.");
 
         Ok(())
     }
 
     /// Initialize a single vCPU from a given ID.
     fn init_single_cpu(&mut self, mut cpu_id: u32) -> NitroCliResult<()> {
-        EnclaveHandle::do_ioctl(self.enc_fd, NE_ADD_VCPU, &mut cpu_id)
+        ne_ioctl(self.enc_fd, NeAddVcpu, &mut cpu_id)
             .map_err(|e| e.add_subaction("Add vCPU ioctl failed".to_string()))?;
 
         self.cpu_ids.push(cpu_id);
-        debug!("Added CPU with ID {}.", cpu_id);
+        enclave_log!(debug, "Added CPU with ID {}.", cpu_id);
 
         Ok(())
     }
@@ -713,6 +1958,41 @@ impl EnclaveHandle {
         Ok(())
     }
 
+    /// Warn if the enclave memory and vCPUs have not all been taken from the same NUMA node.
+    fn check_numa_locality(&self) {
+        let mut mem_nodes = BTreeSet::new();
+        for region in self.resource_allocator.mem_regions.iter() {
+            match region.numa_node() {
+                Ok(node) => {
+                    mem_nodes.insert(node);
+                }
+                Err(e) => {
+                    enclave_log!(
+                        debug,
+                        "Failed to get memory region NUMA node: {:?}",
+                        e.subactions
+                    );
+                    return;
+                }
+            }
+        }
+
+        let cpu_nodes: BTreeSet<u32> = self
+            .cpu_ids
+            .iter()
+            .filter_map(|&cpu_id| get_cpu_numa_node(cpu_id))
+            .collect();
+
+        if !cpu_nodes.is_empty() && mem_nodes.union(&cpu_nodes).count() > 1 {
+            enclave_log!(
+                warn,
+                "Enclave memory is on NUMA node(s) {:?}, while its vCPUs are on node(s) {:?}.",
+                mem_nodes,
+                cpu_nodes
+            );
+        }
+    }
+
     /// Start an enclave after providing it with its necessary resources.
     fn start(&mut self, connection: Option<&Connection>) -> NitroCliResult<EnclaveStartInfo> {
         let mut start = EnclaveStartInfo {
@@ -720,8 +2000,9 @@ impl EnclaveHandle {
             enclave_cid: self.enclave_cid.unwrap_or(0),
         };
 
-        EnclaveHandle::do_ioctl(self.enc_fd, NE_START_ENCLAVE, &mut start)
+        ne_ioctl(self.enc_fd, NeStartEnclave, &mut start)
             .map_err(|e| e.add_subaction("Start enclave ioctl failed".to_string()))?;
+        self.started_at = Some((Instant::now(), SystemTime::now()));
 
         safe_conn_eprintln(
             connection,
@@ -747,7 +2028,7 @@ impl EnclaveHandle {
             self.resource_allocator
                 .free()
                 .map_err(|e| e.add_subaction("Failed to release used memory".to_string()))?;
-            info!("Enclave terminated.");
+            enclave_log!(info, "Enclave terminated.");
 
             // Mark enclave as terminated.
             self.clear();
@@ -781,88 +2062,16 @@ impl EnclaveHandle {
         self.enclave_cid = Some(0);
         self.enc_fd = -1;
         self.slot_uid = 0;
+        self.started_at = None;
     }
 
     /// Terminate the enclave if `run-enclave` failed.
     fn terminate_enclave_error(&mut self, err: &str) {
         let err_msg = format!("{}. Terminating the enclave...", err);
 
-        // Notify the user and the logger of the error, then terminate the enclave.
-        notify_error(&err_msg);
-        self.terminate_enclave_and_notify();
-    }
-
-    /// Wrapper over an `ioctl()` operation
-    fn do_ioctl<T>(fd: RawFd, ioctl_code: u64, arg: &mut T) -> NitroCliResult<i32> {
-        let rc = unsafe { libc::ioctl(fd, ioctl_code as _, arg) };
-        if rc >= 0 {
-            return Ok(rc);
-        }
-
-        let err_msg = match Error::last_os_error().raw_os_error().unwrap_or(0) as u32 {
-            NE_ERR_VCPU_ALREADY_USED => "The provided vCPU is already used".to_string(),
-            NE_ERR_VCPU_NOT_IN_CPU_POOL => {
-                "The provided vCPU is not available in the CPU pool".to_string()
-            }
-            NE_ERR_VCPU_INVALID_CPU_CORE => {
-                "The vCPU core ID is invalid for the CPU pool".to_string()
-            }
-            NE_ERR_INVALID_MEM_REGION_SIZE => {
-                "The memory region's size is not a multiple of 2 MiB".to_string()
-            }
-            NE_ERR_INVALID_MEM_REGION_ADDR => "The memory region's address is invalid".to_string(),
-            NE_ERR_UNALIGNED_MEM_REGION_ADDR => {
-                "The memory region's address is not aligned".to_string()
-            }
-            NE_ERR_MEM_REGION_ALREADY_USED => "The memory region is already used".to_string(),
-            NE_ERR_MEM_NOT_HUGE_PAGE => {
-                "The memory region is not backed by contiguous physical huge page(s)".to_string()
-            }
-            NE_ERR_MEM_DIFFERENT_NUMA_NODE => {
-                "The memory region's pages and the CPUs belong to different NUMA nodes".to_string()
-            }
-            NE_ERR_MEM_MAX_REGIONS => {
-                "The maximum number of memory regions per enclave has been reached".to_string()
-            }
-            NE_ERR_NO_MEM_REGIONS_ADDED => {
-                "The enclave cannot start because no memory regions have been added".to_string()
-            }
-            NE_ERR_NO_VCPUS_ADDED => {
-                "The enclave cannot start because no vCPUs have been added".to_string()
-            }
-            NE_ERR_ENCLAVE_MEM_MIN_SIZE => {
-                "The enclave's memory size is lower than the minimum supported".to_string()
-            }
-            NE_ERR_FULL_CORES_NOT_USED => {
-                "The enclave cannot start because full CPU cores have not been set".to_string()
-            }
-            NE_ERR_NOT_IN_INIT_STATE => {
-                "The enclave is in an incorrect state to set resources or start".to_string()
-            }
-            NE_ERR_INVALID_VCPU => {
-                "The provided vCPU is out of range of the available CPUs".to_string()
-            }
-            NE_ERR_NO_CPUS_AVAIL_IN_POOL => {
-                "The enclave cannot be created because no CPUs are available in the pool"
-                    .to_string()
-            }
-            NE_ERR_INVALID_PAGE_SIZE => {
-                "The memory region is not backed by page(s) multiple of 2 MiB".to_string()
-            }
-            NE_ERR_INVALID_FLAG_VALUE => {
-                "The provided flags value in the ioctl arg data structure is invalid".to_string()
-            }
-            NE_ERR_INVALID_ENCLAVE_CID => {
-                "The provided enclave CID is invalid, being a well-known CID or the parent VM CID"
-                    .to_string()
-            }
-            e => format!("An error has occurred: {} (rc: {})", e, rc),
-        };
-
-        Err(new_nitro_cli_failure!(
-            &err_msg,
-            NitroCliErrorEnum::IoctlFailure
-        ))
+        // Notify the user and the logger of the error, then terminate the enclave.
+        notify_error(&err_msg);
+        self.terminate_enclave_and_notify();
     }
 }
 
@@ -870,7 +2079,7 @@ impl Drop for EnclaveHandle {
     fn drop(&mut self) {
         // Check if we are (still) owning an enclave.
         if self.enclave_cid.unwrap_or(0) == 0 {
-            debug!("Resource manager does not hold an enclave.");
+            enclave_log!(debug, "Resource manager does not hold an enclave.");
             return;
         }
 
@@ -899,6 +2108,18 @@ impl EnclaveManager {
         })
     }
 
+    /// Create a manager which holds the given enclave descriptor, without any enclave memory.
+    #[cfg(test)]
+    pub(crate) fn with_descriptor(enc_fd: RawFd, enclave_cid: u64) -> Self {
+        let mut enclave_handle = EnclaveHandle::default();
+        enclave_handle.enclave_cid = Some(enclave_cid);
+        enclave_handle.enc_fd = enc_fd;
+        EnclaveManager {
+            enclave_handle: Arc::new(Mutex::new(enclave_handle)),
+            ..Default::default()
+        }
+    }
+
     /// Launch an enclave using the previously-set configuration.
     ///
     /// The enclave handle is locked throughout enclave creation. This is fine, since
@@ -981,6 +2202,21 @@ impl EnclaveManager {
         Ok(locked_handle.build_info.clone())
     }
 
+    /// Get how long the enclave has been running for, together with the wall-clock time at which
+    /// it has been started. The uptime is measured on the monotonic clock, so it isn't affected by
+    /// adjustments of the system time.
+    pub fn get_uptime(&self) -> NitroCliResult<Option<(Duration, SystemTime)>> {
+        let locked_handle = self.enclave_handle.lock().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to acquire lock: {:?}", e),
+                NitroCliErrorEnum::LockAcquireFailure
+            )
+        })?;
+        Ok(locked_handle
+            .started_at
+            .map(|(instant, time)| (instant.elapsed(), time)))
+    }
+
     /// Get metadata from enclave handle
     pub fn get_metadata(&self) -> NitroCliResult<Option<EifIdentityInfo>> {
         let locked_handle = self.enclave_handle.lock().map_err(|e| {
@@ -1087,9 +2323,842 @@ impl EnclaveManager {
             .clear();
         Ok(())
     }
+
+    /// Release the enclave descriptor and free the enclave memory, if the enclave is still held.
+    /// Unlike `terminate_enclave()`, nothing is done once the enclave has been terminated.
+    pub fn release_enclave(&mut self) -> NitroCliResult<()> {
+        self.enclave_handle
+            .lock()
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to acquire lock: {:?}", e),
+                    NitroCliErrorEnum::LockAcquireFailure
+                )
+            })?
+            .terminate_enclave()
+    }
+}
+
+/// Find the kernel page size (in bytes) of the mapping which contains `addr`, given the
+/// content of an `smaps` file.
+fn get_backing_page_size<B: BufRead>(smaps: B, addr: u64) -> Option<u64> {
+    let mut in_mapping = false;
+
+    for line in smaps.lines().map_while(Result::ok) {
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().unwrap_or("");
+
+        // Mapping headers start with an address range, such as "7f0000000000-7f0000200000".
+        if let Some((start, end)) = first.split_once('-') {
+            if let (Ok(start), Ok(end)) =
+                (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16))
+            {
+                in_mapping = addr >= start && addr < end;
+                continue;
+            }
+        }
+
+        if in_mapping && first == "KernelPageSize:" {
+            return tokens
+                .next()
+                .and_then(|size| size.parse::<u64>().ok())
+                .map(|size| size * KiB);
+        }
+    }
+
+    None
+}
+
+/// Get the host memory used by the given memory regions, each given by its address and size,
+/// as reported by `/proc/self/smaps`.
+pub fn get_host_memory_stats(regions: &[(u64, u64)]) -> NitroCliResult<MemStats> {
+    let smaps = File::open(SMAPS_FILEPATH).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to open {}: {:?}", SMAPS_FILEPATH, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![SMAPS_FILEPATH, "Open"])
+    })?;
+
+    Ok(parse_host_memory_stats(BufReader::new(smaps), regions))
+}
+
+/// Get the host memory used by the given memory regions, given the content of an `smaps` file.
+/// A mapping which holds several regions is only counted once.
+fn parse_host_memory_stats<B: BufRead>(smaps: B, regions: &[(u64, u64)]) -> MemStats {
+    let mappings = parse_smaps(smaps);
+    let mut counted = vec![false; mappings.len()];
+    let mut stats = MemStats::default();
+
+    for &(addr, size) in regions {
+        let end = addr.saturating_add(size);
+        let mut holders = 0;
+        let mut covered = 0;
+
+        for (index, mapping) in mappings.iter().enumerate() {
+            if mapping.start >= end || mapping.end <= addr {
+                continue;
+            }
+
+            holders += 1;
+            covered += mapping.end.min(end) - mapping.start.max(addr);
+            if !counted[index] {
+                counted[index] = true;
+                stats.rss_bytes += (mapping.rss + mapping.hugetlb) * KiB;
+                if mapping.kernel_page_size * KiB >= MIN_ENCLAVE_PAGE_SIZE {
+                    stats.hugepages += mapping.hugetlb / mapping.kernel_page_size;
+                }
+            }
+        }
+
+        stats.split |= holders != 1 || covered != size;
+    }
+
+    stats
+}
+
+/// Parse the mappings listed in an `smaps` file, in their order of appearance.
+fn parse_smaps<B: BufRead>(smaps: B) -> Vec<SmapsMapping> {
+    let mut mappings: Vec<SmapsMapping> = Vec::new();
+
+    for line in smaps.lines().map_while(Result::ok) {
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().unwrap_or("");
+
+        if let Some((start, end)) = first.split_once('-') {
+            if let (Ok(start), Ok(end)) =
+                (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16))
+            {
+                mappings.push(SmapsMapping {
+                    start,
+                    end,
+                    ..Default::default()
+                });
+                continue;
+            }
+        }
+
+        let (mapping, value) = match (
+            mappings.last_mut(),
+            tokens.next().and_then(|value| value.parse::<u64>().ok()),
+        ) {
+            (Some(mapping), Some(value)) => (mapping, value),
+            _ => continue,
+        };
+        match first {
+            "Rss:" => mapping.rss = value,
+            "Shared_Hugetlb:" | "Private_Hugetlb:" => mapping.hugetlb += value,
+            "KernelPageSize:" => mapping.kernel_page_size = value,
+            _ => (),
+        }
+    }
+
+    mappings
+}
+
+/// Get the NUMA node holding most of the pages of the mapping which starts at, or otherwise
+/// contains, `addr`, as reported by a `numa_maps` file.
+fn get_numa_node<B: BufRead>(numa_maps: B, addr: u64) -> Option<u32> {
+    // Each line describes a mapping by its start address, in increasing order.
+    let mapping = numa_maps
+        .lines()
+        .map_while(Result::ok)
+        .take_while(|line| {
+            line.split_whitespace()
+                .next()
+                .and_then(|start| u64::from_str_radix(start, 16).ok())
+                .map_or(false, |start| start <= addr)
+        })
+        .last()?;
+
+    // Per-node page counts are listed as "N<node>=<pages>".
+    let node = mapping
+        .split_whitespace()
+        .filter_map(|token| token.strip_prefix('N')?.split_once('='))
+        .filter_map(|(node, pages)| Some((node.parse::<u32>().ok()?, pages.parse::<u64>().ok()?)))
+        .max_by_key(|&(_, pages)| pages)
+        .map_or(0, |(node, _)| node);
+
+    Some(node)
+}
+
+/// Read the NE CPU pool, which is configured as a CPU list (such as `2-3,6-7`). A pool which is
+/// empty or missing because the NE driver module hasn't been configured is returned as empty.
+pub fn read_cpu_pool() -> NitroCliResult<CpuPool> {
+    match std::fs::read_to_string(POOL_FILENAME) {
+        Ok(cpu_list) => parse_cpu_pool(&cpu_list),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CpuPool::default()),
+        Err(e) => Err(new_nitro_cli_failure!(
+            &format!("Failed to read the NE CPU pool: {}", e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![POOL_FILENAME, "Read"])),
+    }
+}
+
+/// Parse the content of the NE CPU pool file.
+fn parse_cpu_pool(cpu_list: &str) -> NitroCliResult<CpuPool> {
+    let cpu_list = cpu_list.trim();
+    if cpu_list.is_empty() {
+        return Ok(CpuPool::default());
+    }
+
+    let cpu_ids = CpuInfo::parse_cpu_pool_line(cpu_list)
+        .map_err(|e| e.add_subaction("Failed to parse the NE CPU pool".to_string()))?;
+
+    Ok(CpuPool {
+        cpu_ids: cpu_ids.into_iter().collect(),
+    })
+}
+
+/// Get CPU 0 along with its hyperthread siblings, which the host keeps for itself by default.
+pub fn host_reserved_cpus() -> Vec<u32> {
+    host_reserved_cpus_in(Path::new(CPU_SYSFS_DIR))
+}
+
+/// Get the CPUs of core 0, based on the topology found in the given sysfs directory. CPU 0 is
+/// reserved even if its siblings can't be read.
+fn host_reserved_cpus_in(cpu_sysfs_dir: &Path) -> Vec<u32> {
+    get_thread_siblings(cpu_sysfs_dir, 0).unwrap_or_else(|_| vec![0])
+}
+
+/// Select `cpu_count` CPUs from the NE CPU pool such that only whole cores are used, so that
+/// no hyperthread sibling of an enclave vCPU is left running host workloads. Core 0 is left out,
+/// with a warning, unless `allow_cpu0` is set.
+pub fn select_whole_cores(
+    cpu_pool: &[u32],
+    cpu_count: u32,
+    allow_cpu0: UnsafeAllowCpu0,
+) -> NitroCliResult<Vec<u32>> {
+    select_whole_cores_in(Path::new(CPU_SYSFS_DIR), cpu_pool, cpu_count, allow_cpu0)
+}
+
+/// Select whole cores from the CPU pool, based on the topology found in the given sysfs directory.
+fn select_whole_cores_in(
+    cpu_sysfs_dir: &Path,
+    cpu_pool: &[u32],
+    cpu_count: u32,
+    allow_cpu0: UnsafeAllowCpu0,
+) -> NitroCliResult<Vec<u32>> {
+    let mut pool: BTreeSet<u32> = cpu_pool.iter().cloned().collect();
+    if !allow_cpu0.0 {
+        let reserved: Vec<u32> = host_reserved_cpus_in(cpu_sysfs_dir)
+            .into_iter()
+            .filter(|cpu_id| pool.remove(cpu_id))
+            .collect();
+        if !reserved.is_empty() {
+            enclave_log!(
+                warn,
+                "CPU(s) {:?} of core 0 are in the NE CPU pool, but are kept for the host.",
+                reserved
+            );
+        }
+    }
+
+    let mut cores: BTreeSet<Vec<u32>> = BTreeSet::new();
+
+    for &cpu_id in pool.iter() {
+        let siblings = get_thread_siblings(cpu_sysfs_dir, cpu_id)?;
+        let missing: Vec<u32> = siblings
+            .iter()
+            .filter(|sibling| !pool.contains(sibling))
+            .cloned()
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "The CPU with ID {} is in the NE CPU pool, but its sibling(s) {:?} are not",
+                    cpu_id, missing
+                ),
+                NitroCliErrorEnum::IncompleteCpuCore
+            )
+            .add_info(vec![&format!("{:?}", siblings), &format!("{:?}", missing)]));
+        }
+
+        cores.insert(siblings);
+    }
+
+    let mut selected: Vec<u32> = Vec::new();
+    for core in cores {
+        if selected.len() >= cpu_count as usize {
+            break;
+        }
+
+        if selected.len() + core.len() > cpu_count as usize {
+            let left_out = &core[cpu_count as usize - selected.len()..];
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Cannot allocate {} CPUs as whole cores, since core {:?} would be split",
+                    cpu_count, core
+                ),
+                NitroCliErrorEnum::IncompleteCpuCore
+            )
+            .add_info(vec![&format!("{:?}", core), &format!("{:?}", left_out)]));
+        }
+
+        selected.extend(core);
+    }
+
+    if selected.len() < cpu_count as usize {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Insufficient CPUs available (requested {}, but maximum is {})",
+                cpu_count,
+                selected.len()
+            ),
+            NitroCliErrorEnum::InsufficientCpus
+        )
+        .add_info(vec!["cpu-count", &cpu_count.to_string()]));
+    }
+
+    Ok(selected)
+}
+
+/// Select `count` CPUs from the NE CPU pool which form whole cores, as `select_whole_cores()`
+/// does, and return their IDs in ascending order. On hosts with SMT, a count which would split
+/// a core results in an `IncompleteCpuCore` error, and a pool without enough whole cores results
+/// in an `InsufficientCpus` error.
+pub fn select_cpus(
+    pool: &CpuPool,
+    count: u32,
+    allow_cpu0: UnsafeAllowCpu0,
+) -> NitroCliResult<Vec<u32>> {
+    select_cpus_in(Path::new(CPU_SYSFS_DIR), pool, count, allow_cpu0)
+}
+
+/// Select CPUs from the CPU pool, based on the topology found in the given sysfs directory.
+fn select_cpus_in(
+    cpu_sysfs_dir: &Path,
+    pool: &CpuPool,
+    count: u32,
+    allow_cpu0: UnsafeAllowCpu0,
+) -> NitroCliResult<Vec<u32>> {
+    let mut selected = select_whole_cores_in(cpu_sysfs_dir, &pool.available(), count, allow_cpu0)
+        .map_err(|e| e.add_subaction(format!("Failed to select {} CPUs", count)))?;
+    selected.sort_unstable();
+
+    Ok(selected)
+}
+
+/// Get the sorted list of hyperthread siblings of a CPU, including the CPU itself.
+fn get_thread_siblings(cpu_sysfs_dir: &Path, cpu_id: u32) -> NitroCliResult<Vec<u32>> {
+    let siblings_path = cpu_sysfs_dir.join(format!("cpu{}/topology/thread_siblings_list", cpu_id));
+    let siblings_str = std::fs::read_to_string(&siblings_path).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read thread siblings of CPU {}: {}", cpu_id, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![
+            siblings_path.to_str().unwrap_or("thread_siblings_list"),
+            "Read",
+        ])
+    })?;
+
+    let mut siblings = CpuInfo::parse_cpu_pool_line(siblings_str.trim())?;
+    siblings.sort_unstable();
+    siblings.dedup();
+
+    Ok(siblings)
+}
+
+/// Issue an NE ioctl on the given descriptor. The argument is mutable since the driver may
+/// write results back into it, and its type is the one which `C` ties to the command. An
+/// ioctl interrupted by a signal is retried, up to `NE_IOCTL_MAX_EINTR_RETRIES` times.
+pub fn ne_ioctl<C: NeIoctlCommand>(fd: RawFd, _cmd: C, arg: &mut C::Arg) -> NitroCliResult<i32> {
+    let cmd = C::IOCTL;
+    let errno = match retry_ne_ioctl::<C>(fd, arg) {
+        Ok(rc) => return Ok(rc),
+        Err(errno) => errno,
+    };
+
+    // The driver reports a requested CID which it can't assign with a standard error code.
+    if cmd == NeIoctl::StartEnclave {
+        match errno {
+            libc::EADDRINUSE => {
+                return Err(new_nitro_cli_failure!(
+                    &format!(
+                        "{} ioctl failed: The requested enclave CID is already in use",
+                        cmd
+                    ),
+                    NitroCliErrorEnum::CidInUse
+                ))
+            }
+            libc::EOPNOTSUPP => {
+                return Err(new_nitro_cli_failure!(
+                    &format!(
+                        "{} ioctl failed: The NE driver does not support selecting the enclave CID",
+                        cmd
+                    ),
+                    NitroCliErrorEnum::CidSelectionUnsupported
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    Err(new_nitro_cli_failure!(
+        &format!("{} ioctl failed: {}", cmd, ne_ioctl_error_message(errno)),
+        NitroCliErrorEnum::IoctlFailure
+    ))
+}
+
+/// Issue an NE ioctl as `ne_ioctl()` does, reporting a failure as `NitroCliError::IoctlFailed`,
+/// which holds the error code returned by the driver, for callers which match on it.
+pub fn try_ne_ioctl<C: NeIoctlCommand>(
+    fd: RawFd,
+    _cmd: C,
+    arg: &mut C::Arg,
+) -> NitroCliResult<i32, NitroCliError> {
+    retry_ne_ioctl::<C>(fd, arg).map_err(|code| NitroCliError::IoctlFailed {
+        code,
+        ioctl: C::IOCTL.name(),
+    })
+}
+
+/// Issue an NE ioctl, retrying it while it is interrupted by a signal, and return the error
+/// code of a failed ioctl.
+fn retry_ne_ioctl<C: NeIoctlCommand>(fd: RawFd, arg: &mut C::Arg) -> Result<i32, i32> {
+    let mut retries = 0;
+    loop {
+        match unsafe { issue_ne_ioctl(fd, C::IOCTL, arg as *mut C::Arg as *mut libc::c_void) } {
+            Err(libc::EINTR) if retries < NE_IOCTL_MAX_EINTR_RETRIES => retries += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Get the description of an error code returned by a failed NE ioctl.
+pub fn ne_ioctl_error_message(errno: i32) -> String {
+    if errno == libc::EINTR {
+        return format!(
+            "Interrupted by a signal {} times in a row",
+            NE_IOCTL_MAX_EINTR_RETRIES + 1
+        );
+    }
+
+    match errno as u32 {
+        NE_ERR_VCPU_ALREADY_USED => "The provided vCPU is already used".to_string(),
+        NE_ERR_VCPU_NOT_IN_CPU_POOL => {
+            "The provided vCPU is not available in the CPU pool".to_string()
+        }
+        NE_ERR_VCPU_INVALID_CPU_CORE => "The vCPU core ID is invalid for the CPU pool".to_string(),
+        NE_ERR_INVALID_MEM_REGION_SIZE => {
+            "The memory region's size is not a multiple of 2 MiB".to_string()
+        }
+        NE_ERR_INVALID_MEM_REGION_ADDR => "The memory region's address is invalid".to_string(),
+        NE_ERR_UNALIGNED_MEM_REGION_ADDR => {
+            "The memory region's address is not aligned".to_string()
+        }
+        NE_ERR_MEM_REGION_ALREADY_USED => "The memory region is already used".to_string(),
+        NE_ERR_MEM_NOT_HUGE_PAGE => {
+            "The memory region is not backed by contiguous physical huge page(s)".to_string()
+        }
+        NE_ERR_MEM_DIFFERENT_NUMA_NODE => {
+            "The memory region's pages and the CPUs belong to different NUMA nodes".to_string()
+        }
+        NE_ERR_MEM_MAX_REGIONS => {
+            "The maximum number of memory regions per enclave has been reached".to_string()
+        }
+        NE_ERR_NO_MEM_REGIONS_ADDED => {
+            "The enclave cannot start because no memory regions have been added".to_string()
+        }
+        NE_ERR_NO_VCPUS_ADDED => {
+            "The enclave cannot start because no vCPUs have been added".to_string()
+        }
+        NE_ERR_ENCLAVE_MEM_MIN_SIZE => {
+            "The enclave's memory size is lower than the minimum supported".to_string()
+        }
+        NE_ERR_FULL_CORES_NOT_USED => {
+            "The enclave cannot start because full CPU cores have not been set".to_string()
+        }
+        NE_ERR_NOT_IN_INIT_STATE => {
+            "The enclave is in an incorrect state to set resources or start".to_string()
+        }
+        NE_ERR_INVALID_VCPU => {
+            "The provided vCPU is out of range of the available CPUs".to_string()
+        }
+        NE_ERR_NO_CPUS_AVAIL_IN_POOL => {
+            "The enclave cannot be created because no CPUs are available in the pool".to_string()
+        }
+        NE_ERR_INVALID_PAGE_SIZE => {
+            "The memory region is not backed by page(s) multiple of 2 MiB".to_string()
+        }
+        NE_ERR_INVALID_FLAG_VALUE => {
+            "The provided flags value in the ioctl arg data structure is invalid".to_string()
+        }
+        NE_ERR_INVALID_ENCLAVE_CID => {
+            "The provided enclave CID is invalid, being a well-known CID or the parent VM CID"
+                .to_string()
+        }
+        e => format!("An error has occurred: {} (rc: -1)", e),
+    }
+}
+
+/// Check whether all pages of a mapped address range are resident in memory.
+fn is_range_resident(addr: u64, size: u64) -> bool {
+    if addr == 0 {
+        return false;
+    }
+
+    // The residency vector has one entry per base page, even for hugepage mappings.
+    let base_page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    let mut pages = vec![0u8; ceil_div(size, base_page_size) as usize];
+    let rc = unsafe { libc::mincore(addr as *mut libc::c_void, size as usize, pages.as_mut_ptr()) };
+
+    rc == 0 && pages.iter().all(|page| page & 1 == 1)
+}
+
+/// Periodically check that a memory region stays resident, until asked to stop.
+fn residency_monitor(mem_addr: u64, mem_size: u64, interval: Duration, stop: Arc<AtomicBool>) {
+    let mut was_resident = true;
+
+    enclave_log!(
+        debug,
+        "Residency monitor started for memory region at {:#x}.",
+        mem_addr
+    );
+
+    while !stop.load(Ordering::SeqCst) {
+        thread::park_timeout(interval);
+        // The flag is checked again since the region may have been unmapped in the meantime.
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let is_resident = is_range_resident(mem_addr, mem_size);
+        if was_resident && !is_resident {
+            enclave_log!(
+                error,
+                "Memory region at {:#x} ({} bytes) is no longer fully resident.",
+                mem_addr,
+                mem_size
+            );
+        }
+        was_resident = is_resident;
+    }
+
+    enclave_log!(
+        debug,
+        "Residency monitor stopped for memory region at {:#x}.",
+        mem_addr
+    );
+}
+
+/// Find the first hugetlbfs mount which provides pages of the given size.
+pub fn find_hugetlbfs_mount(page_size: HugePageSize) -> NitroCliResult<PathBuf> {
+    let mounts = std::fs::read_to_string(MOUNTS_FILEPATH).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read {}: {}", MOUNTS_FILEPATH, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![MOUNTS_FILEPATH, "Read"])
+    })?;
+    // Mounts without a `pagesize` option use the default hugepage size.
+    let default_page_size = std::fs::read_to_string(MEMINFO_FILEPATH)
+        .ok()
+        .and_then(|meminfo| parse_default_hugepage_size(&meminfo));
+
+    find_hugetlbfs_mount_in(&mounts, page_size, default_page_size)
+}
+
+/// Find the first hugetlbfs mount with the given page size among the `/proc/mounts` lines.
+fn find_hugetlbfs_mount_in(
+    mounts: &str,
+    page_size: HugePageSize,
+    default_page_size: Option<u64>,
+) -> NitroCliResult<PathBuf> {
+    let mut considered = Vec::new();
+
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || fields[2] != "hugetlbfs" {
+            continue;
+        }
+
+        let mount_point = unescape_mount_field(fields[1]);
+        let mount_page_size = fields[3]
+            .split(',')
+            .find_map(|option| option.strip_prefix("pagesize="))
+            .map_or(default_page_size, parse_mount_page_size);
+        if mount_page_size == Some(page_size.size()) {
+            return Ok(PathBuf::from(mount_point));
+        }
+
+        considered.push(match mount_page_size {
+            Some(size) => format!("{} ({} KiB pages)", mount_point, size / KiB),
+            None => format!("{} (unknown page size)", mount_point),
+        });
+    }
+
+    let considered = if considered.is_empty() {
+        "none".to_string()
+    } else {
+        considered.join(", ")
+    };
+
+    Err(new_nitro_cli_failure!(
+        &format!(
+            "No hugetlbfs mount provides {} pages (considered: {})",
+            page_size, considered
+        ),
+        NitroCliErrorEnum::NoHugetlbfsMount
+    )
+    .add_info(vec![&page_size.to_string(), &considered]))
+}
+
+/// Parse a hugetlbfs `pagesize` mount option, such as `2M` or `1024M`, into bytes.
+fn parse_mount_page_size(value: &str) -> Option<u64> {
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], KiB),
+        'M' => (&value[..value.len() - 1], MiB),
+        'G' => (&value[..value.len() - 1], GiB),
+        _ => (value, 1),
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parse the `Hugepagesize` entry of `/proc/meminfo` into bytes.
+fn parse_default_hugepage_size(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Hugepagesize:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kib| kib.trim().parse::<u64>().ok())
+        .map(|kib| kib * KiB)
+}
+
+/// Parse the `MemTotal` entry of `/proc/meminfo` into bytes.
+fn parse_meminfo_total(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kib| kib.trim().parse::<u64>().ok())
+        .map(|kib| kib * KiB)
+}
+
+/// Get the total amount of host memory (in bytes), if it can be determined.
+fn host_total_memory() -> Option<u64> {
+    std::fs::read_to_string(MEMINFO_FILEPATH)
+        .ok()
+        .and_then(|meminfo| parse_meminfo_total(&meminfo))
+}
+
+/// Move the current process into the cgroup v2 directory `path`, so that the memory it
+/// allocates from then on, including enclave memory, is accounted to that cgroup. Fails with
+/// `CgroupUnsupported` if `path` is not part of a cgroup v2 hierarchy, as on cgroup v1 hosts.
+pub fn attach_to_cgroup(path: &Path) -> NitroCliResult<()> {
+    attach_pid_to_cgroup(path, std::process::id())
+}
+
+/// Move the process with the given PID into the cgroup v2 directory `path`.
+fn attach_pid_to_cgroup(path: &Path, pid: u32) -> NitroCliResult<()> {
+    // Only cgroup v2 directories list their available controllers.
+    if !path.join("cgroup.controllers").is_file() {
+        return Err(new_nitro_cli_failure!(
+            &format!("{:?} is not a cgroup v2 directory", path),
+            NitroCliErrorEnum::CgroupUnsupported
+        )
+        .add_info(vec![&path.to_string_lossy()]));
+    }
+
+    let procs_path = path.join("cgroup.procs");
+    std::fs::write(&procs_path, pid.to_string()).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to move process {} into {:?}: {:?}", pid, path, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![&procs_path.to_string_lossy(), "Write"])
+    })?;
+    enclave_log!(info, "Moved process {} into cgroup {:?}.", pid, path);
+
+    Ok(())
+}
+
+/// Get the cgroup v2 directory which `ENCLAVE_CGROUP_ENV_VAR` sets for the enclave process.
+fn enclave_cgroup() -> Option<PathBuf> {
+    std::env::var_os(ENCLAVE_CGROUP_ENV_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Get the memory usage (in bytes) of the cgroup of the current process, as reported by its
+/// `memory.current`. Hugepages are only included there if the cgroup v2 hierarchy has been
+/// mounted with `memory_hugetlb_accounting`, and are otherwise reported by the `hugetlb`
+/// controller. Fails with `CgroupUnsupported` on cgroup v1 hosts.
+pub fn cgroup_memory_usage() -> NitroCliResult<u64> {
+    let membership = std::fs::read_to_string(PROC_SELF_CGROUP_FILEPATH).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read {}: {:?}", PROC_SELF_CGROUP_FILEPATH, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![PROC_SELF_CGROUP_FILEPATH, "Read"])
+    })?;
+
+    cgroup_memory_usage_in(&membership, Path::new(CGROUP_ROOT_DIR))
+}
+
+/// Get the memory usage of the cgroup v2 listed in `membership`, the contents of a
+/// `/proc/<pid>/cgroup` file, in the hierarchy mounted at `cgroup_root`.
+fn cgroup_memory_usage_in(membership: &str, cgroup_root: &Path) -> NitroCliResult<u64> {
+    // The cgroup v2 entry is the only one with hierarchy ID 0 and no controllers.
+    let cgroup = membership
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| {
+            new_nitro_cli_failure!(
+                "The current process is not in a cgroup v2 hierarchy",
+                NitroCliErrorEnum::CgroupUnsupported
+            )
+            .add_info(vec![PROC_SELF_CGROUP_FILEPATH])
+        })?;
+
+    let usage_path = cgroup_root
+        .join(cgroup.trim_start_matches('/'))
+        .join("memory.current");
+    std::fs::read_to_string(&usage_path)
+        .ok()
+        .and_then(|usage| usage.trim().parse::<u64>().ok())
+        .ok_or_else(|| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to read the cgroup memory usage from {:?}",
+                    usage_path
+                ),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![&usage_path.to_string_lossy(), "Read"])
+        })
+}
+
+/// Undo the octal escaping of whitespace and backslashes in a `/proc/mounts` field.
+fn unescape_mount_field(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut rest = field;
+
+    while let Some(index) = rest.find('\\') {
+        result.push_str(&rest[..index]);
+        let escaped = rest.get(index + 1..index + 4);
+        match escaped.and_then(|code| u8::from_str_radix(code, 8).ok()) {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Read a hugepage counter from sysfs.
+fn read_hugepage_count(path: &Path) -> NitroCliResult<u64> {
+    let path_str = path.to_str().unwrap_or("(invalid path)");
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to read hugepage count from {:?}: {}", path, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![path_str, "Read"])
+    })?;
+
+    content.trim().parse::<u64>().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Invalid hugepage count {:?} in {:?}: {}", content, path, e),
+            NitroCliErrorEnum::FileOperationFailure
+        )
+        .add_info(vec![path_str, "Parse"])
+    })
+}
+
+/// Get the number of hugepages of a given size which the host can hand out, excluding the free
+/// pages which are already reserved by existing mappings.
+fn get_available_hugepages(page_size: u64) -> Option<u64> {
+    let dir = Path::new(HUGEPAGES_SYSFS_DIR).join(format!("hugepages-{}kB", page_size / KiB));
+    let free = read_hugepage_count(&dir.join("free_hugepages")).ok()?;
+    let reserved = read_hugepage_count(&dir.join("resv_hugepages")).ok()?;
+    Some(free.saturating_sub(reserved))
+}
+
+/// Find the largest number of pages, up to `max_pages`, for which `probe` succeeds, assuming
+/// that it succeeds for all smaller counts as well.
+fn largest_mappable<F: FnMut(u64) -> bool>(max_pages: u64, mut probe: F) -> u64 {
+    let (mut low, mut high) = (0, max_pages);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if probe(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    low
+}
+
+/// Get the number of free hugepages of a given size on the host, which is 0 for sizes that the
+/// host doesn't support.
+fn get_host_free_hugepages(page_size: u64) -> u64 {
+    let path = Path::new(HUGEPAGES_SYSFS_DIR)
+        .join(format!("hugepages-{}kB", page_size / KiB))
+        .join("free_hugepages");
+    read_hugepage_count(&path).unwrap_or(0)
+}
+
+/// Get the number of free hugepages of a given size on a NUMA node.
+fn get_free_hugepages(node: u32, page_size: u64) -> u64 {
+    let read_count = |path: String| {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+    };
+
+    read_count(format!(
+        "{}/node{}/hugepages/hugepages-{}kB/free_hugepages",
+        NODE_SYSFS_DIR,
+        node,
+        page_size / KiB
+    ))
+    .or_else(|| {
+        // Without NUMA support, all hugepages belong to node 0.
+        if node != 0 {
+            return None;
+        }
+
+        read_count(format!(
+            "{}/hugepages-{}kB/free_hugepages",
+            HUGEPAGES_SYSFS_DIR,
+            page_size / KiB
+        ))
+    })
+    .unwrap_or(0)
+}
+
+/// Get the NUMA node of a CPU, if the kernel exposes it.
+fn get_cpu_numa_node(cpu_id: u32) -> Option<u32> {
+    let cpu_dir = format!("{}/cpu{}", CPU_SYSFS_DIR, cpu_id);
+
+    std::fs::read_dir(cpu_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse::<u32>()
+                .ok()
+        })
 }
 
-/// Write an enclave image file to the specified list of memory regions.
+/// Write an enclave image file to the specified list of memory regions, checking what has
+/// been written against the checksum recorded in the image.
 fn write_eif_to_regions(
     eif_file: &mut File,
     regions: &[MemoryRegion],
@@ -1112,7 +3181,11 @@ fn write_eif_to_regions(
         )
     })?;
 
+    let mem_size = regions.iter().map(|region| region.mem_size).sum();
+    check_image_fits(image_write_offset as u64, file_size as u64, mem_size)?;
+
     let mut total_written: usize = 0;
+    let mut checksum = EifChecksum::new();
 
     for region in regions {
         let offset_plus_file_size = file_size.checked_add(image_write_offset).ok_or_else(|| {
@@ -1146,7 +3219,7 @@ fn write_eif_to_regions(
                 file_size - file_offset,
             );
             region
-                .fill_from_file(eif_file, region_offset, size)
+                .fill_from_file(eif_file, region_offset, size, &mut checksum)
                 .map_err(|e| {
                     e.add_subaction("Failed to fill region with file content".to_string())
                 })?;
@@ -1154,6 +3227,29 @@ fn write_eif_to_regions(
         total_written += region.mem_size as usize;
     }
 
+    checksum
+        .verify()
+        .map_err(|e| e.add_subaction("Failed to verify the loaded EIF".to_string()))
+}
+
+/// Check that an image of `image_size` bytes, loaded at the `memory_offset` reported by the
+/// `NE_GET_IMAGE_LOAD_INFO` ioctl, fits in `mem_size` bytes of enclave memory.
+pub fn check_image_fits(memory_offset: u64, image_size: u64, mem_size: u64) -> NitroCliResult<()> {
+    let fits = memory_offset
+        .checked_add(image_size)
+        .map_or(false, |end| end <= mem_size);
+    if !fits {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "The enclave image of {} bytes, loaded at offset {:#x}, doesn't fit in the {} MiB of enclave memory",
+                image_size,
+                memory_offset,
+                mem_size / MiB
+            ),
+            NitroCliErrorEnum::MemoryOverflow
+        ));
+    }
+
     Ok(())
 }
 
@@ -1183,6 +3279,111 @@ pub fn between_packets_delay() -> Option<Duration> {
     None
 }
 
+/// Get the fraction of the host memory which must remain free after allocating enclave memory.
+/// It is read from the `NITRO_CLI_MEMORY_RESERVE_FRACTION` environment variable and falls back
+/// to the default when the variable is unset or not a number in `[0, 1)`.
+pub fn memory_reserve_fraction() -> f64 {
+    if let Ok(value) = std::env::var(MEMORY_RESERVE_FRACTION_ENV_VAR) {
+        match value.parse::<f64>() {
+            Ok(fraction) if (0.0..1.0).contains(&fraction) => return fraction,
+            _ => enclave_log!(
+                warn,
+                "Ignoring invalid {} value `{}`",
+                MEMORY_RESERVE_FRACTION_ENV_VAR,
+                value
+            ),
+        }
+    }
+
+    DEFAULT_MEMORY_RESERVE_FRACTION
+}
+
+/// Reject a request for `requested` bytes of enclave memory which would leave less than
+/// `reserve_fraction` of the `host_total` bytes of host memory free.
+fn enforce_memory_policy(
+    requested: u64,
+    host_total: u64,
+    reserve_fraction: f64,
+) -> NitroCliResult<()> {
+    let reserved = (host_total as f64 * reserve_fraction).ceil() as u64;
+    let allowed = host_total.saturating_sub(reserved);
+
+    if requested > allowed {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Requested {} MiB of enclave memory, but at most {} MiB may be used: {:.0}% of the {} MiB of host memory ({} MiB) must remain free",
+                requested / MiB,
+                allowed / MiB,
+                reserve_fraction * 100.0,
+                host_total / MiB,
+                reserved / MiB
+            ),
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        )
+        .add_info(vec!["memory", &(requested / MiB).to_string()]));
+    }
+
+    Ok(())
+}
+
+/// Downsize a request for `requested` bytes of `page_size` hugepages to what can currently be
+/// allocated, as long as at least `min_acceptable` bytes remain. This is an opt-in alternative
+/// to failing on a shortfall, and relies on the estimate of
+/// `MemoryRegion::largest_contiguous_available()`.
+pub fn clamp_to_available(
+    requested: u64,
+    page_size: u64,
+    min_acceptable: u64,
+) -> NitroCliResult<u64> {
+    let available = MemoryRegion::largest_contiguous_available(page_size)?;
+    clamp_size(requested, available, min_acceptable)
+}
+
+/// Clamp `requested` bytes to the `available` ones, failing if fewer than `min_acceptable`
+/// bytes would remain.
+fn clamp_size(requested: u64, available: u64, min_acceptable: u64) -> NitroCliResult<u64> {
+    if min_acceptable > requested {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "The minimum of {} MiB exceeds the {} MiB of requested memory",
+                min_acceptable / MiB,
+                requested / MiB
+            ),
+            NitroCliErrorEnum::InvalidArgument
+        )
+        .add_info(vec!["min-acceptable", &(min_acceptable / MiB).to_string()]));
+    }
+
+    if requested <= available {
+        return Ok(requested);
+    }
+
+    if available < min_acceptable {
+        return Err(new_nitro_cli_failure!(
+            &format!(
+                "Only {} MiB of memory can be allocated, below the minimum of {} MiB",
+                available / MiB,
+                min_acceptable / MiB
+            ),
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        )
+        .add_info(vec!["memory", &(min_acceptable / MiB).to_string()]));
+    }
+
+    enclave_log!(
+        warn,
+        "Clamping the requested {} MiB of enclave memory to the {} MiB which can be allocated",
+        requested / MiB,
+        available / MiB
+    );
+    Ok(available)
+}
+
+/// Check whether `requested` bytes exceed `CONTIGUOUS_MEMORY_WARN_PERCENT` of the `limit` bytes.
+fn approaches_limit(requested: u64, limit: u64) -> bool {
+    requested as u128 * 100 > limit as u128 * CONTIGUOUS_MEMORY_WARN_PERCENT as u128
+}
+
 /// Helper function which contains heuristic for enclave build timeout calculation
 ///
 /// # Arguments
@@ -1207,10 +3408,801 @@ pub fn calculate_necessary_timeout(eif_size: u64) -> c_int {
 
 #[cfg(test)]
 mod tests {
-    use super::calculate_necessary_timeout;
+    use super::*;
+    use crate::common::construct_error_message;
     use crate::enclave_proc::utils::GiB;
     use eif_loader::TIMEOUT_MINUTE_MS;
 
+    const SMAPS_SAMPLE: &str = "\
+55bcedace000-55bcedad0000 r--p 00000000 fe:00 280762    /usr/bin/head
+Size:                  8 kB
+KernelPageSize:        4 kB
+MMUPageSize:           4 kB
+7f0000000000-7f0000400000 rw-p 00000000 00:0f 1024      /anon_hugepage (deleted)
+Size:               4096 kB
+KernelPageSize:     2048 kB
+MMUPageSize:        2048 kB
+";
+
+    /// Tests that the backing page size is found for the mapping containing an address.
+    #[test]
+    fn test_get_backing_page_size() {
+        assert_eq!(
+            get_backing_page_size(SMAPS_SAMPLE.as_bytes(), 0x55bcedacf000),
+            Some(4 * KiB)
+        );
+        assert_eq!(
+            get_backing_page_size(SMAPS_SAMPLE.as_bytes(), 0x7f0000200000),
+            Some(2 * MiB)
+        );
+        assert_eq!(
+            get_backing_page_size(SMAPS_SAMPLE.as_bytes(), 0x7f0000400000),
+            None
+        );
+    }
+
+    /// Tests that the host memory of regions is summed over the mappings which hold them.
+    #[test]
+    fn test_parse_host_memory_stats() {
+        let smaps = "\
+7f0000000000-7f0000400000 rw-p 00000000 00:0f 1024      /anon_hugepage (deleted)
+Size:               4096 kB
+KernelPageSize:     2048 kB
+Rss:                   0 kB
+Shared_Hugetlb:        0 kB
+Private_Hugetlb:    4096 kB
+7f0000400000-7f0000600000 rw-p 00000000 00:00 0
+Size:               2048 kB
+KernelPageSize:        4 kB
+Rss:                  12 kB
+Shared_Hugetlb:        0 kB
+Private_Hugetlb:       0 kB
+7f0000600000-7f0000800000 r--p 00000000 00:00 0
+Size:               2048 kB
+KernelPageSize:        4 kB
+Rss:                   8 kB
+";
+
+        let stats = parse_host_memory_stats(smaps.as_bytes(), &[(0x7f0000000000, 4 * MiB)]);
+        assert_eq!(
+            stats,
+            MemStats {
+                rss_bytes: 4 * MiB,
+                hugepages: 2,
+                split: false,
+            }
+        );
+
+        // A mapping holding two regions is counted once.
+        let stats = parse_host_memory_stats(
+            smaps.as_bytes(),
+            &[(0x7f0000000000, 2 * MiB), (0x7f0000200000, 2 * MiB)],
+        );
+        assert_eq!(stats.rss_bytes, 4 * MiB);
+        assert_eq!(stats.hugepages, 2);
+        assert!(!stats.split);
+
+        // A region spread over two mappings is split.
+        let stats = parse_host_memory_stats(smaps.as_bytes(), &[(0x7f0000400000, 4 * MiB)]);
+        assert_eq!(stats.rss_bytes, 20 * KiB);
+        assert_eq!(stats.hugepages, 0);
+        assert!(stats.split);
+
+        // So is a region which is not entirely mapped.
+        let stats = parse_host_memory_stats(smaps.as_bytes(), &[(0x7f0000600000, 4 * MiB)]);
+        assert!(stats.split);
+        assert!(parse_host_memory_stats(smaps.as_bytes(), &[(0x1000, 2 * MiB)]).split);
+    }
+
+    /// Tests that each NE ioctl maps to its request code and failures are reported uniformly.
+    #[test]
+    fn test_ne_ioctl() {
+        assert_eq!(
+            NeIoctl::CreateVm.request_code(),
+            NE_CREATE_VM as libc::c_ulong
+        );
+        assert_eq!(
+            NeIoctl::SetUserMemoryRegion.request_code(),
+            NE_SET_USER_MEMORY_REGION as libc::c_ulong
+        );
+        assert_eq!(
+            NeIoctl::AddVcpu.request_code(),
+            NE_ADD_VCPU as libc::c_ulong
+        );
+        assert_eq!(
+            NeIoctl::StartEnclave.request_code(),
+            NE_START_ENCLAVE as libc::c_ulong
+        );
+        assert_eq!(
+            NeIoctl::GetImageLoadInfo.request_code(),
+            NE_GET_IMAGE_LOAD_INFO as libc::c_ulong
+        );
+
+        let mut cpu_id: u32 = 0;
+        let err = ne_ioctl(-1, NeAddVcpu, &mut cpu_id).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IoctlFailure);
+        assert!(err.subactions[0].starts_with("NE_ADD_VCPU ioctl failed"));
+    }
+
+    /// Create a fake CPU sysfs directory with the given thread sibling lists.
+    fn fake_cpu_sysfs(siblings: &[(u32, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (cpu_id, siblings_list) in siblings {
+            let topology_dir = dir.path().join(format!("cpu{}/topology", cpu_id));
+            std::fs::create_dir_all(&topology_dir).unwrap();
+            std::fs::write(
+                topology_dir.join("thread_siblings_list"),
+                format!("{}\n", siblings_list),
+            )
+            .unwrap();
+        }
+        dir
+    }
+
+    /// Tests that hugetlbfs mounts are matched by their page size.
+    #[test]
+    fn test_find_hugetlbfs_mount() {
+        let mounts = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+hugetlbfs /dev/hugepages hugetlbfs rw,relatime,pagesize=2M 0 0
+none /mnt/huge\\0401G hugetlbfs rw,relatime,pagesize=1024M 0 0
+none /mnt/huge1G hugetlbfs rw,relatime,pagesize=1G 0 0
+";
+
+        assert_eq!(
+            find_hugetlbfs_mount_in(mounts, HugePageSize::Mib2, None).unwrap(),
+            PathBuf::from("/dev/hugepages")
+        );
+        assert_eq!(
+            find_hugetlbfs_mount_in(mounts, HugePageSize::Gib1, None).unwrap(),
+            PathBuf::from("/mnt/huge 1G")
+        );
+
+        // A mount without a page size option provides default-sized hugepages.
+        let mounts = "hugetlbfs /dev/hugepages hugetlbfs rw,relatime 0 0\n";
+        assert_eq!(
+            find_hugetlbfs_mount_in(mounts, HugePageSize::Mib2, Some(2 * MiB)).unwrap(),
+            PathBuf::from("/dev/hugepages")
+        );
+
+        let err = find_hugetlbfs_mount_in(mounts, HugePageSize::Gib1, None).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NoHugetlbfsMount);
+        assert_eq!(
+            err.additional_info,
+            vec!["1 GiB", "/dev/hugepages (unknown page size)"]
+        );
+
+        let err = find_hugetlbfs_mount_in("", HugePageSize::Mib2, None).unwrap_err();
+        assert_eq!(err.additional_info, vec!["2 MiB", "none"]);
+
+        assert_eq!(
+            parse_default_hugepage_size("HugePages_Free:  0\nHugepagesize:    2048 kB\n"),
+            Some(2 * MiB)
+        );
+        assert_eq!(parse_mount_page_size("2048k"), Some(2 * MiB));
+        assert_eq!(parse_mount_page_size("huge"), None);
+    }
+
+    /// Tests that the enclave image must fit in enclave memory after its load offset.
+    #[test]
+    fn test_check_image_fits() {
+        assert!(check_image_fits(8 * MiB, 56 * MiB, 64 * MiB).is_ok());
+
+        let err = check_image_fits(8 * MiB, 56 * MiB + 1, 64 * MiB).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::MemoryOverflow);
+        assert_eq!(
+            err.subactions[0],
+            "The enclave image of 58720257 bytes, loaded at offset 0x800000, doesn't fit in the 64 MiB of enclave memory"
+        );
+        assert!(check_image_fits(u64::MAX, 1, u64::MAX).is_err());
+    }
+
+    /// Tests that the NE CPU pool is parsed from its CPU list.
+    #[test]
+    fn test_parse_cpu_pool() {
+        let pool = parse_cpu_pool("6-7,2-3\n").unwrap();
+        assert_eq!(pool.available(), vec![2, 3, 6, 7]);
+        assert_eq!(pool.count(), 4);
+        assert!(pool.contains(3));
+        assert!(!pool.contains(4));
+
+        // The pool is empty if the NE driver hasn't been given any CPU.
+        let pool = parse_cpu_pool("\n").unwrap();
+        assert!(pool.is_empty());
+        assert_eq!(pool.available(), Vec::<u32>::new());
+
+        let err = parse_cpu_pool("2-3-4").unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::CpuError);
+    }
+
+    /// Tests that processes are only moved into cgroup v2 directories.
+    #[test]
+    fn test_attach_pid_to_cgroup() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = attach_pid_to_cgroup(dir.path(), 42).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::CgroupUnsupported);
+
+        std::fs::write(dir.path().join("cgroup.controllers"), "cpu memory\n").unwrap();
+        attach_pid_to_cgroup(dir.path(), 42).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("cgroup.procs")).unwrap(),
+            "42"
+        );
+    }
+
+    /// Tests that the memory usage is read from the cgroup v2 of the process.
+    #[test]
+    fn test_cgroup_memory_usage() {
+        let root = tempfile::tempdir().unwrap();
+        let cgroup = root.path().join("enclaves").join("enc1");
+        std::fs::create_dir_all(&cgroup).unwrap();
+        std::fs::write(cgroup.join("memory.current"), "2147483648\n").unwrap();
+
+        assert_eq!(
+            cgroup_memory_usage_in("0::/enclaves/enc1\n", root.path()).unwrap(),
+            2 * GiB
+        );
+
+        let v1_membership = "12:memory:/enclaves/enc1\n11:cpuset:/\n";
+        let err = cgroup_memory_usage_in(v1_membership, root.path()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::CgroupUnsupported);
+
+        let err = cgroup_memory_usage_in("0::/missing\n", root.path()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+    }
+
+    /// Tests that enclave memory requests must leave the reserved host memory free.
+    #[test]
+    fn test_enforce_memory_policy() {
+        assert_eq!(
+            parse_meminfo_total("MemTotal:       16384000 kB\nMemFree:  1024 kB\n"),
+            Some(16384000 * KiB)
+        );
+
+        assert!(enforce_memory_policy(12 * GiB, 16 * GiB, 0.25).is_ok());
+        assert!(enforce_memory_policy(16 * GiB, 16 * GiB, 0.0).is_ok());
+
+        let err = enforce_memory_policy(12 * GiB + MiB, 16 * GiB, 0.25).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+        assert_eq!(err.additional_info, vec!["memory", "12289"]);
+        assert!(err.subactions[0].contains("at most 12288 MiB"));
+        assert!(err.subactions[0].contains("25% of the 16384 MiB of host memory (4096 MiB)"));
+    }
+
+    /// Tests that memory regions can't be created outside of a hugetlbfs mount.
+    #[test]
+    fn test_new_at_mount_not_hugetlbfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = MemoryRegion::new_at_mount(2 * MiB, dir.path()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NoHugetlbfsMount);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    /// Tests that CPUs are selected as whole cores and that split cores are rejected.
+    #[test]
+    fn test_select_whole_cores() {
+        let sysfs = fake_cpu_sysfs(&[
+            (1, "1,5"),
+            (2, "2,6"),
+            (3, "3,7"),
+            (5, "1,5"),
+            (6, "2,6"),
+            (7, "3,7"),
+        ]);
+
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[1, 2, 5, 6], 4, UnsafeAllowCpu0::default())
+                .unwrap();
+        assert_eq!(selected, vec![1, 5, 2, 6]);
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[6, 2, 5, 1], 2, UnsafeAllowCpu0::default())
+                .unwrap();
+        assert_eq!(selected, vec![1, 5]);
+
+        // A CPU whose sibling is not in the pool.
+        let err = select_whole_cores_in(
+            sysfs.path(),
+            &[1, 2, 3, 5, 6],
+            2,
+            UnsafeAllowCpu0::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IncompleteCpuCore);
+        assert_eq!(err.additional_info, vec!["[3, 7]", "[7]"]);
+
+        // An odd CPU count would split a core.
+        let err = select_whole_cores_in(sysfs.path(), &[1, 2, 5, 6], 3, UnsafeAllowCpu0::default())
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IncompleteCpuCore);
+        assert_eq!(err.additional_info, vec!["[2, 6]", "[6]"]);
+
+        let err = select_whole_cores_in(sysfs.path(), &[1, 5], 4, UnsafeAllowCpu0::default())
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientCpus);
+
+        // Ranges are accepted, and single-threaded cores need no sibling.
+        let sysfs = fake_cpu_sysfs(&[(0, "0-1"), (1, "0-1"), (4, "4")]);
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[0, 1, 4], 3, UnsafeAllowCpu0(true)).unwrap();
+        assert_eq!(selected, vec![0, 1, 4]);
+
+        let err =
+            select_whole_cores_in(sysfs.path(), &[2], 1, UnsafeAllowCpu0::default()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+    }
+
+    /// Tests that CPU 0 and its siblings are left out of the selection unless explicitly allowed.
+    #[test]
+    fn test_select_whole_cores_skips_cpu0() {
+        let sysfs = fake_cpu_sysfs(&[(0, "0,2"), (1, "1,3"), (2, "0,2"), (3, "1,3")]);
+        assert_eq!(host_reserved_cpus_in(sysfs.path()), vec![0, 2]);
+
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[0, 1, 2, 3], 2, UnsafeAllowCpu0::default())
+                .unwrap();
+        assert_eq!(selected, vec![1, 3]);
+        let err = select_whole_cores_in(sysfs.path(), &[0, 1, 2, 3], 4, UnsafeAllowCpu0::default())
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientCpus);
+
+        // A sibling of CPU 0 is left out, even if CPU 0 itself is not in the pool.
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[1, 2, 3], 2, UnsafeAllowCpu0::default()).unwrap();
+        assert_eq!(selected, vec![1, 3]);
+
+        let selected =
+            select_whole_cores_in(sysfs.path(), &[0, 1, 2, 3], 4, UnsafeAllowCpu0(true)).unwrap();
+        assert_eq!(selected, vec![0, 2, 1, 3]);
+
+        // CPU 0 is reserved even if its topology is unknown.
+        let sysfs = fake_cpu_sysfs(&[(1, "1")]);
+        assert_eq!(host_reserved_cpus_in(sysfs.path()), vec![0]);
+    }
+
+    /// Tests that the selected CPUs form whole cores and are listed in ascending order.
+    #[test]
+    fn test_select_cpus() {
+        // Two threads per core, with sibling IDs half the CPU count apart.
+        let sysfs = fake_cpu_sysfs(&[
+            (1, "1,5"),
+            (2, "2,6"),
+            (3, "3,7"),
+            (5, "1,5"),
+            (6, "2,6"),
+            (7, "3,7"),
+        ]);
+        let pool = parse_cpu_pool("1-3,5-7").unwrap();
+
+        assert_eq!(
+            select_cpus_in(sysfs.path(), &pool, 4, UnsafeAllowCpu0::default()).unwrap(),
+            vec![1, 2, 5, 6]
+        );
+        assert_eq!(
+            select_cpus_in(sysfs.path(), &pool, 6, UnsafeAllowCpu0::default()).unwrap(),
+            vec![1, 2, 3, 5, 6, 7]
+        );
+
+        let err = select_cpus_in(sysfs.path(), &pool, 3, UnsafeAllowCpu0::default()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IncompleteCpuCore);
+        assert_eq!(
+            err.subactions[0],
+            "Cannot allocate 3 CPUs as whole cores, since core [2, 6] would be split"
+        );
+        let err = select_cpus_in(sysfs.path(), &pool, 8, UnsafeAllowCpu0::default()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientCpus);
+
+        // Adjacent siblings, whose cores are listed as ranges.
+        let sysfs = fake_cpu_sysfs(&[(2, "2-3"), (3, "2-3"), (4, "4-5"), (5, "4-5")]);
+        let pool = parse_cpu_pool("2-5").unwrap();
+        assert_eq!(
+            select_cpus_in(sysfs.path(), &pool, 2, UnsafeAllowCpu0::default()).unwrap(),
+            vec![2, 3]
+        );
+        let err = select_cpus_in(sysfs.path(), &pool, 1, UnsafeAllowCpu0::default()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IncompleteCpuCore);
+
+        // Without SMT, any count which fits the pool is accepted.
+        let sysfs = fake_cpu_sysfs(&[(1, "1"), (2, "2"), (3, "3")]);
+        let pool = parse_cpu_pool("1-3").unwrap();
+        assert_eq!(
+            select_cpus_in(sysfs.path(), &pool, 3, UnsafeAllowCpu0::default()).unwrap(),
+            vec![1, 2, 3]
+        );
+        let err = select_cpus_in(
+            sysfs.path(),
+            &CpuPool::default(),
+            1,
+            UnsafeAllowCpu0::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientCpus);
+    }
+
+    /// Tests that the NUMA node holding most pages of a mapping is reported.
+    #[test]
+    fn test_get_numa_node() {
+        let numa_maps = "\
+55bcedace000 default file=/usr/bin/head mapped=2 N0=2 kernelpagesize_kB=4
+7f0000000000 default file=/anon_hugepage\\040(deleted) huge dirty=2 N0=1 N1=3 kernelpagesize_kB=2048
+7f0000800000 default file=/anon_hugepage\\040(deleted) huge kernelpagesize_kB=2048
+";
+
+        assert_eq!(get_numa_node(numa_maps.as_bytes(), 0x55bcedace000), Some(0));
+        assert_eq!(get_numa_node(numa_maps.as_bytes(), 0x7f0000000000), Some(1));
+        assert_eq!(get_numa_node(numa_maps.as_bytes(), 0x7f0000200000), Some(1));
+        // Mappings without resident pages default to node 0.
+        assert_eq!(get_numa_node(numa_maps.as_bytes(), 0x7f0000800000), Some(0));
+        assert_eq!(get_numa_node(numa_maps.as_bytes(), 0x1000), None);
+    }
+
+    /// Tests that zeroing a region faults in all of its pages, and that regions which aren't
+    /// backed by hugepages are reported afterwards.
+    #[test]
+    fn test_zero_and_is_resident() {
+        let size = 4 * KiB;
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+
+        // The region is unmapped on drop.
+        let mut region = MemoryRegion::new_with(0, addr as u64, size);
+        assert!(!region.is_resident());
+
+        unsafe { std::ptr::write_bytes(addr as *mut u8, 0xff, size as usize) };
+        let err = region.zero().unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidMemoryRegion);
+        assert!(region.is_resident());
+
+        let bytes = unsafe { std::slice::from_raw_parts(addr as *const u8, size as usize) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+
+        assert!(!MemoryRegion::new_with(0, 0, 0).is_resident());
+    }
+
+    /// Tests that file-backed regions are aligned and share their contents with the file.
+    #[test]
+    fn test_memory_region_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"snapshot").unwrap();
+
+        let err = MemoryRegion::from_file(file.path(), MiB, HugePageSize::Mib2).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidMemoryRegion);
+
+        let region = MemoryRegion::from_file(file.path(), 4 * MiB, HugePageSize::Mib2).unwrap();
+        assert_eq!(region.mem_addr() % (2 * MiB), 0);
+        assert_eq!(region.mem_size(), 4 * MiB);
+        assert_eq!(file.as_file().metadata().unwrap().len(), 4 * MiB);
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(region.mem_addr() as *mut u8, (4 * MiB) as usize)
+        };
+        assert_eq!(&bytes[..8], b"snapshot");
+        bytes[4 * MiB as usize - 1] = 0xaa;
+        region.release().unwrap();
+
+        // The file outlives the region, along with the writes to it.
+        let contents = std::fs::read(file.path()).unwrap();
+        assert_eq!(contents[4 * MiB as usize - 1], 0xaa);
+    }
+
+    /// Tests the search for the largest obtainable region and the warning threshold.
+    #[test]
+    fn test_largest_contiguous_available() {
+        let mut probes = Vec::new();
+        let pages = largest_mappable(100, |pages| {
+            probes.push(pages);
+            pages <= 37
+        });
+        assert_eq!(pages, 37);
+        assert!(probes.len() <= 7);
+        assert_eq!(largest_mappable(100, |_| true), 100);
+        assert_eq!(largest_mappable(100, |_| false), 0);
+        assert_eq!(largest_mappable(0, |_| panic!("No probe expected")), 0);
+
+        let err = MemoryRegion::largest_contiguous_available(3 * MiB).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NoSuchHugepageFlag);
+
+        assert!(!approaches_limit(90 * MiB, 100 * MiB));
+        assert!(approaches_limit(91 * MiB, 100 * MiB));
+        assert!(approaches_limit(MiB, 0));
+    }
+
+    /// Tests that requests are clamped to the available memory, but not below the minimum.
+    #[test]
+    fn test_clamp_to_available() {
+        assert_eq!(clamp_size(512 * MiB, GiB, 256 * MiB).unwrap(), 512 * MiB);
+        assert_eq!(clamp_size(GiB, 768 * MiB, 256 * MiB).unwrap(), 768 * MiB);
+        assert_eq!(clamp_size(GiB, 256 * MiB, 256 * MiB).unwrap(), 256 * MiB);
+
+        let err = clamp_size(GiB, 128 * MiB, 256 * MiB).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+        assert_eq!(err.additional_info, vec!["memory", "256"]);
+
+        let err = clamp_size(256 * MiB, GiB, 512 * MiB).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidArgument);
+
+        let err = clamp_to_available(GiB, 3 * MiB, 0).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NoSuchHugepageFlag);
+    }
+
+    /// Map a 2 MiB anonymous region, standing in for a hugepage region in pool tests.
+    fn anonymous_pool_region() -> NitroCliResult<MemoryRegion> {
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                POOL_REGION_SIZE as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+
+        Ok(MemoryRegion::new_with(0, addr as u64, POOL_REGION_SIZE))
+    }
+
+    /// Tests that pool regions are reused, zeroed on release and limited by the pool capacity.
+    #[test]
+    fn test_memory_region_pool() {
+        assert!(MemoryRegionPool::with_allocator(3, 2, anonymous_pool_region).is_err());
+
+        let mut pool = MemoryRegionPool::with_allocator(1, 2, anonymous_pool_region).unwrap();
+        let utilization = |in_use, free| PoolUtilization {
+            in_use,
+            free,
+            capacity: 2,
+        };
+        assert_eq!(pool.utilization(), utilization(0, 1));
+
+        let first = pool.acquire().unwrap();
+        let first_addr = first.mem_addr;
+        unsafe { std::ptr::write_bytes(first_addr as *mut u8, 0xff, POOL_REGION_SIZE as usize) };
+
+        // The pool grows up to its capacity.
+        let second = pool.acquire().unwrap();
+        assert_eq!(pool.utilization(), utilization(2, 0));
+        let err = pool.acquire().unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+
+        pool.release(first).unwrap();
+        assert_eq!(pool.utilization(), utilization(1, 1));
+        let reused = pool.acquire().unwrap();
+        assert_eq!(reused.mem_addr, first_addr);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(first_addr as *const u8, POOL_REGION_SIZE as usize)
+        };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+
+        // Only regions of the pool's size may be released to it.
+        let err = pool
+            .release(MemoryRegion::new_with(0, 0, POOL_REGION_SIZE))
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidMemoryRegion);
+
+        pool.release(reused).unwrap();
+        pool.release(second).unwrap();
+        assert_eq!(pool.utilization(), utilization(0, 2));
+    }
+
+    /// Tests that the residency monitor exits once its memory region is freed.
+    #[test]
+    fn test_residency_monitor() {
+        let size = 4 * KiB;
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_POPULATE,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+
+        let mut region = MemoryRegion::new_with(0, addr as u64, size);
+        // The monitor is woken up on drop, well before its check interval elapses.
+        let monitor = region.start_residency_monitor(Duration::from_secs(3600));
+        assert!(region.residency_monitor.is_some());
+
+        drop(region);
+        assert!(monitor.join().is_ok());
+    }
+
+    /// Tests that node-bound regions are rejected when no hugepage size fits them or when
+    /// the node cannot provide enough hugepages.
+    #[test]
+    fn test_new_on_node() {
+        let err = MemoryRegion::new_on_node(MiB, 0).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidMemoryRegion);
+
+        // A node which does not exist has no free hugepages at all.
+        let err = MemoryRegion::new_on_node(4 * MiB, u32::MAX).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientNodeMemory);
+        assert_eq!(
+            err.additional_info,
+            vec![u32::MAX.to_string(), "2".to_string()]
+        );
+    }
+
+    /// Tests that a region which the host lacks the free hugepages for is refused before being
+    /// mapped.
+    #[test]
+    fn test_new_checks_free_hugepages() {
+        // No host has free 16 GiB hugepages to spare in tests.
+        let err = MemoryRegion::new(libc::MAP_HUGE_16GB, EagerFault::default()).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+        assert_eq!(
+            err.subactions[0],
+            "Cannot allocate 16384 MiB of 16384 MiB hugepages: need 1 pages, only 0 free"
+        );
+        assert_eq!(err.additional_info, vec!["memory", "16384"]);
+    }
+
+    /// Tests that hugepage sizes map to the expected values and that explicitly-sized
+    /// regions must cover a whole number of pages.
+    #[test]
+    fn test_new_with_page_size() {
+        assert_eq!(HugePageSize::Mib2.size(), 2 * MiB);
+        assert_eq!(HugePageSize::Gib1.size(), GiB);
+        assert_eq!(HugePageSize::Gib1.mmap_flag(), libc::MAP_HUGE_1GB);
+        assert_eq!(
+            HugePageSize::Gib1.nr_hugepages_path(),
+            "/sys/kernel/mm/hugepages/hugepages-1048576kB/nr_hugepages"
+        );
+
+        let result = MemoryRegion::new_with_page_size(3 * MiB, HugePageSize::Mib2);
+        assert_eq!(
+            result.unwrap_err().error_code,
+            NitroCliErrorEnum::InvalidMemoryRegion
+        );
+
+        let result = MemoryRegion::new_with_page_size(0, HugePageSize::Gib1);
+        assert!(result.is_err());
+    }
+
+    /// Tests that faulting in a region reports its progress once per step.
+    #[test]
+    fn test_fault_in_progress() {
+        let page_size = page_size::get() as u64;
+        let size = 5 * page_size;
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+        let region = MemoryRegion::new_with(0, addr as u64, size);
+
+        let mut reports = Vec::new();
+        region
+            .fault_in(page_size, 2 * page_size, |done, total| {
+                reports.push((done, total))
+            })
+            .unwrap();
+        assert_eq!(
+            reports,
+            vec![(2 * page_size, size), (4 * page_size, size), (size, size)]
+        );
+    }
+
+    /// Tests that regions are unmapped on release, skipping the hugepage check when their
+    /// page size is unknown or has no counters, and that releasing twice is harmless.
+    #[test]
+    fn test_release() {
+        let page_size = page_size::get() as u64;
+        for known_page_size in [None, Some(page_size)] {
+            let addr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    page_size as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(addr, libc::MAP_FAILED);
+
+            let mut region = MemoryRegion::new_with(0, addr as u64, page_size);
+            region.page_size = known_page_size;
+            assert!(region.free().is_ok());
+            assert_eq!(region.mem_addr(), 0);
+            assert!(region.release().is_ok());
+
+            let addr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    page_size as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(addr, libc::MAP_FAILED);
+            let mut region = MemoryRegion::new_with(0, addr as u64, page_size);
+            region.page_size = known_page_size;
+            assert!(region.release().is_ok());
+            // The region is no longer mapped.
+            assert_eq!(
+                unsafe { libc::msync(addr, page_size as usize, libc::MS_ASYNC) },
+                -1
+            );
+        }
+    }
+
+    /// Tests that hugepage counters are read from sysfs and that invalid ones are reported.
+    #[test]
+    fn test_read_hugepage_count() {
+        assert_eq!(
+            HugePageSize::Mib2.free_hugepages_path(),
+            "/sys/kernel/mm/hugepages/hugepages-2048kB/free_hugepages"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("free_hugepages");
+        std::fs::write(&path, "4096\n").unwrap();
+        assert_eq!(read_hugepage_count(&path).unwrap(), 4096);
+
+        std::fs::write(&path, "many\n").unwrap();
+        let err = read_hugepage_count(&path).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+        assert_eq!(err.additional_info[1], "Parse");
+
+        let err = read_hugepage_count(&dir.path().join("missing")).unwrap_err();
+        assert_eq!(err.additional_info[1], "Read");
+    }
+
+    /// Tests that misaligned, wrongly-sized or non-hugepage regions are rejected.
+    #[test]
+    fn test_validate_with_page_size() {
+        // The regions have never been mapped, so they must not be unmapped either.
+        let regions = std::mem::ManuallyDrop::new([
+            MemoryRegion::new_with(0, 2 * MiB, 4 * MiB),
+            MemoryRegion::new_with(0, MiB, 4 * MiB),
+            MemoryRegion::new_with(0, 2 * MiB, 3 * MiB),
+        ]);
+
+        assert!(regions[0].validate_with_page_size(2 * MiB).is_ok());
+
+        let err = regions[0].validate_with_page_size(4 * KiB).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidMemoryRegion);
+        assert!(construct_error_message(&err).contains("backing page size of `4` KiB"));
+
+        let err = regions[1].validate_with_page_size(2 * MiB).unwrap_err();
+        assert!(err.subactions[0].contains("not aligned to the 2048 KiB page size"));
+
+        let err = regions[2].validate_with_page_size(2 * MiB).unwrap_err();
+        assert!(err.subactions[0].contains("not a multiple of the 2048 KiB page size"));
+    }
+
     #[test]
     fn test_timeout_calculation() {
         assert_eq!(calculate_necessary_timeout(2 * GiB), TIMEOUT_MINUTE_MS);
@@ -3,19 +3,134 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
-use inotify::{EventMask, Inotify, WatchMask};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
 use log::{debug, warn};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sched::{sched_setaffinity, CpuSet};
+use nix::sys::socket::sockopt::PeerCredentials;
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType, UnixAddr};
+use nix::sys::stat::{fchmod, Mode};
+use nix::unistd::{chown, Gid, Pid, Uid};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem::size_of;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use vmm_sys_util::eventfd::{EventFd, EFD_NONBLOCK};
 
-use crate::common::get_socket_path;
+use crate::common::{current_instance_id, get_socket_path};
 use crate::common::{ExitGracefully, NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
-use crate::new_nitro_cli_failure;
+use crate::enclave_proc::resource_manager::read_cpu_pool;
+use crate::enclave_proc::{unix_socket_paths_of, PROC_DIR};
+use crate::{enclave_log, new_nitro_cli_failure};
+
+/// The default size of the buffer used for reading socket `inotify` events.
+pub const DEFAULT_EVENT_BUFFER_SIZE: usize = 4096;
+
+/// The maximum length of a file name (`NAME_MAX` from `<linux/limits.h>`).
+const NAME_MAX: usize = 255;
+
+/// The smallest buffer which is guaranteed to fit a single `inotify` event.
+const MIN_EVENT_BUFFER_SIZE: usize = size_of::<libc::inotify_event>() + NAME_MAX + 1;
+
+/// The prefix of the names given to abstract namespace enclave process sockets.
+const ABSTRACT_SOCKET_PREFIX: &str = "nitro_enclaves/";
+
+/// The kernel's list of Unix sockets, which includes the abstract namespace ones.
+const NET_UNIX_FILEPATH: &str = "/proc/net/unix";
+
+/// The `__SO_ACCEPTCON` flag shown in `/proc/net/unix` for listening sockets.
+const NET_UNIX_LISTENING_FLAGS: &str = "00010000";
+
+/// How often an abstract namespace socket is checked for still being bound.
+const ABSTRACT_SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The longest time for which the liveness monitor sleeps between checks.
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait before checking again that a socket which refused a connection is stale.
+const STALE_SOCKET_RECHECK_DELAY: Duration = Duration::from_millis(50);
+
+/// The suffix appended to the name of a socket file to name the file its state is persisted to.
+const SOCKET_STATE_FILE_SUFFIX: &str = ".state";
+
+/// The backlog of pending connections for an abstract namespace socket.
+const ABSTRACT_SOCKET_BACKLOG: usize = 128;
+
+/// The reason for which the socket monitor requests the enclave process to shut down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The socket file has been deleted by an external action or, for an abstract namespace
+    /// socket, the socket is no longer listening.
+    SocketDeleted,
+}
+
+/// An event observed while monitoring an enclave process socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketEvent {
+    /// The socket is bound and its monitoring has started. Only reported by
+    /// `start_monitoring_with_events()`.
+    Created,
+    /// The socket file has been deleted because the socket was closed.
+    Deleted,
+    /// The socket file has been deleted by an external action or, for an abstract namespace
+    /// socket, the socket is no longer listening.
+    ExternalDeletion,
+}
+
+/// Where a socket monitoring thread reports what it observes.
+struct MonitorNotifiers {
+    /// The owner to ask for shutting down once the socket has been deleted externally.
+    shutdown: Option<Sender<ShutdownReason>>,
+    /// The consumer of the socket events, if any.
+    events: Option<Sender<SocketEvent>>,
+}
+
+/// The callback which a `SocketWatcher` invokes with the path of a watched socket file once
+/// that file has been deleted.
+type DeletionCallback = Box<dyn FnMut(&Path) + Send>;
+
+/// A socket file watched by a `SocketWatcher`.
+struct WatchedSocket {
+    /// The path of the socket file.
+    path: PathBuf,
+    /// The callback to invoke once the file has been deleted.
+    on_deleted: DeletionCallback,
+}
+
+/// Watches the files of many sockets through a single `inotify` instance and a single thread,
+/// which is cheaper than a thread per socket when one process manages many enclaves.
+pub struct SocketWatcher {
+    /// The handle for adding and removing watches on the shared `inotify` instance.
+    watches: Mutex<Watches>,
+    /// The watched sockets, by the descriptor of their watch.
+    sockets: Arc<Mutex<HashMap<WatchDescriptor, WatchedSocket>>>,
+    /// The event used for waking up the watcher thread when the watcher is dropped.
+    shutdown_event: EventFd,
+    /// The thread which reads and demultiplexes the `inotify` events.
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The state of an enclave process socket which is persisted across enclave process restarts.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PersistedSocketState {
+    /// The socket's file-system path.
+    socket_path: PathBuf,
+    /// The PID of the process which listens on the socket.
+    pid: u32,
+}
 
 /// The structure which manages the Unix socket that an enclave process listens on for commands.
-#[derive(Default)]
 pub struct EnclaveProcSock {
     /// The socket's file-system path.
     socket_path: PathBuf,
@@ -23,6 +138,62 @@ pub struct EnclaveProcSock {
     remove_listener_thread: Option<JoinHandle<()>>,
     /// A flag indicating if socket removal was requested.
     requested_remove: Arc<AtomicBool>,
+    /// The size of the buffer used for reading `inotify` events.
+    event_buffer_size: usize,
+    /// The socket's name in the abstract namespace, if it doesn't live on the file-system.
+    abstract_name: Option<String>,
+    /// The last time a CLI instance has pinged the enclave process.
+    last_seen: Arc<Mutex<Option<Instant>>>,
+    /// The time after which a warning is emitted if no CLI instance has pinged.
+    liveness_timeout: Option<Duration>,
+    /// The thread which warns when no CLI instance has pinged for too long.
+    liveness_thread: Option<JoinHandle<()>>,
+    /// The event used for waking up the socket file event listener when the socket is closed.
+    shutdown_event: Option<EventFd>,
+    /// The shared watcher which monitors the socket file instead of a dedicated thread, along
+    /// with the descriptor of the socket's watch.
+    shared_watch: Option<(Arc<SocketWatcher>, WatchDescriptor)>,
+    /// How long to wait for an externally deleted socket file to reappear before shutting down.
+    deletion_grace: Option<Duration>,
+    /// The host CPUs which the socket monitoring thread is pinned to, if any.
+    monitor_cpus: Option<Vec<u32>>,
+    /// The file mode given to the socket file once it is bound, if any.
+    mode: Option<u32>,
+    /// The user and group IDs given to the socket file once it is bound, if any.
+    owner: Option<(u32, u32)>,
+    /// The notifier given when monitoring was started, kept for monitoring another path after
+    /// `rebind()`.
+    shutdown_notifier: Option<Sender<ShutdownReason>>,
+}
+
+/// The grace window given to an externally deleted socket file for reappearing.
+struct DeletionGrace {
+    /// How long to wait before checking the socket file again.
+    window: Duration,
+    /// The inode of the socket file bound by the current process.
+    inode: u64,
+}
+
+impl Default for EnclaveProcSock {
+    fn default() -> Self {
+        EnclaveProcSock {
+            socket_path: PathBuf::default(),
+            remove_listener_thread: None,
+            requested_remove: Arc::new(AtomicBool::new(false)),
+            event_buffer_size: DEFAULT_EVENT_BUFFER_SIZE,
+            abstract_name: None,
+            last_seen: Arc::new(Mutex::new(None)),
+            liveness_timeout: None,
+            liveness_thread: None,
+            shutdown_event: None,
+            shared_watch: None,
+            deletion_grace: None,
+            monitor_cpus: None,
+            mode: None,
+            owner: None,
+            shutdown_notifier: None,
+        }
+    }
 }
 
 /// The enclave process socket must allow cloning, since that is needed by the socket-listening thread.
@@ -33,6 +204,18 @@ impl Clone for EnclaveProcSock {
             socket_path: self.socket_path.clone(),
             remove_listener_thread: None,
             requested_remove: self.requested_remove.clone(),
+            event_buffer_size: self.event_buffer_size,
+            abstract_name: self.abstract_name.clone(),
+            last_seen: self.last_seen.clone(),
+            liveness_timeout: self.liveness_timeout,
+            liveness_thread: None,
+            shutdown_event: None,
+            shared_watch: None,
+            deletion_grace: self.deletion_grace,
+            monitor_cpus: self.monitor_cpus.clone(),
+            mode: self.mode,
+            owner: self.owner,
+            shutdown_notifier: None,
         }
     }
 }
@@ -47,175 +230,1415 @@ impl Drop for EnclaveProcSock {
 impl EnclaveProcSock {
     /// Create a new `EnclaveProcSock` instance from a given enclave ID.
     pub fn new(enclave_id: &str) -> NitroCliResult<Self> {
-        let socket_path = get_socket_path(enclave_id).map_err(|_| {
-            new_nitro_cli_failure!(
-                "Failed to create enclave process socket",
-                NitroCliErrorEnum::SocketPathNotFound
-            )
-        })?;
+        let socket_path = get_socket_path(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to create enclave process socket".to_string()))?;
 
         Ok(EnclaveProcSock {
             socket_path,
             remove_listener_thread: None,
             requested_remove: Arc::new(AtomicBool::new(false)),
+            event_buffer_size: DEFAULT_EVENT_BUFFER_SIZE,
+            abstract_name: None,
+            last_seen: Arc::new(Mutex::new(None)),
+            liveness_timeout: None,
+            liveness_thread: None,
+            shutdown_event: None,
+            shared_watch: None,
+            deletion_grace: None,
+            monitor_cpus: None,
+            mode: None,
+            owner: None,
+            shutdown_notifier: None,
         })
     }
 
-    /// Get the path to the managed Unix socket.
-    pub fn get_path(&self) -> &Path {
-        self.socket_path.as_path()
+    /// Create a new `EnclaveProcSock` instance for an enclave of the current EC2 instance, whose
+    /// ID is made of the instance ID obtained from the instance metadata service and the given
+    /// enclave suffix. This fails if the instance metadata service is unreachable, which is the
+    /// case when not running on EC2.
+    pub fn new_for_current_instance(enclave_suffix: &str) -> NitroCliResult<Self> {
+        let instance_id = current_instance_id()
+            .map_err(|e| e.add_subaction("Failed to create enclave process socket".to_string()))?;
+
+        EnclaveProcSock::new(&format!("{}-enc{}", instance_id, enclave_suffix))
+    }
+
+    /// Create a new `EnclaveProcSock` instance from a given enclave ID, which uses a socket in
+    /// the Linux abstract namespace instead of one on the file-system. Such a socket doesn't need
+    /// a writable sockets directory and it vanishes automatically once the process which has
+    /// bound it exits, so it never has to be removed.
+    pub fn new_abstract(enclave_id: &str) -> NitroCliResult<Self> {
+        let socket_path = get_socket_path(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to create enclave process socket".to_string()))?;
+        let socket_name = socket_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                new_nitro_cli_failure!(
+                    &format!("Invalid enclave process socket name: {:?}", socket_path),
+                    NitroCliErrorEnum::SocketPathNotFound
+                )
+            })?;
+
+        Ok(EnclaveProcSock {
+            socket_path: PathBuf::new(),
+            remove_listener_thread: None,
+            requested_remove: Arc::new(AtomicBool::new(false)),
+            event_buffer_size: DEFAULT_EVENT_BUFFER_SIZE,
+            abstract_name: Some(format!("{}{}", ABSTRACT_SOCKET_PREFIX, socket_name)),
+            last_seen: Arc::new(Mutex::new(None)),
+            liveness_timeout: None,
+            liveness_thread: None,
+            shutdown_event: None,
+            shared_watch: None,
+            deletion_grace: None,
+            monitor_cpus: None,
+            mode: None,
+            owner: None,
+            shutdown_notifier: None,
+        })
+    }
+
+    /// Check if the managed Unix socket lives in the abstract namespace.
+    pub fn is_abstract(&self) -> bool {
+        self.abstract_name.is_some()
+    }
+
+    /// Bind a listener to the managed Unix socket. The socket file gets the mode and owner set
+    /// with `with_permissions()` and `with_owner()`, if any.
+    pub fn bind(&self) -> NitroCliResult<UnixListener> {
+        let socket_name = match self.abstract_name.as_ref() {
+            Some(socket_name) => socket_name,
+            None if self.mode.is_none() && self.owner.is_none() => {
+                return UnixListener::bind(self.get_path()).map_err(|e| {
+                    new_nitro_cli_failure!(
+                        &format!("Failed to bind to socket {:?}: {:?}", self.socket_path, e),
+                        NitroCliErrorEnum::SocketError
+                    )
+                })
+            }
+            None => return self.bind_with_permissions(),
+        };
+
+        let socket_fd = new_unix_socket_fd()?;
+        let socket_addr = abstract_socket_addr(socket_name)?;
+
+        // The descriptor is owned by the listener from now on, so it gets closed on failure.
+        let listener = unsafe { UnixListener::from_raw_fd(socket_fd) };
+        socket::bind(socket_fd, &socket_addr)
+            .and_then(|_| socket::listen(socket_fd, ABSTRACT_SOCKET_BACKLOG))
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!(
+                        "Failed to bind to abstract socket @{}: {:?}",
+                        socket_name, e
+                    ),
+                    NitroCliErrorEnum::SocketError
+                )
+            })?;
+
+        Ok(listener)
+    }
+
+    /// Bind a listener to the managed socket file and give the file its configured mode and
+    /// owner. The mode is set on the socket before binding, which Linux applies to the file it
+    /// creates (restricted further by the umask), so the file is never accessible more widely
+    /// than requested. It is then set once more, since the umask may have cleared some of its bits.
+    fn bind_with_permissions(&self) -> NitroCliResult<UnixListener> {
+        let socket_path = self.get_path();
+        let path_str = socket_path.to_str().unwrap_or("(invalid path)");
+        let socket_fd = new_unix_socket_fd()?;
+        let listener = unsafe { UnixListener::from_raw_fd(socket_fd) };
+
+        if let Some(mode) = self.mode {
+            fchmod(socket_fd, Mode::from_bits_truncate(mode)).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!(
+                        "Failed to set the mode of socket {:?}: {:?}",
+                        socket_path, e
+                    ),
+                    NitroCliErrorEnum::SocketError
+                )
+            })?;
+        }
+
+        let socket_addr = UnixAddr::new(socket_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Invalid socket path {:?}: {:?}", socket_path, e),
+                NitroCliErrorEnum::SocketError
+            )
+        })?;
+        socket::bind(socket_fd, &socket_addr)
+            .and_then(|_| socket::listen(socket_fd, ABSTRACT_SOCKET_BACKLOG))
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to bind to socket {:?}: {:?}", socket_path, e),
+                    NitroCliErrorEnum::SocketError
+                )
+            })?;
+
+        // A socket file which can't be given its permissions must not be left accessible.
+        let remove_on_error = |err: NitroCliFailure| {
+            let _ = std::fs::remove_file(socket_path);
+            err
+        };
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(mode)).map_err(
+                |e| {
+                    remove_on_error(
+                        new_nitro_cli_failure!(
+                            &format!(
+                                "Failed to set the mode of socket {:?}: {:?}",
+                                socket_path, e
+                            ),
+                            NitroCliErrorEnum::FileOperationFailure
+                        )
+                        .add_info(vec![path_str, "Chmod"]),
+                    )
+                },
+            )?;
+        }
+        if let Some((uid, gid)) = self.owner {
+            chown(
+                socket_path,
+                Some(Uid::from_raw(uid)),
+                Some(Gid::from_raw(gid)),
+            )
+            .map_err(|e| {
+                remove_on_error(
+                    new_nitro_cli_failure!(
+                        &format!(
+                            "Failed to change the owner of socket {:?} to {}:{}: {:?}",
+                            socket_path, uid, gid, e
+                        ),
+                        NitroCliErrorEnum::FileOperationFailure
+                    )
+                    .add_info(vec![path_str, "Chown"]),
+                )
+            })?;
+        }
+
+        Ok(listener)
+    }
+
+    /// Connect to the managed Unix socket.
+    pub fn connect(&self) -> NitroCliResult<UnixStream> {
+        let socket_name = match self.abstract_name.as_ref() {
+            Some(socket_name) => socket_name,
+            None => {
+                return UnixStream::connect(self.get_path()).map_err(|e| {
+                    new_nitro_cli_failure!(
+                        &format!(
+                            "Failed to connect to socket {:?}: {:?}",
+                            self.socket_path, e
+                        ),
+                        NitroCliErrorEnum::SocketError
+                    )
+                })
+            }
+        };
+
+        let socket_fd = new_unix_socket_fd()?;
+        let socket_addr = abstract_socket_addr(socket_name)?;
+
+        let stream = unsafe { UnixStream::from_raw_fd(socket_fd) };
+        socket::connect(socket_fd, &socket_addr).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to connect to abstract socket @{}: {:?}",
+                    socket_name, e
+                ),
+                NitroCliErrorEnum::SocketError
+            )
+        })?;
+
+        Ok(stream)
+    }
+
+    /// Set the size of the buffer used for reading `inotify` events, which must be able
+    /// to hold at least one event for the longest possible file name.
+    pub fn with_event_buffer_size(mut self, event_buffer_size: usize) -> io::Result<Self> {
+        if event_buffer_size < MIN_EVENT_BUFFER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Event buffer size {} is smaller than the minimum of {} bytes",
+                    event_buffer_size, MIN_EVENT_BUFFER_SIZE
+                ),
+            ));
+        }
+
+        self.event_buffer_size = event_buffer_size;
+        Ok(self)
+    }
+
+    /// Warn whenever no CLI instance has pinged the enclave process for `liveness_timeout`,
+    /// once monitoring has started.
+    pub fn with_liveness_timeout(mut self, liveness_timeout: Duration) -> Self {
+        self.liveness_timeout = Some(liveness_timeout);
+        self
+    }
+
+    /// Give an externally deleted socket file `grace` to reappear before shutting down, since
+    /// some tools delete and then recreate files. Monitoring resumes if the file which reappears
+    /// is still the socket bound by the current process. Only applies to `start_monitoring()`.
+    pub fn with_deletion_grace(mut self, grace: Duration) -> Self {
+        self.deletion_grace = Some(grace);
+        self
+    }
+
+    /// Pin the socket monitoring thread started by `start_monitoring()` to the given host CPUs,
+    /// which must not have been given to the NE driver, so that monitoring doesn't contend with
+    /// the CPUs reserved for enclaves. The thread is unpinned by default.
+    pub fn with_monitor_affinity(mut self, cpus: Vec<u32>) -> Self {
+        self.monitor_cpus = Some(cpus);
+        self
+    }
+
+    /// Give the socket file the given mode (such as `0o600`) when it gets bound.
+    ///
+    /// Any local user who may write to the socket file can connect to the enclave process and
+    /// send it commands, including terminating its enclave. By default, the file gets the mode
+    /// `0o777` restricted by the process umask, which on a host shared by several users may let
+    /// others in. Abstract namespace sockets have no file, so this doesn't apply to them.
+    pub fn with_permissions(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Give the socket file the given owner and group when it gets bound, such as for letting a
+    /// dedicated service account connect to a socket created with a restrictive mode. Changing
+    /// the owner to another user needs `CAP_CHOWN`. Doesn't apply to abstract namespace sockets.
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Record that a CLI instance has just pinged the enclave process.
+    pub fn record_ping(&self) {
+        if let Ok(mut last_seen) = self.last_seen.lock() {
+            *last_seen = Some(Instant::now());
+        }
+    }
+
+    /// Get the last time a CLI instance has pinged the enclave process, if any.
+    pub fn last_seen(&self) -> Option<Instant> {
+        self.last_seen.lock().ok().and_then(|last_seen| *last_seen)
+    }
+
+    /// Get the path to the managed Unix socket.
+    pub fn get_path(&self) -> &Path {
+        self.socket_path.as_path()
+    }
+
+    /// Set the path of the managed Unix socket.
+    pub fn set_path(&mut self, socket_path: PathBuf) {
+        self.socket_path = socket_path;
+    }
+
+    /// Start monitoring the Unix socket's state using `inotify`.
+    ///
+    /// If the socket gets deleted by an external action, the reason is sent through
+    /// `shutdown_notifier` so that its owner can perform a proper teardown. Without a
+    /// notifier (or if it can no longer be reached), the current process exits.
+    ///
+    /// Abstract namespace sockets can't be watched with `inotify`, so they are instead
+    /// periodically checked for still listening.
+    pub fn start_monitoring(
+        &mut self,
+        shutdown_notifier: Option<Sender<ShutdownReason>>,
+    ) -> NitroCliResult<()> {
+        self.start_monitoring_with(MonitorNotifiers {
+            shutdown: shutdown_notifier,
+            events: None,
+        })
+    }
+
+    /// Start monitoring the Unix socket like `start_monitoring()`, and also report the socket's
+    /// lifecycle through the returned receiver: `Created` once monitoring has started, then either
+    /// `Deleted` or `ExternalDeletion`. The sender belongs to the monitoring thread, so the
+    /// receiver gets disconnected once that thread exits, and events which are never received are
+    /// freed along with the receiver.
+    pub fn start_monitoring_with_events(
+        &mut self,
+        shutdown_notifier: Option<Sender<ShutdownReason>>,
+    ) -> NitroCliResult<Receiver<SocketEvent>> {
+        let (events_tx, events_rx) = mpsc::channel();
+        self.start_monitoring_with(MonitorNotifiers {
+            shutdown: shutdown_notifier,
+            events: Some(events_tx),
+        })?;
+
+        Ok(events_rx)
+    }
+
+    /// Start monitoring the Unix socket, reporting to the given notifiers.
+    fn start_monitoring_with(&mut self, notifiers: MonitorNotifiers) -> NitroCliResult<()> {
+        self.shutdown_notifier = notifiers.shutdown.clone();
+        if let Some(liveness_timeout) = self.liveness_timeout {
+            let last_seen_clone = self.last_seen.clone();
+            let requested_remove_clone = self.requested_remove.clone();
+            self.liveness_thread = Some(thread::spawn(move || {
+                liveness_monitor(last_seen_clone, requested_remove_clone, liveness_timeout)
+            }));
+        }
+
+        let monitor_cpu_set = match self.monitor_cpus.as_ref() {
+            Some(cpus) => Some(
+                monitor_cpu_set(cpus, &read_cpu_pool()?.available())
+                    .map_err(|e| e.add_subaction("Invalid monitoring CPUs".to_string()))?,
+            ),
+            None => None,
+        };
+
+        if let Some(socket_name) = self.abstract_name.clone() {
+            let requested_remove_clone = self.requested_remove.clone();
+            notifiers.event(SocketEvent::Created);
+            self.remove_listener_thread = Some(thread::spawn(move || {
+                pin_current_thread(monitor_cpu_set.as_ref());
+                abstract_socket_listener(socket_name, requested_remove_clone, notifiers)
+            }));
+            return Ok(());
+        }
+
+        let path_clone = self.socket_path.clone();
+        let requested_remove_clone = self.requested_remove.clone();
+        let event_buffer_size = self.event_buffer_size;
+        let socket_inotify = Inotify::init().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to initialize socket notifications: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+
+        // Relevant events to listen for are:
+        // - IN_DELETE_SELF: triggered when the socket file inode gets removed.
+        // - IN_ATTRIB: triggered when the reference count of the file inode changes.
+        socket_inotify
+            .watches()
+            .add(
+                self.socket_path.as_path(),
+                WatchMask::ATTRIB | WatchMask::DELETE_SELF,
+            )
+            .map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    return self.vanished_error();
+                }
+                new_nitro_cli_failure!(
+                    &format!("Failed to add watch to inotify: {:?}", e),
+                    NitroCliErrorEnum::InotifyError
+                )
+            })?;
+        let shutdown_event = EventFd::new(EFD_NONBLOCK).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to create socket monitoring shutdown event: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+        let shutdown_event_clone = shutdown_event.try_clone().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to clone socket monitoring shutdown event: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+        self.shutdown_event = Some(shutdown_event);
+        let deletion_grace = match self.deletion_grace {
+            Some(window) => Some(DeletionGrace {
+                window,
+                inode: std::fs::symlink_metadata(&self.socket_path)
+                    .map_err(|e| {
+                        new_nitro_cli_failure!(
+                            &format!(
+                                "Failed to inspect socket file {:?}: {:?}",
+                                self.socket_path, e
+                            ),
+                            NitroCliErrorEnum::FileOperationFailure
+                        )
+                    })?
+                    .ino(),
+            }),
+            None => None,
+        };
+
+        notifiers.event(SocketEvent::Created);
+        self.remove_listener_thread = Some(thread::spawn(move || {
+            pin_current_thread(monitor_cpu_set.as_ref());
+            socket_removal_listener(
+                path_clone,
+                requested_remove_clone,
+                socket_inotify,
+                shutdown_event_clone,
+                notifiers,
+                event_buffer_size,
+                deletion_grace,
+            )
+        }));
+        Ok(())
+    }
+
+    /// Build the error for a socket file which has been deleted before it could be watched.
+    /// The file can't be brought back, even if the current process is still bound to the
+    /// socket, since a socket can't be bound again to a new path.
+    fn vanished_error(&self) -> NitroCliFailure {
+        let path_str = self.socket_path.to_string_lossy();
+        let still_bound = unix_socket_paths_of(std::process::id(), Path::new(PROC_DIR))
+            .map_or(false, |paths| paths.contains(&self.socket_path));
+        let reason = if still_bound {
+            "its file has been deleted while the current process is still bound to it"
+        } else {
+            "it is no longer bound by the current process"
+        };
+
+        new_nitro_cli_failure!(
+            &format!(
+                "The socket {} vanished before it could be monitored: {}",
+                path_str, reason
+            ),
+            NitroCliErrorEnum::SocketVanished
+        )
+        .add_info(vec![&path_str])
+    }
+
+    /// Monitor the Unix socket file without a dedicated thread, as a stream which yields a single
+    /// event once the file gets deleted and then ends. The stream must be polled from within a
+    /// Tokio runtime with its I/O driver enabled. Abstract namespace sockets have no file to
+    /// monitor, so they are not supported.
+    #[cfg(feature = "async")]
+    pub fn monitor_stream(
+        &self,
+    ) -> NitroCliResult<impl futures::Stream<Item = SocketEvent> + Send + 'static> {
+        use futures::StreamExt;
+
+        if self.is_abstract() {
+            return Err(new_nitro_cli_failure!(
+                "Abstract namespace sockets can't be monitored for deletion",
+                NitroCliErrorEnum::InotifyError
+            ));
+        }
+
+        let socket_inotify = Inotify::init().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to initialize socket notifications: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+        socket_inotify
+            .watches()
+            .add(
+                self.socket_path.as_path(),
+                WatchMask::ATTRIB | WatchMask::DELETE_SELF,
+            )
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to add watch to inotify: {:?}", e),
+                    NitroCliErrorEnum::InotifyError
+                )
+            })?;
+        let events = socket_inotify
+            .into_event_stream(vec![0u8; self.event_buffer_size])
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to create socket notification stream: {:?}", e),
+                    NitroCliErrorEnum::InotifyError
+                )
+            })?;
+
+        let socket_path = self.socket_path.clone();
+        let requested_remove = self.requested_remove.clone();
+        Ok(events
+            .filter_map(move |event| {
+                let socket_event = match event {
+                    Ok(event) if is_deletion_event(event.mask, &socket_path) => {
+                        if requested_remove.load(Ordering::SeqCst) {
+                            Some(SocketEvent::Deleted)
+                        } else {
+                            warn!("The enclave process socket has been deleted!");
+                            Some(SocketEvent::ExternalDeletion)
+                        }
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("Failed to read socket file events: {:?}", e);
+                        None
+                    }
+                };
+                futures::future::ready(socket_event)
+            })
+            .take(1))
+    }
+
+    /// Start monitoring the Unix socket's state through a watcher shared with other sockets,
+    /// instead of a dedicated thread. Deletions are handled like in `start_monitoring()`.
+    pub fn start_monitoring_shared(
+        &mut self,
+        watcher: Arc<SocketWatcher>,
+        shutdown_notifier: Option<Sender<ShutdownReason>>,
+    ) -> NitroCliResult<()> {
+        if self.is_abstract() {
+            return Err(new_nitro_cli_failure!(
+                "Abstract namespace sockets can't be monitored by a socket watcher",
+                NitroCliErrorEnum::InotifyError
+            ));
+        }
+
+        self.shutdown_notifier = shutdown_notifier.clone();
+        let requested_remove = self.requested_remove.clone();
+        let watch = watcher.watch(&self.socket_path, move |socket_path| {
+            if requested_remove.load(Ordering::SeqCst) {
+                debug!(
+                    "The enclave process socket {:?} has deleted itself.",
+                    socket_path
+                );
+            } else {
+                warn!(
+                    "The enclave process socket {:?} has been deleted!",
+                    socket_path
+                );
+                notify_shutdown(shutdown_notifier.as_ref(), ShutdownReason::SocketDeleted);
+            }
+        })?;
+        self.shared_watch = Some((watcher, watch));
+
+        Ok(())
+    }
+
+    /// Move the managed Unix socket file to `new_path`, such as when relocating the sockets
+    /// directory. A listening socket can't be bound to another path, so the socket file is linked
+    /// to `new_path` instead, which must therefore be on the same file-system. If the socket is
+    /// being monitored, monitoring of `new_path` is started with the same shutdown notifier before
+    /// the old path is removed, and that removal is marked as requested, so it doesn't trigger a
+    /// shutdown. Socket events requested with `start_monitoring_with_events()` are only reported
+    /// for the old path, ending with `SocketEvent::Deleted`.
+    pub fn rebind(&mut self, new_path: PathBuf) -> NitroCliResult<()> {
+        if self.is_abstract() {
+            return Err(new_nitro_cli_failure!(
+                "Abstract namespace sockets have no path to move",
+                NitroCliErrorEnum::SocketError
+            ));
+        }
+
+        if new_path == self.socket_path {
+            return Ok(());
+        }
+
+        std::fs::hard_link(&self.socket_path, &new_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to link socket file {:?} to {:?}: {:?}",
+                    self.socket_path, new_path, e
+                ),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![
+                new_path
+                    .to_str()
+                    .unwrap_or("Invalid unicode socket file name"),
+                "Link",
+            ])
+        })?;
+
+        // The moved socket gets its own removal flag, so that the old path can be removed while
+        // the new one is still monitored. Dropping it on failure removes the link again.
+        let mut moved = self.clone();
+        moved.socket_path = new_path;
+        moved.requested_remove = Arc::new(AtomicBool::new(false));
+        let shutdown_notifier = self.shutdown_notifier.clone();
+        if let Some((watcher, _)) = self.shared_watch.as_ref() {
+            moved.start_monitoring_shared(watcher.clone(), shutdown_notifier)?;
+        } else if self.remove_listener_thread.is_some() {
+            moved.start_monitoring_with(MonitorNotifiers {
+                shutdown: shutdown_notifier,
+                events: None,
+            })?;
+        }
+
+        let old = std::mem::replace(self, moved);
+        debug!(
+            "Moved the enclave process socket from {:?} to {:?}.",
+            old.socket_path, self.socket_path
+        );
+        old.close()
+    }
+
+    /// Remove the managed Unix socket and clean up after it. This is called with a mutable self-reference.
+    fn close_mut(&mut self) -> NitroCliResult<()> {
+        // Delete the socket from the disk. Also mark that this operation is intended, so that the
+        // socket file monitoring thread doesn't exit forcefully when notifying the deletion.
+        self.requested_remove.store(true, Ordering::SeqCst);
+        if !self.is_abstract() && self.socket_path.exists() {
+            std::fs::remove_file(&self.socket_path).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!(
+                        "Failed to remove socket file {:?} from disk: {:?}",
+                        self.socket_path, e
+                    ),
+                    NitroCliErrorEnum::FileOperationFailure
+                )
+                .add_info(vec![
+                    self.socket_path
+                        .to_str()
+                        .unwrap_or("Invalid unicode socket file name"),
+                    "Remove",
+                ])
+            })?;
+        }
+
+        if let Some(liveness_thread) = self.liveness_thread.take() {
+            liveness_thread.join().map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to join liveness monitoring thread: {:?}", e),
+                    NitroCliErrorEnum::ThreadJoinFailure
+                )
+            })?;
+        }
+
+        if let Some((watcher, watch)) = self.shared_watch.take() {
+            watcher.unwatch(watch)?;
+        }
+        self.shutdown_notifier = None;
+
+        // Wake up the event listener thread, in case the deletion has not produced any event
+        // (such as when the socket file had already been removed).
+        if let Some(shutdown_event) = self.shutdown_event.take() {
+            shutdown_event.write(1).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to signal socket monitoring shutdown: {:?}", e),
+                    NitroCliErrorEnum::InotifyError
+                )
+            })?;
+        }
+
+        // Since the socket file has been deleted, we also wait for the event listener thread to finish.
+        if self.remove_listener_thread.is_some() {
+            self.remove_listener_thread
+                .take()
+                .unwrap()
+                .join()
+                .map_err(|e| {
+                    new_nitro_cli_failure!(
+                        &format!("Failed to join socket notification thread: {:?}", e),
+                        NitroCliErrorEnum::ThreadJoinFailure
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the managed Unix socket and clean up after it.
+    pub fn close(mut self) -> NitroCliResult<()> {
+        self.close_mut()
+            .map_err(|e| e.add_subaction("Close socket".to_string()))
+    }
+
+    /// Persist the socket's path, along with the PID of the current process which listens on it,
+    /// to a file in the given directory. A restarted process can then use `restore()` to manage
+    /// the socket again without recreating it.
+    pub fn persist(&self, dir: &Path) -> NitroCliResult<()> {
+        if self.is_abstract() {
+            return Err(new_nitro_cli_failure!(
+                "Cannot persist the state of an abstract namespace socket",
+                NitroCliErrorEnum::InvalidArgument
+            ));
+        }
+
+        let state = PersistedSocketState {
+            socket_path: self.socket_path.clone(),
+            pid: std::process::id(),
+        };
+        let state_path = socket_state_path(dir, &self.socket_path)?;
+        let contents = serde_json::to_vec(&state).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to serialize socket state: {:?}", e),
+                NitroCliErrorEnum::SerdeError
+            )
+        })?;
+
+        std::fs::write(&state_path, contents).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to write socket state to {:?}: {:?}", state_path, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![&state_path.to_string_lossy(), "Write"])
+        })
+    }
+
+    /// Restore the socket of the enclave with the given ID from the state persisted to the
+    /// given directory by `persist()`. This fails unless the process recorded in the state is
+    /// still alive and still holds the socket, after which the socket can be monitored again.
+    pub fn restore(enclave_id: &str, dir: &Path) -> NitroCliResult<Self> {
+        let mut socket = EnclaveProcSock::new(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to restore socket".to_string()))?;
+        socket.restore_from(dir, Path::new(PROC_DIR))?;
+
+        Ok(socket)
+    }
+
+    /// Restore the state of the socket as `restore()` does, checking its owner in the given
+    /// procfs directory.
+    fn restore_from(&mut self, dir: &Path, proc_dir: &Path) -> NitroCliResult<()> {
+        let state_path = socket_state_path(dir, &self.socket_path)?;
+        let contents = std::fs::read(&state_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to read socket state from {:?}: {:?}", state_path, e),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![&state_path.to_string_lossy(), "Read"])
+        })?;
+        let state: PersistedSocketState = serde_json::from_slice(&contents).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to parse socket state {:?}: {:?}", state_path, e),
+                NitroCliErrorEnum::SerdeError
+            )
+        })?;
+
+        let owned = unix_socket_paths_of(state.pid, proc_dir)
+            .map_err(|e| e.add_subaction("Failed to restore socket".to_string()))?
+            .contains(&state.socket_path);
+        if !owned {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Process {} no longer holds the socket {:?}",
+                    state.pid, state.socket_path
+                ),
+                NitroCliErrorEnum::NotAnEnclaveProcess
+            )
+            .add_info(vec![&state.pid.to_string()]));
+        }
+
+        self.socket_path = state.socket_path;
+        Ok(())
+    }
+
+    /// Remove the socket file of the enclave with the given ID if it has been left behind by an
+    /// enclave process which no longer runs, such as one that was killed. Returns `true` if the
+    /// file has been removed.
+    pub fn cleanup_stale(enclave_id: &str) -> NitroCliResult<bool> {
+        let socket_path = get_socket_path(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to clean up stale socket".to_string()))?;
+        EnclaveProcSock::remove_if_stale(&socket_path)
+    }
+
+    /// Remove the socket file at the given path if no process listens on it.
+    ///
+    /// A process which is currently binding the socket refuses connections until it starts
+    /// listening, so a refusal only counts if it persists after a short delay and the file has
+    /// not been replaced in the meantime.
+    pub(crate) fn remove_if_stale(socket_path: &Path) -> NitroCliResult<bool> {
+        let inode = match std::fs::symlink_metadata(socket_path) {
+            Ok(metadata) => metadata.ino(),
+            Err(_) => return Ok(false),
+        };
+
+        for attempt in 0..2 {
+            if attempt > 0 {
+                thread::sleep(STALE_SOCKET_RECHECK_DELAY);
+            }
+
+            match UnixStream::connect(socket_path) {
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+                _ => return Ok(false),
+            }
+        }
+
+        match std::fs::symlink_metadata(socket_path) {
+            Ok(metadata) if metadata.ino() == inode => (),
+            _ => return Ok(false),
+        }
+
+        debug!("Removing stale socket {:?}.", socket_path);
+        std::fs::remove_file(socket_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to remove stale socket file {:?}: {:?}",
+                    socket_path, e
+                ),
+                NitroCliErrorEnum::FileOperationFailure
+            )
+            .add_info(vec![
+                socket_path
+                    .to_str()
+                    .unwrap_or("Invalid unicode socket file name"),
+                "Remove",
+            ])
+        })?;
+
+        Ok(true)
+    }
+}
+
+impl SocketWatcher {
+    /// Create a new `SocketWatcher` instance and start its thread.
+    pub fn new() -> NitroCliResult<Self> {
+        let inotify = Inotify::init().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to initialize socket notifications: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+        let shutdown_event = EventFd::new(EFD_NONBLOCK).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to create socket watcher shutdown event: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+        let shutdown_event_clone = shutdown_event.try_clone().map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to clone socket watcher shutdown event: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+        })?;
+
+        let watches = inotify.watches();
+        let sockets = Arc::new(Mutex::new(HashMap::new()));
+        let sockets_clone = sockets.clone();
+        let thread =
+            thread::spawn(move || socket_watcher_run(inotify, sockets_clone, shutdown_event_clone));
+
+        Ok(SocketWatcher {
+            watches: Mutex::new(watches),
+            sockets,
+            shutdown_event,
+            thread: Some(thread),
+        })
+    }
+
+    /// Watch the socket file at `path`, invoking `on_deleted` from the watcher thread once the
+    /// file gets deleted, after which the file is no longer watched.
+    pub fn watch<F>(&self, path: &Path, on_deleted: F) -> NitroCliResult<WatchDescriptor>
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        // The sockets stay locked until the new one is registered, so that the watcher thread
+        // can't miss an event on it.
+        let mut sockets = lock_sockets(&self.sockets)?;
+        let watch = self
+            .watches
+            .lock()
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to acquire lock on socket watches: {:?}", e),
+                    NitroCliErrorEnum::LockAcquireFailure
+                )
+            })?
+            .add(path, WatchMask::ATTRIB | WatchMask::DELETE_SELF)
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to add watch for {:?} to inotify: {:?}", path, e),
+                    NitroCliErrorEnum::InotifyError
+                )
+            })?;
+
+        sockets.insert(
+            watch.clone(),
+            WatchedSocket {
+                path: path.to_path_buf(),
+                on_deleted: Box::new(on_deleted),
+            },
+        );
+
+        Ok(watch)
+    }
+
+    /// Stop watching a socket file. This does nothing if the file has already been reported
+    /// as deleted.
+    pub fn unwatch(&self, watch: WatchDescriptor) -> NitroCliResult<()> {
+        if lock_sockets(&self.sockets)?.remove(&watch).is_some() {
+            if let Ok(mut watches) = self.watches.lock() {
+                // The kernel drops the watch by itself once the file is gone.
+                let _ = watches.remove(watch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of socket files which are currently watched.
+    pub fn len(&self) -> usize {
+        self.sockets.lock().map_or(0, |sockets| sockets.len())
+    }
+
+    /// Check if no socket file is currently watched.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for SocketWatcher {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown_event.write(1) {
+            warn!("Failed to signal socket watcher shutdown: {:?}", e);
+            return;
+        }
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Failed to join socket watcher thread.");
+            }
+        }
+    }
+}
+
+/// Lock the sockets of a `SocketWatcher`.
+fn lock_sockets(
+    sockets: &Mutex<HashMap<WatchDescriptor, WatchedSocket>>,
+) -> NitroCliResult<std::sync::MutexGuard<'_, HashMap<WatchDescriptor, WatchedSocket>>> {
+    sockets.lock().map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to acquire lock on watched sockets: {:?}", e),
+            NitroCliErrorEnum::LockAcquireFailure
+        )
+    })
+}
+
+/// Read the `inotify` events of a `SocketWatcher` and invoke the callbacks of the sockets which
+/// have been deleted, until `shutdown_event` gets signaled.
+fn socket_watcher_run(
+    mut inotify: Inotify,
+    sockets: Arc<Mutex<HashMap<WatchDescriptor, WatchedSocket>>>,
+    shutdown_event: EventFd,
+) {
+    let mut buffer = vec![0u8; DEFAULT_EVENT_BUFFER_SIZE];
+
+    loop {
+        let mut poll_fds = [
+            PollFd::new(inotify.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(shutdown_event.as_raw_fd(), PollFlags::POLLIN),
+        ];
+        match poll(&mut poll_fds, -1) {
+            Ok(_) => (),
+            Err(Errno::EINTR) => continue,
+            Err(e) => {
+                warn!("Socket watcher poll error: {:?}", e);
+                return;
+            }
+        }
+
+        if poll_fds[1]
+            .revents()
+            .map_or(false, |revents| revents.contains(PollFlags::POLLIN))
+        {
+            debug!("The socket watcher has been stopped.");
+            return;
+        }
+
+        let events = match inotify.read_events(&mut buffer) {
+            Ok(events) => events,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => {
+                warn!("Socket watcher error: {:?}", e);
+                return;
+            }
+        };
+
+        let mut deleted = Vec::new();
+        {
+            let mut sockets = match sockets.lock() {
+                Ok(sockets) => sockets,
+                Err(_) => return,
+            };
+            let mut overflowed = false;
+
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    warn!("The socket watcher event queue has overflowed, so events may have been lost.");
+                    overflowed = true;
+                    continue;
+                }
+
+                let is_deleted = sockets.get(&event.wd).map_or(false, |socket| {
+                    is_deletion_event(event.mask, &socket.path)
+                        || (event.mask.contains(EventMask::IGNORED) && !socket.path.exists())
+                });
+                if is_deleted {
+                    deleted.extend(sockets.remove(&event.wd));
+                }
+            }
+
+            // The deletion events themselves may have been lost, so every socket is checked.
+            if overflowed {
+                let gone: Vec<WatchDescriptor> = sockets
+                    .iter()
+                    .filter(|(_, socket)| !socket.path.exists())
+                    .map(|(watch, _)| watch.clone())
+                    .collect();
+                for watch in gone {
+                    deleted.extend(sockets.remove(&watch));
+                }
+            }
+        }
+
+        // The callbacks are invoked without holding the lock, so that they may use the watcher.
+        for mut socket in deleted {
+            (socket.on_deleted)(&socket.path);
+        }
     }
+}
 
-    /// Set the path of the managed Unix socket.
-    pub fn set_path(&mut self, socket_path: PathBuf) {
-        self.socket_path = socket_path;
+/// Build the CPU set of the socket monitoring thread, whose CPUs must be outside the NE CPU pool.
+fn monitor_cpu_set(cpus: &[u32], pool_cpus: &[u32]) -> NitroCliResult<CpuSet> {
+    if cpus.is_empty() {
+        return Err(new_nitro_cli_failure!(
+            "The socket monitoring thread needs at least one CPU",
+            NitroCliErrorEnum::InvalidCpuConfiguration
+        ));
     }
 
-    /// Start monitoring the Unix socket's state using `inotify`.
-    pub fn start_monitoring(&mut self, exit_on_delete: bool) -> NitroCliResult<()> {
-        let path_clone = self.socket_path.clone();
-        let requested_remove_clone = self.requested_remove.clone();
-        let socket_inotify = Inotify::init().map_err(|e| {
+    let mut cpu_set = CpuSet::new();
+    for &cpu_id in cpus {
+        if pool_cpus.contains(&cpu_id) {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "CPU {} is in the NE CPU pool, so it is reserved for enclaves",
+                    cpu_id
+                ),
+                NitroCliErrorEnum::InvalidCpuConfiguration
+            )
+            .add_info(vec!["cpu-ids", &cpu_id.to_string()]));
+        }
+        cpu_set.set(cpu_id as usize).map_err(|e| {
             new_nitro_cli_failure!(
-                &format!("Failed to initialize socket notifications: {:?}", e),
-                NitroCliErrorEnum::InotifyError
+                &format!("CPU {} can't be added to a CPU set: {:?}", cpu_id, e),
+                NitroCliErrorEnum::InvalidCpuConfiguration
             )
+            .add_info(vec!["cpu-ids", &cpu_id.to_string()])
         })?;
-
-        // Relevant events to listen for are:
-        // - IN_DELETE_SELF: triggered when the socket file inode gets removed.
-        // - IN_ATTRIB: triggered when the reference count of the file inode changes.
-        socket_inotify
-            .watches()
-            .add(
-                self.socket_path.as_path(),
-                WatchMask::ATTRIB | WatchMask::DELETE_SELF,
-            )
-            .map_err(|e| {
-                new_nitro_cli_failure!(
-                    &format!("Failed to add watch to inotify: {:?}", e),
-                    NitroCliErrorEnum::InotifyError
-                )
-            })?;
-        self.remove_listener_thread = Some(thread::spawn(move || {
-            socket_removal_listener(
-                path_clone,
-                requested_remove_clone,
-                socket_inotify,
-                exit_on_delete,
-            )
-        }));
-        Ok(())
     }
 
-    /// Remove the managed Unix socket and clean up after it. This is called with a mutable self-reference.
-    fn close_mut(&mut self) -> NitroCliResult<()> {
-        // Delete the socket from the disk. Also mark that this operation is intended, so that the
-        // socket file monitoring thread doesn't exit forcefully when notifying the deletion.
-        self.requested_remove.store(true, Ordering::SeqCst);
-        if self.socket_path.exists() {
-            std::fs::remove_file(&self.socket_path).map_err(|e| {
-                new_nitro_cli_failure!(
-                    &format!(
-                        "Failed to remove socket file {:?} from disk: {:?}",
-                        self.socket_path, e
-                    ),
-                    NitroCliErrorEnum::FileOperationFailure
-                )
-                .add_info(vec![
-                    self.socket_path
-                        .to_str()
-                        .unwrap_or("Invalid unicode socket file name"),
-                    "Remove",
-                ])
-            })?;
-        }
+    Ok(cpu_set)
+}
 
-        // Since the socket file has been deleted, we also wait for the event listener thread to finish.
-        if self.remove_listener_thread.is_some() {
-            self.remove_listener_thread
-                .take()
-                .unwrap()
-                .join()
-                .map_err(|e| {
-                    new_nitro_cli_failure!(
-                        &format!("Failed to join socket notification thread: {:?}", e),
-                        NitroCliErrorEnum::ThreadJoinFailure
-                    )
-                })?;
+/// Pin the current thread to `cpu_set`, if given. The thread keeps running unpinned if this
+/// fails, since monitoring the socket matters more than where it happens.
+fn pin_current_thread(cpu_set: Option<&CpuSet>) {
+    if let Some(cpu_set) = cpu_set {
+        if let Err(e) = sched_setaffinity(Pid::from_raw(0), cpu_set) {
+            enclave_log!(
+                warn,
+                "Failed to pin the socket monitoring thread, it runs unpinned: {:?}",
+                e
+            );
         }
-
-        Ok(())
     }
+}
 
-    /// Remove the managed Unix socket and clean up after it.
-    pub fn close(mut self) -> NitroCliResult<()> {
-        self.close_mut()
-            .map_err(|e| e.add_subaction("Close socket".to_string()))
-    }
+/// Check if an `inotify` event on the socket file means that the file has been deleted.
+///
+/// We monitor the DELETE_SELF event, which occurs when the inode is no longer referenced by
+/// anybody. We also monitor the IN_ATTRIB event, which gets triggered whenever the inode
+/// reference count changes. To make sure this is a deletion, we also verify if the socket file
+/// is still present in the file-system. An overflowed queue may have dropped the deletion event.
+fn is_deletion_event(mask: EventMask, socket_path: &Path) -> bool {
+    (mask.contains(EventMask::ATTRIB)
+        || mask.contains(EventMask::DELETE_SELF)
+        || mask.contains(EventMask::Q_OVERFLOW))
+        && !socket_path.exists()
 }
 
-/// Listen for an `inotify` event when the socket gets deleted from the disk.
+/// Listen for an `inotify` event when the socket gets deleted from the disk, or for
+/// `shutdown_event` to be signaled when the socket is closed.
 fn socket_removal_listener(
     socket_path: PathBuf,
     requested_remove: Arc<AtomicBool>,
     mut socket_inotify: Inotify,
-    exit_on_delete: bool,
+    shutdown_event: EventFd,
+    notifiers: MonitorNotifiers,
+    event_buffer_size: usize,
+    deletion_grace: Option<DeletionGrace>,
 ) {
-    let mut buffer = [0u8; 4096];
+    let mut buffer = vec![0u8; event_buffer_size];
     let mut done = false;
 
-    debug!("Socket file event listener started for {:?}.", socket_path);
+    enclave_log!(
+        debug,
+        "Socket file event listener started for {:?}.",
+        socket_path
+    );
 
     while !done {
+        let mut poll_fds = [
+            PollFd::new(socket_inotify.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(shutdown_event.as_raw_fd(), PollFlags::POLLIN),
+        ];
+        match poll(&mut poll_fds, -1) {
+            Ok(_) => (),
+            Err(Errno::EINTR) => continue,
+            Err(e) => Err(new_nitro_cli_failure!(
+                &format!("Socket removal listener poll error: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+            .set_action("Run Enclave".to_string()))
+            .ok_or_exit_with_errno(Some("Failed to wait for inotify events")),
+        }
+
+        if poll_fds[1]
+            .revents()
+            .map_or(false, |revents| revents.contains(PollFlags::POLLIN))
+        {
+            enclave_log!(
+                debug,
+                "The enclave process socket monitoring has been stopped."
+            );
+            notifiers.event(SocketEvent::Deleted);
+            break;
+        }
+
         // Read events.
-        let events = socket_inotify
-            .read_events_blocking(&mut buffer)
-            .map_err(|e| {
-                new_nitro_cli_failure!(
-                    &format!("Socket removal listener error: {:?}", e),
-                    NitroCliErrorEnum::InotifyError
-                )
-                .set_action("Run Enclave".to_string())
-            })
-            .ok_or_exit_with_errno(Some("Failed to read inotify events"));
+        let events = match socket_inotify.read_events(&mut buffer) {
+            Ok(events) => events,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => Err(new_nitro_cli_failure!(
+                &format!("Socket removal listener error: {:?}", e),
+                NitroCliErrorEnum::InotifyError
+            )
+            .set_action("Run Enclave".to_string()))
+            .ok_or_exit_with_errno(Some("Failed to read inotify events")),
+        };
 
+        let mut overflowed = false;
+        let mut reappeared = false;
         for event in events {
-            // We monitor the DELETE_SELF event, which occurs when the inode is no longer referenced by anybody. We
-            // also monitor the IN_ATTRIB event, which gets triggered whenever the inode reference count changes. To
-            // make sure this is a deletion, we also verify if the socket file is still present in the file-system.
-            if (event.mask.contains(EventMask::ATTRIB)
-                || event.mask.contains(EventMask::DELETE_SELF))
-                && !socket_path.exists()
+            // Once the event queue overflows, the deletion event itself may have been dropped.
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                enclave_log!(
+                    warn,
+                    "The socket file event queue has overflowed, so events may have been lost."
+                );
+                overflowed = true;
+            }
+
+            if (overflowed || is_deletion_event(event.mask, &socket_path)) && !socket_path.exists()
             {
                 if requested_remove.load(Ordering::SeqCst) {
                     // At this point, the socket is shutting itself down and has notified the
                     // monitoring thread, so we just exit the loop gracefully.
-                    debug!("The enclave process socket has deleted itself.");
+                    enclave_log!(debug, "The enclave process socket has deleted itself.");
+                    notifiers.event(SocketEvent::Deleted);
                     done = true;
+                } else if deletion_grace
+                    .as_ref()
+                    .map_or(false, |grace| socket_reappeared(&socket_path, grace))
+                {
+                    enclave_log!(
+                        warn,
+                        "The enclave process socket {:?} has been deleted and then restored, \
+                         resuming monitoring.",
+                        socket_path
+                    );
+                    reappeared = true;
                 } else {
-                    // At this point, the socket has been deleted by an external action, so
-                    // we exit forcefully, since there is no longer any way for a CLI instance
-                    // to tell the current enclave process to terminate.
-                    warn!("The enclave process socket has been deleted!");
-                    if exit_on_delete {
-                        std::process::exit(1);
-                    }
+                    // At this point, the socket has been deleted by an external action, so there
+                    // is no longer any way for a CLI instance to tell the current enclave process
+                    // to terminate. We ask the owner to shut down and, if that isn't possible, we
+                    // exit forcefully.
+                    enclave_log!(warn, "The enclave process socket has been deleted!");
+                    notifiers.event(SocketEvent::ExternalDeletion);
+                    notifiers.shutdown(ShutdownReason::SocketDeleted);
                     done = true;
                 }
+                break;
+            }
+        }
+
+        // Adding the watch again keeps it in place if it is still valid, or restores it if
+        // the socket file has been replaced while the events were being dropped.
+        if (overflowed || reappeared) && !done {
+            if let Err(e) = socket_inotify.watches().add(
+                socket_path.as_path(),
+                WatchMask::ATTRIB | WatchMask::DELETE_SELF,
+            ) {
+                enclave_log!(warn, "Failed to re-add the socket file watch: {:?}", e);
             }
         }
     }
 
+    enclave_log!(debug, "Enclave process socket monitoring is done.");
+}
+
+/// Wait for the grace window, then check if the socket file exists again and is still the
+/// socket bound by the current process, as when it has been moved away and back.
+fn socket_reappeared(socket_path: &Path, grace: &DeletionGrace) -> bool {
+    thread::sleep(grace.window);
+
+    std::fs::symlink_metadata(socket_path).map_or(false, |metadata| metadata.ino() == grace.inode)
+}
+
+/// Warn once for every period longer than `liveness_timeout` in which no CLI instance has pinged.
+fn liveness_monitor(
+    last_seen: Arc<Mutex<Option<Instant>>>,
+    requested_remove: Arc<AtomicBool>,
+    liveness_timeout: Duration,
+) {
+    let started = Instant::now();
+    let mut warned_since = None;
+
+    while !requested_remove.load(Ordering::SeqCst) {
+        thread::sleep(LIVENESS_CHECK_INTERVAL.min(liveness_timeout));
+
+        let since = last_seen
+            .lock()
+            .ok()
+            .and_then(|last_seen| *last_seen)
+            .unwrap_or(started);
+        if since.elapsed() >= liveness_timeout && warned_since != Some(since) {
+            warn!(
+                "No CLI instance has pinged the enclave process for {:?}.",
+                since.elapsed()
+            );
+            warned_since = Some(since);
+        }
+    }
+}
+
+/// Periodically check that an abstract namespace socket is still listening.
+fn abstract_socket_listener(
+    socket_name: String,
+    requested_remove: Arc<AtomicBool>,
+    notifiers: MonitorNotifiers,
+) {
+    debug!("Abstract socket monitor started for @{}.", socket_name);
+
+    while !requested_remove.load(Ordering::SeqCst) {
+        thread::sleep(ABSTRACT_SOCKET_POLL_INTERVAL);
+
+        let is_listening = File::open(NET_UNIX_FILEPATH)
+            .map(|net_unix| is_abstract_socket_listening(BufReader::new(net_unix), &socket_name))
+            .unwrap_or(true);
+
+        // The socket may also have stopped listening because it is being closed on purpose.
+        if !is_listening && !requested_remove.load(Ordering::SeqCst) {
+            warn!("The enclave process socket is no longer listening!");
+            notifiers.event(SocketEvent::ExternalDeletion);
+            notifiers.shutdown(ShutdownReason::SocketDeleted);
+            return;
+        }
+    }
+
+    notifiers.event(SocketEvent::Deleted);
     debug!("Enclave process socket monitoring is done.");
 }
 
+/// Check if a `/proc/net/unix` listing contains a listening abstract socket with the given name.
+fn is_abstract_socket_listening<B: BufRead>(net_unix: B, socket_name: &str) -> bool {
+    let listed_name = format!("@{}", socket_name);
+
+    net_unix.lines().map_while(Result::ok).any(|line| {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        // The columns are: Num, RefCount, Protocol, Flags, Type, St, Inode and Path.
+        tokens.len() == 8 && tokens[3] == NET_UNIX_LISTENING_FLAGS && tokens[7] == listed_name
+    })
+}
+
+/// Get the path of the file in `dir` which the state of the socket at `socket_path` is persisted to.
+fn socket_state_path(dir: &Path, socket_path: &Path) -> NitroCliResult<PathBuf> {
+    let socket_name = socket_path.file_name().ok_or_else(|| {
+        new_nitro_cli_failure!(
+            &format!("Invalid enclave process socket path: {:?}", socket_path),
+            NitroCliErrorEnum::SocketPathNotFound
+        )
+    })?;
+    let mut state_name = socket_name.to_os_string();
+    state_name.push(SOCKET_STATE_FILE_SUFFIX);
+
+    Ok(dir.join(state_name))
+}
+
+/// Create a new Unix stream socket descriptor.
+fn new_unix_socket_fd() -> NitroCliResult<RawFd> {
+    socket::socket(
+        AddressFamily::Unix,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to create Unix socket: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })
+}
+
+/// Check that the peer of a connection to an enclave process socket may send it commands, which
+/// is the case for the user owning the current process, for root and for the users whose UIDs
+/// are listed in `allowed`. The peer's credentials are the ones from when it connected, as
+/// reported by `SO_PEERCRED`, so they can't be forged afterwards.
+pub fn authorize_peer(stream: &UnixStream, allowed: &[u32]) -> NitroCliResult<()> {
+    let creds = socket::getsockopt(stream.as_raw_fd(), PeerCredentials).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Failed to get the credentials of the peer: {:?}", e),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+
+    check_peer_uid(creds.uid(), Uid::current().as_raw(), allowed).map_err(|e| {
+        enclave_log!(
+            warn,
+            "Rejected connection from PID {} (UID {}, GID {}).",
+            creds.pid(),
+            creds.uid(),
+            creds.gid()
+        );
+        e
+    })
+}
+
+/// Check that the peer with the given UID is the owner `own_uid`, root or in `allowed`.
+fn check_peer_uid(uid: u32, own_uid: u32, allowed: &[u32]) -> NitroCliResult<()> {
+    if uid == own_uid || uid == 0 || allowed.contains(&uid) {
+        return Ok(());
+    }
+
+    Err(new_nitro_cli_failure!(
+        &format!(
+            "The user with UID {} may not connect to the enclave process",
+            uid
+        ),
+        NitroCliErrorEnum::UnauthorizedPeer
+    )
+    .add_info(vec![&uid.to_string()]))
+}
+
+/// Get the address of a socket in the abstract namespace.
+fn abstract_socket_addr(socket_name: &str) -> NitroCliResult<UnixAddr> {
+    UnixAddr::new_abstract(socket_name.as_bytes()).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!("Invalid abstract socket name {}: {:?}", socket_name, e),
+            NitroCliErrorEnum::SocketError
+        )
+    })
+}
+
+impl MonitorNotifiers {
+    /// Report a socket event, if events have been asked for. A consumer which has dropped its
+    /// receiver no longer gets events.
+    fn event(&self, event: SocketEvent) {
+        if let Some(events) = self.events.as_ref() {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Ask the owner of the socket to shut down, exiting if it can't be reached.
+    fn shutdown(&self, reason: ShutdownReason) {
+        notify_shutdown(self.shutdown.as_ref(), reason);
+    }
+}
+
+/// Forward a shutdown request to the socket's owner or exit if there is nobody to handle it.
+fn notify_shutdown(shutdown_notifier: Option<&Sender<ShutdownReason>>, reason: ShutdownReason) {
+    match shutdown_notifier.map(|notifier| notifier.send(reason)) {
+        Some(Ok(())) => debug!("Requested enclave process shutdown: {:?}.", reason),
+        _ => std::process::exit(1),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use std::os::unix::net::UnixListener;
     use std::process::Command;
+    use std::sync::mpsc;
 
     const DUMMY_ENCLAVE_ID: &str = "i-0000000000000000-enc0123456789012345";
     const THREADS_STR: &str = "Threads:";
@@ -254,11 +1677,103 @@ mod tests {
                 .contains("0123456789012345"));
             assert!(socket.remove_listener_thread.is_none());
             assert!(!socket.requested_remove.load(Ordering::SeqCst));
+            assert_eq!(socket.event_buffer_size, DEFAULT_EVENT_BUFFER_SIZE);
+            assert!(!socket.is_abstract());
         }
     }
 
+    /// Tests that pings are shared between socket clones and that the liveness monitor
+    /// stops when the socket is closed.
+    #[test]
+    fn test_liveness() {
+        // A distinct ID avoids clashing with the other abstract socket test.
+        let mut socket = EnclaveProcSock::new_abstract("i-0000000000000000-enc0000000000000017")
+            .unwrap()
+            .with_liveness_timeout(Duration::from_millis(10));
+        assert!(socket.last_seen().is_none());
+
+        let socket_clone = socket.clone();
+        socket_clone.record_ping();
+        let last_seen = socket.last_seen();
+        assert!(last_seen.is_some());
+
+        let _listener = socket.bind().unwrap();
+        assert!(socket.start_monitoring(None).is_ok());
+        assert!(socket.liveness_thread.is_some());
+
+        assert!(socket.close_mut().is_ok());
+        assert!(socket.liveness_thread.is_none());
+        assert_eq!(socket.last_seen(), last_seen);
+    }
+
+    /// Tests that listening abstract sockets are found in a `/proc/net/unix` listing.
+    #[test]
+    fn test_is_abstract_socket_listening() {
+        let net_unix = "\
+Num       RefCount Protocol Flags    Type St Inode Path
+0000000000000000: 00000002 00000000 00010000 0001 01 20943 @nitro_enclaves/1.sock
+0000000000000000: 00000003 00000000 00000000 0001 03 20950 @nitro_enclaves/2.sock
+0000000000000000: 00000002 00000000 00010000 0001 01 20961 /run/nitro_enclaves/3.sock
+";
+
+        assert!(is_abstract_socket_listening(
+            net_unix.as_bytes(),
+            "nitro_enclaves/1.sock"
+        ));
+        assert!(!is_abstract_socket_listening(
+            net_unix.as_bytes(),
+            "nitro_enclaves/2.sock"
+        ));
+        assert!(!is_abstract_socket_listening(
+            net_unix.as_bytes(),
+            "nitro_enclaves/3.sock"
+        ));
+    }
+
+    /// Tests that an abstract socket can be connected to without a file on disk, and that
+    /// a shutdown is requested once it stops listening.
+    #[test]
+    fn test_abstract_socket() {
+        let mut socket = EnclaveProcSock::new_abstract(DUMMY_ENCLAVE_ID).unwrap();
+        assert!(socket.is_abstract());
+        assert_eq!(socket.get_path(), Path::new(""));
+
+        let listener = socket.bind().unwrap();
+        assert!(socket.connect().is_ok());
+
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        assert!(socket.start_monitoring(Some(shutdown_tx)).is_ok());
+
+        drop(listener);
+        assert_eq!(
+            shutdown_rx.recv_timeout(std::time::Duration::from_secs(2)),
+            Ok(ShutdownReason::SocketDeleted)
+        );
+        assert!(socket.connect().is_err());
+        assert!(socket.close_mut().is_ok());
+    }
+
+    /// Tests that the `inotify` event buffer size is only accepted if it can hold
+    /// at least one event.
+    #[test]
+    fn test_with_event_buffer_size() {
+        let socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID).unwrap();
+        let result = socket.with_event_buffer_size(MIN_EVENT_BUFFER_SIZE - 1);
+
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+        let socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID)
+            .unwrap()
+            .with_event_buffer_size(MIN_EVENT_BUFFER_SIZE)
+            .unwrap();
+
+        assert_eq!(socket.event_buffer_size, MIN_EVENT_BUFFER_SIZE);
+        assert_eq!(socket.clone().event_buffer_size, MIN_EVENT_BUFFER_SIZE);
+    }
+
     /// Tests that removing the socket file by means other than `close()` does not
-    /// trigger a `socket.requested_remove` change.
+    /// trigger a `socket.requested_remove` change and that a shutdown is requested instead.
     #[test]
     fn test_start_monitoring() {
         let socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID);
@@ -274,7 +1789,8 @@ mod tests {
                     )
                 })
                 .ok_or_exit_with_errno(Some("Error binding"));
-            let result = socket.start_monitoring(false);
+            let (shutdown_tx, shutdown_rx) = mpsc::channel();
+            let result = socket.start_monitoring(Some(shutdown_tx));
 
             assert!(result.is_ok());
 
@@ -283,7 +1799,144 @@ mod tests {
             std::thread::sleep(std::time::Duration::from_millis(WAIT_REMOVE_MILLIS));
 
             assert!(!socket.requested_remove.load(Ordering::SeqCst));
+            assert_eq!(
+                shutdown_rx.recv_timeout(std::time::Duration::from_secs(1)),
+                Ok(ShutdownReason::SocketDeleted)
+            );
+        }
+    }
+
+    /// Tests that the socket lifecycle is reported as events, and that the events receiver gets
+    /// disconnected once monitoring is done.
+    #[test]
+    fn test_start_monitoring_with_events() {
+        let timeout = std::time::Duration::from_secs(2);
+
+        let mut socket = EnclaveProcSock::new("i-0000000000000000-enc0000000000000095").unwrap();
+        let _listener = socket.bind().unwrap();
+        let events = socket.start_monitoring_with_events(None).unwrap();
+        assert_eq!(events.recv_timeout(timeout), Ok(SocketEvent::Created));
+        socket.close_mut().unwrap();
+        assert_eq!(events.recv_timeout(timeout), Ok(SocketEvent::Deleted));
+        assert_eq!(
+            events.recv_timeout(timeout),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        );
+
+        let mut socket = EnclaveProcSock::new("i-0000000000000000-enc0000000000000096").unwrap();
+        let _listener = socket.bind().unwrap();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let events = socket
+            .start_monitoring_with_events(Some(shutdown_tx))
+            .unwrap();
+        std::fs::remove_file(socket.get_path()).unwrap();
+        assert_eq!(
+            shutdown_rx.recv_timeout(timeout),
+            Ok(ShutdownReason::SocketDeleted)
+        );
+        let received: Vec<SocketEvent> = events.iter().collect();
+        assert_eq!(
+            received,
+            vec![SocketEvent::Created, SocketEvent::ExternalDeletion]
+        );
+        socket.close_mut().unwrap();
+    }
+
+    /// Tests that a rebound socket is reachable and monitored at its new path only, and that
+    /// removing the old path doesn't request a shutdown.
+    #[test]
+    fn test_rebind() {
+        let timeout = std::time::Duration::from_secs(2);
+
+        let mut socket = EnclaveProcSock::new("i-0000000000000000-enc0000000000000098").unwrap();
+        let listener = socket.bind().unwrap();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let events = socket
+            .start_monitoring_with_events(Some(shutdown_tx))
+            .unwrap();
+        assert_eq!(events.recv_timeout(timeout), Ok(SocketEvent::Created));
+
+        let old_path = socket.get_path().to_path_buf();
+        let new_path = old_path.with_extension("moved");
+        socket.rebind(new_path.clone()).unwrap();
+        assert_eq!(socket.get_path(), new_path.as_path());
+        assert!(!old_path.exists());
+        assert_eq!(events.recv_timeout(timeout), Ok(SocketEvent::Deleted));
+        assert_eq!(
+            events.recv_timeout(timeout),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        );
+        assert!(shutdown_rx
+            .recv_timeout(std::time::Duration::from_millis(WAIT_REMOVE_MILLIS))
+            .is_err());
+
+        // The listener bound to the old path accepts connections made to the new one.
+        let _client = UnixStream::connect(&new_path).unwrap();
+        assert!(listener.accept().is_ok());
+
+        // Rebinding to the current path does nothing, and a missing directory is reported.
+        socket.rebind(new_path.clone()).unwrap();
+        let err = socket
+            .rebind(PathBuf::from("/nonexistent/enclave.sock"))
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+        assert!(new_path.exists());
+
+        std::fs::remove_file(&new_path).unwrap();
+        assert_eq!(
+            shutdown_rx.recv_timeout(timeout),
+            Ok(ShutdownReason::SocketDeleted)
+        );
+        socket.close_mut().unwrap();
+    }
+
+    /// Tests that the socket deletion is still detected after a burst of events, which may
+    /// overflow the `inotify` event queue.
+    #[test]
+    fn test_start_monitoring_after_event_burst() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut socket = EnclaveProcSock::new("i-0000000000000000-enc0000000000000018").unwrap();
+        let _listener = socket.bind().unwrap();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        assert!(socket.start_monitoring(Some(shutdown_tx)).is_ok());
+
+        // Every permission change produces an IN_ATTRIB event.
+        for i in 0..20000 {
+            let mode = if i % 2 == 0 { 0o600 } else { 0o700 };
+            std::fs::set_permissions(&socket.socket_path, std::fs::Permissions::from_mode(mode))
+                .unwrap();
         }
+        assert!(shutdown_rx.try_recv().is_err());
+
+        std::fs::remove_file(&socket.socket_path).unwrap();
+        assert_eq!(
+            shutdown_rx.recv_timeout(std::time::Duration::from_secs(5)),
+            Ok(ShutdownReason::SocketDeleted)
+        );
+    }
+
+    /// Tests that the shutdown event stops the event listener without any socket file event.
+    #[test]
+    fn test_shutdown_event() {
+        let mut socket = EnclaveProcSock::new("i-0000000000000000-enc0000000000000028").unwrap();
+        let _listener = socket.bind().unwrap();
+        assert!(socket.start_monitoring(None).is_ok());
+
+        socket.requested_remove.store(true, Ordering::SeqCst);
+        socket.shutdown_event.take().unwrap().write(1).unwrap();
+
+        let listener_thread = socket.remove_listener_thread.take().unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            done_tx.send(listener_thread.join().is_ok()).unwrap();
+        });
+        assert_eq!(
+            done_rx.recv_timeout(std::time::Duration::from_secs(1)),
+            Ok(true)
+        );
+        assert!(socket.socket_path.exists());
+        assert!(socket.close_mut().is_ok());
     }
 
     /// Test that calling `close()` changes `socket.requested_remove` to True and
@@ -311,7 +1964,7 @@ mod tests {
                     )
                 })
                 .ok_or_exit_with_errno(Some("Error binding"));
-            let result = socket.start_monitoring(true);
+            let result = socket.start_monitoring(None);
 
             assert!(result.is_ok());
 
@@ -333,4 +1986,257 @@ mod tests {
         // Check that the number of threads remains the same before and after running the test
         assert_eq!(crt_num_threads0, crt_num_threads1);
     }
+
+    /// Tests that only the owner, root and the allowed users may connect.
+    #[test]
+    fn test_authorize_peer() {
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        assert!(authorize_peer(&stream, &[]).is_ok());
+
+        assert!(check_peer_uid(1000, 1000, &[]).is_ok());
+        assert!(check_peer_uid(0, 1000, &[]).is_ok());
+        assert!(check_peer_uid(1001, 1000, &[1002, 1001]).is_ok());
+
+        let err = check_peer_uid(1001, 1000, &[1002]).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::UnauthorizedPeer);
+        assert_eq!(err.additional_info, vec!["1001"]);
+    }
+
+    /// Tests that bound socket files get the requested mode and owner.
+    #[test]
+    fn test_bind_with_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let mut socket = EnclaveProcSock::default();
+        socket.set_path(socket_path.clone());
+
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = nix::unistd::getgid().as_raw();
+        let socket = socket.with_permissions(0o600).with_owner(uid, gid);
+        let listener = socket.bind().unwrap();
+        let metadata = std::fs::metadata(&socket_path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o600);
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+        assert!(socket.connect().is_ok());
+        drop(listener);
+        std::fs::remove_file(&socket_path).unwrap();
+
+        // Bits cleared by the umask are set once the socket has been bound.
+        let socket = socket.with_permissions(0o666);
+        let _listener = socket.bind().unwrap();
+        let metadata = std::fs::metadata(&socket_path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o666);
+    }
+
+    /// Tests that only socket files which nobody listens on are removed as stale.
+    #[test]
+    fn test_remove_if_stale() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let live_path = dir.path().join("1.sock");
+        let _listener = UnixListener::bind(&live_path).unwrap();
+        assert!(!EnclaveProcSock::remove_if_stale(&live_path).unwrap());
+        assert!(live_path.exists());
+
+        let stale_path = dir.path().join("2.sock");
+        drop(UnixListener::bind(&stale_path).unwrap());
+        assert!(EnclaveProcSock::remove_if_stale(&stale_path).unwrap());
+        assert!(!stale_path.exists());
+
+        assert!(!EnclaveProcSock::remove_if_stale(&stale_path).unwrap());
+    }
+
+    /// Tests that a socket file which reappears within the grace window is monitored again.
+    #[test]
+    fn test_deletion_grace() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let moved_path = dir.path().join("1.sock.moved");
+        let mut socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID)
+            .unwrap()
+            .with_deletion_grace(Duration::from_millis(500));
+        socket.set_path(socket_path.clone());
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        socket.start_monitoring(Some(shutdown_tx)).unwrap();
+
+        // The socket file is deleted and restored from a hard link, so the same socket is still
+        // bound to it.
+        std::fs::hard_link(&socket_path, &moved_path).unwrap();
+        std::fs::remove_file(&socket_path).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        std::fs::rename(&moved_path, &socket_path).unwrap();
+        assert_eq!(
+            shutdown_rx.recv_timeout(Duration::from_secs(1)),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        );
+
+        // A different file doesn't count as the socket reappearing.
+        std::fs::remove_file(&socket_path).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        std::fs::write(&socket_path, "").unwrap();
+        assert_eq!(
+            shutdown_rx.recv_timeout(Duration::from_secs(2)),
+            Ok(ShutdownReason::SocketDeleted)
+        );
+    }
+
+    /// Tests that a socket is only restored while the persisting process still holds it.
+    #[test]
+    fn test_persist_restore() {
+        let sockets_dir = tempfile::tempdir().unwrap();
+        let state_dir = tempfile::tempdir().unwrap();
+        let proc_dir = Path::new(PROC_DIR);
+        let socket_path = sockets_dir.path().join("1.sock");
+
+        let mut socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID).unwrap();
+        socket.set_path(socket_path.clone());
+        socket.persist(state_dir.path()).unwrap();
+
+        // The state belongs to the socket it has been persisted for.
+        let mut other = EnclaveProcSock::new(DUMMY_ENCLAVE_ID).unwrap();
+        other.set_path(sockets_dir.path().join("2.sock"));
+        let err = other.restore_from(state_dir.path(), proc_dir).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+
+        // Nobody listens on the socket yet.
+        let mut restored = EnclaveProcSock::new(DUMMY_ENCLAVE_ID).unwrap();
+        restored.set_path(socket_path.clone());
+        let err = restored
+            .restore_from(state_dir.path(), proc_dir)
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::NotAnEnclaveProcess);
+
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+        restored.restore_from(state_dir.path(), proc_dir).unwrap();
+        assert_eq!(restored.get_path(), socket_path);
+
+        // Closing the restored socket removes its file.
+        restored.close().unwrap();
+        assert!(!socket_path.exists());
+
+        let err = EnclaveProcSock::new_abstract(DUMMY_ENCLAVE_ID)
+            .unwrap()
+            .persist(state_dir.path())
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidArgument);
+    }
+
+    /// Tests that the socket monitoring stream tells external deletions from requested ones.
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_monitor_stream() {
+        use futures::StreamExt;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        for requested in [false, true] {
+            let socket_path = dir.path().join("1.sock");
+            let _listener = UnixListener::bind(&socket_path).unwrap();
+            let mut socket = EnclaveProcSock::default();
+            socket.set_path(socket_path.clone());
+
+            let events = runtime.block_on(async {
+                let stream = socket.monitor_stream().unwrap();
+                socket.requested_remove.store(requested, Ordering::SeqCst);
+                std::fs::remove_file(&socket_path).unwrap();
+                stream.collect::<Vec<_>>().await
+            });
+
+            let expected = if requested {
+                SocketEvent::Deleted
+            } else {
+                SocketEvent::ExternalDeletion
+            };
+            assert_eq!(events, vec![expected]);
+        }
+    }
+
+    /// Tests that a shared watcher reports the deletion of each socket file to its own callback
+    /// and that closed sockets are no longer watched.
+    #[test]
+    fn test_socket_watcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = Arc::new(SocketWatcher::new().unwrap());
+        let (deleted_tx, deleted_rx) = mpsc::channel();
+
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| dir.path().join(format!("{}.sock", i)))
+            .collect();
+        let _listeners: Vec<UnixListener> = paths
+            .iter()
+            .map(|path| UnixListener::bind(path).unwrap())
+            .collect();
+        for path in paths.iter().take(2) {
+            let deleted_tx = deleted_tx.clone();
+            watcher
+                .watch(path, move |path| {
+                    deleted_tx.send(path.to_path_buf()).unwrap();
+                })
+                .unwrap();
+        }
+        assert_eq!(watcher.len(), 2);
+
+        std::fs::remove_file(&paths[1]).unwrap();
+        assert_eq!(
+            deleted_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            paths[1]
+        );
+        assert_eq!(watcher.len(), 1);
+
+        // A socket closed on purpose is unwatched without the owner being told to shut down.
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let mut socket = EnclaveProcSock::default();
+        socket.set_path(paths[2].clone());
+        socket
+            .start_monitoring_shared(watcher.clone(), Some(shutdown_tx))
+            .unwrap();
+        assert_eq!(watcher.len(), 2);
+        socket.close().unwrap();
+        assert!(!paths[2].exists());
+        assert!(shutdown_rx
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+        assert_eq!(watcher.len(), 1);
+
+        drop(watcher);
+        assert!(deleted_rx.try_recv().is_err());
+    }
+
+    /// Tests that a socket file deleted before its monitoring starts is reported as vanished.
+    #[test]
+    fn test_start_monitoring_vanished() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let mut socket = EnclaveProcSock::new(DUMMY_ENCLAVE_ID).unwrap();
+        socket.set_path(socket_path.clone());
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+        std::fs::remove_file(&socket_path).unwrap();
+
+        let err = socket.start_monitoring(None).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::SocketVanished);
+        assert_eq!(err.additional_info, vec![socket_path.to_string_lossy()]);
+        assert!(err.subactions[0].ends_with("still bound to it"));
+        assert!(socket.remove_listener_thread.is_none());
+    }
+
+    /// Tests that the monitoring thread may only be pinned to CPUs outside the NE CPU pool.
+    #[test]
+    fn test_monitor_cpu_set() {
+        let cpu_set = monitor_cpu_set(&[0, 5], &[1, 2, 3, 4]).unwrap();
+        assert!(cpu_set.is_set(0).unwrap());
+        assert!(cpu_set.is_set(5).unwrap());
+        assert!(!cpu_set.is_set(1).unwrap());
+
+        let err = monitor_cpu_set(&[0, 2], &[1, 2, 3, 4]).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidCpuConfiguration);
+        assert_eq!(err.additional_info, vec!["cpu-ids", "2"]);
+
+        assert!(monitor_cpu_set(&[], &[1, 2, 3, 4]).is_err());
+        assert!(monitor_cpu_set(&[CpuSet::count() as u32], &[]).is_err());
+    }
 }
@@ -3,11 +3,17 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+use chrono::offset::Utc;
+use chrono::DateTime;
 use std::fs::metadata;
 use std::fs::File;
 use std::io::Read;
+use std::time::SystemTime;
 
-use crate::common::json_output::{EnclaveDescribeInfo, EnclaveRunInfo, MetadataDescribeInfo};
+use crate::common::json_output::{
+    EnclaveDescribeInfo, EnclaveRunInfo, MetadataDescribeInfo, DEBUG_MODE_FLAG,
+    DESCRIBE_SCHEMA_VERSION,
+};
 use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
 use crate::enclave_proc::resource_manager::EnclaveManager;
 use crate::enclave_proc::resource_manager::NE_ENCLAVE_DEBUG_MODE;
@@ -27,14 +33,19 @@ pub const GiB: u64 = 1024 * MiB;
 
 /// Get a string representation of the bit-mask which holds the enclave launch flags.
 pub fn flags_to_string(flags: u64) -> String {
-    if flags & NE_ENCLAVE_DEBUG_MODE == NE_ENCLAVE_DEBUG_MODE {
-        "DEBUG_MODE"
+    if is_debug_mode(flags) {
+        DEBUG_MODE_FLAG
     } else {
         "NONE"
     }
     .to_string()
 }
 
+/// Check if the enclave launch flags include the debug mode flag.
+pub fn is_debug_mode(flags: u64) -> bool {
+    flags & NE_ENCLAVE_DEBUG_MODE == NE_ENCLAVE_DEBUG_MODE
+}
+
 /// Obtain the enclave information requested by the `describe-enclaves` command.
 pub fn get_enclave_describe_info(
     enclave_manager: &EnclaveManager,
@@ -45,6 +56,7 @@ pub fn get_enclave_describe_info(
     let mut describe_meta: Option<MetadataDescribeInfo> = None;
     let mut img_name: Option<String> = None;
     let mut img_version: Option<String> = None;
+    let uptime = enclave_manager.get_uptime()?;
 
     if with_metadata {
         if let Some(meta) = enclave_manager.get_metadata()? {
@@ -55,6 +67,7 @@ pub fn get_enclave_describe_info(
     }
 
     let info = EnclaveDescribeInfo {
+        schema_version: DESCRIBE_SCHEMA_VERSION,
         enclave_name: Some(enclave_manager.enclave_name.clone()),
         enclave_id: generate_enclave_id(slot_uid)?,
         process_id: std::process::id(),
@@ -64,6 +77,9 @@ pub fn get_enclave_describe_info(
         memory_mib,
         state: state.to_string(),
         flags: flags_to_string(flags),
+        debug_mode: is_debug_mode(flags),
+        start_time: uptime.map(|(_, time)| format_start_time(time)),
+        uptime_secs: uptime.map(|(uptime, _)| uptime.as_secs()),
         build_info: Some(enclave_manager.get_measurements()?),
         img_name,
         img_version,
@@ -72,6 +88,11 @@ pub fn get_enclave_describe_info(
     Ok(info)
 }
 
+/// Format the time at which an enclave has been started for the `describe-enclaves` output.
+fn format_start_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
 /// Obtain the enclave information requested by the `run-enclaves` command.
 pub fn get_run_enclaves_info(
     enclave_name: String,
@@ -176,6 +197,13 @@ mod tests {
         }
     }
 
+    /// Tests that the start time of an enclave is formatted as an RFC 3339 UTC timestamp.
+    #[test]
+    fn test_format_start_time() {
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        assert_eq!(format_start_time(time), "2020-09-13T12:26:40Z");
+    }
+
     /// Tests that `flags_to_string()` returns the correct String representation
     /// when the NE_ENCLAVE_DEBUG_MODE is either set or unset.
     #[test]
@@ -0,0 +1,307 @@
+// Copyright 2020-2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use log::{debug, warn};
+use nix::poll::{poll, PollFd, PollFlags};
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use vsock::{VsockAddr, VsockListener, VsockStream};
+
+use crate::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult, VMADDR_CID_PARENT};
+use crate::new_nitro_cli_failure;
+
+/// The byte which an enclave sends as a heartbeat, the same as the one it sends once booted.
+pub const HEARTBEAT: u8 = 0xB7;
+
+/// The longest time the watchdog thread waits before checking whether it must stop.
+const WATCHDOG_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A listener of the connections over which an enclave sends its heartbeats.
+trait HeartbeatListener: AsRawFd + Send + 'static {
+    /// The type of the accepted connections.
+    type Stream: Read + AsRawFd + Send;
+
+    /// Accept a connection, along with the CID of its peer.
+    fn accept_from(&self) -> io::Result<(Self::Stream, u32)>;
+}
+
+impl HeartbeatListener for VsockListener {
+    type Stream = VsockStream;
+
+    fn accept_from(&self) -> io::Result<(VsockStream, u32)> {
+        self.accept().map(|(stream, addr)| (stream, addr.cid()))
+    }
+}
+
+/// A running watchdog of an enclave, which is stopped when dropped.
+pub struct Watchdog {
+    /// Whether a heartbeat has been received within the timeout so far.
+    healthy: Arc<AtomicBool>,
+    /// Set to ask the watchdog thread to stop.
+    stop: Arc<AtomicBool>,
+    /// The thread which receives the heartbeats.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Check whether the enclave has sent a heartbeat within the timeout so far. Once the
+    /// timeout has expired, the enclave remains unhealthy.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+
+    /// Stop the watchdog, without calling its timeout handler.
+    pub fn stop(mut self) -> NitroCliResult<()> {
+        self.stop_thread()
+    }
+
+    /// Ask the watchdog thread to stop and wait for it.
+    fn stop_thread(&mut self) -> NitroCliResult<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to join watchdog thread: {:?}", e),
+                    NitroCliErrorEnum::ThreadJoinFailure
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_thread() {
+            warn!("Failed to stop the watchdog: {:?}", e);
+        }
+    }
+}
+
+/// Start watching an enclave through the heartbeats it sends to the given vsock port of the
+/// parent instance. Any number of connections may be used, with each `HEARTBEAT` byte received
+/// from the enclave restarting the timeout, and connections from other CIDs being ignored.
+///
+/// If no heartbeat arrives within `timeout`, the enclave is marked as unhealthy and `on_timeout`
+/// is called with its CID on the watchdog thread, after which the watchdog stops. This is distinct
+/// from the monitoring of the enclave process socket, which only tells whether the enclave process
+/// is alive: a hung enclave keeps its enclave process running. `on_timeout` may terminate the
+/// enclave, for instance by sending a terminate command to its enclave process.
+pub fn start<F>(cid: u32, port: u32, timeout: Duration, on_timeout: F) -> NitroCliResult<Watchdog>
+where
+    F: FnOnce(u32) + Send + 'static,
+{
+    let listener = VsockListener::bind(&VsockAddr::new(VMADDR_CID_PARENT, port)).map_err(|e| {
+        new_nitro_cli_failure!(
+            &format!(
+                "Failed to bind watchdog listener to vsock port {}: {:?}",
+                port, e
+            ),
+            NitroCliErrorEnum::SocketError
+        )
+    })?;
+
+    Ok(start_with(listener, cid, timeout, on_timeout))
+}
+
+/// Start a watchdog which receives the heartbeats of the enclave from `listener`.
+fn start_with<L, F>(listener: L, cid: u32, timeout: Duration, on_timeout: F) -> Watchdog
+where
+    L: HeartbeatListener,
+    F: FnOnce(u32) + Send + 'static,
+{
+    let healthy = Arc::new(AtomicBool::new(true));
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_healthy = healthy.clone();
+    let thread_stop = stop.clone();
+    let thread = thread::spawn(move || {
+        if let Err(e) = watch(&listener, cid, timeout, &thread_stop) {
+            warn!("The watchdog of enclave {} has failed: {:?}", cid, e);
+        } else if !thread_stop.load(Ordering::SeqCst) {
+            thread_healthy.store(false, Ordering::SeqCst);
+            warn!(
+                "Enclave {} has sent no heartbeat for {:?}, it is unhealthy.",
+                cid, timeout
+            );
+            on_timeout(cid);
+        }
+    });
+
+    Watchdog {
+        healthy,
+        stop,
+        thread: Some(thread),
+    }
+}
+
+/// Receive the heartbeats of the enclave until either none arrives within `timeout` or the
+/// watchdog is asked to stop.
+fn watch<L: HeartbeatListener>(
+    listener: &L,
+    cid: u32,
+    timeout: Duration,
+    stop: &AtomicBool,
+) -> NitroCliResult<()> {
+    let mut streams: Vec<L::Stream> = Vec::new();
+    let mut last_heartbeat = Instant::now();
+
+    while !stop.load(Ordering::SeqCst) {
+        let elapsed = last_heartbeat.elapsed();
+        if elapsed >= timeout {
+            break;
+        }
+
+        let wait = std::cmp::min(timeout - elapsed, WATCHDOG_POLL_INTERVAL);
+        let mut fds: Vec<PollFd> = std::iter::once(listener.as_raw_fd())
+            .chain(streams.iter().map(|stream| stream.as_raw_fd()))
+            .map(|fd: RawFd| PollFd::new(fd, PollFlags::POLLIN))
+            .collect();
+        match poll(&mut fds, wait.as_millis() as i32 + 1) {
+            Ok(_) => (),
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => {
+                return Err(new_nitro_cli_failure!(
+                    &format!("Failed to poll for heartbeats: {:?}", e),
+                    NitroCliErrorEnum::SocketError
+                ))
+            }
+        }
+        let ready: Vec<bool> = fds
+            .iter()
+            .map(|fd| fd.revents().map_or(false, |revents| !revents.is_empty()))
+            .collect();
+
+        // Read from the ready connections before accepting new ones, so indices stay valid.
+        for index in (0..streams.len()).rev() {
+            if !ready[index + 1] {
+                continue;
+            }
+
+            let mut buf = [0u8; 64];
+            match streams[index].read(&mut buf) {
+                Ok(0) | Err(_) => {
+                    streams.remove(index);
+                }
+                Ok(len) => {
+                    if buf[..len].contains(&HEARTBEAT) {
+                        last_heartbeat = Instant::now();
+                    }
+                }
+            }
+        }
+
+        if ready[0] {
+            match listener.accept_from() {
+                Ok((stream, peer_cid)) if peer_cid == cid => streams.push(stream),
+                Ok((_, peer_cid)) => {
+                    debug!("Ignoring heartbeat connection from CID {}.", peer_cid)
+                }
+                Err(e) => warn!("Failed to accept heartbeat connection: {:?}", e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+
+    /// A Unix socket listener, whose peers all appear to have the same CID.
+    struct UnixHeartbeats(UnixListener, u32);
+
+    impl AsRawFd for UnixHeartbeats {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl HeartbeatListener for UnixHeartbeats {
+        type Stream = UnixStream;
+
+        fn accept_from(&self) -> io::Result<(UnixStream, u32)> {
+            self.0.accept().map(|(stream, _)| (stream, self.1))
+        }
+    }
+
+    /// Start a watchdog of the enclave with CID 16, whose heartbeat connections come from
+    /// `peer_cid`.
+    fn start_unix_watchdog(
+        peer_cid: u32,
+        timeout: Duration,
+    ) -> (tempfile::TempDir, PathBuf, Watchdog, mpsc::Receiver<u32>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("heartbeat.sock");
+        let listener = UnixHeartbeats(UnixListener::bind(&path).unwrap(), peer_cid);
+        let (timeout_tx, timeout_rx) = mpsc::channel();
+        let watchdog = start_with(listener, 16, timeout, move |cid| {
+            timeout_tx.send(cid).unwrap()
+        });
+
+        (dir, path, watchdog, timeout_rx)
+    }
+
+    /// Tests that heartbeats keep an enclave healthy, and that it times out once they stop.
+    #[test]
+    fn test_watchdog_heartbeats() {
+        let timeout = Duration::from_millis(300);
+        let (_dir, path, watchdog, timeout_rx) = start_unix_watchdog(16, timeout);
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        for _ in 0..10 {
+            stream.write_all(&[HEARTBEAT]).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(watchdog.is_healthy());
+        assert!(timeout_rx.try_recv().is_err());
+
+        drop(stream);
+        assert_eq!(timeout_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 16);
+        assert!(!watchdog.is_healthy());
+        watchdog.stop().unwrap();
+    }
+
+    /// Tests that heartbeats from other CIDs or of other values are ignored.
+    #[test]
+    fn test_watchdog_ignores_other_peers() {
+        let timeout = Duration::from_millis(200);
+        let (_dir, path, watchdog, timeout_rx) = start_unix_watchdog(17, timeout);
+        // The connection may already have been closed by the watchdog.
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let _ = stream.write_all(&[HEARTBEAT]);
+        assert_eq!(timeout_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 16);
+        assert!(!watchdog.is_healthy());
+
+        let (_dir, path, _watchdog, timeout_rx) = start_unix_watchdog(16, timeout);
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let started = Instant::now();
+        // The connection gets closed once the watchdog has timed out.
+        while started.elapsed() < Duration::from_millis(400) {
+            let _ = stream.write_all(&[0]);
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(timeout_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 16);
+    }
+
+    /// Tests that a stopped watchdog doesn't call its timeout handler.
+    #[test]
+    fn test_watchdog_stop() {
+        let (_dir, _path, watchdog, timeout_rx) =
+            start_unix_watchdog(16, Duration::from_millis(200));
+        watchdog.stop().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(timeout_rx.try_recv().is_err());
+    }
+}
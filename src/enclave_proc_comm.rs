@@ -3,20 +3,30 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
-use log::{debug, info};
+use log::{debug, info, warn};
+use nix::errno::Errno;
 use nix::sys::epoll;
 use nix::sys::epoll::{EpollEvent, EpollFlags, EpollOp};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::socket::getsockopt;
+use nix::sys::socket::sockopt::PeerCredentials;
 use nix::unistd::*;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::borrow::BorrowMut;
 use std::fs;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::common::commands_parser::EmptyArgs;
-use crate::common::logger::EnclaveProcLogWriter;
+use crate::common::commands_parser::{DescribeEnclavesArgs, EmptyArgs};
+use crate::common::json_output::{EnclaveDescribeInfo, EnclaveTerminateInfo};
+use crate::common::logger::{enclave_log_target, EnclaveProcLogWriter};
+use crate::common::protocol::{
+    client_handshake, handshake, read_response, write_command, Command, Response,
+};
 use crate::common::{
     enclave_proc_command_send_single, get_socket_path, get_sockets_dir_path, notify_error,
     read_u64_le, receive_from_stream,
@@ -25,14 +35,47 @@ use crate::common::{
     EnclaveProcessCommandType, EnclaveProcessReply, NitroCliErrorEnum, NitroCliFailure,
     NitroCliResult,
 };
-use crate::common::{ENCLAVE_PROC_WAIT_TIMEOUT_MSEC, MSG_ENCLAVE_CONFIRM};
-use crate::enclave_proc::enclave_process_run;
+use crate::common::{ENCLAVE_PROC_WAIT_TIMEOUT_MSEC, MSG_ENCLAVE_CONFIRM, MSG_ENCLAVE_PONG};
+use crate::enclave_proc::socket::EnclaveProcSock;
+use crate::enclave_proc::utils::generate_enclave_id;
+use crate::enclave_proc::{enclave_process_run, socket_slot_id, wait_for_process_exit};
 use crate::new_nitro_cli_failure;
 
+/// How long an enclave process is given to exit after each signal sent to stop it.
+const ENCLAVE_PROC_KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// How many times an enclave process socket is connected to by default before the enclave
+/// process is considered to be gone.
+const DEFAULT_CONNECT_ATTEMPTS: u32 = 3;
+
+/// How long to wait by default before connecting to an enclave process socket again.
+const DEFAULT_CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How often and how fast to retry connecting to an enclave process whose socket is missing or
+/// refuses connections, which may happen for a moment while its enclave is being terminated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of connection attempts, including the first one.
+    pub attempts: u32,
+    /// The delay between consecutive attempts.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: DEFAULT_CONNECT_ATTEMPTS,
+            delay: DEFAULT_CONNECT_RETRY_DELAY,
+        }
+    }
+}
+
 /// Spawn an enclave process and wait until it has detached and has
 /// taken ownership of its communication socket.
 pub fn enclave_proc_spawn(logger: &EnclaveProcLogWriter) -> NitroCliResult<UnixStream> {
-    let (cli_socket, enclave_proc_socket) = UnixStream::pair().map_err(|e| {
+    // An invalid log target is reported by the CLI, since the enclave process can't tell anyone.
+    let log_target = enclave_log_target()?;
+    let (mut cli_socket, enclave_proc_socket) = UnixStream::pair().map_err(|e| {
         new_nitro_cli_failure!(
             &format!("Could not create a socket pair: {:?}", e),
             NitroCliErrorEnum::SocketPairCreationFailure
@@ -56,7 +99,7 @@ pub fn enclave_proc_spawn(logger: &EnclaveProcLogWriter) -> NitroCliResult<UnixS
 
     if let Ok(ForkResult::Child) = fork_status {
         // This is our intermediate child process.
-        enclave_process_run(enclave_proc_socket, logger);
+        enclave_process_run(enclave_proc_socket, logger, log_target);
     } else {
         fork_status.map_err(|e| {
             new_nitro_cli_failure!(
@@ -64,17 +107,27 @@ pub fn enclave_proc_spawn(logger: &EnclaveProcLogWriter) -> NitroCliResult<UnixS
                 NitroCliErrorEnum::ProcessSpawnFailure
             )
         })?;
+
+        // Our copy of the child's end is closed, so that the handshake below fails
+        // instead of hanging if the enclave process dies before replying.
+        drop(enclave_proc_socket);
     }
 
     // The enclave process will open a socket named "<enclave_id>.sock", but this
     // will only become available after the enclave has been successfully launched.
     // Until then, we can only use the pre-initialized socket pair to communicate
-    // with the new process.
+    // with the new process, which expects the protocol handshake on it as on any
+    // other connection.
+    handshake(&mut cli_socket)
+        .map_err(|e| e.add_subaction("Failed to handshake with the enclave process".to_string()))?;
+
     Ok(cli_socket)
 }
 
-/// Connect to all existing enclave processes, returning a connection to each.
-pub fn enclave_proc_connect_to_all() -> NitroCliResult<Vec<UnixStream>> {
+/// Connect to all existing enclave processes, returning a connection to each. An enclave process
+/// which fails the protocol handshake, such as one of another CLI version which speaks another
+/// protocol version or none at all, is reported and yields an error in place of its connection.
+pub fn enclave_proc_connect_to_all() -> NitroCliResult<Vec<NitroCliResult<UnixStream>>> {
     let paths = fs::read_dir(get_sockets_dir_path()).map_err(|e| {
         new_nitro_cli_failure!(
             &format!("Failed to access sockets directory: {:?}", e),
@@ -95,10 +148,20 @@ pub fn enclave_proc_connect_to_all() -> NitroCliResult<Vec<UnixStream>> {
 
                 // At this point we have found a potential socket.
                 match UnixStream::connect(path_str) {
-                    Ok(conn) => {
-                        // We have connected to an enclave process.
+                    Ok(mut conn) => {
+                        // We have connected to an enclave process, which must speak our protocol.
+                        if let Err(e) = client_handshake(&mut conn) {
+                            notify_error(&format!(
+                                "Failed to handshake with the enclave process at '{}': {}",
+                                path_str, e
+                            ));
+                            return Some(Err(e.add_subaction(format!(
+                                "Failed to handshake with the enclave process at '{}'",
+                                path_str
+                            ))));
+                        }
                         info!("Connected to: {}", path_str);
-                        return Some(conn);
+                        return Some(Ok(conn));
                     }
                     Err(e) => {
                         if e.kind() == ErrorKind::PermissionDenied {
@@ -126,16 +189,18 @@ pub fn enclave_proc_connect_to_all() -> NitroCliResult<Vec<UnixStream>> {
 
 /// Open a connection to an enclave-specific socket.
 pub fn enclave_proc_connect_to_single(enclave_id: &str) -> NitroCliResult<UnixStream> {
-    let socket_path = get_socket_path(enclave_id).map_err(|e| {
-        e.add_subaction("Connect to specific enclave process".to_string())
-            .set_error_code(NitroCliErrorEnum::SocketError)
-    })?;
-    UnixStream::connect(socket_path).map_err(|e| {
+    let socket_path = get_socket_path(enclave_id)
+        .map_err(|e| e.add_subaction("Connect to specific enclave process".to_string()))?;
+    let mut comm = UnixStream::connect(socket_path).map_err(|e| {
         new_nitro_cli_failure!(
             &format!("Failed to connect to specific enclave process: {:?}", e),
             NitroCliErrorEnum::SocketError
         )
-    })
+    })?;
+    client_handshake(&mut comm)
+        .map_err(|e| e.add_subaction("Connect to specific enclave process".to_string()))?;
+
+    Ok(comm)
 }
 
 /// Broadcast a command to all available enclave processes.
@@ -159,8 +224,9 @@ where
             e.add_subaction("Failed to send command to all enclave processes".to_string())
         })?
         .into_iter()
-        .map(|mut socket| {
+        .map(|socket| {
             // Send the command.
+            let mut socket = socket?;
             enclave_proc_command_send_single(cmd, args, socket.borrow_mut())?;
 
             let raw_fd = socket.into_raw_fd();
@@ -394,6 +460,387 @@ pub fn enclave_proc_get_cid(enclave_id: &str) -> NitroCliResult<u64> {
     Ok(enclave_cid)
 }
 
+/// Check that the enclave process owning the enclave with the given full ID is responsive.
+pub fn enclave_proc_ping(enclave_id: &str) -> NitroCliResult<()> {
+    EnclaveProcClient::connect_by_id(enclave_id)?.ping()
+}
+
+/// Ping an enclave process over an open connection to its socket.
+pub fn enclave_proc_ping_stream(comm: &mut UnixStream) -> NitroCliResult<()> {
+    enclave_proc_command_send_single::<EmptyArgs>(EnclaveProcessCommandType::Ping, None, comm)
+        .map_err(|e| e.add_subaction("Failed to send ping to enclave process".to_string()))?;
+    read_pong(comm)
+}
+
+/// Read the reply of an enclave process to a ping and check that it is a pong.
+fn read_pong(comm: &mut UnixStream) -> NitroCliResult<()> {
+    let reply = read_u64_le(comm)
+        .map_err(|e| e.add_subaction(String::from("Failed to read pong from enclave process")))?;
+
+    if reply != MSG_ENCLAVE_PONG {
+        return Err(new_nitro_cli_failure!(
+            &format!("Enclave process replied to ping with {:#x}", reply),
+            NitroCliErrorEnum::EnclaveProcessSendReplyFailure
+        ));
+    }
+
+    Ok(())
+}
+
+/// A client for issuing several commands to the enclave process which owns a given enclave.
+///
+/// The client opens a session with the enclave process on its first request and keeps it for the
+/// following ones, which are all sent as framed commands over the same connection. If the
+/// enclave process has closed the session in the meantime, which is only noticed when the
+/// connection is reset while a command is being sent, the command is sent once more over a new
+/// session. A command which has been fully sent is never sent again, since it may have been
+/// executed already.
+pub struct EnclaveProcClient {
+    /// The path of the enclave process socket.
+    socket_path: PathBuf,
+    /// The session with the enclave process, once one has been opened.
+    session: Option<UnixStream>,
+}
+
+impl EnclaveProcClient {
+    /// Create a client for the enclave process which owns the enclave with the given full ID.
+    pub fn connect_by_id(enclave_id: &str) -> NitroCliResult<Self> {
+        let socket_path = get_socket_path(enclave_id)
+            .map_err(|e| e.add_subaction("Failed to get enclave process socket".to_string()))?;
+        EnclaveProcClient::with_socket_path(&socket_path)
+    }
+
+    /// Create a client for the enclave process listening on the given socket.
+    fn with_socket_path(socket_path: &Path) -> NitroCliResult<Self> {
+        if !socket_path.exists() {
+            return Err(new_nitro_cli_failure!(
+                &format!("Enclave process socket {:?} does not exist", socket_path),
+                NitroCliErrorEnum::SocketPathNotFound
+            ));
+        }
+
+        Ok(EnclaveProcClient {
+            socket_path: socket_path.to_path_buf(),
+            session: None,
+        })
+    }
+
+    /// Check that the enclave process is responsive.
+    pub fn ping(&mut self) -> NitroCliResult<()> {
+        match self.request(Command::Ping)? {
+            Response::Pong => Ok(()),
+            _ => Err(EnclaveProcClient::unexpected_response("Ping")),
+        }
+    }
+
+    /// Describe the enclave owned by the enclave process.
+    pub fn describe(&mut self, args: &DescribeEnclavesArgs) -> NitroCliResult<EnclaveDescribeInfo> {
+        match self.request(Command::Describe(args.clone()))? {
+            Response::Describe(info) => Ok(*info),
+            _ => Err(EnclaveProcClient::unexpected_response("Describe")),
+        }
+    }
+
+    /// Describe the enclave owned by the enclave process, retrying to connect as given by
+    /// `retry` while its socket is missing or refuses connections. If the socket is still missing
+    /// after the last attempt, the enclave is considered terminated and a `SocketPathNotFound`
+    /// error is returned, while a socket which still refuses connections results in an
+    /// `EnclaveProcessUnavailable` error.
+    pub fn describe_with_retry(
+        &mut self,
+        args: &DescribeEnclavesArgs,
+        retry: &RetryPolicy,
+    ) -> NitroCliResult<EnclaveDescribeInfo> {
+        if self.session.is_none() {
+            let mut attempt = 1;
+            let comm = loop {
+                let err = match UnixStream::connect(&self.socket_path) {
+                    Ok(comm) => break comm,
+                    Err(e) => e,
+                };
+
+                let transient = matches!(
+                    err.kind(),
+                    ErrorKind::NotFound | ErrorKind::ConnectionRefused
+                );
+                if !transient || attempt >= retry.attempts {
+                    return Err(self.connect_error(&err, attempt));
+                }
+
+                debug!(
+                    "Enclave process socket {:?} is unavailable ({}), retrying in {:?}.",
+                    self.socket_path, err, retry.delay
+                );
+                std::thread::sleep(retry.delay);
+                attempt += 1;
+            };
+            self.session = Some(EnclaveProcClient::start_session(comm)?);
+        }
+
+        self.describe(args)
+    }
+
+    /// Build the error for the enclave process socket being unavailable after `attempts`
+    /// connection attempts, the last of which has failed with `err`.
+    fn connect_error(&self, err: &std::io::Error, attempts: u32) -> NitroCliFailure {
+        let socket_path = self.socket_path.to_string_lossy();
+        match err.kind() {
+            ErrorKind::NotFound => new_nitro_cli_failure!(
+                &format!(
+                    "Enclave process socket {:?} has been removed, so its enclave has terminated",
+                    self.socket_path
+                ),
+                NitroCliErrorEnum::SocketPathNotFound
+            ),
+            ErrorKind::ConnectionRefused => new_nitro_cli_failure!(
+                &format!(
+                    "Enclave process socket {:?} refused {} connection attempt(s)",
+                    self.socket_path, attempts
+                ),
+                NitroCliErrorEnum::EnclaveProcessUnavailable
+            )
+            .add_info(vec![&socket_path]),
+            _ => new_nitro_cli_failure!(
+                &format!("Failed to connect to enclave process: {:?}", err),
+                NitroCliErrorEnum::SocketError
+            ),
+        }
+    }
+
+    /// Get (at most) the last `bytes` bytes of the console output of the enclave, which the
+    /// enclave process captures for enclaves in debug mode only, if
+    /// `NITRO_CLI_CONSOLE_HISTORY_KIB` is set. This can be used after the enclave has crashed,
+    /// even if no console has been attached to it. The output is empty if it is not captured.
+    pub fn recent_console(&mut self, bytes: u64) -> NitroCliResult<Vec<u8>> {
+        let output = match self.request(Command::GetRecentConsole(bytes))? {
+            Response::Console(output) => output,
+            _ => return Err(EnclaveProcClient::unexpected_response("GetRecentConsole")),
+        };
+
+        if output.len() as u64 > bytes {
+            return Err(new_nitro_cli_failure!(
+                &format!(
+                    "Enclave process replied with {} bytes of console output, but at most {} were requested",
+                    output.len(), bytes
+                ),
+                NitroCliErrorEnum::EnclaveProcessSendReplyFailure
+            ));
+        }
+
+        Ok(output)
+    }
+
+    /// Terminate the enclave owned by the enclave process.
+    pub fn terminate(&mut self) -> NitroCliResult<EnclaveTerminateInfo> {
+        match self.request(Command::Terminate)? {
+            Response::Terminate(info) => Ok(info),
+            _ => Err(EnclaveProcClient::unexpected_response("Terminate")),
+        }
+    }
+
+    /// Terminate the enclave owned by the enclave process, escalating if the enclave process
+    /// hasn't replied within the given timeout. It is then sent `SIGTERM` and, if it still
+    /// hasn't exited after `ENCLAVE_PROC_KILL_GRACE`, `SIGKILL`. Once the enclave process is gone,
+    /// its socket is removed, while the driver frees the enclave slot as the enclave descriptor
+    /// gets closed along with the process.
+    pub fn terminate_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> NitroCliResult<EnclaveTerminateInfo> {
+        let mut comm = UnixStream::connect(&self.socket_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to connect to enclave process: {:?}", e),
+                NitroCliErrorEnum::SocketError
+            )
+        })?;
+        // These are the credentials of the enclave process as it started listening on the socket.
+        let pid = getsockopt(comm.as_raw_fd(), PeerCredentials)
+            .map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to get the enclave process credentials: {:?}", e),
+                    NitroCliErrorEnum::SocketError
+                )
+            })?
+            .pid() as u32;
+        comm.set_read_timeout(Some(timeout)).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to set the reply timeout: {:?}", e),
+                NitroCliErrorEnum::SocketError
+            )
+        })?;
+        // An enclave process which doesn't complete the handshake is as hung as one which doesn't
+        // reply to the command, unless it speaks another protocol version.
+        let responsive = match handshake(&mut comm) {
+            Ok(()) => true,
+            Err(e) if e.error_code == NitroCliErrorEnum::ProtocolVersionMismatch => return Err(e),
+            Err(e) => {
+                debug!(
+                    "Enclave process {} did not complete the handshake: {:?}",
+                    pid, e
+                );
+                false
+            }
+        };
+        if responsive {
+            enclave_proc_command_send_single::<EmptyArgs>(
+                EnclaveProcessCommandType::Terminate,
+                None,
+                &mut comm,
+            )
+            .map_err(|e| e.add_subaction("Failed to send Terminate command".to_string()))?;
+            info!("Sent command: Terminate");
+
+            match enclave_proc_handle_output::<EnclaveTerminateInfo>(&mut comm) {
+                (Some(info), Some(0)) => return Ok(info),
+                (_, Some(status)) => {
+                    return Err(new_nitro_cli_failure!(
+                        &format!(
+                        "Terminate command was not executed by the enclave process (status: {})",
+                        status
+                    ),
+                        NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+                    ))
+                }
+                (_, None) => (),
+            }
+        }
+
+        warn!(
+            "Enclave process {} did not reply within {:?}, sending SIGTERM.",
+            pid, timeout
+        );
+        if signal_and_wait(pid, Signal::SIGTERM, ENCLAVE_PROC_KILL_GRACE).is_err() {
+            warn!("Enclave process {} did not exit, sending SIGKILL.", pid);
+            signal_and_wait(pid, Signal::SIGKILL, ENCLAVE_PROC_KILL_GRACE)
+                .map_err(|e| e.add_subaction("Failed to kill enclave process".to_string()))?;
+        }
+
+        if EnclaveProcSock::remove_if_stale(&self.socket_path)? {
+            info!(
+                "Deleted socket of killed enclave process: {:?}",
+                self.socket_path
+            );
+        }
+
+        let slot_id = socket_slot_id(&self.socket_path).ok_or_else(|| {
+            new_nitro_cli_failure!(
+                &format!("Invalid enclave process socket {:?}", self.socket_path),
+                NitroCliErrorEnum::SocketPathNotFound
+            )
+        })?;
+        Ok(EnclaveTerminateInfo::new(
+            None,
+            generate_enclave_id(slot_id)?,
+            true,
+        ))
+    }
+
+    /// Send a command over the session and read the response to it, opening a new session if
+    /// there is none yet or if the current one turns out to have been closed by the enclave
+    /// process while sending the command.
+    fn request(&mut self, cmd: Command) -> NitroCliResult<Response> {
+        // The command is encoded beforehand, so that failing to write it can be told apart.
+        let mut frame = Vec::new();
+        write_command(&mut frame, &cmd)?;
+
+        let mut reconnected = false;
+        let session = loop {
+            let mut session = match self.session.take() {
+                Some(session) => session,
+                None => self.open_session()?,
+            };
+
+            match session.write_all(&frame) {
+                Ok(()) => break self.session.insert(session),
+                Err(e)
+                    if !reconnected
+                        && matches!(
+                            e.kind(),
+                            ErrorKind::ConnectionReset | ErrorKind::BrokenPipe
+                        ) =>
+                {
+                    debug!("Reconnecting after failing to send {:?}: {}", cmd, e);
+                    reconnected = true;
+                }
+                Err(e) => {
+                    return Err(new_nitro_cli_failure!(
+                        &format!("Failed to send {:?} command: {:?}", cmd, e),
+                        NitroCliErrorEnum::SocketError
+                    ))
+                }
+            }
+        };
+        info!("Sent command: {:?}", cmd);
+
+        // The session can't be used any further if the response has not been fully read.
+        let response = read_response(session).map_err(|e| {
+            self.session = None;
+            e.add_subaction(format!("Failed to get the response to {:?}", cmd))
+        })?;
+
+        match response {
+            Response::Error(msg) => Err(new_nitro_cli_failure!(
+                &format!(
+                    "{:?} command was not executed by the enclave process: {}",
+                    cmd, msg
+                ),
+                NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+            )),
+            response => Ok(response),
+        }
+    }
+
+    /// Connect to the enclave process and open a new session.
+    fn open_session(&self) -> NitroCliResult<UnixStream> {
+        let comm = UnixStream::connect(&self.socket_path).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!("Failed to connect to enclave process: {:?}", e),
+                NitroCliErrorEnum::SocketError
+            )
+        })?;
+        EnclaveProcClient::start_session(comm)
+    }
+
+    /// Open a session with the enclave process over a newly-connected stream.
+    fn start_session(mut comm: UnixStream) -> NitroCliResult<UnixStream> {
+        client_handshake(&mut comm)?;
+        enclave_proc_command_send_single::<EmptyArgs>(
+            EnclaveProcessCommandType::Session,
+            None,
+            &mut comm,
+        )
+        .map_err(|e| e.add_subaction("Failed to open a session".to_string()))?;
+        Ok(comm)
+    }
+
+    /// Build the error for the enclave process replying to a command with a response which
+    /// belongs to another command.
+    fn unexpected_response(cmd_name: &str) -> NitroCliFailure {
+        new_nitro_cli_failure!(
+            &format!(
+                "Enclave process replied to {} with an unexpected response",
+                cmd_name
+            ),
+            NitroCliErrorEnum::EnclaveProcessSendReplyFailure
+        )
+    }
+}
+
+/// Send a signal to a process and wait for it to exit.
+fn signal_and_wait(pid: u32, signal: Signal, timeout: Duration) -> NitroCliResult<()> {
+    match kill(Pid::from_raw(pid as i32), signal) {
+        Ok(()) | Err(Errno::ESRCH) => (),
+        Err(e) => {
+            return Err(new_nitro_cli_failure!(
+                &format!("Failed to send {} to process {}: {:?}", signal, pid, e),
+                NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+            ))
+        }
+    }
+
+    wait_for_process_exit(pid, timeout)
+}
+
 /// Obtain an enclave's flags given its full ID.
 pub fn enclave_proc_get_flags(enclave_id: &str) -> NitroCliResult<u64> {
     let mut comm = enclave_proc_connect_to_single(enclave_id)
@@ -423,3 +870,314 @@ pub fn enclave_proc_get_flags(enclave_id: &str) -> NitroCliResult<u64> {
 
     Ok(enclave_flags)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::protocol::{read_command, write_response};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    /// Accept `count` sessions, replying to each command with the response given by `reply`,
+    /// if any. A session is closed after a command which isn't replied to, and after
+    /// `Command::Terminate`, as the enclave process exits then. The listener is handed back
+    /// along with the received commands.
+    fn fake_enclave_process(
+        listener: UnixListener,
+        count: usize,
+        reply: fn(&Command) -> Option<Response>,
+    ) -> thread::JoinHandle<(Vec<Command>, UnixListener)> {
+        thread::spawn(move || {
+            let mut commands = Vec::new();
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                handshake(&mut stream).unwrap();
+                // The command is sent twice, once for the listener and once for the handler.
+                for _ in 0..2 {
+                    let cmd = receive_from_stream::<EnclaveProcessCommandType>(&mut stream);
+                    assert_eq!(cmd.unwrap(), EnclaveProcessCommandType::Session);
+                }
+
+                while let Ok(cmd) = read_command(&mut stream) {
+                    commands.push(cmd.clone());
+                    match reply(&cmd) {
+                        Some(response) => write_response(&mut stream, &response).unwrap(),
+                        None => break,
+                    }
+                    if cmd == Command::Terminate {
+                        break;
+                    }
+                }
+            }
+            (commands, listener)
+        })
+    }
+
+    /// Get the response which the enclave process sends after terminating its enclave.
+    fn terminate_response() -> Response {
+        Response::Terminate(EnclaveTerminateInfo::new(
+            None,
+            "i-0000000000000000-enc1".to_string(),
+            true,
+        ))
+    }
+
+    /// Get the response which the enclave process sends for a running debug-mode enclave.
+    fn describe_response() -> Response {
+        let mut info = EnclaveDescribeInfo::new(
+            None,
+            "i-0000000000000000-enc1".to_string(),
+            16,
+            2,
+            vec![1, 3],
+            128,
+            "RUNNING".to_string(),
+            "DEBUG_MODE".to_string(),
+            None,
+            None,
+            None,
+            None,
+        );
+        info.start_time = Some("2020-09-13T12:26:40Z".to_string());
+        info.uptime_secs = Some(42);
+        Response::Describe(Box::new(info))
+    }
+
+    /// Wait until the peer has closed the session of the given client.
+    fn wait_for_session_close(client: &EnclaveProcClient) {
+        let mut fds = [libc::pollfd {
+            fd: client.session.as_ref().unwrap().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        assert_eq!(unsafe { libc::poll(fds.as_mut_ptr(), 1, 5000) }, 1);
+    }
+
+    /// Tests that a client sends all of its commands over a single session and that the
+    /// responses are decoded.
+    #[test]
+    fn test_enclave_proc_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let args = DescribeEnclavesArgs {
+            metadata: false,
+            schema_version: 1,
+        };
+
+        let server = fake_enclave_process(listener, 1, |cmd| match cmd {
+            Command::Ping => Some(Response::Pong),
+            Command::GetConsole => Some(Response::Error("No console".to_string())),
+            _ => Some(terminate_response()),
+        });
+
+        let mut client = EnclaveProcClient::with_socket_path(&socket_path).unwrap();
+        assert!(client.ping().is_ok());
+        assert!(client.ping().is_ok());
+
+        // A terminate response can't be taken for a description.
+        let err = client.describe(&args).err().unwrap();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::EnclaveProcessSendReplyFailure
+        );
+
+        // A failed command leaves the session usable.
+        let err = client.request(Command::GetConsole).err().unwrap();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::EnclaveProcessCommandNotExecuted
+        );
+
+        let info = client.terminate().unwrap();
+        assert_eq!(info.enclave_id, "i-0000000000000000-enc1");
+        assert!(info.terminated);
+
+        let (commands, _) = server.join().unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                Command::Ping,
+                Command::Ping,
+                Command::Describe(args),
+                Command::GetConsole,
+                Command::Terminate
+            ]
+        );
+    }
+
+    /// Tests that a command is sent over a new session if the enclave process has closed the
+    /// previous one, but not once it has been fully sent.
+    #[test]
+    fn test_enclave_proc_client_reconnect() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = fake_enclave_process(listener, 2, |cmd| match cmd {
+            Command::Ping => Some(Response::Pong),
+            _ => Some(terminate_response()),
+        });
+        let mut client = EnclaveProcClient::with_socket_path(&socket_path).unwrap();
+        client.terminate().unwrap();
+        wait_for_session_close(&client);
+        assert!(client.ping().is_ok());
+        client.terminate().unwrap();
+        let (commands, _) = server.join().unwrap();
+        assert_eq!(
+            commands,
+            vec![Command::Terminate, Command::Ping, Command::Terminate]
+        );
+
+        // The session is closed after the command has been received, but before replying.
+        let listener = UnixListener::bind(dir.path().join("2.sock")).unwrap();
+        let server = fake_enclave_process(listener, 1, |_| None);
+        let mut client = EnclaveProcClient::with_socket_path(&dir.path().join("2.sock")).unwrap();
+        let err = client.terminate().err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::SocketError);
+        let (commands, listener) = server.join().unwrap();
+        assert_eq!(commands, vec![Command::Terminate]);
+        listener.set_nonblocking(true).unwrap();
+        assert_eq!(
+            listener.accept().err().unwrap().kind(),
+            ErrorKind::WouldBlock
+        );
+    }
+
+    /// Tests that the recent console output is requested with its size and read back.
+    #[test]
+    fn test_enclave_proc_client_recent_console() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = fake_enclave_process(listener, 1, |cmd| match cmd {
+            Command::GetRecentConsole(bytes) => {
+                // An oversized reply is sent to odd-sized requests.
+                let bytes = bytes.min(&12) + bytes % 2;
+                Some(Response::Console(
+                    b"Kernel panic\n"[13 - bytes as usize..].to_vec(),
+                ))
+            }
+            _ => None,
+        });
+
+        let mut client = EnclaveProcClient::with_socket_path(&socket_path).unwrap();
+        assert_eq!(client.recent_console(4).unwrap(), b"nic\n");
+        let err = client.recent_console(1).err().unwrap();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::EnclaveProcessSendReplyFailure
+        );
+        drop(client);
+        let (commands, _) = server.join().unwrap();
+        assert_eq!(
+            commands,
+            vec![Command::GetRecentConsole(4), Command::GetRecentConsole(1)]
+        );
+    }
+
+    /// Tests that the debug mode of an enclave is reported by the describe reply.
+    #[test]
+    fn test_enclave_proc_client_describe() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = fake_enclave_process(listener, 1, |_| Some(describe_response()));
+
+        let mut client = EnclaveProcClient::with_socket_path(&socket_path).unwrap();
+        let info = client
+            .describe(&DescribeEnclavesArgs {
+                metadata: false,
+                schema_version: 1,
+            })
+            .unwrap();
+        assert!(info.debug_mode);
+        assert_eq!(info.flags, "DEBUG_MODE");
+        drop(client);
+        server.join().unwrap();
+
+        // Descriptions from enclave processes which don't report the debug mode are accepted.
+        let mut value = serde_json::to_value(&info).unwrap();
+        value.as_object_mut().unwrap().remove("DebugMode");
+        let info: EnclaveDescribeInfo = serde_json::from_value(value).unwrap();
+        assert!(!info.debug_mode);
+    }
+
+    /// Tests that describing retries while the socket is unavailable, and that a removed socket
+    /// is told apart from one which refuses connections.
+    #[test]
+    fn test_enclave_proc_client_describe_with_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("1.sock");
+        let args = DescribeEnclavesArgs {
+            metadata: false,
+            schema_version: 1,
+        };
+        let retry = RetryPolicy {
+            attempts: 20,
+            delay: Duration::from_millis(50),
+        };
+        let mut client = EnclaveProcClient {
+            socket_path: socket_path.clone(),
+            session: None,
+        };
+
+        // The socket appears while the client is retrying.
+        let server_path = socket_path.clone();
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let listener = UnixListener::bind(&server_path).unwrap();
+            fake_enclave_process(listener, 1, |_| Some(describe_response()))
+                .join()
+                .unwrap()
+                .0
+        });
+        let info = client.describe_with_retry(&args, &retry).unwrap();
+        assert_eq!(info.enclave_id, "i-0000000000000000-enc1");
+        assert_eq!(info.uptime_secs, Some(42));
+        assert_eq!(info.start_time.as_deref(), Some("2020-09-13T12:26:40Z"));
+        client.session = None;
+        assert_eq!(
+            server.join().unwrap(),
+            vec![Command::Describe(args.clone())]
+        );
+
+        // The listener is gone, but its socket file is left behind.
+        let retry = RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(10),
+        };
+        let err = client.describe_with_retry(&args, &retry).err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::EnclaveProcessUnavailable);
+        assert_eq!(
+            err.subactions[0],
+            format!(
+                "Enclave process socket {:?} refused 3 connection attempt(s)",
+                socket_path
+            )
+        );
+
+        fs::remove_file(&socket_path).unwrap();
+        let err = client.describe_with_retry(&args, &retry).err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::SocketPathNotFound);
+    }
+
+    /// Tests that a client can't be created for a missing socket or a malformed enclave ID.
+    #[test]
+    fn test_enclave_proc_client_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = EnclaveProcClient::with_socket_path(&dir.path().join("1.sock"))
+            .err()
+            .unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::SocketPathNotFound);
+
+        let err = EnclaveProcClient::connect_by_id("i-0000000000000000_enc1")
+            .err()
+            .unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::MalformedEnclaveId);
+    }
+}
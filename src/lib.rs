@@ -8,6 +8,8 @@
 
 /// The common module (shared between the CLI and enclave process).
 pub mod common;
+/// The module which verifies enclave image files.
+pub mod eif;
 /// The enclave process module.
 pub mod enclave_proc;
 /// The module covering the communication between a CLI instance and enclave processes.
@@ -21,7 +23,6 @@ use aws_nitro_enclaves_image_format::{generate_build_info, utils::get_pcrs};
 use log::{debug, info};
 use sha2::{Digest, Sha384};
 use std::collections::BTreeMap;
-use std::convert::TryFrom;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write};
 use std::os::unix::net::UnixStream;
@@ -31,13 +32,14 @@ use common::commands_parser::{BuildEnclavesArgs, EmptyArgs, RunEnclavesArgs};
 use common::json_output::{
     EifDescribeInfo, EnclaveBuildInfo, EnclaveTerminateInfo, MetadataDescribeInfo,
 };
+use common::protocol::client_handshake;
 use common::{enclave_proc_command_send_single, get_sockets_dir_path};
 use common::{EnclaveProcessCommandType, NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
 use enclave_proc_comm::{
     enclave_proc_command_send_all, enclave_proc_handle_outputs, enclave_process_handle_all_replies,
 };
 
-use utils::{Console, PcrType};
+use utils::PcrType;
 
 /// Hypervisor CID as defined by <http://man7.org/linux/man-pages/man7/vsock.7.html>.
 pub const VMADDR_CID_HYPERVISOR: u32 = 0;
@@ -356,16 +358,8 @@ pub fn enclave_console(
     enclave_cid: u64,
     disconnect_timeout_sec: Option<u64>,
 ) -> NitroCliResult<()> {
-    let console = Console::new(
-        VMADDR_CID_HYPERVISOR,
-        u32::try_from(enclave_cid).map_err(|err| {
-            new_nitro_cli_failure!(
-                &format!("Failed to parse enclave CID: {:?}", err),
-                NitroCliErrorEnum::IntegerParsingError
-            )
-        })? + CID_TO_CONSOLE_PORT_OFFSET,
-    )
-    .map_err(|e| e.add_subaction("Connect to enclave console".to_string()))?;
+    let console = enclave_proc::console::attach(enclave_cid)
+        .map_err(|e| e.add_subaction("Connect to enclave console".to_string()))?;
     println!("Successfully connected to the console.");
     console
         .read_to(io::stdout().by_ref(), disconnect_timeout_sec)
@@ -416,12 +410,15 @@ pub fn terminate_all_enclaves() -> NitroCliResult<()> {
             }
         };
 
-        if enclave_proc_command_send_single::<EmptyArgs>(
-            EnclaveProcessCommandType::Terminate,
-            None,
-            &mut stream,
-        )
-        .is_err()
+        if client_handshake(&mut stream)
+            .and_then(|_| {
+                enclave_proc_command_send_single::<EmptyArgs>(
+                    EnclaveProcessCommandType::Terminate,
+                    None,
+                    &mut stream,
+                )
+            })
+            .is_err()
         {
             failed_connections.push(entry.path());
         } else {
@@ -661,6 +658,17 @@ macro_rules! create_app {
                             .required(false)
                             .conflicts_with("config"),
                     )
+                    .arg(
+                        Arg::with_name("unsafe-allow-cpu0")
+                            .long("unsafe-allow-cpu0")
+                            .takes_value(false)
+                            .help(
+                                "Allow CPU 0 and its siblings to be given to the enclave. The host \
+                                relies on CPU 0 for handling critical interrupts, so this may \
+                                destabilize it."
+                            )
+                            .conflicts_with("config"),
+                    )
                     .arg(
                         Arg::with_name("config")
                             .long("config")
@@ -701,6 +709,13 @@ macro_rules! create_app {
                             .required_unless("all")
                             .conflicts_with("enclave-id")
                             .conflicts_with("all"),
+                    )
+                    .arg(
+                        Arg::with_name("timeout")
+                            .long("timeout")
+                            .takes_value(true)
+                            .help("The time in seconds after which an unresponsive enclave process is killed")
+                            .conflicts_with("all"),
                     ),
             )
             .subcommand(
@@ -779,7 +794,13 @@ macro_rules! create_app {
                         Arg::with_name("metadata")
                             .long("metadata")
                             .help("Adds EIF metadata of the current enclaves to the command output.")
-                        ),
+                        )
+                    .arg(
+                        Arg::with_name("schema-version")
+                            .long("schema-version")
+                            .takes_value(true)
+                            .help("The schema version which the command output must follow. Defaults to the latest version."),
+                    ),
             )
             .subcommand(
                 SubCommand::with_name("console")
@@ -11,6 +11,7 @@ extern crate lazy_static;
 use clap::{App, AppSettings, Arg, SubCommand};
 use log::info;
 use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
 use nitro_cli::common::commands_parser::{
     BuildEnclavesArgs, ConsoleArgs, DescribeEnclavesArgs, EmptyArgs, ExplainArgs, PcrArgs,
@@ -26,6 +27,7 @@ use nitro_cli::enclave_proc::resource_manager::NE_ENCLAVE_DEBUG_MODE;
 use nitro_cli::enclave_proc_comm::{
     enclave_proc_command_send_all, enclave_proc_connect_to_single, enclave_proc_get_cid,
     enclave_proc_get_flags, enclave_proc_spawn, enclave_process_handle_all_replies,
+    EnclaveProcClient,
 };
 use nitro_cli::{
     build_enclaves, console_enclaves, create_app, describe_eif, get_all_enclave_names,
@@ -152,41 +154,68 @@ fn main() {
                         .set_action(TERMINATE_ENCLAVE_STR.to_string())
                     })
                     .ok_or_exit_with_errno(None);
-                let mut comm = enclave_proc_connect_to_single(&terminate_args.enclave_id)
+                if let Some(timeout_sec) = terminate_args.timeout_sec {
+                    let info = EnclaveProcClient::connect_by_id(&terminate_args.enclave_id)
+                        .and_then(|mut client| {
+                            client.terminate_with_timeout(Duration::from_secs(timeout_sec))
+                        })
+                        .map_err(|e| {
+                            e.add_subaction("Failed to terminate enclave".to_string())
+                                .set_action(TERMINATE_ENCLAVE_STR.to_string())
+                        })
+                        .ok_or_exit_with_errno(None);
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&info)
+                            .map_err(|e| new_nitro_cli_failure!(
+                                &format!("Failed to print JSON object: {:?}", e),
+                                NitroCliErrorEnum::SerdeError
+                            )
+                            .set_action(TERMINATE_ENCLAVE_STR.to_string()))
+                            .ok_or_exit_with_errno(None)
+                    );
+                } else {
+                    let mut comm = enclave_proc_connect_to_single(&terminate_args.enclave_id)
+                        .map_err(|e| {
+                            e.add_subaction("Failed to connect to enclave process".to_string())
+                                .set_action(TERMINATE_ENCLAVE_STR.to_string())
+                        })
+                        .ok_or_exit_with_errno(None);
+                    // TODO: Replicate output of old CLI on invalid enclave IDs.
+                    enclave_proc_command_send_single::<EmptyArgs>(
+                        EnclaveProcessCommandType::Terminate,
+                        None,
+                        &mut comm,
+                    )
                     .map_err(|e| {
-                        e.add_subaction("Failed to connect to enclave process".to_string())
+                        e.add_subaction("Failed to send single command".to_string())
                             .set_action(TERMINATE_ENCLAVE_STR.to_string())
                     })
                     .ok_or_exit_with_errno(None);
-                // TODO: Replicate output of old CLI on invalid enclave IDs.
-                enclave_proc_command_send_single::<EmptyArgs>(
-                    EnclaveProcessCommandType::Terminate,
-                    None,
-                    &mut comm,
-                )
-                .map_err(|e| {
-                    e.add_subaction("Failed to send single command".to_string())
-                        .set_action(TERMINATE_ENCLAVE_STR.to_string())
-                })
-                .ok_or_exit_with_errno(None);
 
-                info!("Sent command: Terminate");
-                replies.push(comm);
-                enclave_process_handle_all_replies::<EnclaveTerminateInfo>(
-                    &mut replies,
-                    0,
-                    false,
-                    vec![0],
-                )
-                .map_err(|e| {
-                    e.add_subaction("Failed to handle all enclave process replies".to_string())
-                        .set_action(TERMINATE_ENCLAVE_STR.to_string())
-                })
-                .ok_or_exit_with_errno(None);
+                    info!("Sent command: Terminate");
+                    replies.push(comm);
+                    enclave_process_handle_all_replies::<EnclaveTerminateInfo>(
+                        &mut replies,
+                        0,
+                        false,
+                        vec![0],
+                    )
+                    .map_err(|e| {
+                        e.add_subaction("Failed to handle all enclave process replies".to_string())
+                            .set_action(TERMINATE_ENCLAVE_STR.to_string())
+                    })
+                    .ok_or_exit_with_errno(None);
+                }
             }
         }
         Some(("describe-enclaves", args)) => {
-            let describe_args = DescribeEnclavesArgs::new_with(args);
+            let describe_args = DescribeEnclavesArgs::new_with(args)
+                .map_err(|e| {
+                    e.add_subaction("Failed to construct DescribeEnclaves arguments".to_string())
+                        .set_action(DESCRIBE_ENCLAVE_STR.to_string())
+                })
+                .ok_or_exit_with_errno(None);
             let (comms, comm_errors) = enclave_proc_command_send_all::<DescribeEnclavesArgs>(
                 EnclaveProcessCommandType::Describe,
                 Some(&describe_args),
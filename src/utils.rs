@@ -10,10 +10,11 @@ use nix::sys::socket::{connect, socket};
 use nix::sys::socket::{AddressFamily, SockFlag, SockType, VsockAddr};
 use nix::sys::time::{TimeVal, TimeValLike};
 use nix::unistd::read;
+use std::collections::VecDeque;
 use std::io::Write;
 use std::mem::size_of;
-use std::os::unix::io::IntoRawFd;
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use std::thread::sleep;
 use std::time::{Duration, SystemTime};
 use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
@@ -25,6 +26,9 @@ use crate::new_nitro_cli_failure;
 /// The size of the buffers used for reading console data.
 const BUFFER_SIZE: usize = 1024;
 
+/// The number of bytes of console output which a `Console` keeps by default.
+pub const DEFAULT_CONSOLE_HISTORY_SIZE: usize = 64 * 1024;
+
 /// The console connection time-out, in milliseconds.
 const CONSOLE_CONNECT_TIMEOUT: i64 = 20000;
 
@@ -45,10 +49,84 @@ pub enum PcrType {
     SigningCertificate,
 }
 
+/// The most recent output of a console, kept in a ring buffer of bounded size. Clones share
+/// the same buffer, so the output can be read on other threads while the console is being read.
+#[derive(Clone)]
+pub struct ConsoleHistory {
+    /// The buffered output, oldest byte first.
+    data: Arc<Mutex<VecDeque<u8>>>,
+    /// The largest number of bytes kept.
+    capacity: usize,
+}
+
+impl ConsoleHistory {
+    /// Create an empty history which keeps at most `capacity` bytes. A capacity of 0 disables it.
+    pub fn new(capacity: usize) -> Self {
+        ConsoleHistory {
+            data: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Get the largest number of bytes kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append console output, dropping the oldest bytes once the capacity is exceeded.
+    pub fn record(&self, output: &[u8]) {
+        let output = &output[output.len().saturating_sub(self.capacity)..];
+        if output.is_empty() {
+            return;
+        }
+
+        let mut data = self.lock();
+        let excess = (data.len() + output.len()).saturating_sub(self.capacity);
+        data.drain(..excess);
+        data.extend(output);
+    }
+
+    /// Get (at most) the last `bytes` bytes of output. The buffer is only locked while being
+    /// copied, which takes as long as copying `capacity()` bytes at most.
+    pub fn tail(&self, bytes: usize) -> Vec<u8> {
+        let data = self.lock();
+        let skip = data.len().saturating_sub(bytes);
+        let (front, back) = data.as_slices();
+        let mut tail = Vec::with_capacity(data.len() - skip);
+        if skip < front.len() {
+            tail.extend_from_slice(&front[skip..]);
+            tail.extend_from_slice(back);
+        } else {
+            tail.extend_from_slice(&back[skip - front.len()..]);
+        }
+
+        tail
+    }
+
+    /// Lock the buffer. It is never left inconsistent by a panic, so poisoning is ignored.
+    fn lock(&self) -> MutexGuard<'_, VecDeque<u8>> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
 /// The structure representing the console of an enclave.
 pub struct Console {
     /// The file descriptor used for connecting to the enclave's console.
     fd: RawFd,
+    /// Console data which has been read, but not yet consumed by `read_line()`.
+    pending: Vec<u8>,
+    /// The most recent output read from the console.
+    history: ConsoleHistory,
+}
+
+impl FromRawFd for Console {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Console {
+            fd,
+            pending: Vec::new(),
+            history: ConsoleHistory::new(DEFAULT_CONSOLE_HISTORY_SIZE),
+        }
+    }
 }
 
 impl Drop for Console {
@@ -79,14 +157,26 @@ impl Console {
             err.add_subaction("Failed to set console connect timeout".to_string())
         })?;
 
-        connect(socket_fd, &sockaddr).map_err(|err| {
-            new_nitro_cli_failure!(
+        if let Err(err) = connect(socket_fd, &sockaddr) {
+            unsafe { close(socket_fd) };
+            // Only enclaves started in debug mode accept console connections.
+            let error_code = match err {
+                nix::errno::Errno::ECONNREFUSED | nix::errno::Errno::ECONNRESET => {
+                    NitroCliErrorEnum::EnclaveConsoleUnavailable
+                }
+                _ => NitroCliErrorEnum::EnclaveConsoleConnectionFailure,
+            };
+            return Err(new_nitro_cli_failure!(
                 &format!("Failed to connect to the console: {:?}", err),
-                NitroCliErrorEnum::EnclaveConsoleConnectionFailure
-            )
-        })?;
+                error_code
+            ));
+        }
 
-        Ok(Console { fd: socket_fd })
+        Ok(Console {
+            fd: socket_fd,
+            pending: Vec::new(),
+            history: ConsoleHistory::new(DEFAULT_CONSOLE_HISTORY_SIZE),
+        })
     }
 
     /// Create a new non-blocking `Console` connection from a given enclave CID and a vsock port.
@@ -138,7 +228,73 @@ impl Console {
             },
         };
 
-        Ok(Console { fd: socket_fd })
+        Ok(Console {
+            fd: socket_fd,
+            pending: Vec::new(),
+            history: ConsoleHistory::new(DEFAULT_CONSOLE_HISTORY_SIZE),
+        })
+    }
+
+    /// Keep the console output in `history` from now on, instead of in the console's own
+    /// history of `DEFAULT_CONSOLE_HISTORY_SIZE` bytes. The output kept so far is discarded.
+    pub fn set_history(&mut self, history: ConsoleHistory) {
+        self.history = history;
+    }
+
+    /// Get a handle to the recent console output, which may be read on another thread.
+    pub fn history(&self) -> ConsoleHistory {
+        self.history.clone()
+    }
+
+    /// Get (at most) the last `bytes` bytes of the output read from the console so far.
+    pub fn tail(&self, bytes: usize) -> Vec<u8> {
+        self.history.tail(bytes)
+    }
+
+    /// Read a line of console output, including its trailing newline, into `line`. Returns the
+    /// number of bytes read, which is 0 once the console has been closed.
+    pub fn read_line(&mut self, line: &mut String) -> NitroCliResult<usize> {
+        loop {
+            if let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+                let data: Vec<u8> = self.pending.drain(..=pos).collect();
+                line.push_str(&String::from_utf8_lossy(&data));
+                return Ok(data.len());
+            }
+
+            let mut buffer = [0u8; BUFFER_SIZE];
+            let size = read(self.fd, &mut buffer).map_err(|e| {
+                new_nitro_cli_failure!(
+                    &format!("Failed to read data from the console: {:?}", e),
+                    NitroCliErrorEnum::EnclaveConsoleReadError
+                )
+            })?;
+
+            if size == 0 {
+                // The last line may not be newline-terminated.
+                let data = std::mem::take(&mut self.pending);
+                line.push_str(&String::from_utf8_lossy(&data));
+                return Ok(data.len());
+            }
+
+            self.history.record(&buffer[..size]);
+            self.pending.extend_from_slice(&buffer[..size]);
+        }
+    }
+
+    /// Stream the console output to `output` until the console is closed.
+    pub fn stream_to(&mut self, mut output: impl Write) -> NitroCliResult<()> {
+        let pending = std::mem::take(&mut self.pending);
+        output.write_all(&pending).map_err(|e| {
+            new_nitro_cli_failure!(
+                &format!(
+                    "Failed to write data from the console to the given stream: {:?}",
+                    e
+                ),
+                NitroCliErrorEnum::EnclaveConsoleWriteOutputError
+            )
+        })?;
+
+        self.read_to(&mut output, None)
     }
 
     /// Read a chunk of raw data from the console and output it.
@@ -237,6 +393,7 @@ impl Console {
                         }
 
                         if size > 0 {
+                            self.history.record(&buffer[..size]);
                             output.write(&buffer[..size]).map_err(|e| {
                                 new_nitro_cli_failure!(
                                     &format!(
@@ -268,6 +425,7 @@ impl Console {
 
             if let Ok(size) = result {
                 if size > 0 {
+                    self.history.record(&buffer[..size]);
                     let mut buf_vec = buffer.to_vec();
                     buf_vec.truncate(size);
                     (*buf).append(&mut buf_vec);
@@ -321,7 +479,7 @@ fn vsock_set_connect_timeout(fd: RawFd, millis: i64) -> NitroCliResult<()> {
 /// limit of enclave memory based on the EIF file size.
 pub fn ceil_div(lhs: u64, rhs: u64) -> u64 {
     if rhs == 0 {
-        return std::u64::MAX;
+        return u64::MAX;
     }
 
     lhs / rhs
@@ -2,26 +2,45 @@
 // SPDX-License-Identifier: Apache-2.0
 #![deny(warnings)]
 
+use lazy_static::lazy_static;
 use libc::{VMADDR_CID_HOST, VMADDR_CID_LOCAL};
-use std::fs::File;
-use std::os::unix::io::{AsRawFd, RawFd};
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use driver_bindings::bindings::ne_enclave_start_info;
-use nitro_cli::common::{NitroCliErrorEnum, NitroCliFailure, NitroCliResult};
+use eif_loader::{enclave_ready, EifLoaderError};
+use nitro_cli::common::{
+    NitroCliError, NitroCliErrorEnum, NitroCliFailure, NitroCliResult, ENCLAVE_READY_VSOCK_PORT,
+    VMADDR_CID_PARENT,
+};
 use nitro_cli::enclave_proc::cpu_info::CpuInfo;
 use nitro_cli::enclave_proc::resource_manager::{
-    EnclaveStartInfo, MemoryRegion, NE_ADD_VCPU, NE_CREATE_VM, NE_SET_USER_MEMORY_REGION,
-    NE_START_ENCLAVE,
+    get_host_memory_stats, ne_ioctl, read_cpu_pool, select_whole_cores, try_ne_ioctl, EagerFault,
+    EnclaveStartInfo, HugePageSize, ImageLoadInfo, MemStats, MemoryRegion, NeAddVcpu, NeCreateVm,
+    NeGetImageLoadInfo, NeSetUserMemoryRegion, NeStartEnclave, UnsafeAllowCpu0, UserMemoryRegion,
+    NE_CREATE_VM, NE_ENCLAVE_DEBUG_MODE, NE_SET_USER_MEMORY_REGION,
 };
-use nitro_cli::enclave_proc::utils::MiB;
+use nitro_cli::enclave_proc::utils::{GiB, MiB};
+use vsock::{VsockAddr, VsockListener};
 
 const ENCLAVE_MEM_2MB_CHUNKS: u64 = 48;
 #[cfg(target_arch = "aarch64")]
 const ENCLAVE_MEM_32MB_CHUNKS: u64 = 3;
 pub const NE_DEVICE_PATH: &str = "/dev/nitro_enclaves";
 
-/// This is similar to `MemoryRegion`, except it doesn't implement `Drop`.
+/// This is similar to `MemoryRegion`, except it doesn't implement `Drop`. Regions built from a
+/// `MemoryRegion` take its address and size, so the two always match; `new()` allows any
+/// combination, for checking how the driver rejects invalid regions.
 #[allow(dead_code)]
 pub struct EnclaveMemoryRegion {
     /// Flags to determine the usage for the memory region.
@@ -42,14 +61,34 @@ impl EnclaveMemoryRegion {
     }
 
     fn new_from(region: &MemoryRegion) -> Self {
+        EnclaveMemoryRegion::with_flags(region, 0)
+    }
+
+    fn with_flags(region: &MemoryRegion, flags: u64) -> Self {
         EnclaveMemoryRegion {
-            flags: 0,
+            flags,
             mem_size: region.mem_size(),
             mem_addr: region.mem_addr(),
         }
     }
 }
 
+/// Merge the runs of memory regions in which each region starts where the previous one ends.
+fn coalesce_mem_regions(regions: &[MemoryRegion]) -> Vec<EnclaveMemoryRegion> {
+    let mut coalesced: Vec<EnclaveMemoryRegion> = Vec::new();
+
+    for region in regions {
+        match coalesced.last_mut() {
+            Some(last) if last.mem_addr + last.mem_size == region.mem_addr() => {
+                last.mem_size += region.mem_size();
+            }
+            _ => coalesced.push(EnclaveMemoryRegion::new_from(region)),
+        }
+    }
+
+    coalesced
+}
+
 /// Class that covers communication with the NE driver.
 pub struct NitroEnclavesDeviceDriver {
     // NE device file.
@@ -58,99 +97,976 @@ pub struct NitroEnclavesDeviceDriver {
 
 impl NitroEnclavesDeviceDriver {
     /// Open the file descriptor for communicating with the NE driver.
-    pub fn new() -> NitroCliResult<Self> {
-        Ok(NitroEnclavesDeviceDriver {
-            file: File::open(NE_DEVICE_PATH).map_err(|e| {
-                NitroCliFailure::new()
-                    .add_subaction(format!("Could not open {}: {}", NE_DEVICE_PATH, e))
-                    .set_error_code(NitroCliErrorEnum::FileOperationFailure)
-                    .set_file_and_line(file!(), line!())
-                    .add_info(vec![NE_DEVICE_PATH, "Open"])
-            })?,
-        })
+    pub fn new() -> NitroCliResult<Self, NitroCliError> {
+        File::open(NE_DEVICE_PATH)
+            .map(|file| NitroEnclavesDeviceDriver { file })
+            .map_err(NitroCliError::DeviceOpenFailed)
     }
 
-    /// Allocate an enclave slot and return an enclave fd.
-    pub fn create_enclave(&mut self) -> NitroCliResult<NitroEnclave> {
-        let mut slot_uid: u64 = 0;
-        // This is safe because we are providing valid values.
-        let enc_fd =
-            unsafe { libc::ioctl(self.file.as_raw_fd(), NE_CREATE_VM as _, &mut slot_uid) };
+    /// Open the file descriptor for communicating with the NE driver through the device
+    /// found at a custom path.
+    pub fn with_path(path: impl AsRef<Path>) -> NitroCliResult<Self> {
+        NitroEnclavesDeviceDriver::open_with_retry(path.as_ref(), 1, Duration::from_millis(0))
+    }
 
-        if enc_fd < 0 {
-            return Err(NitroCliFailure::new()
-                .add_subaction(format!(
-                    "Could not create an enclave descriptor: {}",
-                    enc_fd
-                ))
-                .set_error_code(NitroCliErrorEnum::IoctlFailure)
-                .set_file_and_line(file!(), line!()));
+    /// Open the file descriptor for communicating with the NE driver, making up to `attempts`
+    /// attempts while the device is missing or not yet accessible (such as during boot).
+    pub fn new_with_retry(attempts: u32, delay: Duration) -> NitroCliResult<Self> {
+        NitroEnclavesDeviceDriver::open_with_retry(Path::new(NE_DEVICE_PATH), attempts, delay)
+    }
+
+    /// Open the device at `path`, retrying as described for `new_with_retry`.
+    fn open_with_retry(path: &Path, attempts: u32, delay: Duration) -> NitroCliResult<Self> {
+        let mut attempt = 1;
+
+        loop {
+            match File::open(path) {
+                Ok(file) => return Ok(NitroEnclavesDeviceDriver { file }),
+                Err(e)
+                    if attempt < attempts
+                        && matches!(
+                            e.kind(),
+                            ErrorKind::NotFound | ErrorKind::PermissionDenied
+                        ) =>
+                {
+                    attempt += 1;
+                    thread::sleep(delay);
+                }
+                Err(e) => {
+                    return Err(NitroCliFailure::new()
+                        .add_subaction(format!(
+                            "Could not open {} after {} attempt(s): {}",
+                            path.display(),
+                            attempt,
+                            e
+                        ))
+                        .set_error_code(NitroCliErrorEnum::FileOperationFailure)
+                        .set_file_and_line(file!(), line!())
+                        .add_info(vec![
+                            path.to_str().unwrap_or("Invalid unicode device path"),
+                            "Open",
+                        ]))
+                }
+            }
         }
+    }
+
+    /// Check if an enclave with the given memory size and number of vCPUs could be created on
+    /// the current host, given the free hugepages and the NE CPU pool. No enclave slot is
+    /// created and no hugepage is reserved, so the result may change before an actual launch.
+    pub fn check_capacity(&self, mem_bytes: u64, vcpus: u32) -> NitroCliResult<CapacityReport> {
+        let available_memory = available_hugepage_memory();
+        let available_vcpus = CpuInfo::new()
+            .map_err(|e| e.add_subaction("Could not read the NE CPU pool".to_string()))?
+            .get_cpu_candidates()
+            .len() as u32;
+
+        Ok(CapacityReport::new(
+            mem_bytes,
+            vcpus,
+            available_memory,
+            available_vcpus,
+        ))
+    }
+
+    /// Allocate an enclave slot and return an enclave fd.
+    pub fn create_enclave(&mut self) -> NitroCliResult<NitroEnclave, NitroCliError> {
+        #[cfg(feature = "metrics")]
+        let started_at = Instant::now();
+        let mut slot_uid: u64 = 0;
+        let enc_fd = try_ne_ioctl(self.file.as_raw_fd(), NeCreateVm, &mut slot_uid)?;
 
         if slot_uid == 0 {
             return Err(NitroCliFailure::new()
                 .add_subaction("Obtained invalid slot ID".to_string())
                 .set_error_code(NitroCliErrorEnum::IoctlFailure)
-                .set_file_and_line(file!(), line!()));
+                .set_file_and_line(file!(), line!())
+                .into());
+        }
+
+        let mut enclave = NitroEnclave::new(enc_fd).unwrap();
+        enclave.slot_uid = slot_uid;
+        #[cfg(feature = "metrics")]
+        {
+            enclave.timings.slot_creation = Some(started_at.elapsed());
+        }
+
+        Ok(enclave)
+    }
+
+    /// Create `count` enclaves, each with `mem_each` bytes of memory backed by 2 MiB hugepages
+    /// and `cpus_each` vCPUs forming whole cores, none of which is given to more than one of
+    /// them. If any enclave can't be created, the ones created so far are terminated, so no
+    /// partial batch is left behind, and the error tells the index of the failed enclave.
+    pub fn create_enclaves(
+        &mut self,
+        count: usize,
+        mem_each: u64,
+        cpus_each: u32,
+    ) -> NitroCliResult<Vec<NitroEnclave>> {
+        let pool = read_cpu_pool()
+            .map_err(|e| e.add_subaction("Could not read the NE CPU pool".to_string()))?
+            .available();
+
+        self.create_enclaves_with(
+            count,
+            |used| {
+                let free: Vec<u32> = pool
+                    .iter()
+                    .filter(|cpu_id| !used.contains(cpu_id))
+                    .cloned()
+                    .collect();
+                select_whole_cores(&free, cpus_each, UnsafeAllowCpu0::default())
+            },
+            || MemoryRegion::new_with_page_size(mem_each, HugePageSize::Mib2).map(|r| vec![r]),
+        )
+    }
+
+    /// Create a batch of enclaves as `create_enclaves()` does, with the CPUs of each enclave
+    /// selected by `select_cpus`, given the CPUs assigned to the batch so far, and its memory
+    /// allocated by `allocate_memory`.
+    fn create_enclaves_with<C, M>(
+        &mut self,
+        count: usize,
+        mut select_cpus: C,
+        mut allocate_memory: M,
+    ) -> NitroCliResult<Vec<NitroEnclave>>
+    where
+        C: FnMut(&[u32]) -> NitroCliResult<Vec<u32>>,
+        M: FnMut() -> NitroCliResult<Vec<MemoryRegion>>,
+    {
+        let mut enclaves: Vec<NitroEnclave> = Vec::with_capacity(count);
+        let mut used_cpus: Vec<u32> = Vec::new();
+
+        for index in 0..count {
+            let result = self.create_enclave().map_err(NitroCliFailure::from);
+            let result = result.and_then(|mut enclave| {
+                let regions = allocate_memory()?;
+                enclave.add_mem_regions(&regions)?;
+                enclave.owned_mem_regions = regions;
+                for cpu_id in select_cpus(&used_cpus)? {
+                    enclave.add_cpu(cpu_id)?;
+                }
+                Ok(enclave)
+            });
+
+            match result {
+                Ok(enclave) => {
+                    used_cpus.extend_from_slice(enclave.assigned_vcpus());
+                    enclaves.push(enclave);
+                }
+                Err(mut err) => {
+                    err = err.add_subaction(format!(
+                        "Could not create enclave {} of a batch of {}",
+                        index, count
+                    ));
+                    for enclave in enclaves {
+                        if let Err(close_err) = enclave.terminate() {
+                            err = err.add_subaction(format!(
+                                "Could not roll back the batch: {:?}",
+                                close_err.subactions
+                            ));
+                        }
+                    }
+
+                    return Err(err);
+                }
+            }
         }
 
-        Ok(NitroEnclave::new(enc_fd).unwrap())
+        Ok(enclaves)
     }
 }
 
+/// Get the memory in bytes provided by the free hugepages of all supported sizes.
+fn available_hugepage_memory() -> u64 {
+    // Hosts which don't support a hugepage size don't provide its counters either.
+    [HugePageSize::Mib2, HugePageSize::Gib1]
+        .iter()
+        .map(|page_size| {
+            MemoryRegion::available_hugepages(*page_size).unwrap_or(0) * page_size.size()
+        })
+        .sum()
+}
+
 /// Class for managing a Nitro Enclave provided by NitroEnclavesDeviceDriver.
 pub struct NitroEnclave {
     enc_fd: RawFd,
+    /// The slot UID assigned by the NE driver when the enclave was created.
+    slot_uid: u64,
+    /// The CID assigned by the NE driver when the enclave was started.
+    cid: Option<u64>,
+    /// The IDs of the CPUs which have been added to the enclave.
+    vcpus: Vec<u32>,
+    /// The memory regions which have been added to the enclave, in the order of their addition.
+    mem_regions: Vec<MemoryRegionInfo>,
+    /// The slot from which the next free slot is searched for when adding a memory region.
+    next_mem_slot: u32,
+    /// The memory allocated on behalf of the enclave, which is unmapped once the enclave has
+    /// been released.
+    owned_mem_regions: Vec<MemoryRegion>,
+    /// The lifecycle stage the enclave has reached.
+    state: EnclaveState,
+    /// The flags with which the enclave has been started.
+    start_flags: Option<u64>,
+    /// How long the enclave setup steps have taken so far.
+    #[cfg(feature = "metrics")]
+    timings: EnclaveTimings,
+}
+
+/// The lifecycle stages of an enclave, which operations move through in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnclaveState {
+    /// An enclave slot has been allocated, but no resources have been added to it.
+    SlotAllocated,
+    /// Memory, but no vCPUs, has been added to the enclave.
+    MemoryAttached,
+    /// vCPUs have been added to the enclave, possibly along with memory.
+    VcpusAttached,
+    /// The enclave has been started.
+    Running,
+    /// The enclave descriptor has been closed, which releases the enclave slot.
+    Terminated,
+}
+
+/// Whether a started enclave has exited, as reported by `NitroEnclave::wait_for_exit()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The enclave has stopped running, either on its own (such as on guest shutdown or panic)
+    /// or because its descriptor has been closed.
+    Exited,
+    /// The enclave was still running when the wait timed out.
+    Running,
+}
+
+impl EnclaveState {
+    /// Check if an enclave in this state may move to the `to` state.
+    fn can_transition_to(self, to: EnclaveState) -> bool {
+        use EnclaveState::*;
+
+        matches!(
+            (self, to),
+            (SlotAllocated, MemoryAttached)
+                | (MemoryAttached, MemoryAttached)
+                | (SlotAllocated, VcpusAttached)
+                | (MemoryAttached, VcpusAttached)
+                | (VcpusAttached, VcpusAttached)
+                | (VcpusAttached, Running)
+                | (SlotAllocated, Terminated)
+                | (MemoryAttached, Terminated)
+                | (VcpusAttached, Terminated)
+                | (Running, Terminated)
+        )
+    }
+}
+
+/// The flags with which an enclave is started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EnclaveStartFlags(u64);
+
+impl EnclaveStartFlags {
+    /// Start the enclave in debug mode, which makes its console available.
+    pub const DEBUG_MODE: EnclaveStartFlags = EnclaveStartFlags(NE_ENCLAVE_DEBUG_MODE);
+
+    /// Get the raw flags passed to the NE driver.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for EnclaveStartFlags {
+    type Output = EnclaveStartFlags;
+
+    fn bitor(self, rhs: EnclaveStartFlags) -> EnclaveStartFlags {
+        EnclaveStartFlags(self.0 | rhs.0)
+    }
+}
+
+/// A resource of which an enclave would need more than the host has available.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum CapacityShortfall {
+    /// Not enough memory is available in free hugepages.
+    Memory { requested: u64, available: u64 },
+    /// Not enough CPUs are part of the NE CPU pool.
+    Vcpus { requested: u32, available: u32 },
+}
+
+/// The result of checking whether an enclave configuration fits on the current host.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CapacityReport {
+    /// The memory in bytes provided by the free hugepages of all supported sizes.
+    pub available_memory: u64,
+    /// The number of CPUs in the NE CPU pool.
+    pub available_vcpus: u32,
+    /// The resources which are short for the requested configuration.
+    pub shortfalls: Vec<CapacityShortfall>,
+}
+
+impl CapacityReport {
+    fn new(mem_bytes: u64, vcpus: u32, available_memory: u64, available_vcpus: u32) -> Self {
+        let mut shortfalls = Vec::new();
+        if mem_bytes > available_memory {
+            shortfalls.push(CapacityShortfall::Memory {
+                requested: mem_bytes,
+                available: available_memory,
+            });
+        }
+        if vcpus > available_vcpus {
+            shortfalls.push(CapacityShortfall::Vcpus {
+                requested: vcpus,
+                available: available_vcpus,
+            });
+        }
+
+        CapacityReport {
+            available_memory,
+            available_vcpus,
+            shortfalls,
+        }
+    }
+
+    /// Check if the requested configuration fits the available resources.
+    pub fn is_satisfiable(&self) -> bool {
+        self.shortfalls.is_empty()
+    }
+}
+
+/// A memory region which has been added to an enclave.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct MemoryRegionInfo {
+    /// The slot of the region, which is unique among the regions of the enclave.
+    pub slot: u32,
+    /// The flags with which the region has been added.
+    pub flags: u64,
+    /// The virtual address of the region in the enclave process.
+    pub addr: u64,
+    /// The size of the region in bytes.
+    pub size: u64,
+}
+
+/// The resources which have been attached to an enclave.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ResourceSummary {
+    /// The total size in bytes of the enclave memory.
+    pub memory_bytes: u64,
+    /// The number of memory regions backing the enclave memory.
+    pub memory_regions: usize,
+    /// The IDs of the enclave vCPUs.
+    pub vcpu_ids: Vec<u32>,
+    /// The enclave CID, which is only known once the enclave has started.
+    pub cid: Option<u64>,
+    /// Whether the enclave has been started in debug mode, in which its PCRs are all zeroes.
+    pub debug_mode: bool,
+}
+
+impl std::fmt::Display for ResourceSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "memory: {} MiB in {} region(s), cpu-ids: {:?}, enclave-cid: ",
+            self.memory_bytes / MiB,
+            self.memory_regions,
+            self.vcpu_ids
+        )?;
+        match self.cid {
+            Some(cid) => write!(f, "{}", cid)?,
+            None => write!(f, "none")?,
+        }
+        write!(f, ", debug-mode: {}", self.debug_mode)
+    }
+}
+
+/// How long each successful step of setting up an enclave has taken, including the ioctl and
+/// the time the NE driver spends faulting in and pinning the enclave memory.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnclaveTimings {
+    /// The duration of the enclave slot creation, if the enclave has been obtained through
+    /// `NitroEnclavesDeviceDriver::create_enclave()`.
+    pub slot_creation: Option<Duration>,
+    /// The duration of adding each memory region, in the order in which they have been added.
+    pub mem_regions: Vec<Duration>,
+    /// The duration of adding each vCPU, in the order in which they have been added.
+    pub vcpus: Vec<Duration>,
+    /// The duration of the start ioctl, once the enclave has started.
+    pub start: Option<Duration>,
+}
+
+#[cfg(feature = "metrics")]
+impl EnclaveTimings {
+    /// Get the overall duration of the steps recorded so far.
+    pub fn total(&self) -> Duration {
+        self.slot_creation.unwrap_or_default()
+            + self.mem_regions.iter().sum::<Duration>()
+            + self.vcpus.iter().sum::<Duration>()
+            + self.start.unwrap_or_default()
+    }
 }
 
 impl NitroEnclave {
     pub fn new(enc_fd: RawFd) -> NitroCliResult<Self> {
-        Ok(NitroEnclave { enc_fd })
+        Ok(NitroEnclave {
+            enc_fd,
+            slot_uid: 0,
+            cid: None,
+            vcpus: Vec::new(),
+            mem_regions: Vec::new(),
+            next_mem_slot: 0,
+            owned_mem_regions: Vec::new(),
+            state: EnclaveState::SlotAllocated,
+            start_flags: None,
+            #[cfg(feature = "metrics")]
+            timings: EnclaveTimings::default(),
+        })
     }
 
-    fn release(&mut self) {
-        // Close enclave descriptor.
-        let rc = unsafe { libc::close(self.enc_fd) };
-        if rc < 0 {
-            panic!("Could not close enclave descriptor: {}.", rc)
+    /// Get how long the enclave setup steps have taken so far.
+    #[cfg(feature = "metrics")]
+    pub fn timings(&self) -> &EnclaveTimings {
+        &self.timings
+    }
+
+    /// Get the lifecycle stage the enclave has reached.
+    pub fn state(&self) -> EnclaveState {
+        self.state
+    }
+
+    /// Check that the enclave may move to the `to` state, without moving it there.
+    fn check_transition(&self, to: EnclaveState) -> NitroCliResult<()> {
+        if !self.state.can_transition_to(to) {
+            let from = format!("{:?}", self.state);
+            let to = format!("{:?}", to);
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!(
+                    "Invalid enclave state transition from {} to {}",
+                    from, to
+                ))
+                .set_error_code(NitroCliErrorEnum::InvalidStateTransition)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec![&from, &to]));
         }
+
+        Ok(())
     }
 
-    pub fn add_mem_region(&mut self, mem_region: EnclaveMemoryRegion) -> NitroCliResult<()> {
-        let rc = unsafe { libc::ioctl(self.enc_fd, NE_SET_USER_MEMORY_REGION as _, &mem_region) };
+    /// Close the enclave descriptor, reporting any failure to the caller.
+    pub fn try_close(&mut self) -> NitroCliResult<()> {
+        if self.enc_fd < 0 {
+            return Ok(());
+        }
+
+        let rc = unsafe { libc::close(self.enc_fd) };
+        // The descriptor must not be closed again, even if closing it has failed.
+        self.enc_fd = -1;
+        self.state = EnclaveState::Terminated;
         if rc < 0 {
             return Err(NitroCliFailure::new()
-                .add_subaction(format!("Could not add memory region: {}", rc))
-                .set_error_code(NitroCliErrorEnum::IoctlSetMemoryRegionFailure)
+                .add_subaction(format!(
+                    "Could not close enclave descriptor: {}",
+                    std::io::Error::last_os_error()
+                ))
+                .set_error_code(NitroCliErrorEnum::FileOperationFailure)
                 .set_file_and_line(file!(), line!()));
         }
 
         Ok(())
     }
 
+    /// Terminate the enclave by closing its descriptor, upon which the NE driver frees the
+    /// enclave slot and unpins the memory regions added to it, and report any failure to the
+    /// caller. The enclave is consumed, so it can't be used or closed again afterwards. Dropping
+    /// an enclave terminates it as well, but only logs failures.
+    pub fn terminate(mut self) -> NitroCliResult<()> {
+        self.try_close()
+            .map_err(|e| e.add_subaction(format!("Could not terminate enclave {}", self.slot_uid)))
+    }
+
+    /// Terminate the enclave on a best-effort basis, logging any failure.
+    fn release(&mut self) {
+        // Close enclave descriptor, without panicking since this is called on drop.
+        if let Err(e) = self.try_close() {
+            warn!("Failed to release enclave: {:?}", e.subactions);
+        }
+    }
+
+    /// Check that resources may be added to the enclave, moving it to the `to` state. The NE
+    /// driver rejects adding resources to a running enclave with an otherwise unhelpful error.
+    fn check_can_add(&self, resource: &str, to: EnclaveState) -> NitroCliResult<()> {
+        self.check_transition(to).map_err(|e| {
+            if self.state == EnclaveState::Running {
+                e.add_subaction(format!("Cannot add {} after enclave start", resource))
+            } else {
+                e.add_subaction(format!("Cannot add {} to the enclave", resource))
+            }
+        })
+    }
+
+    /// Get the state reached by adding memory to the enclave.
+    fn state_with_memory(&self) -> EnclaveState {
+        match self.state {
+            EnclaveState::VcpusAttached => EnclaveState::VcpusAttached,
+            _ => EnclaveState::MemoryAttached,
+        }
+    }
+
+    /// Add a memory region to the enclave in the next free slot, and return that slot.
+    pub fn add_mem_region(
+        &mut self,
+        mem_region: EnclaveMemoryRegion,
+    ) -> NitroCliResult<u32, NitroCliError> {
+        let mut slot = self.next_mem_slot;
+        while self.mem_regions.iter().any(|region| region.slot == slot) {
+            slot += 1;
+        }
+
+        self.add_mem_region_at(mem_region, slot)?;
+        self.next_mem_slot = slot + 1;
+
+        Ok(slot)
+    }
+
+    /// Add a memory region to the enclave in the given slot, which no other region may hold, and
+    /// return that slot. Slots are only tracked by `NitroEnclave`, since the NE driver adds each
+    /// region after the previous ones, so this is only needed for choosing how the regions are
+    /// reported.
+    pub fn add_mem_region_at(
+        &mut self,
+        mem_region: EnclaveMemoryRegion,
+        slot: u32,
+    ) -> NitroCliResult<u32, NitroCliError> {
+        let next_state = self.state_with_memory();
+        self.check_can_add("memory", next_state)?;
+        if self.mem_regions.iter().any(|region| region.slot == slot) {
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!("Memory slot {} already holds a region", slot))
+                .set_error_code(NitroCliErrorEnum::MemorySlotInUse)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec![&slot.to_string()])
+                .into());
+        }
+        self.check_no_overlap(&mem_region)?;
+        #[cfg(feature = "metrics")]
+        let started_at = Instant::now();
+        let mut user_mem_region = UserMemoryRegion {
+            flags: mem_region.flags,
+            memory_size: mem_region.mem_size,
+            userspace_addr: mem_region.mem_addr,
+        };
+        try_ne_ioctl(self.enc_fd, NeSetUserMemoryRegion, &mut user_mem_region)?;
+
+        #[cfg(feature = "metrics")]
+        self.timings.mem_regions.push(started_at.elapsed());
+        self.mem_regions.push(MemoryRegionInfo {
+            slot,
+            flags: mem_region.flags,
+            addr: mem_region.mem_addr,
+            size: mem_region.mem_size,
+        });
+        self.state = next_state;
+
+        Ok(slot)
+    }
+
+    /// Check that a memory region doesn't overlap any region which has already been added. The NE
+    /// driver rejects overlapping regions as well, but without telling which region is overlapped.
+    fn check_no_overlap(&self, mem_region: &EnclaveMemoryRegion) -> NitroCliResult<()> {
+        let start = mem_region.mem_addr;
+        let end = start.saturating_add(mem_region.mem_size);
+        let existing = self
+            .mem_regions
+            .iter()
+            .find(|region| start < region.addr + region.size && region.addr < end);
+
+        match existing {
+            Some(existing) => Err(NitroCliFailure::new()
+                .add_subaction(format!(
+                    "Memory region [{:#x}, {:#x}) overlaps region {} at [{:#x}, {:#x})",
+                    start,
+                    end,
+                    existing.slot,
+                    existing.addr,
+                    existing.addr + existing.size
+                ))
+                .set_error_code(NitroCliErrorEnum::RegionOverlap)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec![&existing.slot.to_string()])),
+            None => Ok(()),
+        }
+    }
+
+    /// Add several memory regions to the enclave. The NE driver has no ioctl for removing a
+    /// region, so if a region after the first one fails, the enclave descriptor is closed in order
+    /// for the driver to release the slot along with the regions added so far, instead of leaving
+    /// a partially configured enclave behind.
+    pub fn add_mem_regions(&mut self, regions: &[MemoryRegion]) -> NitroCliResult<()> {
+        self.add_enclave_mem_regions(regions.iter().map(EnclaveMemoryRegion::new_from).collect())
+    }
+
+    /// Add several memory regions to the enclave like `add_mem_regions()`, but merge each run
+    /// of virtually contiguous regions into a single region first, so that fewer ioctls are issued.
+    pub fn add_mem_regions_coalesced(&mut self, regions: &[MemoryRegion]) -> NitroCliResult<()> {
+        let coalesced = coalesce_mem_regions(regions);
+        debug!(
+            "Coalesced {} memory regions into {}, saving {} ioctl(s)",
+            regions.len(),
+            coalesced.len(),
+            regions.len() - coalesced.len()
+        );
+
+        self.add_enclave_mem_regions(coalesced)
+    }
+
+    fn add_enclave_mem_regions(&mut self, regions: Vec<EnclaveMemoryRegion>) -> NitroCliResult<()> {
+        let count = regions.len();
+        for (index, region) in regions.into_iter().enumerate() {
+            if let Err(err) = self.add_mem_region(region) {
+                let mut err = NitroCliFailure::from(err).add_subaction(format!(
+                    "Could not add memory region {} of {}",
+                    index, count
+                ));
+
+                if index > 0 {
+                    if let Err(close_err) = self.try_close() {
+                        err = err.add_subaction(format!(
+                            "Could not release the partially configured enclave: {:?}",
+                            close_err.subactions
+                        ));
+                    }
+                }
+
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn add_cpu(&mut self, cpu_id: u32) -> NitroCliResult<()> {
+        self.check_can_add("vCPUs", EnclaveState::VcpusAttached)?;
         let mut actual_cpu_id: u32 = cpu_id;
-        let rc = unsafe { libc::ioctl(self.enc_fd, NE_ADD_VCPU as _, &mut actual_cpu_id) };
-        if rc < 0 {
-            return Err(NitroCliFailure::new()
-                .add_subaction(format!("Could not add vCPU: {}", rc))
+        #[cfg(feature = "metrics")]
+        let started_at = Instant::now();
+        ne_ioctl(self.enc_fd, NeAddVcpu, &mut actual_cpu_id).map_err(|e| {
+            e.add_subaction("Could not add vCPU".to_string())
                 .set_error_code(NitroCliErrorEnum::IoctlAddVcpuFailure)
-                .set_file_and_line(file!(), line!()));
-        }
+        })?;
+
+        #[cfg(feature = "metrics")]
+        self.timings.vcpus.push(started_at.elapsed());
+        // The driver reports the CPU it has picked, if none has been requested.
+        self.vcpus.push(actual_cpu_id);
+        self.state = EnclaveState::VcpusAttached;
 
         Ok(())
     }
 
-    pub fn start(&mut self, start_info: EnclaveStartInfo) -> NitroCliResult<()> {
-        let rc = unsafe { libc::ioctl(self.enc_fd, NE_START_ENCLAVE as _, &start_info) };
-        if rc < 0 {
+    /// Add a vCPU after checking that it is part of the NE CPU pool and not already in use.
+    pub fn add_vcpu(&mut self, cpu_id: u32) -> NitroCliResult<()> {
+        self.check_can_add("vCPUs", EnclaveState::VcpusAttached)?;
+        if self.vcpus.contains(&cpu_id) {
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!(
+                    "The CPU with ID {} has already been added to the enclave",
+                    cpu_id
+                ))
+                .set_error_code(NitroCliErrorEnum::InvalidCpuConfiguration)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec!["cpu-ids", &cpu_id.to_string()]));
+        }
+
+        CpuInfo::new()
+            .and_then(|cpu_info| cpu_info.check_cpu_ids(&[cpu_id]))
+            .map_err(|e| e.add_subaction(format!("Could not validate vCPU {}", cpu_id)))?;
+
+        self.add_cpu(cpu_id)
+    }
+
+    /// Get the offset in enclave memory at which the NE driver requires the image to be loaded,
+    /// for an image of the kind given by `flags` (such as an EIF). The image must fit in the
+    /// enclave memory after this offset, as `check_image_fits()` verifies.
+    pub fn image_load_info(&self, flags: u64) -> NitroCliResult<ImageLoadInfo> {
+        let mut load_info = ImageLoadInfo {
+            flags,
+            memory_offset: 0,
+        };
+        ne_ioctl(self.enc_fd, NeGetImageLoadInfo, &mut load_info).map_err(|e| {
+            e.add_subaction("Could not get image load info".to_string())
+                .set_error_code(NitroCliErrorEnum::IoctlImageLoadInfoFailure)
+        })?;
+
+        Ok(load_info)
+    }
+
+    /// Get the memory regions which have been added to the enclave, in the order in which they
+    /// have been added.
+    pub fn memory_regions(&self) -> &[MemoryRegionInfo] {
+        &self.mem_regions
+    }
+
+    /// Get the IDs of the CPUs which have been added to the enclave.
+    pub fn assigned_vcpus(&self) -> &[u32] {
+        &self.vcpus
+    }
+
+    /// Start the enclave once memory and vCPUs have been added to it. The returned information
+    /// holds the CID assigned by the NE driver, while the slot UID is available from `slot_uid()`.
+    pub fn start(&mut self, flags: EnclaveStartFlags) -> NitroCliResult<EnclaveStartInfo> {
+        if self.mem_regions.is_empty() || self.vcpus.is_empty() {
             return Err(NitroCliFailure::new()
-                .add_subaction(format!("Could not start enclave: {}", rc))
+                .add_subaction(format!(
+                    "Cannot start an enclave with {} memory region(s) and {} vCPU(s)",
+                    self.mem_regions.len(),
+                    self.vcpus.len()
+                ))
                 .set_error_code(NitroCliErrorEnum::IoctlEnclaveStartFailure)
                 .set_file_and_line(file!(), line!()));
         }
 
-        Ok(())
+        let start_info = EnclaveStartInfo {
+            flags: flags.bits(),
+            enclave_cid: 0,
+        };
+        self.start_raw(start_info)
+    }
+
+    /// Start the enclave with the given vsock CID instead of one generated by the NE driver.
+    /// The CID must not be a well-known one, the CID of the parent VM or one which doesn't fit
+    /// 32 bits. A CID which another enclave has been started with results in a `CidInUse` error,
+    /// while an NE driver which can't select CIDs results in a `CidSelectionUnsupported` error.
+    pub fn start_with_cid(
+        &mut self,
+        flags: EnclaveStartFlags,
+        cid: u64,
+    ) -> NitroCliResult<EnclaveStartInfo> {
+        if cid <= VMADDR_CID_PARENT as u64 || cid >= u32::MAX as u64 {
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!(
+                    "CID {} is reserved or out of range, so it can't be used for an enclave",
+                    cid
+                ))
+                .set_error_code(NitroCliErrorEnum::InvalidArgument)
+                .add_info(vec!["enclave-cid", &cid.to_string()])
+                .set_file_and_line(file!(), line!()));
+        }
+
+        let start_info = EnclaveStartInfo {
+            flags: flags.bits(),
+            enclave_cid: cid,
+        };
+        self.start_raw(start_info)
+    }
+
+    /// Start the enclave and wait for it to signal that it has booted, for at most `timeout`
+    /// overall. If the enclave isn't ready in time, an `EnclaveStartTimeout` error is returned
+    /// and the partially-started enclave is terminated by closing its descriptor.
+    ///
+    /// The timeout is best-effort: the start ioctl can't always be interrupted, so it is only
+    /// enforced once the ioctl returns, when waiting for the boot heartbeat.
+    pub fn start_with_timeout(
+        &mut self,
+        flags: EnclaveStartFlags,
+        timeout: Duration,
+    ) -> NitroCliResult<EnclaveStartInfo> {
+        let started_at = Instant::now();
+        let listener =
+            VsockListener::bind(&VsockAddr::new(VMADDR_CID_PARENT, ENCLAVE_READY_VSOCK_PORT))
+                .map_err(|e| {
+                    NitroCliFailure::new()
+                        .add_subaction(format!("Could not bind the boot heartbeat listener: {}", e))
+                        .set_error_code(NitroCliErrorEnum::EnclaveBootFailure)
+                        .set_file_and_line(file!(), line!())
+                })?;
+        let start_info = self.start(flags)?;
+
+        let remaining = timeout.saturating_sub(started_at.elapsed());
+        let poll_timeout_ms = remaining.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if let Err(e) = enclave_ready(listener, poll_timeout_ms) {
+            let err = if e == EifLoaderError::VsockTimeoutError {
+                NitroCliFailure::new()
+                    .add_subaction(format!(
+                        "The enclave has not booted within {} ms",
+                        timeout.as_millis()
+                    ))
+                    .set_error_code(NitroCliErrorEnum::EnclaveStartTimeout)
+                    .add_info(vec![&timeout.as_millis().to_string()])
+            } else {
+                NitroCliFailure::new()
+                    .add_subaction(format!("Waiting on the enclave to boot failed: {:?}", e))
+                    .set_error_code(NitroCliErrorEnum::EnclaveBootFailure)
+            };
+            if let Err(close_err) = self.try_close() {
+                warn!(
+                    "Failed to terminate the enclave after its start failed: {:?}",
+                    close_err.subactions
+                );
+            }
+            return Err(err.set_file_and_line(file!(), line!()));
+        }
+
+        Ok(start_info)
+    }
+
+    /// Issue the start ioctl with the given information, only checking that the enclave may be
+    /// started in its current state.
+    pub fn start_raw(
+        &mut self,
+        mut start_info: EnclaveStartInfo,
+    ) -> NitroCliResult<EnclaveStartInfo> {
+        self.check_transition(EnclaveState::Running)
+            .map_err(|e| e.add_subaction("Could not start enclave".to_string()))?;
+        #[cfg(feature = "metrics")]
+        let started_at = Instant::now();
+        ne_ioctl(self.enc_fd, NeStartEnclave, &mut start_info).map_err(|e| {
+            let e = e.add_subaction("Could not start enclave".to_string());
+            match e.error_code {
+                NitroCliErrorEnum::CidInUse | NitroCliErrorEnum::CidSelectionUnsupported => e,
+                _ => e.set_error_code(NitroCliErrorEnum::IoctlEnclaveStartFailure),
+            }
+        })?;
+
+        #[cfg(feature = "metrics")]
+        {
+            self.timings.start = Some(started_at.elapsed());
+        }
+        self.cid = Some(start_info.enclave_cid);
+        self.start_flags = Some(start_info.flags);
+        self.state = EnclaveState::Running;
+
+        Ok(start_info)
+    }
+
+    /// Get the slot UID assigned by the NE driver, or 0 if the enclave was not obtained through
+    /// `NitroEnclavesDeviceDriver::create_enclave()`.
+    pub fn slot_uid(&self) -> u64 {
+        self.slot_uid
+    }
+
+    /// Get the vsock CID of the enclave. The NE driver only reports it in reply to the start
+    /// ioctl, so the value is recorded then and is unavailable before the enclave has started.
+    pub fn cid(&self) -> NitroCliResult<u64> {
+        self.cid.ok_or_else(|| {
+            NitroCliFailure::new()
+                .add_subaction("The enclave has not been started, so it has no CID".to_string())
+                .set_error_code(NitroCliErrorEnum::IoctlEnclaveStartFailure)
+                .set_file_and_line(file!(), line!())
+        })
+    }
+
+    /// Check if the enclave has been started in debug mode, in which its PCRs are all zeroes.
+    pub fn debug_mode(&self) -> bool {
+        self.start_flags.map_or(false, |flags| {
+            flags & NE_ENCLAVE_DEBUG_MODE == NE_ENCLAVE_DEBUG_MODE
+        })
+    }
+
+    /// Wait for at most `timeout` for the enclave to exit. The NE driver signals an exited
+    /// enclave by hanging up its descriptor, which is waited for with `poll()`, so the exit is
+    /// noticed as soon as it happens rather than on the next describe. An enclave which has
+    /// already been terminated is reported as exited right away.
+    pub fn wait_for_exit(&self, timeout: Duration) -> NitroCliResult<ExitStatus> {
+        match self.state {
+            EnclaveState::Running => (),
+            EnclaveState::Terminated => return Ok(ExitStatus::Exited),
+            _ => {
+                return Err(NitroCliFailure::new()
+                    .add_subaction(format!(
+                        "Cannot wait for the exit of an enclave in the {:?} state",
+                        self.state
+                    ))
+                    .set_error_code(NitroCliErrorEnum::IoctlEnclaveStartFailure)
+                    .set_file_and_line(file!(), line!()))
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut poll_fd = libc::pollfd {
+                fd: self.enc_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = remaining.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            let rc = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+            if rc < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(NitroCliFailure::new()
+                    .add_subaction(format!("Could not poll the enclave descriptor: {}", err))
+                    .set_error_code(NitroCliErrorEnum::EpollError)
+                    .set_file_and_line(file!(), line!()));
+            }
+
+            if poll_fd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+                return Err(NitroCliFailure::new()
+                    .add_subaction(format!(
+                        "The enclave descriptor reported error events {:#x}",
+                        poll_fd.revents
+                    ))
+                    .set_error_code(NitroCliErrorEnum::EpollError)
+                    .set_file_and_line(file!(), line!()));
+            }
+            if poll_fd.revents & (libc::POLLHUP | libc::POLLIN) != 0 {
+                debug!("Enclave {} has exited", self.slot_uid);
+                return Ok(ExitStatus::Exited);
+            }
+            // Poll may return slightly early, so time out only once the deadline has passed.
+            if Instant::now() >= deadline {
+                return Ok(ExitStatus::Running);
+            }
+        }
+    }
+
+    /// Get the host memory used by the memory regions added to the enclave so far, as reported
+    /// by `/proc/self/smaps`.
+    pub fn host_memory_stats(&self) -> NitroCliResult<MemStats> {
+        let regions: Vec<(u64, u64)> = self
+            .mem_regions
+            .iter()
+            .map(|region| (region.addr, region.size))
+            .collect();
+
+        get_host_memory_stats(&regions).map_err(|e| {
+            e.add_subaction(format!(
+                "Could not get the host memory of enclave {}",
+                self.slot_uid
+            ))
+        })
+    }
+
+    /// Get a summary of the resources attached to the enclave so far. No ioctl is issued, since
+    /// the resources are recorded as they are added.
+    pub fn resource_summary(&self) -> ResourceSummary {
+        ResourceSummary {
+            memory_bytes: self.mem_regions.iter().map(|region| region.size).sum(),
+            memory_regions: self.mem_regions.len(),
+            vcpu_ids: self.vcpus.clone(),
+            cid: self.cid,
+            debug_mode: self.debug_mode(),
+        }
+    }
+}
+
+/// The enclave descriptor may be used for ioctls which aren't wrapped by `NitroEnclave`, as
+/// long as the enclave is alive. It remains owned by the enclave, which closes it on drop or
+/// termination, so it must not be closed by the caller. It is -1 once the enclave has been closed.
+impl AsRawFd for NitroEnclave {
+    fn as_raw_fd(&self) -> RawFd {
+        self.enc_fd
+    }
+}
+
+/// Take ownership of the enclave descriptor, which the caller must close to terminate the enclave.
+/// Any memory which has been allocated on behalf of the enclave is unmapped, but the NE driver
+/// keeps the pages already added to the enclave until its descriptor is closed.
+impl IntoRawFd for NitroEnclave {
+    fn into_raw_fd(mut self) -> RawFd {
+        let enc_fd = self.enc_fd;
+        // The descriptor now belongs to the caller, so it must not be closed on drop.
+        self.enc_fd = -1;
+        enc_fd
     }
 }
 
@@ -163,51 +1079,419 @@ impl Drop for NitroEnclave {
     }
 }
 
+lazy_static! {
+    /// The resources claimed by the live leases of the current process.
+    static ref LEASE_CLAIMS: Mutex<LeaseClaims> = Mutex::new(LeaseClaims::default());
+}
+
+/// The resources claimed by leases, indexed by lease ID.
+#[derive(Default)]
+struct LeaseClaims {
+    /// The ID of the next lease.
+    next_id: u64,
+    /// The CPUs, memory and expiry time claimed by each lease.
+    claims: BTreeMap<u64, (Vec<u32>, u64, Instant)>,
+}
+
+/// A claim on a set of CPUs and an amount of memory, held for a bounded duration before an
+/// enclave is launched with them. The claim is released once the lease expires, is dropped or
+/// is converted into an enclave, so concurrent launches don't race for the same resources.
+pub struct ResourceLease {
+    /// The ID under which the claim is recorded.
+    id: u64,
+    /// The claimed CPU IDs.
+    cpus: Vec<u32>,
+    /// The claimed memory in bytes.
+    mem: u64,
+    /// The time at which the claim lapses.
+    expires_at: Instant,
+}
+
+impl ResourceLease {
+    /// Claim the given CPUs and memory (in bytes) for `ttl`. Fails if a CPU is claimed by
+    /// another live lease, or if the memory claimed by all live leases would exceed the memory
+    /// available in free hugepages.
+    pub fn new(cpus: Vec<u32>, mem: u64, ttl: Duration) -> NitroCliResult<Self> {
+        ResourceLease::claim(cpus, mem, ttl, available_hugepage_memory())
+    }
+
+    /// Claim resources as `new()` does, given the memory available for all leases.
+    fn claim(
+        cpus: Vec<u32>,
+        mem: u64,
+        ttl: Duration,
+        available_memory: u64,
+    ) -> NitroCliResult<Self> {
+        let mut leases = LEASE_CLAIMS.lock().map_err(|e| {
+            NitroCliFailure::new()
+                .add_subaction(format!("Could not lock the lease claims: {:?}", e))
+                .set_error_code(NitroCliErrorEnum::LockAcquireFailure)
+                .set_file_and_line(file!(), line!())
+        })?;
+        let now = Instant::now();
+        leases
+            .claims
+            .retain(|_, (_, _, expires_at)| *expires_at > now);
+
+        if let Some(cpu_id) = cpus.iter().find(|cpu_id| {
+            leases
+                .claims
+                .values()
+                .any(|(claimed, _, _)| claimed.contains(cpu_id))
+        }) {
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!("The CPU with ID {} is already leased", cpu_id))
+                .set_error_code(NitroCliErrorEnum::InvalidCpuConfiguration)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec!["cpu-ids", &cpu_id.to_string()]));
+        }
+
+        let claimed_memory: u64 = leases.claims.values().map(|(_, mem, _)| mem).sum();
+        if claimed_memory.saturating_add(mem) > available_memory {
+            return Err(NitroCliFailure::new()
+                .add_subaction(format!(
+                    "Cannot lease {} MiB of memory: {} MiB of the {} MiB available are already leased",
+                    mem / MiB,
+                    claimed_memory / MiB,
+                    available_memory / MiB
+                ))
+                .set_error_code(NitroCliErrorEnum::InsufficientMemoryAvailable)
+                .set_file_and_line(file!(), line!())
+                .add_info(vec!["memory", &(mem / MiB).to_string()]));
+        }
+
+        let id = leases.next_id;
+        leases.next_id += 1;
+        let expires_at = now + ttl;
+        leases.claims.insert(id, (cpus.clone(), mem, expires_at));
+
+        Ok(ResourceLease {
+            id,
+            cpus,
+            mem,
+            expires_at,
+        })
+    }
+
+    /// Get the claimed CPU IDs.
+    pub fn cpus(&self) -> &[u32] {
+        &self.cpus
+    }
+
+    /// Get the claimed memory in bytes.
+    pub fn memory(&self) -> u64 {
+        self.mem
+    }
+
+    /// Check if the claim has lapsed, after which its resources may be leased again.
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+
+    /// Create an enclave with the leased CPUs, releasing the claim. The leased memory is for the
+    /// caller to add, as the lease only accounts for it.
+    pub fn into_enclave(
+        self,
+        driver: &mut NitroEnclavesDeviceDriver,
+    ) -> NitroCliResult<NitroEnclave> {
+        if self.is_expired() {
+            return Err(NitroCliFailure::new()
+                .add_subaction("The resource lease has expired".to_string())
+                .set_error_code(NitroCliErrorEnum::InvalidArgument)
+                .set_file_and_line(file!(), line!()));
+        }
+
+        let mut enclave = driver.create_enclave()?;
+        for cpu_id in &self.cpus {
+            enclave
+                .add_cpu(*cpu_id)
+                .map_err(|e| e.add_subaction(format!("Could not add leased vCPU {}", cpu_id)))?;
+        }
+
+        Ok(enclave)
+    }
+}
+
+impl Drop for ResourceLease {
+    fn drop(&mut self) {
+        match LEASE_CLAIMS.lock() {
+            Ok(mut leases) => {
+                leases.claims.remove(&self.id);
+            }
+            Err(e) => warn!("Failed to release resource lease: {:?}", e),
+        }
+    }
+}
+
+/// Words which indicate that a dmesg line reports a problem.
+const DMESG_SEVERITY_CHECKS: [&str; 4] = ["WARNING", "BUG", "ERROR", "FAILURE"];
+
+/// Words which identify dmesg lines logged by the NE driver or its device.
+const DMESG_NE_CHECKS: [&str; 2] = [
+    "nitro_enclaves",
+    // NE PCI device identifier
+    "pci 0000:00:02.0",
+];
+
+/// The kernel log device, which can be read when running `dmesg` isn't possible.
+const KMSG_PATH: &str = "/dev/kmsg";
+
 // Class for checking the dmesg logs.
 pub struct CheckDmesg {
     recorded_line: usize,
+    /// The command which prints the kernel log.
+    command: Vec<String>,
+    /// The kernel log device read when the command fails.
+    kmsg_path: PathBuf,
 }
 
 impl CheckDmesg {
     pub fn new() -> NitroCliResult<Self> {
-        Ok(CheckDmesg { recorded_line: 0 })
+        Ok(CheckDmesg {
+            recorded_line: 0,
+            command: vec!["dmesg".to_string()],
+            kmsg_path: PathBuf::from(KMSG_PATH),
+        })
+    }
+
+    /// Obtain the log lines from dmesg, falling back to reading the kernel log device. A
+    /// `FilePermissionsError` is returned if the kernel log is restricted (such as with
+    /// `kernel.dmesg_restrict=1`) and a `FileOperationFailure` if it can't be read otherwise.
+    fn get_dmesg_lines(&self) -> NitroCliResult<Vec<String>> {
+        let dmesg_err = match self.run_dmesg() {
+            Ok(lines) => return Ok(lines),
+            Err(err) => err,
+        };
+
+        match CheckDmesg::read_kmsg(&self.kmsg_path) {
+            Ok(lines) => Ok(lines),
+            Err(kmsg_err) => {
+                let permission_denied = dmesg_err.kind() == ErrorKind::PermissionDenied
+                    || kmsg_err.kind() == ErrorKind::PermissionDenied;
+                let error_code = if permission_denied {
+                    NitroCliErrorEnum::FilePermissionsError
+                } else {
+                    NitroCliErrorEnum::FileOperationFailure
+                };
+
+                Err(NitroCliFailure::new()
+                    .add_subaction(format!(
+                        "Could not read the kernel log (dmesg: {}, {}: {})",
+                        dmesg_err,
+                        self.kmsg_path.display(),
+                        kmsg_err
+                    ))
+                    .set_error_code(error_code)
+                    .set_file_and_line(file!(), line!()))
+            }
+        }
+    }
+
+    /// Run the dmesg command and split its output into lines.
+    fn run_dmesg(&self) -> std::io::Result<Vec<String>> {
+        let dmesg = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .output()?;
+
+        if !dmesg.status.success() {
+            let stderr = String::from_utf8_lossy(&dmesg.stderr).trim().to_string();
+            let kind = if stderr.contains("Operation not permitted")
+                || stderr.contains("Permission denied")
+            {
+                ErrorKind::PermissionDenied
+            } else {
+                ErrorKind::Other
+            };
+            return Err(std::io::Error::new(
+                kind,
+                format!("{} ({})", dmesg.status, stderr),
+            ));
+        }
+
+        let message = String::from_utf8_lossy(&dmesg.stdout);
+        // The output ends with a newline, which must not count as an (empty) line of its own.
+        Ok(message.lines().map(|s| s.to_string()).collect())
     }
 
-    /// Obtain the log lines from dmesg.
-    fn get_dmesg_lines(&mut self) -> NitroCliResult<Vec<String>> {
-        let dmesg = Command::new("dmesg")
-            .output()
-            .expect("Failed to execute dmesg process");
-        let message = String::from_utf8(dmesg.stdout).unwrap();
-        let lines: Vec<String> = message.split('\n').map(|s| s.to_string()).collect();
+    /// Read the records currently held by the kernel log device, formatted as dmesg does.
+    fn read_kmsg(path: &Path) -> std::io::Result<Vec<String>> {
+        let mut kmsg = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        let mut lines = Vec::new();
+        let mut record = vec![0u8; 8192];
+
+        loop {
+            // Each read returns a single record, until none is left.
+            let len = match kmsg.read(&mut record) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // The record has been overwritten before it could be read.
+                Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+                Err(e) => return Err(e),
+            };
+            let record = String::from_utf8_lossy(&record[..len]);
+            if let Some(line) = CheckDmesg::format_kmsg_record(&record) {
+                lines.push(line);
+            }
+        }
+
         Ok(lines)
     }
 
+    /// Format a `/dev/kmsg` record (`<prio>,<seq>,<usecs>,<flags>;<message>`) as a dmesg line.
+    fn format_kmsg_record(record: &str) -> Option<String> {
+        let (header, message) = record.split_once(';')?;
+        let usecs = header.split(',').nth(2)?.parse::<u64>().ok()?;
+        // Continuation lines hold the record's key-value properties.
+        let message = message.lines().next().unwrap_or("");
+
+        Some(format!(
+            "[{:5}.{:06}] {}",
+            usecs / 1_000_000,
+            usecs % 1_000_000,
+            message
+        ))
+    }
+
+    /// Obtain the log lines from dmesg, or `None` with a warning if the kernel log is restricted,
+    /// in which case the dmesg checks are skipped.
+    fn get_checked_lines(&self) -> NitroCliResult<Option<Vec<String>>> {
+        match self.get_dmesg_lines() {
+            Ok(lines) => Ok(Some(lines)),
+            Err(err) if err.error_code == NitroCliErrorEnum::FilePermissionsError => {
+                warn!("Skipping the dmesg check: {:?}", err.subactions);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Record the current number of lines from dmesg.
     pub fn record_current_line(&mut self) -> NitroCliResult<()> {
-        self.recorded_line = self.get_dmesg_lines().unwrap().len();
+        if let Some(lines) = self.get_checked_lines()? {
+            self.recorded_line = lines.len();
+        }
         Ok(())
     }
 
     /// Verify if dmesg number of lines changed from the last recorded line.
     pub fn expect_no_changes(&mut self) -> NitroCliResult<()> {
-        let checks = [
-            "WARNING",
-            "BUG",
-            "ERROR",
-            "FAILURE",
-            "nitro_enclaves",
-            // NE PCI device identifier
-            "pci 0000:00:02.0",
-        ];
-        let lines = self.get_dmesg_lines().unwrap();
+        let mut checks = DMESG_SEVERITY_CHECKS.to_vec();
+        checks.extend_from_slice(&DMESG_NE_CHECKS);
+        let lines = match self.get_checked_lines()? {
+            Some(lines) => lines,
+            None => return Ok(()),
+        };
+
+        CheckDmesg::check_lines(
+            CheckDmesg::lines_after(&lines, self.recorded_line).into_iter(),
+            &checks,
+        )
+    }
+
+    /// Get the dmesg lines which have been added since the last recorded line.
+    pub fn new_lines(&self) -> NitroCliResult<Vec<String>> {
+        let lines = self.get_dmesg_lines()?;
+
+        Ok(CheckDmesg::lines_after(&lines, self.recorded_line)
+            .into_iter()
+            .map(|(_, line)| line.clone())
+            .collect())
+    }
+
+    /// Verify that none of the dmesg lines containing `prefix` which have been added since the
+    /// last recorded line report a problem. Lines from other subsystems are ignored.
+    pub fn expect_no_changes_for_prefix(&mut self, prefix: &str) -> NitroCliResult<()> {
+        let lines = match self.get_checked_lines()? {
+            Some(lines) => lines,
+            None => return Ok(()),
+        };
+
+        CheckDmesg::check_lines(
+            CheckDmesg::lines_after(&lines, self.recorded_line)
+                .into_iter()
+                .filter(|(_, line)| line.contains(prefix)),
+            &DMESG_SEVERITY_CHECKS,
+        )
+    }
 
-        for line in lines.iter().skip(self.recorded_line) {
+    /// Verify that no problems have been reported in dmesg since the given kernel uptime, as
+    /// shown in the timestamp prefix of each line (such as `[ 1234.567890]`).
+    pub fn expect_no_changes_since(&mut self, kernel_uptime_secs: f64) -> NitroCliResult<()> {
+        let mut checks = DMESG_SEVERITY_CHECKS.to_vec();
+        checks.extend_from_slice(&DMESG_NE_CHECKS);
+        let lines = match self.get_checked_lines()? {
+            Some(lines) => lines,
+            None => return Ok(()),
+        };
+
+        CheckDmesg::check_lines(
+            CheckDmesg::lines_since(&lines, kernel_uptime_secs).into_iter(),
+            &checks,
+        )
+    }
+
+    /// Get the current kernel uptime, in the same unit as dmesg timestamps.
+    pub fn kernel_uptime_secs() -> NitroCliResult<f64> {
+        std::fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|uptime| uptime.split_whitespace().next()?.parse::<f64>().ok())
+            .ok_or_else(|| {
+                NitroCliFailure::new()
+                    .add_subaction("Could not read the kernel uptime".to_string())
+                    .set_error_code(NitroCliErrorEnum::FileOperationFailure)
+                    .set_file_and_line(file!(), line!())
+                    .add_info(vec!["/proc/uptime", "Read"])
+            })
+    }
+
+    /// Parse the timestamp prefix of a dmesg line, in seconds.
+    fn parse_timestamp(line: &str) -> Option<f64> {
+        let (timestamp, _) = line.strip_prefix('[')?.split_once(']')?;
+        timestamp.trim().parse::<f64>().ok()
+    }
+
+    /// Get the indexed lines which follow the first `recorded_line` lines.
+    fn lines_after(lines: &[String], recorded_line: usize) -> Vec<(usize, &String)> {
+        lines.iter().enumerate().skip(recorded_line).collect()
+    }
+
+    /// Get the indexed lines logged at or after `since`. Lines without a timestamp, such as
+    /// continuation lines, take the timestamp of the closest timestamped line before them.
+    fn lines_since(lines: &[String], since: f64) -> Vec<(usize, &String)> {
+        let mut last_timestamp = None;
+
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                if let Some(timestamp) = CheckDmesg::parse_timestamp(line) {
+                    last_timestamp = Some(timestamp);
+                }
+                last_timestamp.map_or(false, |timestamp| timestamp >= since)
+            })
+            .collect()
+    }
+
+    /// Fail on the first of the indexed lines which contains any of the checked words.
+    fn check_lines<'a, I>(lines: I, checks: &[&str]) -> NitroCliResult<()>
+    where
+        I: Iterator<Item = (usize, &'a String)>,
+    {
+        for (index, line) in lines {
             let upper_line = line.to_uppercase();
             for word in checks.iter() {
                 if upper_line.contains(&word.to_uppercase()) {
                     return Err(NitroCliFailure::new()
-                        .add_subaction(format!("Dmesg line: {} contains: {}", line, word))
+                        .add_subaction(format!(
+                            "Dmesg line {}: {} contains: {}",
+                            index + 1,
+                            line,
+                            word
+                        ))
                         .set_error_code(NitroCliErrorEnum::IoctlFailure)
                         .set_file_and_line(file!(), line!()));
                 }
@@ -221,10 +1505,86 @@ impl CheckDmesg {
 #[cfg(test)]
 mod test_dev_driver {
     use super::*;
+    use std::os::unix::io::FromRawFd;
+
+    #[cfg(feature = "mock")]
+    use nitro_cli::enclave_proc::ne_backend::{set_ne_ioctl_backend, MockBackend};
+    #[cfg(feature = "mock")]
+    use nitro_cli::enclave_proc::resource_manager::{
+        check_image_fits, NE_EIF_IMAGE, NE_IOCTL_MAX_EINTR_RETRIES,
+    };
+    #[cfg(feature = "mock")]
+    use std::rc::Rc;
+
+    /// The CPU pool of the mock NE driver.
+    #[cfg(feature = "mock")]
+    const MOCK_CPU_POOL: [u32; 4] = [1, 2, 3, 4];
+
+    /// Open the NE device which the tests run against. With the `mock` feature, its ioctls are
+    /// simulated for the current thread.
+    #[cfg(not(feature = "mock"))]
+    fn open_ne_device() -> NitroEnclavesDeviceDriver {
+        NitroEnclavesDeviceDriver::new().expect("Failed to open NE device")
+    }
+
+    /// Open the NE device which the tests run against. With the `mock` feature, its ioctls are
+    /// simulated for the current thread.
+    #[cfg(feature = "mock")]
+    fn open_ne_device() -> NitroEnclavesDeviceDriver {
+        set_ne_ioctl_backend(Rc::new(MockBackend::new(MOCK_CPU_POOL.to_vec())));
+        NitroEnclavesDeviceDriver::with_path("/dev/null").unwrap()
+    }
+
+    /// Get the CPUs of the NE CPU pool which may be added to enclaves.
+    #[cfg(not(feature = "mock"))]
+    fn ne_cpu_candidates() -> Vec<u32> {
+        CpuInfo::new()
+            .expect("Failed to obtain CpuInfo.")
+            .get_cpu_candidates()
+    }
+
+    /// Get the CPUs of the NE CPU pool which may be added to enclaves.
+    #[cfg(feature = "mock")]
+    fn ne_cpu_candidates() -> Vec<u32> {
+        MOCK_CPU_POOL.to_vec()
+    }
+
+    /// Allocate a memory region which can be added to an enclave.
+    #[cfg(not(feature = "mock"))]
+    fn enclave_mem_region(
+        page_flag: libc::c_int,
+        eager_fault: EagerFault,
+    ) -> NitroCliResult<MemoryRegion> {
+        MemoryRegion::new(page_flag, eager_fault)
+    }
+
+    /// Allocate a memory region which can be added to an enclave. The mock driver doesn't need
+    /// hugepages, so the memory is faulted in as it gets touched.
+    #[cfg(feature = "mock")]
+    fn enclave_mem_region(
+        page_flag: libc::c_int,
+        _eager_fault: EagerFault,
+    ) -> NitroCliResult<MemoryRegion> {
+        let size = if page_flag == libc::MAP_HUGE_32MB {
+            32 * MiB
+        } else {
+            2 * MiB
+        };
+        Ok(mock_mem_region(size))
+    }
 
     #[test]
     pub fn test_ne_dev_open() {
-        let mut driver = NitroEnclavesDeviceDriver::new().expect("Failed to open NE device");
+        // A fixture device node may be provided when the NE driver isn't loaded.
+        let device_path =
+            std::env::var("NE_DEVICE_PATH").unwrap_or_else(|_| NE_DEVICE_PATH.to_string());
+        if !Path::new(&device_path).exists() {
+            eprintln!("Skipping test: {} does not exist.", device_path);
+            return;
+        }
+
+        let mut driver =
+            NitroEnclavesDeviceDriver::with_path(&device_path).expect("Failed to open NE device");
         let enc_fd = unsafe { libc::ioctl(driver.file.as_raw_fd(), NE_CREATE_VM as _, 0) };
         assert!(
             enc_fd < 0,
@@ -239,6 +1599,11 @@ mod test_dev_driver {
             "Should not have been able to create enclave with invalid ioctl"
         );
 
+        // Only the actual NE driver can allocate enclave slots.
+        if device_path != NE_DEVICE_PATH {
+            return;
+        }
+
         let mut slot_alloc_num: u64 = 1;
         if let Ok(value) = std::env::var("NE_SLOT_ALLOC_NUM") {
             if let Ok(value) = value.parse::<u64>() {
@@ -259,17 +1624,456 @@ mod test_dev_driver {
         check_dmesg.expect_no_changes().unwrap();
     }
 
+    #[test]
+    pub fn test_ne_dev_with_path() {
+        let error = NitroEnclavesDeviceDriver::with_path("/nonexistent/nitro_enclaves")
+            .err()
+            .unwrap();
+        assert_eq!(error.additional_info[0], "/nonexistent/nitro_enclaves");
+
+        let driver = NitroEnclavesDeviceDriver::with_path("/dev/null");
+        assert!(driver.is_ok());
+    }
+
+    #[test]
+    pub fn test_ne_dev_open_with_retry() {
+        if std::path::Path::new(NE_DEVICE_PATH).exists() {
+            return;
+        }
+
+        // A missing device is retried, with the delay applied between attempts.
+        let start = Instant::now();
+        let result = NitroEnclavesDeviceDriver::new_with_retry(3, Duration::from_millis(20));
+        assert!(result.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert!(result.err().unwrap().subactions[0].contains("after 3 attempt(s)"));
+    }
+
+    #[test]
+    pub fn test_enclave_add_vcpu_twice() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        enclave.vcpus.push(1);
+
+        let result = enclave.add_vcpu(1);
+        assert_eq!(
+            result.err().unwrap().error_code,
+            NitroCliErrorEnum::InvalidCpuConfiguration
+        );
+        assert_eq!(enclave.assigned_vcpus(), &[1]);
+    }
+
+    #[test]
+    pub fn test_resource_lease() {
+        let lease = ResourceLease::claim(
+            vec![1001, 1002],
+            64 * MiB,
+            Duration::from_secs(60),
+            128 * MiB,
+        )
+        .unwrap();
+        assert_eq!(lease.cpus(), &[1001, 1002]);
+        assert_eq!(lease.memory(), 64 * MiB);
+        assert!(!lease.is_expired());
+
+        // Leased CPUs and memory can't be leased again.
+        let err = ResourceLease::claim(vec![1002], 0, Duration::from_secs(60), 128 * MiB)
+            .err()
+            .unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidCpuConfiguration);
+        assert_eq!(err.additional_info, vec!["cpu-ids", "1002"]);
+        let err = ResourceLease::claim(vec![1003], 96 * MiB, Duration::from_secs(60), 128 * MiB)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+
+        // Dropping a lease releases its claim.
+        drop(lease);
+        let lease =
+            ResourceLease::claim(vec![1002], 96 * MiB, Duration::from_millis(10), 128 * MiB)
+                .unwrap();
+
+        // So does its expiry.
+        thread::sleep(Duration::from_millis(20));
+        assert!(lease.is_expired());
+        let other =
+            ResourceLease::claim(vec![1002], 96 * MiB, Duration::from_secs(60), 128 * MiB).unwrap();
+        drop(lease);
+        assert!(ResourceLease::claim(vec![1002], 0, Duration::from_secs(60), 128 * MiB).is_err());
+        drop(other);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_resource_lease_into_enclave() {
+        let mut driver = open_ne_device();
+        let lease = ResourceLease::claim(vec![3, 4], 0, Duration::from_secs(60), 0).unwrap();
+        let enclave = lease.into_enclave(&mut driver).unwrap();
+        assert_eq!(enclave.assigned_vcpus(), &[3, 4]);
+        assert_eq!(enclave.state(), EnclaveState::VcpusAttached);
+
+        // The claim is released once the enclave holds the CPUs.
+        let lease = ResourceLease::claim(vec![3], 0, Duration::from_millis(1), 0).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        let err = lease.into_enclave(&mut driver).err().unwrap();
+        assert_eq!(err.subactions, vec!["The resource lease has expired"]);
+    }
+
+    #[test]
+    pub fn test_check_dmesg_lines() {
+        let lines: Vec<String> = vec![
+            "usb 1-1: ERROR: device descriptor read".to_string(),
+            "nitro_enclaves: Enclave started".to_string(),
+            "nitro_enclaves: WARNING: slot not freed".to_string(),
+        ];
+
+        // Unrelated lines are skipped once filtered out.
+        let result = CheckDmesg::check_lines(
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.contains("nitro_enclaves")),
+            &DMESG_SEVERITY_CHECKS,
+        );
+        assert_eq!(
+            result.err().unwrap().subactions[0],
+            "Dmesg line 3: nitro_enclaves: WARNING: slot not freed contains: WARNING"
+        );
+
+        let result = CheckDmesg::check_lines(
+            lines.iter().enumerate().take(2).skip(1),
+            &DMESG_SEVERITY_CHECKS,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    pub fn test_check_dmesg_restricted() {
+        let check_dmesg = CheckDmesg {
+            recorded_line: 0,
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "echo 'dmesg: read kernel buffer failed: Operation not permitted' >&2; exit 1"
+                    .to_string(),
+            ],
+            kmsg_path: PathBuf::from("/nonexistent/kmsg"),
+        };
+
+        let err = check_dmesg.get_dmesg_lines().unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FilePermissionsError);
+        assert!(err.subactions[0].contains("Operation not permitted"));
+
+        // The checks are skipped instead of failing.
+        let mut check_dmesg = check_dmesg;
+        check_dmesg.record_current_line().unwrap();
+        check_dmesg.expect_no_changes().unwrap();
+        check_dmesg
+            .expect_no_changes_for_prefix("nitro_enclaves")
+            .unwrap();
+        check_dmesg.expect_no_changes_since(0.0).unwrap();
+
+        // Other failures are still reported.
+        check_dmesg.command = vec!["false".to_string()];
+        let err = check_dmesg.expect_no_changes().unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+
+        assert_eq!(
+            CheckDmesg::format_kmsg_record(
+                "6,1234,10500000,-;nitro_enclaves: Enclave started\n SUBSYSTEM=pci\n"
+            ),
+            Some("[   10.500000] nitro_enclaves: Enclave started".to_string())
+        );
+        assert_eq!(CheckDmesg::format_kmsg_record("malformed"), None);
+    }
+
+    #[test]
+    pub fn test_check_dmesg_lines_after() {
+        let lines: Vec<String> = vec![
+            "nitro_enclaves: Initialized".to_string(),
+            "nitro_enclaves: Enclave started".to_string(),
+            "nitro_enclaves: Enclave stopped".to_string(),
+        ];
+
+        let new_lines: Vec<&String> = CheckDmesg::lines_after(&lines, 1)
+            .into_iter()
+            .map(|(index, line)| {
+                assert_eq!(&lines[index], line);
+                line
+            })
+            .collect();
+        assert_eq!(
+            new_lines,
+            vec![
+                "nitro_enclaves: Enclave started",
+                "nitro_enclaves: Enclave stopped"
+            ]
+        );
+        assert!(CheckDmesg::lines_after(&lines, 3).is_empty());
+        assert!(CheckDmesg::lines_after(&lines, 4).is_empty());
+    }
+
+    #[test]
+    pub fn test_check_dmesg_lines_since() {
+        let lines: Vec<String> = vec![
+            "[   10.000000] nitro_enclaves: Initialized".to_string(),
+            "[   20.500000] nitro_enclaves: Enclave started".to_string(),
+            "  continuation of the previous line".to_string(),
+            "[  100.000000] usb 1-1: new device".to_string(),
+        ];
+
+        assert_eq!(CheckDmesg::parse_timestamp(&lines[1]), Some(20.5));
+        assert_eq!(CheckDmesg::parse_timestamp(&lines[2]), None);
+
+        let indices: Vec<usize> = CheckDmesg::lines_since(&lines, 20.5)
+            .iter()
+            .map(|(index, _)| *index)
+            .collect();
+        assert_eq!(indices, vec![1, 2, 3]);
+        assert!(CheckDmesg::lines_since(&lines, 100.5).is_empty());
+    }
+
+    #[test]
+    pub fn test_enclave_try_close() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        assert!(enclave.cid().is_err());
+        assert_eq!(enclave.state(), EnclaveState::SlotAllocated);
+
+        // Closing an already closed descriptor must not fail.
+        assert!(enclave.try_close().is_ok());
+        assert_eq!(enclave.state(), EnclaveState::Terminated);
+        assert!(enclave.try_close().is_ok());
+
+        // A terminated enclave can't be reused.
+        let err = enclave.add_cpu(1).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidStateTransition);
+        assert_eq!(err.additional_info, vec!["Terminated", "VcpusAttached"]);
+        let err = enclave.start_raw(EnclaveStartInfo::default()).unwrap_err();
+        assert_eq!(err.additional_info, vec!["Terminated", "Running"]);
+
+        // A descriptor that cannot be closed is reported, and dropping does not panic.
+        let mut enclave = NitroEnclave::new(RawFd::MAX).unwrap();
+        assert!(enclave.try_close().is_err());
+        drop(NitroEnclave::new(RawFd::MAX).unwrap());
+    }
+
+    #[test]
+    pub fn test_enclave_wait_for_exit() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let write_end = unsafe { File::from_raw_fd(fds[1]) };
+        let mut enclave = NitroEnclave::new(fds[0]).unwrap();
+
+        let err = enclave.wait_for_exit(Duration::from_millis(0)).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IoctlEnclaveStartFailure);
+
+        // The NE driver hangs up the descriptor of an exited enclave, like a closed pipe does.
+        enclave.state = EnclaveState::Running;
+        let status = enclave.wait_for_exit(Duration::from_millis(50)).unwrap();
+        assert_eq!(status, ExitStatus::Running);
+
+        let closer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(write_end);
+        });
+        let status = enclave.wait_for_exit(Duration::from_secs(10)).unwrap();
+        assert_eq!(status, ExitStatus::Exited);
+        closer.join().unwrap();
+
+        enclave.try_close().unwrap();
+        let status = enclave.wait_for_exit(Duration::from_secs(10)).unwrap();
+        assert_eq!(status, ExitStatus::Exited);
+    }
+
+    #[test]
+    pub fn test_enclave_terminate() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let enclave = NitroEnclave::new(fd).unwrap();
+        assert!(enclave.terminate().is_ok());
+
+        let err = NitroEnclave::new(RawFd::MAX)
+            .unwrap()
+            .terminate()
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::FileOperationFailure);
+        assert_eq!(err.subactions[1], "Could not terminate enclave 0");
+    }
+
+    #[test]
+    pub fn test_enclave_add_mem_regions() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        // The regions have never been mapped, so they must not be unmapped either.
+        let regions = std::mem::ManuallyDrop::new([MemoryRegion::new_with(0, 2 * MiB, 2 * MiB)]);
+
+        assert!(enclave.add_mem_regions(&[]).is_ok());
+
+        // A failure on the first region leaves the enclave untouched.
+        let err = enclave.add_mem_regions(&regions[..]).unwrap_err();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::IoctlSetMemoryRegionFailure
+        );
+        assert_eq!(
+            err.subactions.last().unwrap(),
+            "Could not add memory region 0 of 1"
+        );
+        assert!(enclave.enc_fd >= 0);
+    }
+
+    #[test]
+    pub fn test_capacity_report() {
+        let report = CapacityReport::new(256 * MiB, 2, 512 * MiB, 4);
+        assert!(report.is_satisfiable());
+
+        let report = CapacityReport::new(GiB, 4, 512 * MiB, 2);
+        assert!(!report.is_satisfiable());
+        assert_eq!(
+            report.shortfalls,
+            vec![
+                CapacityShortfall::Memory {
+                    requested: GiB,
+                    available: 512 * MiB
+                },
+                CapacityShortfall::Vcpus {
+                    requested: 4,
+                    available: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_coalesce_mem_regions() {
+        // The regions have never been mapped, so they must not be unmapped either.
+        let regions = std::mem::ManuallyDrop::new([
+            MemoryRegion::new_with(0, 2 * MiB, 2 * MiB),
+            MemoryRegion::new_with(0, 4 * MiB, 2 * MiB),
+            MemoryRegion::new_with(0, 8 * MiB, 4 * MiB),
+            MemoryRegion::new_with(0, 12 * MiB, 2 * MiB),
+            MemoryRegion::new_with(0, 2 * MiB, 2 * MiB),
+        ]);
+
+        let coalesced: Vec<(u64, u64)> = coalesce_mem_regions(&regions[..])
+            .iter()
+            .map(|region| (region.mem_addr, region.mem_size))
+            .collect();
+        assert_eq!(
+            coalesced,
+            vec![(2 * MiB, 4 * MiB), (8 * MiB, 6 * MiB), (2 * MiB, 2 * MiB)]
+        );
+        assert!(coalesce_mem_regions(&[]).is_empty());
+
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        let err = enclave.add_mem_regions_coalesced(&regions[..]).unwrap_err();
+        assert_eq!(
+            err.subactions.last().unwrap(),
+            "Could not add memory region 0 of 3"
+        );
+    }
+
+    #[test]
+    pub fn test_enclave_resource_summary() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        assert_eq!(enclave.resource_summary(), ResourceSummary::default());
+
+        // Failed additions are not recorded.
+        assert!(enclave
+            .add_mem_region(EnclaveMemoryRegion::new(0, 2 * MiB, 2 * MiB))
+            .is_err());
+        assert!(enclave.add_cpu(1).is_err());
+        assert_eq!(enclave.resource_summary(), ResourceSummary::default());
+
+        enclave.mem_regions = (0..2)
+            .map(|slot| MemoryRegionInfo {
+                slot,
+                flags: 0,
+                addr: (slot as u64 + 1) * 2 * MiB,
+                size: 2 * MiB,
+            })
+            .collect();
+        enclave.vcpus = vec![1, 3];
+        enclave.cid = Some(16);
+        enclave.start_flags = Some(NE_ENCLAVE_DEBUG_MODE);
+
+        let summary = enclave.resource_summary();
+        assert_eq!(
+            summary.to_string(),
+            "memory: 4 MiB in 2 region(s), cpu-ids: [1, 3], enclave-cid: 16, debug-mode: true"
+        );
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "memory_bytes": 4 * MiB,
+                "memory_regions": 2,
+                "vcpu_ids": [1, 3],
+                "cid": 16,
+                "debug_mode": true,
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_enclave_start_preconditions() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut enclave = NitroEnclave::new(fd).unwrap();
+        assert_eq!(enclave.slot_uid(), 0);
+
+        let err = enclave.start(EnclaveStartFlags::DEBUG_MODE).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IoctlEnclaveStartFailure);
+        assert!(err.subactions[0].contains("0 memory region(s) and 0 vCPU(s)"));
+
+        enclave.mem_regions = vec![MemoryRegionInfo {
+            slot: 0,
+            flags: 0,
+            addr: 2 * MiB,
+            size: 2 * MiB,
+        }];
+        let err = enclave.start(EnclaveStartFlags::default()).unwrap_err();
+        assert!(err.subactions[0].contains("1 memory region(s) and 0 vCPU(s)"));
+
+        // Only an enclave with vCPUs may be started.
+        let err = enclave.start_raw(EnclaveStartInfo::default()).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InvalidStateTransition);
+        assert_eq!(
+            err.subactions[0],
+            "Invalid enclave state transition from SlotAllocated to Running"
+        );
+
+        // Once both are present, the request reaches the driver.
+        enclave.vcpus = vec![1];
+        enclave.state = EnclaveState::VcpusAttached;
+        let err = enclave.start(EnclaveStartFlags::default()).unwrap_err();
+        assert!(err.subactions[0].starts_with("NE_START_ENCLAVE ioctl failed"));
+        assert!(enclave.cid().is_err());
+        assert_eq!(enclave.state(), EnclaveState::VcpusAttached);
+
+        assert_eq!(
+            (EnclaveStartFlags::default() | EnclaveStartFlags::DEBUG_MODE).bits(),
+            NE_ENCLAVE_DEBUG_MODE
+        );
+    }
+
     #[test]
     pub fn test_enclave_memory() {
-        let mut driver = NitroEnclavesDeviceDriver::new().expect("Failed to open NE device");
+        let mut driver = open_ne_device();
         let mut enclave = driver.create_enclave().unwrap();
+        assert_ne!(enclave.slot_uid(), 0);
+        assert_eq!(enclave.state(), EnclaveState::SlotAllocated);
 
         // Add invalid memory region.
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new(0, 0, 2 * MiB));
         assert!(result.is_err());
+        assert_eq!(enclave.state(), EnclaveState::SlotAllocated);
 
         // Create a memory region using hugetlbfs.
-        let region = MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap();
+        let region = enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap();
 
         // Add unaligned memory region.
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new(
@@ -287,28 +2091,33 @@ mod test_dev_driver {
         ));
         assert!(result.is_err());
 
-        // Add wrongly sized memory region of double the memory size.
+        // Add wrongly sized memory region of double the memory size. The mock driver doesn't
+        // check that the memory is mapped, so this and the out of range region are accepted.
+        #[cfg(not(feature = "mock"))]
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new(
             0,
             region.mem_addr(),
             region.mem_size() * 2,
         ));
+        #[cfg(not(feature = "mock"))]
         assert!(result.is_err());
 
         // Add wrongly sized memory region of max value multiple of 2 MiB.
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new(
             0,
             region.mem_addr(),
-            u64::max_value() - (2 * 1024 * 1024) + 1,
+            u64::MAX - (2 * 1024 * 1024) + 1,
         ));
         assert!(result.is_err());
 
         // Add wrong memory region with address out of range.
+        #[cfg(not(feature = "mock"))]
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new(
             0,
             region.mem_addr() + region.mem_size(),
             region.mem_size(),
         ));
+        #[cfg(not(feature = "mock"))]
         assert!(result.is_err());
 
         let mut check_dmesg = CheckDmesg::new().expect("Failed to obtain dmesg object");
@@ -316,38 +2125,80 @@ mod test_dev_driver {
             .record_current_line()
             .expect("Failed to record current line");
 
-        // Correctly add the memory region.
-        let region = MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap();
+        // Correctly add the memory region, whose pages are all faulted in.
+        let region = enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault(true)).unwrap();
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(&region));
         assert!(result.is_ok());
+        assert_eq!(enclave.state(), EnclaveState::MemoryAttached);
 
         check_dmesg.expect_no_changes().unwrap();
 
         // Add the same memory region twice.
-        let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(&region));
-        assert!(result.is_err());
+        let err = enclave
+            .add_mem_region(EnclaveMemoryRegion::new_from(&region))
+            .map_err(NitroCliFailure::from)
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::RegionOverlap);
+        assert_eq!(err.additional_info, vec!["0"]);
+        assert_eq!(enclave.state(), EnclaveState::MemoryAttached);
+
+        // Add memory regions which partially overlap the added one.
+        let (addr, size) = (region.mem_addr(), region.mem_size());
+        for (addr, size) in [(addr - size, 2 * size), (addr + size / 2, size)] {
+            let err = enclave
+                .add_mem_region(EnclaveMemoryRegion::new(0, addr, size))
+                .map_err(NitroCliFailure::from)
+                .unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::RegionOverlap);
+        }
 
         // Add a memory region with invalid flags.
-        let region = MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap();
-        let result = enclave.add_mem_region(EnclaveMemoryRegion::new(
-            1024,
-            region.mem_addr(),
-            region.mem_size(),
-        ));
+        let region = enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap();
+        let result = enclave.add_mem_region(EnclaveMemoryRegion::with_flags(&region, 1024));
         assert!(result.is_err());
+
+        // Only the correctly added region is recorded, in the first slot.
+        assert_eq!(
+            enclave.memory_regions(),
+            &[MemoryRegionInfo {
+                slot: 0,
+                flags: 0,
+                addr,
+                size,
+            }]
+        );
+        assert_eq!(enclave.resource_summary().memory_regions, 1);
+    }
+
+    #[test]
+    pub fn test_enclave_memory_lazy_fault() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+
+        // Regions are faulted in lazily unless asked otherwise.
+        assert_eq!(EagerFault::default(), EagerFault(false));
+
+        // A lazily faulted region can be added and gets its pages once they're touched.
+        let region = enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap();
+        let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(&region));
+        assert!(result.is_ok());
+        unsafe { std::ptr::write_volatile(region.mem_addr() as *mut u8, 0xAA) };
+        assert_eq!(
+            unsafe { std::ptr::read_volatile(region.mem_addr() as *const u8) },
+            0xAA
+        );
     }
 
     #[test]
     pub fn test_enclave_vcpu() {
-        let mut driver = NitroEnclavesDeviceDriver::new().expect("Failed to open NE device");
+        let mut driver = open_ne_device();
         let mut enclave = driver.create_enclave().unwrap();
-        let cpu_info = CpuInfo::new().expect("Failed to obtain CpuInfo.");
 
         // Add an invalid cpu id.
-        let result = enclave.add_cpu(u32::max_value());
+        let result = enclave.add_cpu(u32::MAX);
         assert!(result.is_err());
 
-        let mut candidates = cpu_info.get_cpu_candidates();
+        let mut candidates = ne_cpu_candidates();
         // Instance does not have the appropriate number of cpus.
         if candidates.is_empty() {
             return;
@@ -363,6 +2214,8 @@ mod test_dev_driver {
         // Insert the first valid cpu id.
         let result = enclave.add_cpu(cpu_id);
         assert!(result.is_ok());
+        assert_eq!(enclave.assigned_vcpus(), &[cpu_id]);
+        assert_eq!(enclave.state(), EnclaveState::VcpusAttached);
 
         check_dmesg.expect_no_changes().unwrap();
 
@@ -370,6 +2223,18 @@ mod test_dev_driver {
         let result = enclave.add_cpu(cpu_id);
         assert!(result.is_err());
 
+        // Try inserting the cpu into another enclave.
+        let mut other = driver.create_enclave().unwrap();
+        assert_ne!(other.slot_uid(), enclave.slot_uid());
+        assert!(other.add_cpu(cpu_id).is_err());
+        drop(other);
+
+        // Memory may still be added once cpus have been.
+        let region = enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap();
+        let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(&region));
+        assert!(result.is_ok());
+        assert_eq!(enclave.state(), EnclaveState::VcpusAttached);
+
         check_dmesg
             .record_current_line()
             .expect("Failed to record current line");
@@ -417,17 +2282,18 @@ mod test_dev_driver {
     #[test]
     pub fn test_enclave_start() {
         let mut mem_regions = Vec::new();
-        let mut driver = NitroEnclavesDeviceDriver::new().expect("Failed to open NE device");
+        let mut driver = open_ne_device();
         let mut enclave = driver.create_enclave().unwrap();
 
         // Start enclave without resources.
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         assert!(result.is_err());
 
         // Allocate memory for the enclave.
         #[cfg(target_arch = "x86_64")]
         for _i in 0..ENCLAVE_MEM_2MB_CHUNKS {
-            mem_regions.push(MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap());
+            mem_regions
+                .push(enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap());
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -435,7 +2301,7 @@ mod test_dev_driver {
             let mut mem_2mb_chunks = ENCLAVE_MEM_2MB_CHUNKS;
 
             for _i in 0..ENCLAVE_MEM_32MB_CHUNKS {
-                let region = MemoryRegion::new(libc::MAP_HUGE_32MB);
+                let region = enclave_mem_region(libc::MAP_HUGE_32MB, EagerFault::default());
 
                 if region.is_err() {
                     break;
@@ -447,7 +2313,8 @@ mod test_dev_driver {
             }
 
             for _i in 0..mem_2mb_chunks {
-                mem_regions.push(MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap());
+                mem_regions
+                    .push(enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap());
             }
         }
 
@@ -458,11 +2325,10 @@ mod test_dev_driver {
         }
 
         // Start the enclave without cpus.
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         assert!(result.is_err());
 
-        let cpu_info = CpuInfo::new().expect("Failed to obtain CpuInfo.");
-        let candidates = cpu_info.get_cpu_candidates();
+        let candidates = ne_cpu_candidates();
         // Instance does not have the appropriate number of cpus.
         if candidates.len() < 2 {
             return;
@@ -479,7 +2345,7 @@ mod test_dev_driver {
         }
 
         // Start enclave without memory.
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         assert!(result.is_err());
 
         drop(enclave);
@@ -504,7 +2370,7 @@ mod test_dev_driver {
             .record_current_line()
             .expect("Failed to record current line");
 
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         #[cfg(target_arch = "x86_64")]
         assert!(result.is_err());
         #[cfg(target_arch = "aarch64")]
@@ -541,7 +2407,7 @@ mod test_dev_driver {
             flags: 1234,
             ..Default::default()
         };
-        let result = enclave.start(enclave_start_info);
+        let result = enclave.start_raw(enclave_start_info);
         assert!(result.is_err());
 
         // Start with an invalid CID.
@@ -549,19 +2415,19 @@ mod test_dev_driver {
             enclave_cid: VMADDR_CID_LOCAL as u64,
             ..Default::default()
         };
-        let result = enclave.start(enclave_start_info);
+        let result = enclave.start_raw(enclave_start_info);
         assert!(result.is_err());
 
         enclave_start_info.enclave_cid = VMADDR_CID_HOST as u64;
-        let result = enclave.start(enclave_start_info);
+        let result = enclave.start_raw(enclave_start_info);
         assert!(result.is_err());
 
-        enclave_start_info.enclave_cid = u32::max_value() as u64;
-        let result = enclave.start(enclave_start_info);
+        enclave_start_info.enclave_cid = u32::MAX as u64;
+        let result = enclave.start_raw(enclave_start_info);
         assert!(result.is_err());
 
-        enclave_start_info.enclave_cid = u32::max_value() as u64 + 1234_u64;
-        let result = enclave.start(enclave_start_info);
+        enclave_start_info.enclave_cid = u32::MAX as u64 + 1234_u64;
+        let result = enclave.start_raw(enclave_start_info);
         assert!(result.is_err());
 
         let mut check_dmesg = CheckDmesg::new().expect("Failed to obtain dmesg object");
@@ -570,23 +2436,34 @@ mod test_dev_driver {
             .expect("Failed to record current line");
 
         // Start the enclave.
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         assert!(result.is_ok());
+        assert_eq!(enclave.state(), EnclaveState::Running);
 
         check_dmesg.expect_no_changes().unwrap();
 
         // Try starting an already running enclave.
-        let result = enclave.start(EnclaveStartInfo::default());
+        let result = enclave.start_raw(EnclaveStartInfo::default());
         assert!(result.is_err());
+        assert_eq!(enclave.state(), EnclaveState::Running);
 
         // Try adding an already added memory region
         // after the enclave start.
-        let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(&mem_regions[0]));
-        assert!(result.is_err());
+        let err = enclave
+            .add_mem_region(EnclaveMemoryRegion::new_from(&mem_regions[0]))
+            .map_err(NitroCliFailure::from)
+            .unwrap_err();
+        assert_eq!(
+            err.subactions,
+            vec![
+                "Invalid enclave state transition from Running to MemoryAttached",
+                "Cannot add memory after enclave start"
+            ]
+        );
 
         // Try adding a new memory region after the enclave start.
         let result = enclave.add_mem_region(EnclaveMemoryRegion::new_from(
-            &MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap(),
+            &enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap(),
         ));
         assert!(result.is_err());
 
@@ -596,20 +2473,25 @@ mod test_dev_driver {
 
         // Try adding a new vcpu after enclave start.
         if candidates.len() >= 3 {
-            let result = enclave.add_cpu(candidates[2]);
-            assert!(result.is_err());
+            let err = enclave.add_vcpu(candidates[2]).unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::InvalidStateTransition);
+            assert_eq!(err.additional_info, vec!["Running", "VcpusAttached"]);
         }
+
+        enclave.try_close().unwrap();
+        assert_eq!(enclave.state(), EnclaveState::Terminated);
     }
 
     #[test]
     pub fn test_enclave_multiple_start() {
         let mut mem_regions = Vec::new();
-        let mut driver = NitroEnclavesDeviceDriver::new().expect("Failed to open NE device");
+        let mut driver = open_ne_device();
 
         // Allocate memory for the enclave.
         #[cfg(target_arch = "x86_64")]
         for _i in 0..ENCLAVE_MEM_2MB_CHUNKS {
-            mem_regions.push(MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap());
+            mem_regions
+                .push(enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap());
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -617,7 +2499,7 @@ mod test_dev_driver {
             let mut mem_2mb_chunks = ENCLAVE_MEM_2MB_CHUNKS;
 
             for _i in 0..ENCLAVE_MEM_32MB_CHUNKS {
-                let region = MemoryRegion::new(libc::MAP_HUGE_32MB);
+                let region = enclave_mem_region(libc::MAP_HUGE_32MB, EagerFault::default());
 
                 if region.is_err() {
                     break;
@@ -629,12 +2511,12 @@ mod test_dev_driver {
             }
 
             for _i in 0..mem_2mb_chunks {
-                mem_regions.push(MemoryRegion::new(libc::MAP_HUGE_2MB).unwrap());
+                mem_regions
+                    .push(enclave_mem_region(libc::MAP_HUGE_2MB, EagerFault::default()).unwrap());
             }
         }
 
-        let cpu_info = CpuInfo::new().expect("Failed to obtain CpuInfo.");
-        let candidates = cpu_info.get_cpu_candidates();
+        let candidates = ne_cpu_candidates();
         // Instance does not have the appropriate number of cpus.
         if candidates.len() < 2 {
             return;
@@ -663,8 +2545,342 @@ mod test_dev_driver {
             }
 
             // Start and stop the enclave
-            let result = enclave.start(EnclaveStartInfo::default());
+            let result = enclave.start(EnclaveStartFlags::default());
             assert!(result.is_ok());
+
+            // The driver assigns a CID when none is requested.
+            assert!(enclave.cid().unwrap() > VMADDR_CID_HOST as u64);
+            assert_eq!(result.unwrap().enclave_cid, enclave.cid().unwrap());
+            assert_ne!(enclave.slot_uid(), 0);
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_memory_slots() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+        let region = |index: u64| EnclaveMemoryRegion::new(0, GiB + index * 2 * MiB, 2 * MiB);
+
+        // Slots are assigned in order, skipping the ones given explicitly.
+        assert_eq!(enclave.add_mem_region(region(0)).unwrap(), 0);
+        assert_eq!(enclave.add_mem_region_at(region(1), 2).unwrap(), 2);
+        assert_eq!(enclave.add_mem_region(region(2)).unwrap(), 1);
+        assert_eq!(enclave.add_mem_region(region(3)).unwrap(), 3);
+
+        // A slot can't hold two regions, and failures don't use up slots.
+        let err = NitroCliFailure::from(enclave.add_mem_region_at(region(4), 1).unwrap_err());
+        assert_eq!(err.error_code, NitroCliErrorEnum::MemorySlotInUse);
+        assert_eq!(err.additional_info, vec!["1"]);
+        assert!(enclave.add_mem_region(region(0)).is_err());
+        assert_eq!(enclave.add_mem_region(region(4)).unwrap(), 4);
+
+        let slots: Vec<u32> = enclave
+            .memory_regions()
+            .iter()
+            .map(|info| info.slot)
+            .collect();
+        assert_eq!(slots, vec![0, 2, 1, 3, 4]);
+    }
+
+    /// Map `size` bytes of anonymous memory at a 2 MiB aligned address, as the mock driver
+    /// requires.
+    #[cfg(feature = "mock")]
+    fn mock_mem_region(size: u64) -> MemoryRegion {
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                (size + 2 * MiB) as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+        // Only the aligned part is unmapped on drop, the rest is left to the end of the test.
+        let aligned = (addr as u64 + 2 * MiB - 1) & !(2 * MiB - 1);
+        MemoryRegion::new_with(0, aligned, size)
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_create_enclaves() {
+        let mut driver = open_ne_device();
+        let pool = [1, 2, 3, 4];
+        let select = |used: &[u32]| {
+            let free: Vec<u32> = pool.iter().filter(|c| !used.contains(c)).cloned().collect();
+            if free.len() < 2 {
+                return Err(NitroCliFailure::new()
+                    .add_subaction("Not enough CPUs".to_string())
+                    .set_error_code(NitroCliErrorEnum::InsufficientCpus));
+            }
+            Ok(free[..2].to_vec())
+        };
+
+        let enclaves = driver
+            .create_enclaves_with(2, select, || Ok(vec![mock_mem_region(2 * MiB)]))
+            .unwrap();
+        assert_eq!(enclaves.len(), 2);
+        assert_eq!(enclaves[0].assigned_vcpus(), &[1, 2]);
+        assert_eq!(enclaves[1].assigned_vcpus(), &[3, 4]);
+        for enclave in &enclaves {
+            assert_eq!(enclave.state(), EnclaveState::VcpusAttached);
+            assert_eq!(enclave.resource_summary().memory_bytes, 2 * MiB);
+        }
+        drop(enclaves);
+
+        // The third enclave gets no CPUs, so the whole batch is rolled back.
+        let err = driver
+            .create_enclaves_with(3, select, || Ok(vec![mock_mem_region(2 * MiB)]))
+            .err()
+            .unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::InsufficientCpus);
+        assert_eq!(
+            err.subactions,
+            vec![
+                "Not enough CPUs",
+                "Could not create enclave 2 of a batch of 3"
+            ]
+        );
+
+        // So is the batch whose second enclave gets no memory.
+        let mut allocations = 0;
+        let err = driver
+            .create_enclaves_with(2, select, || {
+                allocations += 1;
+                if allocations > 1 {
+                    return Err(NitroCliFailure::new()
+                        .add_subaction("No hugepages".to_string())
+                        .set_error_code(NitroCliErrorEnum::InsufficientMemoryAvailable));
+                }
+                Ok(vec![mock_mem_region(2 * MiB)])
+            })
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.error_code,
+            NitroCliErrorEnum::InsufficientMemoryAvailable
+        );
+        assert_eq!(
+            err.subactions.last().unwrap(),
+            "Could not create enclave 1 of a batch of 2"
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_host_memory_stats() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+        assert_eq!(enclave.host_memory_stats().unwrap(), MemStats::default());
+
+        let region = mock_mem_region(2 * MiB);
+        enclave
+            .add_mem_region(EnclaveMemoryRegion::new_from(&region))
+            .unwrap();
+        unsafe { std::ptr::write_volatile(region.mem_addr() as *mut u8, 1) };
+
+        let stats = enclave.host_memory_stats().unwrap();
+        assert!(stats.rss_bytes > 0);
+        assert_eq!(stats.hugepages, 0);
+        assert!(!stats.split);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_raw_fd() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+
+        // The borrowed descriptor can be used for ioctls and stays open.
+        let enc_fd = enclave.as_raw_fd();
+        let mut cpu_id: u32 = 1;
+        ne_ioctl(enc_fd, NeAddVcpu, &mut cpu_id).unwrap();
+        assert!(unsafe { libc::fcntl(enc_fd, libc::F_GETFD) } >= 0);
+
+        // Closing the enclave leaves no descriptor to borrow.
+        enclave.try_close().unwrap();
+        assert_eq!(enclave.as_raw_fd(), -1);
+        drop(enclave);
+
+        // A descriptor taken out of the enclave isn't closed with it.
+        let enclave = driver.create_enclave().unwrap();
+        let enc_fd = enclave.into_raw_fd();
+        assert!(enc_fd >= 0);
+        assert!(unsafe { libc::fcntl(enc_fd, libc::F_GETFD) } >= 0);
+        assert_eq!(unsafe { libc::close(enc_fd) }, 0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_ioctl_interrupted() {
+        let backend = Rc::new(MockBackend::new(vec![1, 2, 3, 4]));
+        set_ne_ioctl_backend(backend.clone());
+        let mut driver = NitroEnclavesDeviceDriver::with_path("/dev/null").unwrap();
+
+        // Interrupted ioctls are retried until they get through.
+        backend.interrupt_next(NE_IOCTL_MAX_EINTR_RETRIES);
+        let mut enclave = driver.create_enclave().unwrap();
+        assert_ne!(enclave.slot_uid(), 0);
+
+        // An ioctl which gets interrupted every time is given up on.
+        backend.interrupt_next(NE_IOCTL_MAX_EINTR_RETRIES + 1);
+        let err = enclave.add_cpu(1).err().unwrap();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IoctlAddVcpuFailure);
+        assert!(err.subactions[0].contains("Interrupted by a signal"));
+        assert!(enclave.assigned_vcpus().is_empty());
+
+        enclave.add_cpu(1).unwrap();
+        assert_eq!(enclave.assigned_vcpus(), &[1]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_typed_errors() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+
+        // A region rejected by the driver reports the ioctl and its error code.
+        let err = enclave
+            .add_mem_region(EnclaveMemoryRegion::new(0, 2 * MiB, MiB))
+            .unwrap_err();
+        match err {
+            NitroCliError::IoctlFailed { code, ioctl } => {
+                assert_eq!(code, driver_bindings::NE_ERR_INVALID_MEM_REGION_SIZE as i32);
+                assert_eq!(ioctl, "NE_SET_USER_MEMORY_REGION");
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        // A failure found before issuing the ioctl is reported as it was.
+        enclave
+            .add_mem_region_at(EnclaveMemoryRegion::new(0, 2 * MiB, 2 * MiB), 0)
+            .unwrap();
+        let err = enclave
+            .add_mem_region_at(EnclaveMemoryRegion::new(0, 4 * MiB, 2 * MiB), 0)
+            .unwrap_err();
+        match err {
+            NitroCliError::Failure(failure) => {
+                assert_eq!(failure.error_code, NitroCliErrorEnum::MemorySlotInUse)
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_image_load_info() {
+        let mut driver = open_ne_device();
+        let enclave = driver.create_enclave().unwrap();
+
+        let load_info = enclave.image_load_info(NE_EIF_IMAGE).unwrap();
+        assert_eq!(load_info.flags, NE_EIF_IMAGE);
+        assert_eq!(load_info.memory_offset, 8 * MiB);
+        assert!(check_image_fits(load_info.memory_offset, 16 * MiB, 24 * MiB).is_ok());
+        assert!(check_image_fits(load_info.memory_offset, 16 * MiB, 16 * MiB).is_err());
+
+        let err = enclave.image_load_info(0).unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::IoctlImageLoadInfoFailure);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_start_with_timeout() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+        for i in 0..ENCLAVE_MEM_2MB_CHUNKS {
+            enclave
+                .add_mem_region(EnclaveMemoryRegion::new(0, GiB + i * 2 * MiB, 2 * MiB))
+                .unwrap();
+        }
+        // Add a whole core, as x86_64 enclaves need.
+        enclave.add_cpu(0).unwrap();
+        enclave.add_cpu(0).unwrap();
+
+        // A mock enclave never sends the boot heartbeat.
+        let started_at = Instant::now();
+        let err = enclave
+            .start_with_timeout(EnclaveStartFlags::default(), Duration::from_millis(200))
+            .unwrap_err();
+        // The wait may end slightly early, depending on the timer resolution.
+        assert!(started_at.elapsed() >= Duration::from_millis(150));
+        assert_eq!(err.error_code, NitroCliErrorEnum::EnclaveStartTimeout);
+        assert_eq!(err.additional_info, vec!["200"]);
+        assert_eq!(enclave.state(), EnclaveState::Terminated);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    pub fn test_mock_enclave_start_with_cid() {
+        let mut driver = open_ne_device();
+        let mut enclaves = Vec::new();
+        for region_base in [GiB, 2 * GiB] {
+            let mut enclave = driver.create_enclave().unwrap();
+            for i in 0..ENCLAVE_MEM_2MB_CHUNKS {
+                enclave
+                    .add_mem_region(EnclaveMemoryRegion::new(
+                        0,
+                        region_base + i * 2 * MiB,
+                        2 * MiB,
+                    ))
+                    .unwrap();
+            }
+            enclave.add_cpu(0).unwrap();
+            enclave.add_cpu(0).unwrap();
+            enclaves.push(enclave);
+        }
+
+        for cid in [
+            0,
+            VMADDR_CID_HOST as u64,
+            VMADDR_CID_PARENT as u64,
+            u32::MAX as u64,
+        ] {
+            let err = enclaves[0]
+                .start_with_cid(EnclaveStartFlags::default(), cid)
+                .unwrap_err();
+            assert_eq!(err.error_code, NitroCliErrorEnum::InvalidArgument);
+        }
+
+        let start_info = enclaves[0]
+            .start_with_cid(EnclaveStartFlags::default(), 42)
+            .unwrap();
+        assert_eq!({ start_info.enclave_cid }, 42);
+        assert_eq!(enclaves[0].cid().unwrap(), 42);
+
+        let err = enclaves[1]
+            .start_with_cid(EnclaveStartFlags::default(), 42)
+            .unwrap_err();
+        assert_eq!(err.error_code, NitroCliErrorEnum::CidInUse);
+        assert_eq!(enclaves[1].state(), EnclaveState::VcpusAttached);
+        enclaves[1].start(EnclaveStartFlags::default()).unwrap();
+        assert_ne!(enclaves[1].cid().unwrap(), 42);
+    }
+
+    #[cfg(all(feature = "mock", feature = "metrics"))]
+    #[test]
+    pub fn test_mock_enclave_timings() {
+        let mut driver = open_ne_device();
+        let mut enclave = driver.create_enclave().unwrap();
+        assert!(enclave.timings().slot_creation.is_some());
+
+        for i in 0..ENCLAVE_MEM_2MB_CHUNKS {
+            enclave
+                .add_mem_region(EnclaveMemoryRegion::new(0, GiB + i * 2 * MiB, 2 * MiB))
+                .unwrap();
         }
+        enclave.add_cpu(0).unwrap();
+        enclave.add_cpu(0).unwrap();
+        // Failed steps are not recorded.
+        enclave.add_cpu(42).unwrap_err();
+        assert_eq!(enclave.timings().start, None);
+
+        enclave.start(EnclaveStartFlags::default()).unwrap();
+        let timings = enclave.timings();
+        assert_eq!(timings.mem_regions.len(), ENCLAVE_MEM_2MB_CHUNKS as usize);
+        assert_eq!(timings.vcpus.len(), 2);
+        assert!(timings.start.is_some());
+        assert!(timings.total() >= timings.start.unwrap());
     }
 }
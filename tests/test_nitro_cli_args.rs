@@ -71,6 +71,29 @@ mod test_nitro_cli_args {
         assert!(app.get_matches_from_safe(args).is_err())
     }
 
+    #[test]
+    fn terminate_enclave_timeout() {
+        let app = create_app!();
+        let args = vec![
+            "nitro cli",
+            "terminate-enclave",
+            "--enclave-id",
+            "i-1234_enc123",
+            "--timeout",
+            "30",
+        ];
+
+        assert!(app.get_matches_from_safe(args).is_ok())
+    }
+
+    #[test]
+    fn terminate_enclave_timeout_conflicts_with_all() {
+        let app = create_app!();
+        let args = vec!["nitro cli", "terminate-enclave", "--all", "--timeout", "30"];
+
+        assert!(app.get_matches_from_safe(args).is_err())
+    }
+
     #[test]
     fn terminate_enclave_name_takes_multiple_values() {
         let app = create_app!();
@@ -101,6 +124,14 @@ mod test_nitro_cli_args {
         assert!(app.get_matches_from_safe(args).is_ok())
     }
 
+    #[test]
+    fn describe_enclaves_request_schema_version_correct() {
+        let app = create_app!();
+        let args = vec!["nitro cli", "describe-enclaves", "--schema-version", "1"];
+
+        assert!(app.get_matches_from_safe(args).is_ok())
+    }
+
     #[test]
     fn describe_eif_correct_command() {
         let app = create_app!();
@@ -567,6 +598,39 @@ mod test_nitro_cli_args {
         assert!(app.get_matches_from_safe(args).is_err())
     }
 
+    #[test]
+    fn run_enclave_unsafe_allow_cpu0() {
+        let app = create_app!();
+        let args = vec![
+            "nitro cli",
+            "run-enclave",
+            "--cpu-ids",
+            "0",
+            "1",
+            "--memory",
+            "512",
+            "--eif-path",
+            "dir/image.eif",
+            "--unsafe-allow-cpu0",
+        ];
+
+        assert!(app.get_matches_from_safe(args).is_ok())
+    }
+
+    #[test]
+    fn run_enclave_unsafe_allow_cpu0_conflicts_with_config() {
+        let app = create_app!();
+        let args = vec![
+            "nitro cli",
+            "run-enclave",
+            "--config",
+            "config.json",
+            "--unsafe-allow-cpu0",
+        ];
+
+        assert!(app.get_matches_from_safe(args).is_err())
+    }
+
     #[test]
     fn run_enclave_correct_command_with_name() {
         let app = create_app!();
@@ -216,6 +216,7 @@ mod tests {
         let mut key_file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(key_path)
             .unwrap();
         key_file
@@ -225,6 +226,7 @@ mod tests {
         let mut cert_file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(cert_path)
             .unwrap();
         cert_file.write_all(&cert.to_pem().unwrap()).unwrap();
@@ -314,6 +316,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
         run_describe_terminate(args);
@@ -360,6 +363,7 @@ mod tests {
             memory_mib: 256,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
         run_describe_terminate(args);
@@ -401,6 +405,7 @@ mod tests {
             memory_mib: 2046,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
         run_describe_terminate(args);
@@ -508,6 +513,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
 
@@ -550,6 +556,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: false,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
 
@@ -612,6 +619,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
 
@@ -703,6 +711,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
         let run_result = run_enclaves(&run_args, None).expect("Run enclaves failed");
@@ -794,6 +803,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("testName".to_string()),
         };
         let run_result = run_enclaves(&run_args, None).expect("Run enclaves failed");
@@ -888,6 +898,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: None,
         };
         let names = Vec::new();
@@ -918,6 +929,7 @@ mod tests {
             memory_mib: 128,
             debug_mode: true,
             attach_console: false,
+            unsafe_allow_cpu0: false,
             enclave_name: Some("enclaveName".to_string()),
         };
         let mut names = Vec::new();
@@ -1068,12 +1080,8 @@ mod tests {
         let pcr = get_file_pcr(cert_path, PcrType::SigningCertificate).unwrap();
 
         assert_eq!(
-            eif_info
-                .build_info
-                .measurements
-                .get(&"PCR8".to_string())
-                .unwrap(),
-            pcr.get(&"PCR8".to_string()).unwrap(),
+            eif_info.build_info.measurements.get("PCR8").unwrap(),
+            pcr.get("PCR8").unwrap(),
         );
     }
 }